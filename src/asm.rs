@@ -0,0 +1,1030 @@
+//! The class files as an editable text, and back. The text is a small
+//! subset of YAML, with every part of the class as it is in the file: the
+//! constants by their indices (with a `!tag` for the kind of each one), the
+//! flags and the indices as numbers, and the attributes as hex bytes, the
+//! Code ones split into their parts and the instructions, one per line.
+//!
+//! The comments (the names the indices point to, the flags, the offsets of
+//! the instructions) are only there for reading, nothing is computed from
+//! them, so changing the length of an instruction means fixing up the jumps
+//! over it by hand, same as with any other bytes.
+
+use std::fmt::Write;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::{
+    bytecode,
+    class::{Attribute, ClassFile, Code, Constant, ExceptionHandler, Member},
+    console,
+};
+
+pub fn disassemble(class: &ClassFile) -> String {
+    let mut out = String::new();
+    let comment = |index: u16| match describe(class, index, 0) {
+        Some(description) => format!("  # {}", console::escaped(&description)),
+        None => String::new(),
+    };
+    // writing into a string cannot fail
+    let mut line = |indent: usize, text: &str| {
+        writeln!(out, "{:indent$}{}", "", text, indent = indent).unwrap();
+    };
+
+    line(
+        0,
+        &format!(
+            "# Disassembled by {} {}, `asm` makes the class out of it again",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        ),
+    );
+    line(0, &format!("minor_version: {}", class.minor_version));
+    line(0, &format!("major_version: {}", class.major_version));
+    line(0, "constant_pool:");
+    for (index, constant) in class.constant_pool.iter().enumerate() {
+        if *constant == Constant::Unusable {
+            continue;
+        }
+        let index = index as u16;
+        let value = match constant {
+            Constant::Utf8(bytes) => match utf8_string(bytes) {
+                Some(string) => format!("!utf8 {}", quoted(&string)),
+                None => format!("!utf8_bytes {}", quoted(&hex(bytes))),
+            },
+            Constant::Integer(v) => format!("!integer {}", *v as i32),
+            Constant::Float(v) => format!("!float 0x{:08x}  # {}", v, f32::from_bits(*v)),
+            Constant::Long(v) => format!("!long {}", *v as i64),
+            Constant::Double(v) => format!("!double 0x{:016x}  # {}", v, f64::from_bits(*v)),
+            Constant::Class(i) => format!("!class {}{}", i, comment(index)),
+            Constant::String(i) => format!("!string {}", i),
+            Constant::FieldRef {
+                class,
+                name_and_type,
+            } => format!("!fieldref {} {}{}", class, name_and_type, comment(index)),
+            Constant::MethodRef {
+                class,
+                name_and_type,
+            } => format!("!methodref {} {}{}", class, name_and_type, comment(index)),
+            Constant::InterfaceMethodRef {
+                class,
+                name_and_type,
+            } => format!(
+                "!interfacemethodref {} {}{}",
+                class,
+                name_and_type,
+                comment(index)
+            ),
+            Constant::NameAndType { name, descriptor } => {
+                format!("!nameandtype {} {}{}", name, descriptor, comment(index))
+            }
+            Constant::MethodHandle { kind, reference } => {
+                format!("!methodhandle {} {}{}", kind, reference, comment(index))
+            }
+            Constant::MethodType(i) => format!("!methodtype {}", i),
+            Constant::Dynamic {
+                bootstrap,
+                name_and_type,
+            } => format!("!dynamic {} {}{}", bootstrap, name_and_type, comment(index)),
+            Constant::InvokeDynamic {
+                bootstrap,
+                name_and_type,
+            } => format!(
+                "!invokedynamic {} {}{}",
+                bootstrap,
+                name_and_type,
+                comment(index)
+            ),
+            Constant::Module(i) => format!("!module {}", i),
+            Constant::Package(i) => format!("!package {}", i),
+            Constant::Unusable => unreachable!(),
+        };
+        line(2, &format!("{}: {}", index, value));
+    }
+    line(
+        0,
+        &format!(
+            "access_flags: 0x{:04x}{}",
+            class.access_flags,
+            flags_comment(class.access_flags, CLASS_FLAGS)
+        ),
+    );
+    line(
+        0,
+        &format!(
+            "this_class: {}{}",
+            class.this_class,
+            comment(class.this_class)
+        ),
+    );
+    line(
+        0,
+        &format!(
+            "super_class: {}{}",
+            class.super_class,
+            comment(class.super_class)
+        ),
+    );
+    match class.interfaces.as_slice() {
+        [] => line(0, "interfaces: []"),
+        interfaces => {
+            line(0, "interfaces:");
+            for &interface in interfaces {
+                line(2, &format!("- {}{}", interface, comment(interface)));
+            }
+        }
+    }
+    for (key, members, flags) in [
+        ("fields", &class.fields, FIELD_FLAGS),
+        ("methods", &class.methods, METHOD_FLAGS),
+    ] {
+        if members.is_empty() {
+            line(0, &format!("{}: []", key));
+            continue;
+        }
+        line(0, &format!("{}:", key));
+        for member in members {
+            line(
+                2,
+                &format!(
+                    "- access_flags: 0x{:04x}{}",
+                    member.access_flags,
+                    flags_comment(member.access_flags, flags)
+                ),
+            );
+            line(
+                4,
+                &format!("name: {}{}", member.name_index, comment(member.name_index)),
+            );
+            line(
+                4,
+                &format!(
+                    "descriptor: {}{}",
+                    member.descriptor_index,
+                    comment(member.descriptor_index)
+                ),
+            );
+            write_attributes(class, &member.attributes, 4, &mut line);
+        }
+    }
+    write_attributes(class, &class.attributes, 0, &mut line);
+    line(0, &format!("trailing: {}", quoted(&hex(&class.trailing))));
+    out
+}
+
+fn write_attributes(
+    class: &ClassFile,
+    attributes: &[Attribute],
+    indent: usize,
+    line: &mut impl FnMut(usize, &str),
+) {
+    if attributes.is_empty() {
+        line(indent, "attributes: []");
+        return;
+    }
+    line(indent, "attributes:");
+    for attribute in attributes {
+        let name = class.attribute_name(attribute).ok();
+        line(
+            indent + 2,
+            &format!(
+                "- name: {}{}",
+                attribute.name_index,
+                match &name {
+                    Some(name) => format!("  # {}", console::escaped(name)),
+                    None => String::new(),
+                }
+            ),
+        );
+        let indent = indent + 4;
+        // only the ones that come out the same way they went in are taken
+        // apart, the rest are just bytes
+        let code = match name.as_deref() {
+            Some("Code") => Code::parse(&attribute.info)
+                .ok()
+                .filter(|code| code.to_bytes() == attribute.info),
+            _ => None,
+        };
+        let instructions = code
+            .as_ref()
+            .and_then(|code| bytecode::instructions(&code.code).ok());
+        let (code, instructions) = match (code, instructions) {
+            (Some(code), Some(instructions)) => (code, instructions),
+            _ => {
+                line(indent, &format!("info: {}", quoted(&hex(&attribute.info))));
+                continue;
+            }
+        };
+        line(indent, &format!("max_stack: {}", code.max_stack));
+        line(indent, &format!("max_locals: {}", code.max_locals));
+        line(indent, "code:");
+        for (i, instruction) in instructions.iter().enumerate() {
+            let end = instructions
+                .get(i + 1)
+                .map_or(code.code.len(), |next| next.pc);
+            let mut text = format!(
+                "- {}  # {}: {}",
+                hex(&code.code[instruction.pc..end]),
+                instruction.pc,
+                bytecode::mnemonic(instruction.opcode)
+            );
+            let constant = bytecode::constant_operand(&code.code, instruction)
+                .and_then(|index| describe(class, index, 0));
+            if let Some(constant) = constant {
+                text.push(' ');
+                text.push_str(&console::escaped(&constant));
+            }
+            line(indent + 2, &text);
+        }
+        match code.exception_table.as_slice() {
+            [] => line(indent, "exception_table: []"),
+            handlers => {
+                line(indent, "exception_table:");
+                for handler in handlers {
+                    line(
+                        indent + 2,
+                        &format!(
+                            "- [{}, {}, {}, {}]  # start, end, handler, catch type",
+                            handler.start_pc,
+                            handler.end_pc,
+                            handler.handler_pc,
+                            handler.catch_type
+                        ),
+                    );
+                }
+            }
+        }
+        write_attributes(class, &code.attributes, indent, line);
+    }
+}
+
+/// What the constant is about, for the comments
+fn describe(class: &ClassFile, index: u16, depth: usize) -> Option<String> {
+    // the constants can point at each other in circles in broken classes
+    if depth > 4 {
+        return None;
+    }
+    let utf8 = |index: u16| class.utf8(index).ok().map(|s| s.into_owned());
+    let nested = |index: u16| describe(class, index, depth + 1);
+    Some(match class.constant(index).ok()? {
+        Constant::Utf8(_) => utf8(index)?,
+        Constant::Integer(v) => (*v as i32).to_string(),
+        Constant::Float(v) => f32::from_bits(*v).to_string(),
+        Constant::Long(v) => (*v as i64).to_string(),
+        Constant::Double(v) => f64::from_bits(*v).to_string(),
+        Constant::Class(i)
+        | Constant::MethodType(i)
+        | Constant::Module(i)
+        | Constant::Package(i) => utf8(*i)?,
+        Constant::String(i) => format!("{:?}", utf8(*i)?),
+        Constant::FieldRef {
+            class,
+            name_and_type,
+        }
+        | Constant::MethodRef {
+            class,
+            name_and_type,
+        }
+        | Constant::InterfaceMethodRef {
+            class,
+            name_and_type,
+        } => format!("{}.{}", nested(*class)?, nested(*name_and_type)?),
+        Constant::NameAndType { name, descriptor } => {
+            format!("{}:{}", utf8(*name)?, utf8(*descriptor)?)
+        }
+        Constant::MethodHandle { reference, .. } => nested(*reference)?,
+        Constant::Dynamic {
+            bootstrap,
+            name_and_type,
+        }
+        | Constant::InvokeDynamic {
+            bootstrap,
+            name_and_type,
+        } => format!("bootstrap {} {}", bootstrap, nested(*name_and_type)?),
+        Constant::Unusable => return None,
+    })
+}
+
+const CLASS_FLAGS: &[(u16, &str)] = &[
+    (0x0001, "public"),
+    (0x0010, "final"),
+    (0x0020, "super"),
+    (0x0200, "interface"),
+    (0x0400, "abstract"),
+    (0x1000, "synthetic"),
+    (0x2000, "annotation"),
+    (0x4000, "enum"),
+    (0x8000, "module"),
+];
+
+const FIELD_FLAGS: &[(u16, &str)] = &[
+    (0x0001, "public"),
+    (0x0002, "private"),
+    (0x0004, "protected"),
+    (0x0008, "static"),
+    (0x0010, "final"),
+    (0x0040, "volatile"),
+    (0x0080, "transient"),
+    (0x1000, "synthetic"),
+    (0x4000, "enum"),
+];
+
+const METHOD_FLAGS: &[(u16, &str)] = &[
+    (0x0001, "public"),
+    (0x0002, "private"),
+    (0x0004, "protected"),
+    (0x0008, "static"),
+    (0x0010, "final"),
+    (0x0020, "synchronized"),
+    (0x0040, "bridge"),
+    (0x0080, "varargs"),
+    (0x0100, "native"),
+    (0x0400, "abstract"),
+    (0x0800, "strict"),
+    (0x1000, "synthetic"),
+];
+
+fn flags_comment(flags: u16, names: &[(u16, &str)]) -> String {
+    let names = names
+        .iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>();
+    match names.is_empty() {
+        true => String::new(),
+        false => format!("  # {}", names.join(" ")),
+    }
+}
+
+/// The value of the constant, unless it's malformed or not written the way
+/// it would be written again
+fn utf8_string(bytes: &[u8]) -> Option<String> {
+    let string = cesu8::from_java_cesu8(bytes).ok()?;
+    (*cesu8::to_java_cesu8(&string) == *bytes).then(|| string.into_owned())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 3);
+    for (i, b) in bytes.iter().enumerate() {
+        if i != 0 {
+            out.push(' ');
+        }
+        write!(out, "{:02x}", b).unwrap();
+    }
+    out
+}
+
+/// A YAML double-quoted string
+fn quoted(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\'' => out.push('\''),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\0' => out.push_str("\\0"),
+            c if c.escape_debug().len() > 1 && (c as u32) < 0x10000 => {
+                write!(out, "\\u{:04x}", c as u32).unwrap()
+            }
+            c if c.escape_debug().len() > 1 => write!(out, "\\U{:08x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn assemble(text: &str) -> Result<ClassFile> {
+    let root = parse(text)?;
+    let root = Fields::of(&root, "the class")?;
+    root.expect_only(&[
+        "minor_version",
+        "major_version",
+        "constant_pool",
+        "access_flags",
+        "this_class",
+        "super_class",
+        "interfaces",
+        "fields",
+        "methods",
+        "attributes",
+        "trailing",
+    ])?;
+
+    let mut constant_pool = vec![Constant::Unusable];
+    let constants = Fields::of(root.get("constant_pool")?, "constant_pool")?;
+    for (key, value) in constants.0 {
+        let index = constant_pool.len();
+        let context = || format!("In constant #{}", key);
+        ensure!(
+            key.parse() == Ok(index),
+            "Constant #{} is where #{} should be",
+            key,
+            index
+        );
+        let constant = parse_constant(value).with_context(context)?;
+        let wide = matches!(constant, Constant::Long(_) | Constant::Double(_));
+        constant_pool.push(constant);
+        if wide {
+            constant_pool.push(Constant::Unusable);
+        }
+    }
+    ensure!(
+        constant_pool.len() <= u16::MAX as usize,
+        "Too many constants"
+    );
+
+    let class = ClassFile {
+        minor_version: int(root.get("minor_version")?).context("In minor_version")?,
+        major_version: int(root.get("major_version")?).context("In major_version")?,
+        constant_pool,
+        access_flags: int(root.get("access_flags")?).context("In access_flags")?,
+        this_class: int(root.get("this_class")?).context("In this_class")?,
+        super_class: int(root.get("super_class")?).context("In super_class")?,
+        interfaces: list(root.get("interfaces")?, "interfaces")?
+            .iter()
+            .map(int)
+            .collect::<Result<_>>()
+            .context("In interfaces")?,
+        fields: members(root.get("fields")?, "fields")?,
+        methods: members(root.get("methods")?, "methods")?,
+        attributes: attributes(root.get("attributes")?, "the class attributes")?,
+        trailing: bytes(root.get("trailing")?).context("In trailing")?,
+    };
+    Ok(class)
+}
+
+fn parse_constant(node: &Node) -> Result<Constant> {
+    let text = scalar(node)?;
+    let (tag, value) = text.split_once(' ').unwrap_or((text, ""));
+    let value = value.trim();
+    let numbers = || -> Result<Vec<u64>> { value.split_whitespace().map(number).collect() };
+    let one = || -> Result<u16> {
+        match numbers()?[..] {
+            [v] => Ok(u16::try_from(v)?),
+            _ => bail!("{} takes one index", tag),
+        }
+    };
+    let two = || -> Result<(u16, u16)> {
+        match numbers()?[..] {
+            [a, b] => Ok((u16::try_from(a)?, u16::try_from(b)?)),
+            _ => bail!("{} takes two indices", tag),
+        }
+    };
+    Ok(match tag {
+        "!utf8" => {
+            let bytes = cesu8::to_java_cesu8(&unquoted(value)?).into_owned();
+            ensure!(bytes.len() <= u16::MAX as usize, "The string is too long");
+            Constant::Utf8(bytes)
+        }
+        "!utf8_bytes" => Constant::Utf8(bytes(&Node::Scalar(value.to_owned()))?),
+        "!integer" => Constant::Integer(match value.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16)?,
+            None => value.parse::<i32>()? as u32,
+        }),
+        "!float" => Constant::Float(match value.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16)?,
+            None => value.parse::<f32>()?.to_bits(),
+        }),
+        "!long" => Constant::Long(match value.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16)?,
+            None => value.parse::<i64>()? as u64,
+        }),
+        "!double" => Constant::Double(match value.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16)?,
+            None => value.parse::<f64>()?.to_bits(),
+        }),
+        "!class" => Constant::Class(one()?),
+        "!string" => Constant::String(one()?),
+        "!fieldref" => {
+            let (class, name_and_type) = two()?;
+            Constant::FieldRef {
+                class,
+                name_and_type,
+            }
+        }
+        "!methodref" => {
+            let (class, name_and_type) = two()?;
+            Constant::MethodRef {
+                class,
+                name_and_type,
+            }
+        }
+        "!interfacemethodref" => {
+            let (class, name_and_type) = two()?;
+            Constant::InterfaceMethodRef {
+                class,
+                name_and_type,
+            }
+        }
+        "!nameandtype" => {
+            let (name, descriptor) = two()?;
+            Constant::NameAndType { name, descriptor }
+        }
+        "!methodhandle" => {
+            let (kind, reference) = two()?;
+            Constant::MethodHandle {
+                kind: u8::try_from(kind)?,
+                reference,
+            }
+        }
+        "!methodtype" => Constant::MethodType(one()?),
+        "!dynamic" => {
+            let (bootstrap, name_and_type) = two()?;
+            Constant::Dynamic {
+                bootstrap,
+                name_and_type,
+            }
+        }
+        "!invokedynamic" => {
+            let (bootstrap, name_and_type) = two()?;
+            Constant::InvokeDynamic {
+                bootstrap,
+                name_and_type,
+            }
+        }
+        "!module" => Constant::Module(one()?),
+        "!package" => Constant::Package(one()?),
+        _ => bail!("Unknown kind of constant '{}'", tag),
+    })
+}
+
+fn members(node: &Node, what: &str) -> Result<Vec<Member>> {
+    let mut members = Vec::new();
+    for (i, node) in list(node, what)?.iter().enumerate() {
+        let context = format!("{}[{}]", what, i);
+        let fields = Fields::of(node, &context)?;
+        fields.expect_only(&["access_flags", "name", "descriptor", "attributes"])?;
+        let index = |key: &str| int(fields.get(key)?).with_context(|| format!("In {}", context));
+        members.push(Member {
+            access_flags: index("access_flags")?,
+            name_index: index("name")?,
+            descriptor_index: index("descriptor")?,
+            attributes: attributes(
+                fields.get("attributes")?,
+                &format!("{} attributes", context),
+            )?,
+        });
+    }
+    Ok(members)
+}
+
+fn attributes(node: &Node, what: &str) -> Result<Vec<Attribute>> {
+    let mut result = Vec::new();
+    for (i, node) in list(node, what)?.iter().enumerate() {
+        let context = format!("{}[{}]", what, i);
+        let fields = Fields::of(node, &context)?;
+        let name_index = int(fields.get("name")?).with_context(|| format!("In {}", context))?;
+        if fields.has("info") {
+            fields.expect_only(&["name", "info"])?;
+            let info = bytes(fields.get("info")?).with_context(|| format!("In {}", context))?;
+            result.push(Attribute { name_index, info });
+            continue;
+        }
+        fields.expect_only(&[
+            "name",
+            "max_stack",
+            "max_locals",
+            "code",
+            "exception_table",
+            "attributes",
+        ])?;
+        let mut code = Vec::new();
+        for instruction in list(fields.get("code")?, &context)? {
+            code.extend(bytes(instruction).with_context(|| format!("In {}", context))?);
+        }
+        let mut exception_table = Vec::new();
+        for handler in list(fields.get("exception_table")?, &context)? {
+            let values = list(handler, &context)?
+                .iter()
+                .map(int)
+                .collect::<Result<Vec<u16>>>()
+                .with_context(|| format!("In {}", context))?;
+            let (start_pc, end_pc, handler_pc, catch_type) = match values[..] {
+                [a, b, c, d] => (a, b, c, d),
+                _ => bail!(
+                    "In {}: the handlers are the start, the end, the handler and the catch type",
+                    context
+                ),
+            };
+            exception_table.push(ExceptionHandler {
+                start_pc,
+                end_pc,
+                handler_pc,
+                catch_type,
+            });
+        }
+        let code = Code {
+            max_stack: int(fields.get("max_stack")?).with_context(|| format!("In {}", context))?,
+            max_locals: int(fields.get("max_locals")?)
+                .with_context(|| format!("In {}", context))?,
+            code,
+            exception_table,
+            attributes: attributes(
+                fields.get("attributes")?,
+                &format!("{} attributes", context),
+            )?,
+        };
+        result.push(Attribute {
+            name_index,
+            info: code.to_bytes(),
+        });
+    }
+    Ok(result)
+}
+
+/// The part of YAML that is written above: blocks of mappings and
+/// sequences, plain and double-quoted scalars, and flow sequences of plain
+/// scalars
+#[derive(Debug)]
+enum Node {
+    Scalar(String),
+    Seq(Vec<Node>),
+    Map(Vec<(String, Node)>),
+}
+
+struct Line<'a> {
+    number: usize,
+    indent: usize,
+    text: &'a str,
+}
+
+fn parse(text: &str) -> Result<Node> {
+    let mut lines = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = strip_comment(line);
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() {
+            continue;
+        }
+        ensure!(
+            !line.starts_with('\t'),
+            "On line {}: tabs are not allowed for indentation",
+            number + 1
+        );
+        let mut indent = line.len() - trimmed.len();
+        let mut text = trimmed;
+        // `- key: value` is a sequence item that is a mapping starting on the
+        // same line, so the mapping is made a line of its own
+        while let Some(rest) = text
+            .strip_prefix('-')
+            .filter(|r| r.is_empty() || r.starts_with(' '))
+        {
+            lines.push(Line {
+                number,
+                indent,
+                text: "-",
+            });
+            let item = rest.trim_start();
+            indent += 1 + rest.len() - item.len();
+            text = item;
+        }
+        if !text.is_empty() {
+            lines.push(Line {
+                number,
+                indent,
+                text,
+            });
+        }
+    }
+    let mut pos = 0;
+    let root = match lines.first() {
+        Some(first) => parse_block(&lines, &mut pos, first.indent)?,
+        None => bail!("There's nothing in it"),
+    };
+    if let Some(line) = lines.get(pos) {
+        bail!("On line {}: unexpected indentation", line.number + 1);
+    }
+    Ok(root)
+}
+
+fn parse_block(lines: &[Line], pos: &mut usize, indent: usize) -> Result<Node> {
+    let first = &lines[*pos];
+    if first.text == "-" {
+        let mut items = Vec::new();
+        while let Some(line) = lines.get(*pos).filter(|l| l.indent >= indent) {
+            ensure!(
+                line.indent == indent && line.text == "-",
+                "On line {}: expected another item of the list",
+                line.number + 1
+            );
+            *pos += 1;
+            items.push(parse_nested(lines, pos, indent, line)?);
+        }
+        return Ok(Node::Seq(items));
+    }
+    if split_key(first.text).is_none() {
+        *pos += 1;
+        return scalar_node(first.text, first.number);
+    }
+    let mut entries = Vec::new();
+    while let Some(line) = lines.get(*pos).filter(|l| l.indent >= indent) {
+        let (key, value) = match split_key(line.text) {
+            Some(split) if line.indent == indent => split,
+            _ => bail!("On line {}: expected a key", line.number + 1),
+        };
+        ensure!(
+            entries.iter().all(|(k, _)| k != key),
+            "On line {}: '{}' is given twice",
+            line.number + 1,
+            key
+        );
+        *pos += 1;
+        let value = match value.is_empty() {
+            true => parse_nested(lines, pos, indent, line)?,
+            false => scalar_node(value, line.number)?,
+        };
+        entries.push((key.to_owned(), value));
+    }
+    Ok(Node::Map(entries))
+}
+
+/// The block under the line, an empty scalar if there's none
+fn parse_nested(lines: &[Line], pos: &mut usize, indent: usize, line: &Line) -> Result<Node> {
+    match lines.get(*pos) {
+        Some(next) if next.indent > indent => parse_block(lines, pos, next.indent),
+        _ => scalar_node("", line.number),
+    }
+}
+
+fn scalar_node(text: &str, number: usize) -> Result<Node> {
+    match text.strip_prefix('[') {
+        Some(flow) => match flow.strip_suffix(']') {
+            Some(flow) if flow.trim().is_empty() => Ok(Node::Seq(Vec::new())),
+            Some(flow) => Ok(Node::Seq(
+                flow.split(',')
+                    .map(|item| Node::Scalar(item.trim().to_owned()))
+                    .collect(),
+            )),
+            None => bail!("On line {}: the list is not closed", number + 1),
+        },
+        None => Ok(Node::Scalar(text.to_owned())),
+    }
+}
+
+/// The `key` and the `value` of `key: value`, minding the quotes
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            ':' if !in_quotes => {
+                let rest = &text[i + 1..];
+                if rest.is_empty() || rest.starts_with(' ') {
+                    return Some((text[..i].trim(), rest.trim()));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The line without the comment, which starts with a `#` that is not in
+/// quotes and is at the start or after a space
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            '#' if !in_quotes && previous == ' ' => return line[..i].trim_end(),
+            _ => {}
+        }
+        previous = c;
+    }
+    line.trim_end()
+}
+
+/// The entries of a mapping
+struct Fields<'a>(&'a [(String, Node)], String);
+
+impl<'a> Fields<'a> {
+    fn of(node: &'a Node, what: &str) -> Result<Self> {
+        match node {
+            Node::Map(entries) => Ok(Self(entries, what.to_owned())),
+            _ => bail!("{} should be a mapping", what),
+        }
+    }
+
+    fn has(&self, key: &str) -> bool {
+        self.0.iter().any(|(k, _)| k == key)
+    }
+
+    fn get(&self, key: &str) -> Result<&'a Node> {
+        match self.0.iter().find(|(k, _)| k == key) {
+            Some((_, value)) => Ok(value),
+            None => bail!("{} has no '{}'", self.1, key),
+        }
+    }
+
+    /// A typo in a key shouldn't be silently ignored
+    fn expect_only(&self, keys: &[&str]) -> Result<()> {
+        match self.0.iter().find(|(k, _)| !keys.contains(&k.as_str())) {
+            Some((key, _)) => bail!("Unknown key '{}' in {}", key, self.1),
+            None => Ok(()),
+        }
+    }
+}
+
+fn scalar(node: &Node) -> Result<&str> {
+    match node {
+        Node::Scalar(text) => Ok(text),
+        _ => bail!("Expected a value, not a list or a mapping"),
+    }
+}
+
+fn list<'a>(node: &'a Node, what: &str) -> Result<&'a [Node]> {
+    match node {
+        Node::Seq(items) => Ok(items),
+        _ => bail!("{} should be a list", what),
+    }
+}
+
+/// Decimal or hex
+fn number(text: &str) -> Result<u64> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+    .with_context(|| format!("'{}' is not a number", text))
+}
+
+fn int<T: TryFrom<u64>>(node: &Node) -> Result<T> {
+    let text = scalar(node)?;
+    match T::try_from(number(text)?) {
+        Ok(value) => Ok(value),
+        Err(_) => bail!("{} is too big", text),
+    }
+}
+
+fn bytes(node: &Node) -> Result<Vec<u8>> {
+    let text = scalar(node)?;
+    let text = match text.starts_with('"') {
+        true => unquoted(text)?,
+        false => text.to_owned(),
+    };
+    let digits = text.split_whitespace().collect::<String>();
+    ensure!(digits.len() % 2 == 0, "Odd number of hex digits");
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .with_context(|| format!("'{}' is not a hex byte", &digits[i..i + 2]))
+        })
+        .collect()
+}
+
+fn unquoted(text: &str) -> Result<String> {
+    let inner = match text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        Some(inner) if text.len() >= 2 => inner,
+        _ => bail!("Expected a string in double quotes"),
+    };
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('/') => '/',
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('x') => code_point(&mut chars, 2)?,
+            Some('u') => code_point(&mut chars, 4)?,
+            Some('U') => code_point(&mut chars, 8)?,
+            Some(c) => bail!("Unknown escape '\\{}'", c),
+            None => bail!("The string ends with a backslash"),
+        });
+    }
+    Ok(out)
+}
+
+fn code_point(chars: &mut std::str::Chars, len: usize) -> Result<char> {
+    let digits = chars.take(len).collect::<String>();
+    u32::from_str_radix(&digits, 16)
+        .ok()
+        .filter(|_| digits.len() == len)
+        .and_then(char::from_u32)
+        .with_context(|| format!("Bad escape '{}'", digits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `class Test { int a.b; void run() { return; } }`, the same as the
+    /// one in the tests of the class files
+    fn sample() -> ClassFile {
+        let utf8 = |s: &str| Constant::Utf8(s.as_bytes().to_vec());
+        let code = Code {
+            max_stack: 1,
+            max_locals: 1,
+            code: vec![0xB1], // return
+            exception_table: vec![ExceptionHandler {
+                start_pc: 0,
+                end_pc: 1,
+                handler_pc: 0,
+                catch_type: 0,
+            }],
+            attributes: Vec::new(),
+        };
+        ClassFile {
+            minor_version: 0,
+            major_version: 52,
+            constant_pool: vec![
+                Constant::Unusable,
+                utf8("Test"),
+                Constant::Class(1),
+                utf8("java/lang/Object"),
+                Constant::Class(3),
+                utf8("a.b"),
+                utf8("I"),
+                utf8("run"),
+                utf8("()V"),
+                utf8("Code"),
+                Constant::Long(0x0123_4567_89AB_CDEF),
+                Constant::Unusable,
+                Constant::NameAndType {
+                    name: 5,
+                    descriptor: 6,
+                },
+                Constant::FieldRef {
+                    class: 2,
+                    name_and_type: 12,
+                },
+            ],
+            access_flags: 0x21,
+            this_class: 2,
+            super_class: 4,
+            interfaces: Vec::new(),
+            fields: vec![Member {
+                access_flags: 0x02,
+                name_index: 5,
+                descriptor_index: 6,
+                attributes: Vec::new(),
+            }],
+            methods: vec![Member {
+                access_flags: 0x01,
+                name_index: 7,
+                descriptor_index: 8,
+                attributes: vec![Attribute {
+                    name_index: 9,
+                    info: code.to_bytes(),
+                }],
+            }],
+            attributes: Vec::new(),
+            trailing: vec![0xCA, 0xFE],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let class = sample();
+        let text = disassemble(&class);
+        let assembled = assemble(&text).unwrap();
+        assert_eq!(assembled, class);
+        assert_eq!(disassemble(&assembled), text);
+    }
+
+    /// The error of assembling the sample with `from` replaced with `to`
+    fn broken(from: &str, to: &str) -> String {
+        let text = disassemble(&sample());
+        assert!(text.contains(from), "{}", from);
+        let error = assemble(&text.replacen(from, to, 1)).unwrap_err();
+        format!("{:#}", error)
+    }
+
+    #[test]
+    fn malformed() {
+        let error = broken("  12: !nameandtype", "  11: !nameandtype");
+        assert!(
+            error.contains("Constant #11 is where #12 should be"),
+            "{}",
+            error
+        );
+        let error = broken("!utf8 \"Test\"", "!nonsense \"Test\"");
+        assert!(error.contains("In constant #1"), "{}", error);
+        let error = broken("major_version: 52", "major_version: lots");
+        assert!(error.contains("In major_version"), "{}", error);
+        let error = broken("trailing: \"ca fe\"", "trailing: \"ca f\"");
+        assert!(error.contains("In trailing"), "{}", error);
+        let error = broken("interfaces: []\n", "");
+        assert!(error.contains("interfaces"), "{}", error);
+        assert!(assemble("").is_err());
+    }
+}
@@ -0,0 +1,193 @@
+//! Carrying the permissions, the owner and the extended attributes (which is
+//! also where the ACLs are on Linux) of a file over to the one replacing it,
+//! which otherwise gets whatever a new file gets.
+//!
+//! All of it is best effort, a user can't give a file away to someone else,
+//! and some of the attributes need privileges to be set.
+
+use std::{
+    fs::Permissions,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+/// Makes the file (or the directory) writable for as long as it's held, and
+/// read-only again when dropped
+#[derive(Debug)]
+pub struct Writable {
+    path: PathBuf,
+    original: Permissions,
+}
+
+pub fn writable(path: &Path) -> Result<Writable> {
+    let original = std::fs::metadata(path)
+        .with_context(|| format!("Reading {}", path.display()))?
+        .permissions();
+    let mut permissions = original.clone();
+    // only for the owner, set_readonly(false) gives everyone the right to
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.set_mode(permissions.mode() | 0o200);
+    }
+    #[cfg(not(unix))]
+    permissions.set_readonly(false);
+    std::fs::set_permissions(path, permissions)
+        .with_context(|| format!("Making {} writable", path.display()))?;
+    Ok(Writable {
+        path: path.to_owned(),
+        original,
+    })
+}
+
+impl Drop for Writable {
+    fn drop(&mut self) {
+        // it's the new file by now, if it was the one being replaced
+        if let Err(e) = std::fs::set_permissions(&self.path, self.original.clone()) {
+            log::warn!(
+                "Could not make {} read-only again: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Makes `to` have the metadata that `from` has, as much as it can
+pub fn copy(from: &Path, to: &Path) -> Result<()> {
+    let metadata =
+        std::fs::metadata(from).with_context(|| format!("Reading {}", from.display()))?;
+    // before the permissions, since changing the owner clears the setuid bits
+    #[cfg(unix)]
+    copy_owner(&metadata, to);
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    xattr::copy(from, to);
+    std::fs::set_permissions(to, metadata.permissions())
+        .with_context(|| format!("Setting the permissions of {}", to.display()))
+}
+
+#[cfg(unix)]
+fn copy_owner(metadata: &std::fs::Metadata, to: &Path) {
+    use std::os::unix::fs::MetadataExt;
+    let (uid, gid) = (metadata.uid(), metadata.gid());
+    // someone who's not root can still change the group to one of their own
+    let result = std::os::unix::fs::chown(to, Some(uid), Some(gid))
+        .or_else(|_| std::os::unix::fs::chown(to, None, Some(gid)));
+    if let Err(e) = result {
+        log::debug!("Could not set the owner of {}: {}", to.display(), e);
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod xattr {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt, path::Path};
+
+    use libc::{c_char, c_void};
+
+    #[cfg(target_os = "linux")]
+    unsafe fn list(path: *const c_char, buf: *mut c_char, size: usize) -> isize {
+        libc::listxattr(path, buf, size)
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn get(
+        path: *const c_char,
+        name: *const c_char,
+        buf: *mut c_void,
+        size: usize,
+    ) -> isize {
+        libc::getxattr(path, name, buf, size)
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn set(
+        path: *const c_char,
+        name: *const c_char,
+        value: *const c_void,
+        size: usize,
+    ) -> i32 {
+        libc::setxattr(path, name, value, size, 0)
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn list(path: *const c_char, buf: *mut c_char, size: usize) -> isize {
+        libc::listxattr(path, buf, size, 0)
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn get(
+        path: *const c_char,
+        name: *const c_char,
+        buf: *mut c_void,
+        size: usize,
+    ) -> isize {
+        libc::getxattr(path, name, buf, size, 0, 0)
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn set(
+        path: *const c_char,
+        name: *const c_char,
+        value: *const c_void,
+        size: usize,
+    ) -> i32 {
+        libc::setxattr(path, name, value, size, 0, 0)
+    }
+
+    /// Calls the function with a buffer of the size it says it needs, which
+    /// can change in between, hence the retries
+    fn read(call: impl Fn(*mut u8, usize) -> isize) -> Option<Vec<u8>> {
+        for _ in 0..3 {
+            let size = call(std::ptr::null_mut(), 0);
+            if size < 0 {
+                return None;
+            }
+            let mut buf = vec![0; size as usize];
+            let read = call(buf.as_mut_ptr(), buf.len());
+            if read >= 0 {
+                buf.truncate(read as usize);
+                return Some(buf);
+            }
+        }
+        None
+    }
+
+    pub fn copy(from: &Path, to: &Path) {
+        let (from_c, to_c) = match (
+            CString::new(from.as_os_str().as_bytes()),
+            CString::new(to.as_os_str().as_bytes()),
+        ) {
+            (Ok(from), Ok(to)) => (from, to),
+            _ => return,
+        };
+        // the names, each one ending with a nul
+        let names = match read(|buf, size| unsafe { list(from_c.as_ptr(), buf.cast(), size) }) {
+            Some(names) => names,
+            None => return,
+        };
+        for name in names.split(|&b| b == 0).filter(|name| !name.is_empty()) {
+            let name = match CString::new(name) {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            let value =
+                read(|buf, size| unsafe { get(from_c.as_ptr(), name.as_ptr(), buf.cast(), size) });
+            let result = value.map(|value| unsafe {
+                set(
+                    to_c.as_ptr(),
+                    name.as_ptr(),
+                    value.as_ptr().cast(),
+                    value.len(),
+                )
+            });
+            if result != Some(0) {
+                log::debug!(
+                    "Could not copy the attribute {} to {}",
+                    name.to_string_lossy(),
+                    to.display()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! The --debug-bundle archive, with everything that could be needed to figure
+//! out what went wrong in a run.
+
+use std::{
+    fmt::Debug,
+    fs::File,
+    io::Write,
+    path::Path,
+    process::Command,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use anyhow::{Context, Result};
+use zip::{write::FileOptions, ZipWriter};
+
+/// The classes that failed to be fixed, by their name in the jar
+pub type FailedClasses = Arc<Mutex<Vec<(String, Vec<u8>)>>>;
+
+/// What gets collected during the run
+#[derive(Debug, Default, Clone)]
+pub struct Collector {
+    log: Arc<Mutex<Vec<u8>>>,
+    pub failed_classes: FailedClasses,
+}
+
+impl Collector {
+    /// Sets up the logger, so that everything logged ends up in the bundle
+    /// as well
+    pub fn init_logger(&self, logger: env_logger::Logger) {
+        log::set_max_level(logger.filter());
+        let logger = Logger {
+            inner: logger,
+            log: self.log.clone(),
+        };
+        log::set_boxed_logger(Box::new(logger)).expect("the logger is only set once");
+    }
+
+    pub fn write(&self, path: &Path, opt: &impl Debug, result: &Result<()>) -> Result<()> {
+        let file = File::create(path).with_context(|| format!("Creating {}", path.display()))?;
+        let mut zip = ZipWriter::new(file);
+        let options = FileOptions::default();
+
+        zip.start_file("log.txt", options)?;
+        zip.write_all(&self.log.lock().unwrap_or_else(PoisonError::into_inner))?;
+
+        zip.start_file("report.txt", options)?;
+        match result {
+            Ok(()) => writeln!(zip, "The run finished successfully")?,
+            Err(e) => writeln!(zip, "The run failed: {:?}", e)?,
+        }
+        writeln!(zip, "\nOptions: {:#?}", opt)?;
+
+        zip.start_file("environment.txt", options)?;
+        write_environment(&mut zip)?;
+
+        let failed = self
+            .failed_classes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut written = std::collections::BTreeSet::new();
+        for (name, bytes) in failed.iter() {
+            // the same class can fail in several jars of a tarball
+            if written.insert(name) {
+                zip.start_file(format!("failed/{}", name), options)?;
+                zip.write_all(bytes)?;
+            }
+        }
+
+        zip.finish()?;
+        log::info!(
+            "Written the debug bundle to {}, attach it to the bug report",
+            path.display()
+        );
+        Ok(())
+    }
+}
+
+fn write_environment(out: &mut impl Write) -> Result<()> {
+    writeln!(out, "{} {}", env!("CARGO_PKG_NAME"), crate::long_version())?;
+    writeln!(
+        out,
+        "OS: {} {} ({})",
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        std::env::consts::FAMILY
+    )?;
+    writeln!(out, "Arguments: {:?}", std::env::args().collect::<Vec<_>>())?;
+    if let Ok(cwd) = std::env::current_dir() {
+        writeln!(out, "Working directory: {}", cwd.display())?;
+    }
+    if let Ok(rust_log) = std::env::var("RUST_LOG") {
+        writeln!(out, "RUST_LOG: {}", rust_log)?;
+    }
+    // the differences between the VMs are the whole point of this tool
+    match Command::new("java").arg("-version").output() {
+        Ok(output) => {
+            writeln!(out, "java -version:")?;
+            out.write_all(&output.stderr)?;
+        }
+        Err(e) => writeln!(out, "java -version: {}", e)?,
+    }
+    Ok(())
+}
+
+/// Copies everything that gets logged into the bundle log, on top of the
+/// logging done by the wrapped logger
+struct Logger {
+    inner: env_logger::Logger,
+    log: Arc<Mutex<Vec<u8>>>,
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.inner.matches(record) {
+            let mut log = self.log.lock().unwrap_or_else(PoisonError::into_inner);
+            let _ = writeln!(log, "[{:5}] {}", record.level(), record.args());
+        }
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
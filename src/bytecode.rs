@@ -0,0 +1,333 @@
+//! Walking over the instructions in the Code attributes.
+
+use anyhow::{bail, ensure, Result};
+
+pub const TABLESWITCH: u8 = 0xAA;
+pub const LOOKUPSWITCH: u8 = 0xAB;
+pub const WIDE: u8 = 0xC4;
+pub const JSR: u8 = 0xA8;
+pub const RET: u8 = 0xA9;
+pub const JSR_W: u8 = 0xC9;
+pub const IINC: u8 = 0x84;
+pub const INVOKEVIRTUAL: u8 = 0xB6;
+pub const INVOKESPECIAL: u8 = 0xB7;
+pub const INVOKESTATIC: u8 = 0xB8;
+pub const INVOKEINTERFACE: u8 = 0xB9;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction {
+    pub pc: usize,
+    pub opcode: u8,
+}
+
+impl Instruction {
+    pub fn is_branch(&self) -> bool {
+        matches!(self.opcode, 0x99..=0xA8 | TABLESWITCH | LOOKUPSWITCH | 0xC6..=0xC9)
+    }
+}
+
+/// The names of the opcodes, as in the spec
+const MNEMONICS: [&str; 0xCA] = [
+    "nop",
+    "aconst_null",
+    "iconst_m1",
+    "iconst_0",
+    "iconst_1",
+    "iconst_2",
+    "iconst_3",
+    "iconst_4",
+    "iconst_5",
+    "lconst_0",
+    "lconst_1",
+    "fconst_0",
+    "fconst_1",
+    "fconst_2",
+    "dconst_0",
+    "dconst_1",
+    "bipush",
+    "sipush",
+    "ldc",
+    "ldc_w",
+    "ldc2_w",
+    "iload",
+    "lload",
+    "fload",
+    "dload",
+    "aload",
+    "iload_0",
+    "iload_1",
+    "iload_2",
+    "iload_3",
+    "lload_0",
+    "lload_1",
+    "lload_2",
+    "lload_3",
+    "fload_0",
+    "fload_1",
+    "fload_2",
+    "fload_3",
+    "dload_0",
+    "dload_1",
+    "dload_2",
+    "dload_3",
+    "aload_0",
+    "aload_1",
+    "aload_2",
+    "aload_3",
+    "iaload",
+    "laload",
+    "faload",
+    "daload",
+    "aaload",
+    "baload",
+    "caload",
+    "saload",
+    "istore",
+    "lstore",
+    "fstore",
+    "dstore",
+    "astore",
+    "istore_0",
+    "istore_1",
+    "istore_2",
+    "istore_3",
+    "lstore_0",
+    "lstore_1",
+    "lstore_2",
+    "lstore_3",
+    "fstore_0",
+    "fstore_1",
+    "fstore_2",
+    "fstore_3",
+    "dstore_0",
+    "dstore_1",
+    "dstore_2",
+    "dstore_3",
+    "astore_0",
+    "astore_1",
+    "astore_2",
+    "astore_3",
+    "iastore",
+    "lastore",
+    "fastore",
+    "dastore",
+    "aastore",
+    "bastore",
+    "castore",
+    "sastore",
+    "pop",
+    "pop2",
+    "dup",
+    "dup_x1",
+    "dup_x2",
+    "dup2",
+    "dup2_x1",
+    "dup2_x2",
+    "swap",
+    "iadd",
+    "ladd",
+    "fadd",
+    "dadd",
+    "isub",
+    "lsub",
+    "fsub",
+    "dsub",
+    "imul",
+    "lmul",
+    "fmul",
+    "dmul",
+    "idiv",
+    "ldiv",
+    "fdiv",
+    "ddiv",
+    "irem",
+    "lrem",
+    "frem",
+    "drem",
+    "ineg",
+    "lneg",
+    "fneg",
+    "dneg",
+    "ishl",
+    "lshl",
+    "ishr",
+    "lshr",
+    "iushr",
+    "lushr",
+    "iand",
+    "land",
+    "ior",
+    "lor",
+    "ixor",
+    "lxor",
+    "iinc",
+    "i2l",
+    "i2f",
+    "i2d",
+    "l2i",
+    "l2f",
+    "l2d",
+    "f2i",
+    "f2l",
+    "f2d",
+    "d2i",
+    "d2l",
+    "d2f",
+    "i2b",
+    "i2c",
+    "i2s",
+    "lcmp",
+    "fcmpl",
+    "fcmpg",
+    "dcmpl",
+    "dcmpg",
+    "ifeq",
+    "ifne",
+    "iflt",
+    "ifge",
+    "ifgt",
+    "ifle",
+    "if_icmpeq",
+    "if_icmpne",
+    "if_icmplt",
+    "if_icmpge",
+    "if_icmpgt",
+    "if_icmple",
+    "if_acmpeq",
+    "if_acmpne",
+    "goto",
+    "jsr",
+    "ret",
+    "tableswitch",
+    "lookupswitch",
+    "ireturn",
+    "lreturn",
+    "freturn",
+    "dreturn",
+    "areturn",
+    "return",
+    "getstatic",
+    "putstatic",
+    "getfield",
+    "putfield",
+    "invokevirtual",
+    "invokespecial",
+    "invokestatic",
+    "invokeinterface",
+    "invokedynamic",
+    "new",
+    "newarray",
+    "anewarray",
+    "arraylength",
+    "athrow",
+    "checkcast",
+    "instanceof",
+    "monitorenter",
+    "monitorexit",
+    "wide",
+    "multianewarray",
+    "ifnull",
+    "ifnonnull",
+    "goto_w",
+    "jsr_w",
+];
+
+pub fn mnemonic(opcode: u8) -> &'static str {
+    match opcode {
+        0xCA => "breakpoint",
+        0xFE => "impdep1",
+        0xFF => "impdep2",
+        _ => MNEMONICS.get(opcode as usize).copied().unwrap_or("unknown"),
+    }
+}
+
+fn read_i32(code: &[u8], at: usize) -> Result<i32> {
+    match code.get(at..at + 4) {
+        Some(b) => Ok(i32::from_be_bytes([b[0], b[1], b[2], b[3]])),
+        None => bail!("Truncated instruction at {}", at),
+    }
+}
+
+fn instruction_len(code: &[u8], pc: usize) -> Result<usize> {
+    let opcode = code[pc];
+    Ok(match opcode {
+        0x00..=0x0F => 1,
+        0x10 => 2,
+        0x11 => 3,
+        0x12 => 2,
+        0x13 | 0x14 => 3,
+        0x15..=0x19 => 2,
+        0x1A..=0x35 => 1,
+        0x36..=0x3A => 2,
+        0x3B..=0x83 => 1,
+        IINC => 3,
+        0x85..=0x98 => 1,
+        0x99..=0xA8 => 3,
+        RET => 2,
+        TABLESWITCH => {
+            // the operands are aligned to 4 bytes from the start of the code
+            let base = (pc + 4) & !3;
+            let low = read_i32(code, base + 4)?;
+            let high = read_i32(code, base + 8)?;
+            ensure!(low <= high, "Bad tableswitch at {}", pc);
+            let count = (high as i64 - low as i64 + 1) as usize;
+            base + 12 + count * 4 - pc
+        }
+        LOOKUPSWITCH => {
+            let base = (pc + 4) & !3;
+            let pairs = read_i32(code, base + 4)?;
+            ensure!(pairs >= 0, "Bad lookupswitch at {}", pc);
+            base + 8 + pairs as usize * 8 - pc
+        }
+        0xAC..=0xB1 => 1,
+        0xB2..=0xB8 => 3,
+        0xB9 | 0xBA => 5,
+        0xBB => 3,
+        0xBC => 2,
+        0xBD => 3,
+        0xBE | 0xBF => 1,
+        0xC0 | 0xC1 => 3,
+        0xC2 | 0xC3 => 1,
+        WIDE => match code.get(pc + 1) {
+            Some(&IINC) => 6,
+            Some(_) => 4,
+            None => bail!("Truncated wide instruction at {}", pc),
+        },
+        0xC5 => 4,
+        0xC6 | 0xC7 => 3,
+        0xC8 | JSR_W => 5,
+        0xCA | 0xFE | 0xFF => 1,
+        _ => bail!("Unknown opcode 0x{:02X} at {}", opcode, pc),
+    })
+}
+
+/// The index of the constant the instruction uses, if it uses one
+pub fn constant_operand(code: &[u8], instruction: &Instruction) -> Option<u16> {
+    let at = |offset| code.get(instruction.pc + offset).copied();
+    match instruction.opcode {
+        // ldc
+        0x12 => at(1).map(u16::from),
+        // ldc_w, ldc2_w, field access, invokes, new, anewarray, checkcast,
+        // instanceof and multianewarray
+        0x13 | 0x14 | 0xB2..=0xBB | 0xBD | 0xC0 | 0xC1 | 0xC5 => {
+            Some(u16::from_be_bytes([at(1)?, at(2)?]))
+        }
+        _ => None,
+    }
+}
+
+/// Goes over the instructions of the code, failing on malformed ones
+pub fn instructions(code: &[u8]) -> Result<Vec<Instruction>> {
+    let mut result = Vec::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        let len = instruction_len(code, pc)?;
+        ensure!(pc + len <= code.len(), "Truncated instruction at {}", pc);
+        result.push(Instruction {
+            pc,
+            opcode: code[pc],
+        });
+        pc += len;
+    }
+    Ok(result)
+}
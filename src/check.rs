@@ -0,0 +1,281 @@
+//! Looking for what needs to be fixed in a jar, without fixing it.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{Read, Seek},
+    str::FromStr,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use zip::ZipArchive;
+
+use crate::{
+    bytecode,
+    class::{ClassFile, Code, Constant},
+    fix::{self, BadName},
+    json::Value,
+    limits::Limits,
+};
+
+/// How the report is written out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => bail!("Expected 'text' or 'json'"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    pub total_classes: usize,
+    /// The classes with bad names, by their path in the jar
+    pub classes: Vec<(String, Vec<BadName>)>,
+    /// With the deep check, the classes with bad names that their code can
+    /// get to, by their path in the jar
+    pub reachable: Vec<(String, Vec<ReachableName>)>,
+    /// The classes with the names with control characters in them
+    pub control_chars: Vec<(String, Vec<BadName>)>,
+}
+
+/// A bad name that an instruction gets to through its constant
+#[derive(Debug, Clone)]
+pub struct ReachableName {
+    /// The name and the descriptor of the method with the instruction
+    pub method: String,
+    pub pc: usize,
+    pub name: String,
+}
+
+impl CheckReport {
+    pub fn bad_names(&self) -> usize {
+        self.classes.iter().map(|(_, names)| names.len()).sum()
+    }
+
+    /// Everything found, for the scripts, with `deep` adding the reachable
+    /// names
+    pub fn to_json(&self, deep: bool) -> Value {
+        let bad_names = |classes: &[(String, Vec<BadName>)]| {
+            let classes = classes
+                .iter()
+                .map(|(class, names)| {
+                    let names = names
+                        .iter()
+                        .map(|bad| {
+                            Value::Object(BTreeMap::from([
+                                ("name".into(), Value::String(bad.name.clone())),
+                                ("use".into(), Value::String(bad.name_use.describe().into())),
+                                ("constant".into(), Value::Number(bad.index.into())),
+                            ]))
+                        })
+                        .collect();
+                    class_entry(class, names)
+                })
+                .collect();
+            Value::Array(classes)
+        };
+        let mut root = BTreeMap::from([
+            (
+                "total_classes".into(),
+                Value::Number(self.total_classes as f64),
+            ),
+            ("bad_names".into(), Value::Number(self.bad_names() as f64)),
+            ("classes".into(), bad_names(&self.classes)),
+            ("control_chars".into(), bad_names(&self.control_chars)),
+        ]);
+        if deep {
+            let reachable = self
+                .reachable
+                .iter()
+                .map(|(class, names)| {
+                    let names = names
+                        .iter()
+                        .map(|reachable| {
+                            Value::Object(BTreeMap::from([
+                                ("name".into(), Value::String(reachable.name.clone())),
+                                ("method".into(), Value::String(reachable.method.clone())),
+                                ("pc".into(), Value::Number(reachable.pc as f64)),
+                            ]))
+                        })
+                        .collect();
+                    class_entry(class, names)
+                })
+                .collect();
+            root.insert("reachable".into(), Value::Array(reachable));
+        }
+        Value::Object(root)
+    }
+}
+
+fn class_entry(class: &str, names: Vec<Value>) -> Value {
+    Value::Object(BTreeMap::from([
+        ("class".into(), Value::String(class.to_owned())),
+        ("names".into(), Value::Array(names)),
+    ]))
+}
+
+/// Checks the classes in the jar, and with `deep`, the code in them too
+pub fn check_jar(input: impl Read + Seek, limits: &Limits, deep: bool) -> Result<CheckReport> {
+    let mut zip = ZipArchive::new(input)?;
+    let mut report = CheckReport::default();
+    let mut buf = Vec::new();
+    for i in 0..zip.len() {
+        let file = zip.by_index(i)?;
+        if !file.is_file() || !file.name().ends_with(".class") {
+            continue;
+        }
+        let name = file.name().to_owned();
+        limits.prepare_buffer(&mut buf, file.size());
+        limits
+            .read_class(file, &mut buf)
+            .and_then(|_| limits.check_constant_pool(&buf))
+            .with_context(|| format!("Reading {}", name))?;
+        let class = ClassFile::parse(&buf).with_context(|| format!("Reading {}", name))?;
+        let bad = fix::bad_names(&class, false).with_context(|| format!("Reading {}", name))?;
+        if deep {
+            let reachable =
+                reachable_bad_names(&class).with_context(|| format!("Reading {}", name))?;
+            if !reachable.is_empty() {
+                report.reachable.push((name.clone(), reachable));
+            }
+        }
+        let control =
+            fix::control_char_names(&class, false).with_context(|| format!("Reading {}", name))?;
+        if !control.is_empty() {
+            report.control_chars.push((name.clone(), control));
+        }
+        if !bad.is_empty() {
+            report.classes.push((name, bad));
+        }
+        report.total_classes += 1;
+    }
+    Ok(report)
+}
+
+/// The bad member names the instructions of the class can get to, through
+/// the constants they use, the constants those refer to, and the bootstrap
+/// methods of the dynamic ones. None means the fix is complete for the class,
+/// whatever the attributes this does not know about have in them
+pub fn reachable_bad_names(class: &ClassFile) -> Result<Vec<ReachableName>> {
+    let bootstrap = bootstrap_methods(class)?;
+    let mut result = Vec::new();
+    for method in &class.methods {
+        for attribute in &method.attributes {
+            if class.attribute_name(attribute)? != "Code" {
+                continue;
+            }
+            let code = Code::parse(&attribute.info)?;
+            for instruction in bytecode::instructions(&code.code)? {
+                let index = match bytecode::constant_operand(&code.code, &instruction) {
+                    Some(index) => index,
+                    None => continue,
+                };
+                let mut names = BTreeSet::new();
+                reachable_names(class, index, &bootstrap, &mut BTreeSet::new(), &mut names)?;
+                for name in names {
+                    if fix::fixed_name(&name).is_some() {
+                        result.push(ReachableName {
+                            method: format!(
+                                "{}{}",
+                                class.utf8(method.name_index)?,
+                                class.utf8(method.descriptor_index)?
+                            ),
+                            pc: instruction.pc,
+                            name,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// The member names in the NameAndType constants the constant gets to
+fn reachable_names(
+    class: &ClassFile,
+    index: u16,
+    bootstrap: &[Vec<u16>],
+    seen: &mut BTreeSet<u16>,
+    names: &mut BTreeSet<String>,
+) -> Result<()> {
+    // the constants can refer to each other in circles, through the
+    // bootstrap methods
+    if !seen.insert(index) {
+        return Ok(());
+    }
+    match class.constant(index)? {
+        Constant::FieldRef { name_and_type, .. }
+        | Constant::MethodRef { name_and_type, .. }
+        | Constant::InterfaceMethodRef { name_and_type, .. } => {
+            reachable_names(class, *name_and_type, bootstrap, seen, names)?;
+        }
+        Constant::NameAndType { name, .. } => {
+            names.insert(class.utf8(*name)?.into_owned());
+        }
+        Constant::MethodHandle { reference, .. } => {
+            reachable_names(class, *reference, bootstrap, seen, names)?;
+        }
+        Constant::Dynamic {
+            bootstrap: method,
+            name_and_type,
+        }
+        | Constant::InvokeDynamic {
+            bootstrap: method,
+            name_and_type,
+        } => {
+            reachable_names(class, *name_and_type, bootstrap, seen, names)?;
+            let constants = bootstrap
+                .get(*method as usize)
+                .with_context(|| format!("No bootstrap method #{}", method))?;
+            for constant in constants {
+                reachable_names(class, *constant, bootstrap, seen, names)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// The constants of each of the bootstrap methods, the method handle first
+/// and then the arguments
+fn bootstrap_methods(class: &ClassFile) -> Result<Vec<Vec<u16>>> {
+    let u16_at = |info: &[u8], pos: usize| {
+        info.get(pos..pos + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .context("BootstrapMethods attribute is too short")
+    };
+    let mut methods = Vec::new();
+    for attribute in &class.attributes {
+        if class.attribute_name(attribute)? != "BootstrapMethods" {
+            continue;
+        }
+        let info = &attribute.info;
+        let mut pos = 2;
+        for _ in 0..u16_at(info, 0)? {
+            let count = u16_at(info, pos + 2)? as usize;
+            let mut constants = vec![u16_at(info, pos)?];
+            for i in 0..count {
+                constants.push(u16_at(info, pos + 4 + i * 2)?);
+            }
+            methods.push(constants);
+            pos += 4 + count * 2;
+        }
+        ensure!(
+            pos == info.len(),
+            "BootstrapMethods attribute has the wrong length"
+        );
+    }
+    Ok(methods)
+}
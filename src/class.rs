@@ -0,0 +1,1028 @@
+//! A model of the class file structure that can be parsed and written back.
+//!
+//! Attributes are kept as raw bytes, since nothing in them depends on the
+//! layout of the constant pool other than the indices, so any change to the
+//! constants (like making some UTF8 ones longer) comes down to writing the
+//! pool out again.
+//!
+//! See https://docs.oracle.com/javase/specs/jvms/se17/html/jvms-4.html
+
+use std::{
+    borrow::Cow,
+    io::{self, Cursor, Read},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use byteorder::{ReadBytesExt, WriteBytesExt, BE};
+
+use crate::recovery::{self, Position, Section};
+
+pub const MAGIC: u32 = 0xCAFEBABE;
+
+pub const ACC_PRIVATE: u16 = 0x0002;
+pub const ACC_STATIC: u16 = 0x0008;
+pub const ACC_INTERFACE: u16 = 0x0200;
+pub const ACC_ABSTRACT: u16 = 0x0400;
+pub const ACC_ENUM: u16 = 0x4000;
+
+pub const UTF_8: u8 = 1;
+pub const INTEGER: u8 = 3;
+pub const FLOAT: u8 = 4;
+pub const LONG: u8 = 5;
+pub const DOUBLE: u8 = 6;
+pub const CLASS: u8 = 7;
+pub const STRING: u8 = 8;
+pub const FIELD_REF: u8 = 9;
+pub const METHOD_REF: u8 = 10;
+pub const INTERFACE_METHOD_REF: u8 = 11;
+pub const NAME_AND_TYPE: u8 = 12;
+pub const METHOD_HANDLE: u8 = 15;
+pub const METHOD_TYPE: u8 = 16;
+pub const DYNAMIC: u8 = 17;
+pub const INVOKE_DYNAMIC: u8 = 18;
+pub const MODULE: u8 = 19;
+pub const PACKAGE: u8 = 20;
+
+/// A constant with a tag that is not in the spec (yet), which makes the rest
+/// of the class unreadable, since its length is not known
+#[derive(Debug, Clone, Copy)]
+pub struct UnknownTag {
+    pub tag: u8,
+    /// Of the tag, from the start of the class
+    pub offset: u64,
+}
+
+impl std::fmt::Display for UnknownTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Unknown constant tag {} at offset {}",
+            self.tag, self.offset
+        )
+    }
+}
+
+impl std::error::Error for UnknownTag {}
+
+impl UnknownTag {
+    /// The unknown tag the error is about, if it is about one
+    pub fn find(error: &anyhow::Error) -> Option<Self> {
+        error
+            .chain()
+            .find_map(|e| e.downcast_ref::<Self>())
+            .copied()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Constant {
+    /// Kept as the raw modified UTF-8 bytes, so that even the malformed ones
+    /// are written back exactly as they were
+    Utf8(Vec<u8>),
+    Integer(u32),
+    Float(u32),
+    Long(u64),
+    Double(u64),
+    Class(u16),
+    String(u16),
+    FieldRef {
+        class: u16,
+        name_and_type: u16,
+    },
+    MethodRef {
+        class: u16,
+        name_and_type: u16,
+    },
+    InterfaceMethodRef {
+        class: u16,
+        name_and_type: u16,
+    },
+    NameAndType {
+        name: u16,
+        descriptor: u16,
+    },
+    MethodHandle {
+        kind: u8,
+        reference: u16,
+    },
+    MethodType(u16),
+    Dynamic {
+        bootstrap: u16,
+        name_and_type: u16,
+    },
+    InvokeDynamic {
+        bootstrap: u16,
+        name_and_type: u16,
+    },
+    Module(u16),
+    Package(u16),
+    /// The zeroeth index, and the ones right after longs and doubles
+    Unusable,
+}
+
+impl Constant {
+    fn read(stream: &mut Cursor<&[u8]>) -> Result<Self> {
+        Ok(match stream.read_u8()? {
+            UTF_8 => {
+                let len = stream.read_u16::<BE>()? as usize;
+                let mut bytes = vec![0; len];
+                stream.read_exact(&mut bytes)?;
+                Self::Utf8(bytes)
+            }
+            INTEGER => Self::Integer(stream.read_u32::<BE>()?),
+            FLOAT => Self::Float(stream.read_u32::<BE>()?),
+            LONG => Self::Long(stream.read_u64::<BE>()?),
+            DOUBLE => Self::Double(stream.read_u64::<BE>()?),
+            CLASS => Self::Class(stream.read_u16::<BE>()?),
+            STRING => Self::String(stream.read_u16::<BE>()?),
+            FIELD_REF => Self::FieldRef {
+                class: stream.read_u16::<BE>()?,
+                name_and_type: stream.read_u16::<BE>()?,
+            },
+            METHOD_REF => Self::MethodRef {
+                class: stream.read_u16::<BE>()?,
+                name_and_type: stream.read_u16::<BE>()?,
+            },
+            INTERFACE_METHOD_REF => Self::InterfaceMethodRef {
+                class: stream.read_u16::<BE>()?,
+                name_and_type: stream.read_u16::<BE>()?,
+            },
+            NAME_AND_TYPE => Self::NameAndType {
+                name: stream.read_u16::<BE>()?,
+                descriptor: stream.read_u16::<BE>()?,
+            },
+            METHOD_HANDLE => Self::MethodHandle {
+                kind: stream.read_u8()?,
+                reference: stream.read_u16::<BE>()?,
+            },
+            METHOD_TYPE => Self::MethodType(stream.read_u16::<BE>()?),
+            DYNAMIC => Self::Dynamic {
+                bootstrap: stream.read_u16::<BE>()?,
+                name_and_type: stream.read_u16::<BE>()?,
+            },
+            INVOKE_DYNAMIC => Self::InvokeDynamic {
+                bootstrap: stream.read_u16::<BE>()?,
+                name_and_type: stream.read_u16::<BE>()?,
+            },
+            MODULE => Self::Module(stream.read_u16::<BE>()?),
+            PACKAGE => Self::Package(stream.read_u16::<BE>()?),
+            tag => {
+                return Err(UnknownTag {
+                    tag,
+                    offset: stream.position() - 1,
+                }
+                .into())
+            }
+        })
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        // writing into a vec cannot fail
+        fn u16(out: &mut Vec<u8>, v: u16) {
+            out.write_u16::<BE>(v).unwrap()
+        }
+        match *self {
+            Self::Utf8(ref bytes) => {
+                out.push(UTF_8);
+                u16(out, bytes.len() as u16);
+                out.extend_from_slice(bytes);
+            }
+            Self::Integer(v) => {
+                out.push(INTEGER);
+                out.write_u32::<BE>(v).unwrap();
+            }
+            Self::Float(v) => {
+                out.push(FLOAT);
+                out.write_u32::<BE>(v).unwrap();
+            }
+            Self::Long(v) => {
+                out.push(LONG);
+                out.write_u64::<BE>(v).unwrap();
+            }
+            Self::Double(v) => {
+                out.push(DOUBLE);
+                out.write_u64::<BE>(v).unwrap();
+            }
+            Self::Class(i) => {
+                out.push(CLASS);
+                u16(out, i);
+            }
+            Self::String(i) => {
+                out.push(STRING);
+                u16(out, i);
+            }
+            Self::FieldRef {
+                class,
+                name_and_type,
+            } => {
+                out.push(FIELD_REF);
+                u16(out, class);
+                u16(out, name_and_type);
+            }
+            Self::MethodRef {
+                class,
+                name_and_type,
+            } => {
+                out.push(METHOD_REF);
+                u16(out, class);
+                u16(out, name_and_type);
+            }
+            Self::InterfaceMethodRef {
+                class,
+                name_and_type,
+            } => {
+                out.push(INTERFACE_METHOD_REF);
+                u16(out, class);
+                u16(out, name_and_type);
+            }
+            Self::NameAndType { name, descriptor } => {
+                out.push(NAME_AND_TYPE);
+                u16(out, name);
+                u16(out, descriptor);
+            }
+            Self::MethodHandle { kind, reference } => {
+                out.push(METHOD_HANDLE);
+                out.push(kind);
+                u16(out, reference);
+            }
+            Self::MethodType(i) => {
+                out.push(METHOD_TYPE);
+                u16(out, i);
+            }
+            Self::Dynamic {
+                bootstrap,
+                name_and_type,
+            } => {
+                out.push(DYNAMIC);
+                u16(out, bootstrap);
+                u16(out, name_and_type);
+            }
+            Self::InvokeDynamic {
+                bootstrap,
+                name_and_type,
+            } => {
+                out.push(INVOKE_DYNAMIC);
+                u16(out, bootstrap);
+                u16(out, name_and_type);
+            }
+            Self::Module(i) => {
+                out.push(MODULE);
+                u16(out, i);
+            }
+            Self::Package(i) => {
+                out.push(PACKAGE);
+                u16(out, i);
+            }
+            Self::Unusable => {}
+        }
+    }
+
+    /// Longs and doubles take up two indices
+    fn is_wide(&self) -> bool {
+        matches!(self, Self::Long(_) | Self::Double(_))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attribute {
+    pub name_index: u16,
+    pub info: Vec<u8>,
+}
+
+/// A field or a method, they have the same structure
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    pub access_flags: u16,
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub attributes: Vec<Attribute>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassFile {
+    pub minor_version: u16,
+    pub major_version: u16,
+    pub constant_pool: Vec<Constant>,
+    pub access_flags: u16,
+    pub this_class: u16,
+    pub super_class: u16,
+    pub interfaces: Vec<u16>,
+    pub fields: Vec<Member>,
+    pub methods: Vec<Member>,
+    pub attributes: Vec<Attribute>,
+    /// Whatever was after the end of the class, which the VM ignores
+    pub trailing: Vec<u8>,
+}
+
+/// The state of the lenient parsing, which works around the attribute
+/// lengths that are wrong
+struct Lenient<'a> {
+    constant_pool: &'a [Constant],
+    section: Section,
+    members_left: u16,
+    /// The thing the attributes being read belong to, for the messages
+    owner: String,
+    anomalies: Vec<String>,
+}
+
+fn read_attributes(
+    stream: &mut Cursor<&[u8]>,
+    lenient: &mut Option<Lenient>,
+) -> Result<Vec<Attribute>> {
+    let count = stream.read_u16::<BE>()?;
+    let mut attributes = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let name_index = stream.read_u16::<BE>()?;
+        let mut len = stream.read_u32::<BE>()? as usize;
+        if let Some(lenient) = lenient {
+            let start = stream.position() as usize;
+            let name = match lenient.constant_pool.get(name_index as usize) {
+                Some(Constant::Utf8(name)) => &name[..],
+                _ => b"",
+            };
+            let after = Position {
+                section: lenient.section,
+                members_left: lenient.members_left,
+                attributes_left: count - i - 1,
+            };
+            let end = recovery::attribute_end(
+                stream.get_ref(),
+                start,
+                len,
+                name,
+                lenient.constant_pool,
+                after,
+            );
+            if let Some(end) = end.filter(|&end| end - start != len) {
+                lenient.anomalies.push(format!(
+                    "The {} attribute of {} claims to be {} bytes long, but is {}",
+                    String::from_utf8_lossy(name),
+                    lenient.owner,
+                    len,
+                    end - start
+                ));
+                len = end - start;
+            }
+        }
+        let remaining = (stream.get_ref().len() as u64).saturating_sub(stream.position());
+        ensure!(
+            len as u64 <= remaining,
+            "Attribute length {} is past the end of the class",
+            len
+        );
+        let mut info = vec![0; len];
+        stream.read_exact(&mut info)?;
+        attributes.push(Attribute { name_index, info });
+    }
+    Ok(attributes)
+}
+
+fn write_attributes(attributes: &[Attribute], out: &mut Vec<u8>) {
+    out.write_u16::<BE>(attributes.len() as u16).unwrap();
+    for attribute in attributes {
+        out.write_u16::<BE>(attribute.name_index).unwrap();
+        out.write_u32::<BE>(attribute.info.len() as u32).unwrap();
+        out.extend_from_slice(&attribute.info);
+    }
+}
+
+fn read_members(
+    stream: &mut Cursor<&[u8]>,
+    section: Section,
+    lenient: &mut Option<Lenient>,
+) -> Result<Vec<Member>> {
+    let count = stream.read_u16::<BE>()?;
+    let mut members = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let access_flags = stream.read_u16::<BE>()?;
+        let name_index = stream.read_u16::<BE>()?;
+        let descriptor_index = stream.read_u16::<BE>()?;
+        if let Some(lenient) = lenient {
+            let name = match lenient.constant_pool.get(name_index as usize) {
+                Some(Constant::Utf8(name)) => String::from_utf8_lossy(name).into_owned(),
+                _ => format!("#{}", i),
+            };
+            let kind = match section {
+                Section::Fields => "field",
+                _ => "method",
+            };
+            lenient.owner = format!("{} {}", kind, name);
+            lenient.section = section;
+            lenient.members_left = count - i - 1;
+        }
+        members.push(Member {
+            access_flags,
+            name_index,
+            descriptor_index,
+            attributes: read_attributes(stream, lenient)?,
+        });
+    }
+    Ok(members)
+}
+
+fn write_members(members: &[Member], out: &mut Vec<u8>) {
+    out.write_u16::<BE>(members.len() as u16).unwrap();
+    for member in members {
+        out.write_u16::<BE>(member.access_flags).unwrap();
+        out.write_u16::<BE>(member.name_index).unwrap();
+        out.write_u16::<BE>(member.descriptor_index).unwrap();
+        write_attributes(&member.attributes, out);
+    }
+}
+
+fn read_constant_pool(stream: &mut Cursor<&[u8]>) -> Result<Vec<Constant>> {
+    let constant_pool_count = stream.read_u16::<BE>()? as usize;
+    log::debug!("Constant pool entry count is {}", constant_pool_count);
+
+    let mut constant_pool = Vec::with_capacity(constant_pool_count);
+    // take up unused zeroeth index
+    constant_pool.push(Constant::Unusable);
+
+    while constant_pool.len() < constant_pool_count {
+        let constant = Constant::read(stream)
+            .with_context(|| format!("Reading constant #{}", constant_pool.len()))?;
+        log::trace!("Constant #{} is {:?}", constant_pool.len(), constant);
+        let wide = constant.is_wide();
+        constant_pool.push(constant);
+        if wide {
+            constant_pool.push(Constant::Unusable);
+        }
+    }
+    ensure!(
+        constant_pool.len() == constant_pool_count,
+        "The last constant is a long or a double, taking up an index past the end"
+    );
+    Ok(constant_pool)
+}
+
+impl ClassFile {
+    pub fn parse(bytecode: &[u8]) -> Result<Self> {
+        Ok(Self::parse_impl(bytecode, false)?.0)
+    }
+
+    /// Reads only what the names of the class are in out of the stream,
+    /// which is all of it but the code of the methods (and what's after the
+    /// class), for telling whether there's anything to rename in it without
+    /// having all of it in memory. Not to be written back, the code of the
+    /// methods being empty
+    pub fn read_names(mut input: impl Read) -> Result<Self> {
+        let mut start = Vec::new();
+        read_raw_constant_pool(&mut input, &mut start)?;
+        let mut stream = Cursor::new(&start[..]);
+        ensure!(stream.read_u32::<BE>()? == MAGIC, "Bad magic number");
+        let minor_version = stream.read_u16::<BE>()?;
+        let major_version = stream.read_u16::<BE>()?;
+        let constant_pool = read_constant_pool(&mut stream)?;
+
+        let access_flags = input.read_u16::<BE>()?;
+        let this_class = input.read_u16::<BE>()?;
+        let super_class = input.read_u16::<BE>()?;
+        let interfaces_count = input.read_u16::<BE>()?;
+        let mut interfaces = Vec::with_capacity(interfaces_count as usize);
+        for _ in 0..interfaces_count {
+            interfaces.push(input.read_u16::<BE>()?);
+        }
+        let fields = skim_members(&mut input, &constant_pool).context("Reading fields")?;
+        let methods = skim_members(&mut input, &constant_pool).context("Reading methods")?;
+        let attributes =
+            skim_attributes(&mut input, &constant_pool).context("Reading class attributes")?;
+        Ok(Self {
+            minor_version,
+            major_version,
+            constant_pool,
+            access_flags,
+            this_class,
+            super_class,
+            interfaces,
+            fields,
+            methods,
+            attributes,
+            trailing: Vec::new(),
+        })
+    }
+
+    /// Parses the class, working around the attributes with wrong lengths
+    /// instead of failing on them. Also returns the descriptions of what was
+    /// worked around, if anything
+    pub fn parse_lenient(bytecode: &[u8]) -> Result<(Self, Vec<String>)> {
+        Self::parse_impl(bytecode, true)
+    }
+
+    fn parse_impl(bytecode: &[u8], lenient: bool) -> Result<(Self, Vec<String>)> {
+        let mut stream = Cursor::new(bytecode);
+
+        ensure!(stream.read_u32::<BE>()? == MAGIC, "Bad magic number");
+
+        let minor_version = stream.read_u16::<BE>()?;
+        let major_version = stream.read_u16::<BE>()?;
+
+        let constant_pool = read_constant_pool(&mut stream)?;
+
+        let access_flags = stream.read_u16::<BE>()?;
+        let this_class = stream.read_u16::<BE>()?;
+        let super_class = stream.read_u16::<BE>()?;
+
+        let interfaces_count = stream.read_u16::<BE>()?;
+        let mut interfaces = Vec::with_capacity(interfaces_count as usize);
+        for _ in 0..interfaces_count {
+            interfaces.push(stream.read_u16::<BE>()?);
+        }
+
+        let mut lenient = lenient.then(|| Lenient {
+            constant_pool: &constant_pool,
+            section: Section::Fields,
+            members_left: 0,
+            owner: String::new(),
+            anomalies: Vec::new(),
+        });
+        let fields =
+            read_members(&mut stream, Section::Fields, &mut lenient).context("Reading fields")?;
+        let methods =
+            read_members(&mut stream, Section::Methods, &mut lenient).context("Reading methods")?;
+        if let Some(lenient) = &mut lenient {
+            lenient.section = Section::Class;
+            lenient.members_left = 0;
+            lenient.owner = "the class".into();
+        }
+        let attributes =
+            read_attributes(&mut stream, &mut lenient).context("Reading class attributes")?;
+        let anomalies = lenient.map(|l| l.anomalies).unwrap_or_default();
+        let trailing = bytecode[stream.position() as usize..].to_vec();
+
+        let class = Self {
+            minor_version,
+            major_version,
+            constant_pool,
+            access_flags,
+            this_class,
+            super_class,
+            interfaces,
+            fields,
+            methods,
+            attributes,
+            trailing,
+        };
+        Ok((class, anomalies))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.write_u32::<BE>(MAGIC).unwrap();
+        out.write_u16::<BE>(self.minor_version).unwrap();
+        out.write_u16::<BE>(self.major_version).unwrap();
+
+        out.write_u16::<BE>(self.constant_pool.len() as u16)
+            .unwrap();
+        for constant in self.constant_pool.iter().skip(1) {
+            constant.write(&mut out);
+        }
+
+        out.write_u16::<BE>(self.access_flags).unwrap();
+        out.write_u16::<BE>(self.this_class).unwrap();
+        out.write_u16::<BE>(self.super_class).unwrap();
+        out.write_u16::<BE>(self.interfaces.len() as u16).unwrap();
+        for &interface in &self.interfaces {
+            out.write_u16::<BE>(interface).unwrap();
+        }
+
+        write_members(&self.fields, &mut out);
+        write_members(&self.methods, &mut out);
+        write_attributes(&self.attributes, &mut out);
+        out.extend_from_slice(&self.trailing);
+        out
+    }
+
+    pub fn constant(&self, index: u16) -> Result<&Constant> {
+        self.constant_pool
+            .get(index as usize)
+            .with_context(|| format!("Constant #{} is out of bounds", index))
+    }
+
+    /// The raw bytes of a UTF8 constant
+    pub fn utf8_bytes(&self, index: u16) -> Result<&[u8]> {
+        match self.constant(index)? {
+            Constant::Utf8(bytes) => Ok(bytes),
+            _ => bail!("Constant #{} is not a UTF8_INFO", index),
+        }
+    }
+
+    /// The value of a UTF8 constant
+    pub fn utf8(&self, index: u16) -> Result<Cow<'_, str>> {
+        cesu8::from_java_cesu8(self.utf8_bytes(index)?)
+            .with_context(|| format!("Constant #{} is not valid modified UTF-8", index))
+    }
+
+    /// The name of the class referenced by a CLASS_INFO constant
+    pub fn class_name(&self, index: u16) -> Result<Cow<'_, str>> {
+        match self.constant(index)? {
+            Constant::Class(name) => self.utf8(*name),
+            _ => bail!("Constant #{} is not a CLASS_INFO", index),
+        }
+    }
+
+    pub fn attribute_name(&self, attribute: &Attribute) -> Result<Cow<'_, str>> {
+        self.utf8(attribute.name_index)
+    }
+
+    /// Finds a UTF8 constant with the given value, adding it if there is none
+    pub fn add_utf8(&mut self, value: &str) -> Result<u16> {
+        let bytes = cesu8::to_java_cesu8(value);
+        let existing = self
+            .constant_pool
+            .iter()
+            .position(|c| matches!(c, Constant::Utf8(b) if *b == *bytes));
+        if let Some(index) = existing {
+            return Ok(index as u16);
+        }
+        ensure!(
+            self.constant_pool.len() < u16::MAX as usize,
+            "The constant pool is full"
+        );
+        ensure!(
+            bytes.len() <= u16::MAX as usize,
+            "The new UTF8 constant is too long"
+        );
+        self.constant_pool.push(Constant::Utf8(bytes.into_owned()));
+        Ok((self.constant_pool.len() - 1) as u16)
+    }
+
+    /// Replaces the value of a UTF8 constant, which can change its length
+    pub fn set_utf8(&mut self, index: u16, value: &str) -> Result<()> {
+        let bytes = cesu8::to_java_cesu8(value);
+        ensure!(
+            bytes.len() <= u16::MAX as usize,
+            "The new value of constant #{} is too long",
+            index
+        );
+        match self.constant_pool.get_mut(index as usize) {
+            Some(Constant::Utf8(old)) => *old = bytes.into_owned(),
+            _ => bail!("Constant #{} is not a UTF8_INFO", index),
+        }
+        Ok(())
+    }
+}
+
+/// Reads the raw constant pool (and the header before it) out of the
+/// stream, appending it to `buf`, without reading any more than that
+fn read_raw_constant_pool(mut input: impl Read, buf: &mut Vec<u8>) -> Result<()> {
+    let mut read = |buf: &mut Vec<u8>, len: usize| -> Result<()> {
+        let start = buf.len();
+        (&mut input).take(len as u64).read_to_end(buf)?;
+        ensure!(
+            buf.len() - start == len,
+            "The class ends in the middle of the constant pool"
+        );
+        Ok(())
+    };
+    read(buf, 10)?;
+    let count = u16::from_be_bytes([buf[8], buf[9]]);
+    let mut index = 1;
+    while index < count {
+        read(buf, 1)?;
+        let len = match buf[buf.len() - 1] {
+            UTF_8 => {
+                read(buf, 2)?;
+                u16::from_be_bytes([buf[buf.len() - 2], buf[buf.len() - 1]]) as usize
+            }
+            CLASS | STRING | METHOD_TYPE | MODULE | PACKAGE => 2,
+            METHOD_HANDLE => 3,
+            INTEGER | FLOAT | FIELD_REF | METHOD_REF | INTERFACE_METHOD_REF | NAME_AND_TYPE
+            | DYNAMIC | INVOKE_DYNAMIC => 4,
+            LONG | DOUBLE => {
+                index += 1;
+                8
+            }
+            // the parsing tells about it
+            _ => break,
+        };
+        read(buf, len)?;
+        index += 1;
+    }
+    Ok(())
+}
+
+/// Reads the attributes out of the stream, the code of the methods being
+/// skipped over
+fn skim_attributes(input: &mut impl Read, constant_pool: &[Constant]) -> Result<Vec<Attribute>> {
+    let count = input.read_u16::<BE>()?;
+    let mut attributes = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let name_index = input.read_u16::<BE>()?;
+        let len = input.read_u32::<BE>()?;
+        let mut attribute = input.take(len as u64);
+        let mut info = Vec::new();
+        if matches!(constant_pool.get(name_index as usize), Some(Constant::Utf8(name)) if name == b"Code")
+        {
+            // max_stack, max_locals and the length of the code, which is
+            // then made empty
+            let mut start = [0; 8];
+            attribute.read_exact(&mut start)?;
+            let code_length = u32::from_be_bytes([start[4], start[5], start[6], start[7]]);
+            let skipped = io::copy(
+                &mut (&mut attribute).take(code_length as u64),
+                &mut io::sink(),
+            )?;
+            ensure!(
+                skipped == code_length as u64,
+                "The code is past the end of the class"
+            );
+            info.extend_from_slice(&start[..4]);
+            info.extend_from_slice(&[0; 4]);
+        }
+        attribute.read_to_end(&mut info)?;
+        ensure!(
+            attribute.limit() == 0,
+            "Attribute length {} is past the end of the class",
+            len
+        );
+        attributes.push(Attribute { name_index, info });
+    }
+    Ok(attributes)
+}
+
+fn skim_members(input: &mut impl Read, constant_pool: &[Constant]) -> Result<Vec<Member>> {
+    let count = input.read_u16::<BE>()?;
+    let mut members = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        members.push(Member {
+            access_flags: input.read_u16::<BE>()?,
+            name_index: input.read_u16::<BE>()?,
+            descriptor_index: input.read_u16::<BE>()?,
+            attributes: skim_attributes(input, constant_pool)?,
+        });
+    }
+    Ok(members)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExceptionHandler {
+    pub start_pc: u16,
+    pub end_pc: u16,
+    pub handler_pc: u16,
+    pub catch_type: u16,
+}
+
+/// The Code attribute of a method, which has attributes of its own
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Code {
+    pub max_stack: u16,
+    pub max_locals: u16,
+    pub code: Vec<u8>,
+    pub exception_table: Vec<ExceptionHandler>,
+    pub attributes: Vec<Attribute>,
+}
+
+impl Code {
+    pub fn parse(info: &[u8]) -> Result<Self> {
+        let mut stream = Cursor::new(info);
+        let max_stack = stream.read_u16::<BE>()?;
+        let max_locals = stream.read_u16::<BE>()?;
+        let code_length = stream.read_u32::<BE>()? as usize;
+        ensure!(
+            code_length <= info.len(),
+            "Code length {} is past the end of the attribute",
+            code_length
+        );
+        let mut code = vec![0; code_length];
+        stream.read_exact(&mut code)?;
+
+        let handlers = stream.read_u16::<BE>()?;
+        let mut exception_table = Vec::with_capacity(handlers as usize);
+        for _ in 0..handlers {
+            exception_table.push(ExceptionHandler {
+                start_pc: stream.read_u16::<BE>()?,
+                end_pc: stream.read_u16::<BE>()?,
+                handler_pc: stream.read_u16::<BE>()?,
+                catch_type: stream.read_u16::<BE>()?,
+            });
+        }
+        let attributes =
+            read_attributes(&mut stream, &mut None).context("Reading Code attributes")?;
+        Ok(Self {
+            max_stack,
+            max_locals,
+            code,
+            exception_table,
+            attributes,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.write_u16::<BE>(self.max_stack).unwrap();
+        out.write_u16::<BE>(self.max_locals).unwrap();
+        out.write_u32::<BE>(self.code.len() as u32).unwrap();
+        out.extend_from_slice(&self.code);
+        out.write_u16::<BE>(self.exception_table.len() as u16)
+            .unwrap();
+        for handler in &self.exception_table {
+            out.write_u16::<BE>(handler.start_pc).unwrap();
+            out.write_u16::<BE>(handler.end_pc).unwrap();
+            out.write_u16::<BE>(handler.handler_pc).unwrap();
+            out.write_u16::<BE>(handler.catch_type).unwrap();
+        }
+        write_attributes(&self.attributes, &mut out);
+        out
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// `class Test { int a.b; void run() { return; } }`, more or less
+    pub(crate) fn sample() -> ClassFile {
+        let utf8 = |s: &str| Constant::Utf8(s.as_bytes().to_vec());
+        let code = Code {
+            max_stack: 1,
+            max_locals: 1,
+            code: vec![0xB1], // return
+            exception_table: vec![ExceptionHandler {
+                start_pc: 0,
+                end_pc: 1,
+                handler_pc: 0,
+                catch_type: 0,
+            }],
+            attributes: Vec::new(),
+        };
+        ClassFile {
+            minor_version: 0,
+            major_version: 52,
+            constant_pool: vec![
+                Constant::Unusable,
+                utf8("Test"),
+                Constant::Class(1),
+                utf8("java/lang/Object"),
+                Constant::Class(3),
+                utf8("a.b"),
+                utf8("I"),
+                utf8("run"),
+                utf8("()V"),
+                utf8("Code"),
+                Constant::Long(0x0123_4567_89AB_CDEF),
+                Constant::Unusable,
+                Constant::NameAndType {
+                    name: 5,
+                    descriptor: 6,
+                },
+                Constant::FieldRef {
+                    class: 2,
+                    name_and_type: 12,
+                },
+            ],
+            access_flags: 0x21,
+            this_class: 2,
+            super_class: 4,
+            interfaces: Vec::new(),
+            fields: vec![Member {
+                access_flags: ACC_PRIVATE,
+                name_index: 5,
+                descriptor_index: 6,
+                attributes: Vec::new(),
+            }],
+            methods: vec![Member {
+                access_flags: 0x01,
+                name_index: 7,
+                descriptor_index: 8,
+                attributes: vec![Attribute {
+                    name_index: 9,
+                    info: code.to_bytes(),
+                }],
+            }],
+            attributes: Vec::new(),
+            trailing: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let class = sample();
+        let bytes = class.to_bytes();
+        let parsed = ClassFile::parse(&bytes).unwrap();
+        assert_eq!(parsed, class);
+        assert_eq!(parsed.to_bytes(), bytes);
+        assert_eq!(parsed.class_name(2).unwrap(), "Test");
+    }
+
+    #[test]
+    fn fixing_renames_the_bad_names() {
+        let fixed = crate::fix_class(&sample().to_bytes()).unwrap().unwrap();
+        let parsed = ClassFile::parse(&fixed).unwrap();
+        assert_eq!(parsed.utf8(parsed.fields[0].name_index).unwrap(), "a_b");
+        assert_eq!(crate::fix_class(&fixed).unwrap(), None);
+    }
+
+    #[test]
+    fn trailing_bytes_are_kept() {
+        let mut bytes = sample().to_bytes();
+        bytes.extend_from_slice(b"garbage");
+        let parsed = ClassFile::parse(&bytes).unwrap();
+        assert_eq!(parsed.trailing, b"garbage");
+        assert_eq!(parsed.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn set_utf8_changing_the_length() {
+        let mut class = sample();
+        let before = class.to_bytes();
+
+        class.set_utf8(5, "a_longer_name").unwrap();
+        let bytes = class.to_bytes();
+        assert_eq!(
+            bytes.len(),
+            before.len() + "a_longer_name".len() - "a.b".len()
+        );
+
+        let parsed = ClassFile::parse(&bytes).unwrap();
+        assert_eq!(parsed.utf8(5).unwrap(), "a_longer_name");
+        // everything after the constant is still where it should be
+        assert_eq!(parsed.utf8(6).unwrap(), "I");
+        assert_eq!(
+            parsed.constant(10).unwrap(),
+            &Constant::Long(0x0123_4567_89AB_CDEF)
+        );
+        assert_eq!(parsed.fields, sample().fields);
+        assert_eq!(parsed.methods, sample().methods);
+
+        class.set_utf8(5, "").unwrap();
+        let parsed = ClassFile::parse(&class.to_bytes()).unwrap();
+        assert_eq!(parsed.utf8(5).unwrap(), "");
+
+        assert!(class.set_utf8(2, "not utf8").is_err());
+        assert!(class.set_utf8(100, "out of bounds").is_err());
+        assert!(class.set_utf8(5, &"x".repeat(70_000)).is_err());
+    }
+
+    #[test]
+    fn modified_utf8() {
+        let mut class = sample();
+        class.set_utf8(5, "nul\0 and 😀").unwrap();
+        let bytes = class.utf8_bytes(5).unwrap();
+        // no zero bytes and surrogate pairs instead of the 4-byte sequences
+        assert!(!bytes.contains(&0));
+        assert!(!bytes.iter().any(|&b| b >= 0xF0));
+
+        let parsed = ClassFile::parse(&class.to_bytes()).unwrap();
+        assert_eq!(parsed.utf8(5).unwrap(), "nul\0 and 😀");
+    }
+
+    #[test]
+    fn add_utf8() {
+        let mut class = sample();
+        assert_eq!(class.add_utf8("Code").unwrap(), 9);
+        let index = class.add_utf8("new").unwrap();
+        assert_eq!(index as usize, sample().constant_pool.len());
+        let parsed = ClassFile::parse(&class.to_bytes()).unwrap();
+        assert_eq!(parsed.utf8(index).unwrap(), "new");
+    }
+
+    #[test]
+    fn code_round_trip() {
+        let class = sample();
+        let info = &class.methods[0].attributes[0].info;
+        let code = Code::parse(info).unwrap();
+        assert_eq!(code.code, [0xB1]);
+        assert_eq!(code.exception_table.len(), 1);
+        assert_eq!(&code.to_bytes(), info);
+    }
+
+    #[test]
+    fn read_names_skips_the_code() {
+        let class = sample();
+        let skimmed = ClassFile::read_names(&class.to_bytes()[..]).unwrap();
+        assert_eq!(skimmed.constant_pool, class.constant_pool);
+        assert_eq!(skimmed.fields, class.fields);
+
+        let code = Code::parse(&skimmed.methods[0].attributes[0].info).unwrap();
+        assert!(code.code.is_empty());
+        assert_eq!(code.exception_table.len(), 1);
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = sample().to_bytes();
+        for len in 0..bytes.len() {
+            assert!(ClassFile::parse(&bytes[..len]).is_err(), "{}", len);
+        }
+    }
+
+    #[test]
+    fn bad_constants() {
+        let mut bytes = sample().to_bytes();
+        bytes[0] = 0;
+        assert!(ClassFile::parse(&bytes).is_err());
+
+        // the tag of the first constant
+        let mut bytes = sample().to_bytes();
+        bytes[10] = 2;
+        let error = ClassFile::parse(&bytes).unwrap_err();
+        let unknown = UnknownTag::find(&error).unwrap();
+        assert_eq!((unknown.tag, unknown.offset), (2, 10));
+
+        // a long as the last constant takes an index past the end
+        let mut class = sample();
+        class.constant_pool.truncate(10);
+        class.constant_pool.push(Constant::Long(1));
+        assert!(ClassFile::parse(&class.to_bytes()).is_err());
+    }
+}
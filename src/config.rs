@@ -0,0 +1,58 @@
+//! The config file, with the defaults for the options that are set once and
+//! then wanted on every run.
+//!
+//! It's a JSON object with the options named like the flags, with
+//! underscores, e.g. `{"no_clobber": true}`.
+
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::{dirs, json};
+
+#[derive(Debug, Default)]
+pub struct Config {
+    /// Refuse to overwrite existing outputs and backups
+    pub no_clobber: bool,
+}
+
+impl Config {
+    /// Reads the config, the default one being fine to not exist
+    pub fn load(path: Option<&Path>, portable: bool) -> Result<Self> {
+        let (path, explicit) = match path {
+            Some(path) => (path.to_owned(), true),
+            None => match dirs::config_file(portable) {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if !explicit && e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::default())
+            }
+            Err(e) => return Err(e).with_context(|| format!("Reading {}", path.display())),
+        };
+        let root = json::parse(&contents).with_context(|| format!("Reading {}", path.display()))?;
+        let options = match root.as_object() {
+            Some(options) => options,
+            None => bail!("{} is not a JSON object", path.display()),
+        };
+
+        let mut config = Self::default();
+        for (key, value) in options {
+            let bool = || {
+                value.as_bool().with_context(|| {
+                    format!("'{}' in {} should be true or false", key, path.display())
+                })
+            };
+            match key.as_str() {
+                "no_clobber" => config.no_clobber = bool()?,
+                // a typo silently doing nothing is worse
+                _ => bail!("Unknown option '{}' in {}", key, path.display()),
+            }
+        }
+        log::debug!("Loaded the config from {}", path.display());
+        Ok(config)
+    }
+}
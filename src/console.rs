@@ -0,0 +1,112 @@
+//! Printing the reports. The names in the jars are whatever the obfuscator
+//! felt like, so they get their invisible and control characters escaped,
+//! otherwise they'd mess up the terminal or just look like something else.
+//!
+//! On Windows the standard library writes to the console as UTF-16, which
+//! shows everything fine whatever the code page is, but when the output is
+//! redirected the bytes go as they are, and anything reading them with a
+//! non-UTF-8 code page sees mojibake, so there everything non-ASCII is
+//! escaped too.
+
+use std::{
+    borrow::Cow,
+    fmt,
+    io::{self, Write},
+};
+
+/// Like println!, except that it doesn't panic when the output is closed
+#[macro_export]
+macro_rules! outln {
+    ($($arg:tt)*) => {
+        $crate::console::write_line(format_args!($($arg)*))
+    };
+}
+
+/// The text with the characters that don't show up as themselves written
+/// as `\u{…}` escapes, as in Rust
+pub fn escaped(text: &str) -> Cow<'_, str> {
+    let ascii_only = ascii_only();
+    let needs_escape = |c: char| match c {
+        // those are escaped by escape_debug, but are fine to print
+        '\\' | '\'' | '"' => false,
+        c if !c.is_ascii() && ascii_only => true,
+        c => c.escape_debug().len() > 1,
+    };
+    if !text.chars().any(needs_escape) {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len() + 16);
+    for c in text.chars() {
+        match needs_escape(c) {
+            // the newlines and such are fine as \n, but the non-ASCII
+            // printable ones need the unicode form
+            true if c.escape_debug().len() > 1 => out.extend(c.escape_debug()),
+            true => out.extend(c.escape_unicode()),
+            false => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// A closed pipe (like with `| head`) just ends the program, and the other
+/// errors do too, but with a message
+pub fn write_line(args: fmt::Arguments) {
+    let result = {
+        let mut stdout = io::stdout().lock();
+        stdout.write_fmt(args).and_then(|_| stdout.write_all(b"\n"))
+    };
+    match result {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => std::process::exit(0),
+        Err(e) => {
+            log::error!("Could not write the output: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Asks the question on the terminal, a no if there's nobody to answer
+pub fn confirm(question: &str) -> bool {
+    use std::io::IsTerminal;
+
+    if !io::stdin().is_terminal() {
+        return false;
+    }
+    eprint!("{} [y/N] ", question);
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    crate::i18n::is_yes(&answer)
+}
+
+#[cfg(windows)]
+fn ascii_only() -> bool {
+    use std::{io::IsTerminal, sync::OnceLock};
+
+    const CP_UTF8: u32 = 65001;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetConsoleOutputCP() -> u32;
+        fn GetACP() -> u32;
+    }
+
+    static ASCII_ONLY: OnceLock<bool> = OnceLock::new();
+    *ASCII_ONLY.get_or_init(|| {
+        if io::stdout().is_terminal() {
+            return false;
+        }
+        // without a console at all it's the ANSI code page that's the default
+        let code_page = match unsafe { GetConsoleOutputCP() } {
+            0 => unsafe { GetACP() },
+            code_page => code_page,
+        };
+        code_page != CP_UTF8
+    })
+}
+
+#[cfg(not(windows))]
+fn ascii_only() -> bool {
+    false
+}
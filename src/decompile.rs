@@ -0,0 +1,79 @@
+//! Running a decompiler (CFR, or Vineflower and the other Fernflower forks)
+//! on the classes that were fixed, for looking at what changed.
+
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{bail, Context, Result};
+use zip::ZipArchive;
+
+use crate::temp;
+
+/// Decompiles the classes at the paths in the jar into the directory, with
+/// the rest of the jar there for the decompiler to look things up in
+pub fn decompile(decompiler: &Path, jar: &Path, classes: &[String], into: &Path) -> Result<()> {
+    let dir = temp::create_dir()?;
+    let mut zip = ZipArchive::new(BufReader::new(File::open(jar)?))?;
+    let mut files = Vec::with_capacity(classes.len());
+    for class in classes {
+        // the paths come from the jar, so they could be anything
+        let relative = match zip.by_name(class)?.enclosed_name() {
+            Some(path) => path.to_owned(),
+            None => {
+                log::warn!("Not decompiling {}, its path is weird", class);
+                continue;
+            }
+        };
+        let path = dir.path().join(relative);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = File::create(&path)?;
+        std::io::copy(&mut zip.by_name(class)?, &mut file)?;
+        files.push(path);
+    }
+    std::fs::create_dir_all(into).with_context(|| format!("Creating {}", into.display()))?;
+
+    let mut command = match decompiler.extension().is_some_and(|ext| ext == "jar") {
+        true => {
+            let mut command = Command::new("java");
+            command.arg("-jar").arg(decompiler);
+            command
+        }
+        false => Command::new(decompiler),
+    };
+    let name = decompiler
+        .file_name()
+        .map(|name| name.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    if name.contains("cfr") {
+        command
+            .args(&files)
+            .arg("--outputdir")
+            .arg(into)
+            .arg("--extraclasspath")
+            .arg(jar);
+    } else {
+        // fernflower and its forks take the destination last
+        let mut library = PathBuf::from("-e=");
+        library.as_mut_os_string().push(jar);
+        command.arg(library).args(&files).arg(into);
+    }
+
+    log::info!(
+        "Decompiling {} fixed classes into {}",
+        files.len(),
+        into.display()
+    );
+    let status = command
+        .status()
+        .with_context(|| format!("Running {}", decompiler.display()))?;
+    if !status.success() {
+        bail!("The decompiler failed ({})", status);
+    }
+    Ok(())
+}
@@ -0,0 +1,356 @@
+//! Comparing two jars, to see what a fix (or a game update) actually changed.
+
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek},
+};
+
+use anyhow::Result;
+use zip::ZipArchive;
+
+use crate::{
+    class::{Attribute, ClassFile, Constant, Member},
+    hash,
+    limits::Limits,
+};
+
+#[derive(Debug)]
+pub enum EntryDiff {
+    Added {
+        name: String,
+        size: u64,
+    },
+    Removed {
+        name: String,
+        size: u64,
+    },
+    Changed {
+        name: String,
+        sizes: (u64, u64),
+        /// What changed in the class, empty for the other files
+        details: Vec<String>,
+    },
+}
+
+#[derive(Debug, Default)]
+pub struct JarDiff {
+    pub entries: Vec<EntryDiff>,
+    /// How many files are the same in both
+    pub same: usize,
+}
+
+/// The size and the hash of every file in the jar, by its path
+fn hashes(zip: &mut ZipArchive<impl Read + Seek>) -> Result<BTreeMap<String, (u64, String)>> {
+    let mut hashes = BTreeMap::new();
+    for i in 0..zip.len() {
+        let file = zip.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().to_owned();
+        let size = file.size();
+        hashes.insert(name, (size, hash::sha256_reader(file)?));
+    }
+    Ok(hashes)
+}
+
+fn read_class(
+    zip: &mut ZipArchive<impl Read + Seek>,
+    name: &str,
+    limits: &Limits,
+) -> Result<ClassFile> {
+    let mut buf = Vec::new();
+    limits.read_class(zip.by_name(name)?, &mut buf)?;
+    ClassFile::parse(&buf)
+}
+
+pub fn diff_jars(a: impl Read + Seek, b: impl Read + Seek, limits: &Limits) -> Result<JarDiff> {
+    let mut a = ZipArchive::new(a)?;
+    let mut b = ZipArchive::new(b)?;
+    let (hashes_a, hashes_b) = (hashes(&mut a)?, hashes(&mut b)?);
+
+    let mut diff = JarDiff::default();
+    for (name, (size, hash)) in &hashes_a {
+        let (new_size, new_hash) = match hashes_b.get(name) {
+            Some(entry) => entry,
+            None => {
+                diff.entries.push(EntryDiff::Removed {
+                    name: name.clone(),
+                    size: *size,
+                });
+                continue;
+            }
+        };
+        if hash == new_hash {
+            diff.same += 1;
+            continue;
+        }
+        let mut details = Vec::new();
+        if name.ends_with(".class") {
+            limits.check_time()?;
+            match (
+                read_class(&mut a, name, limits),
+                read_class(&mut b, name, limits),
+            ) {
+                (Ok(old), Ok(new)) => details = class_differences(&old, &new),
+                (Err(e), _) | (_, Err(e)) => details.push(format!(
+                    "could not be read, so only the bytes differ: {:#}",
+                    e
+                )),
+            }
+        }
+        diff.entries.push(EntryDiff::Changed {
+            name: name.clone(),
+            sizes: (*size, *new_size),
+            details,
+        });
+    }
+    for (name, (size, _)) in &hashes_b {
+        if !hashes_a.contains_key(name) {
+            diff.entries.push(EntryDiff::Added {
+                name: name.clone(),
+                size: *size,
+            });
+        }
+    }
+    Ok(diff)
+}
+
+/// The value of a UTF8 constant, made readable even when it's malformed
+fn utf8(class: &ClassFile, index: u16) -> String {
+    match class.utf8(index) {
+        Ok(value) => value.into_owned(),
+        Err(_) => match class.utf8_bytes(index) {
+            Ok(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+            Err(_) => format!("#{}", index),
+        },
+    }
+}
+
+/// The constant with everything it refers to filled in, so that the same
+/// constants compare as equal even if they moved around in the pool
+fn describe(class: &ClassFile, index: u16) -> String {
+    let constant = match class.constant(index) {
+        Ok(constant) => constant,
+        Err(_) => return format!("#{}", index),
+    };
+    let class_name = |index| match class.constant(index) {
+        Ok(Constant::Class(name)) => utf8(class, *name),
+        _ => format!("#{}", index),
+    };
+    let name_and_type = |index| match class.constant(index) {
+        Ok(Constant::NameAndType { name, descriptor }) => {
+            format!("{}:{}", utf8(class, *name), utf8(class, *descriptor))
+        }
+        _ => format!("#{}", index),
+    };
+    match *constant {
+        Constant::Utf8(_) => format!("Utf8 {:?}", utf8(class, index)),
+        Constant::Integer(v) => format!("Integer {}", v as i32),
+        Constant::Float(v) => format!("Float {}", f32::from_bits(v)),
+        Constant::Long(v) => format!("Long {}", v as i64),
+        Constant::Double(v) => format!("Double {}", f64::from_bits(v)),
+        Constant::Class(name) => format!("Class {}", utf8(class, name)),
+        Constant::String(value) => format!("String {:?}", utf8(class, value)),
+        Constant::FieldRef {
+            class: owner,
+            name_and_type: nat,
+        } => format!("Fieldref {}.{}", class_name(owner), name_and_type(nat)),
+        Constant::MethodRef {
+            class: owner,
+            name_and_type: nat,
+        } => format!("Methodref {}.{}", class_name(owner), name_and_type(nat)),
+        Constant::InterfaceMethodRef {
+            class: owner,
+            name_and_type: nat,
+        } => format!(
+            "InterfaceMethodref {}.{}",
+            class_name(owner),
+            name_and_type(nat)
+        ),
+        Constant::NameAndType { .. } => format!("NameAndType {}", name_and_type(index)),
+        Constant::MethodHandle { kind, reference } => {
+            format!("MethodHandle {} {}", kind, describe(class, reference))
+        }
+        Constant::MethodType(descriptor) => format!("MethodType {}", utf8(class, descriptor)),
+        Constant::Dynamic {
+            bootstrap,
+            name_and_type: nat,
+        } => format!("Dynamic #{} {}", bootstrap, name_and_type(nat)),
+        Constant::InvokeDynamic {
+            bootstrap,
+            name_and_type: nat,
+        } => format!("InvokeDynamic #{} {}", bootstrap, name_and_type(nat)),
+        Constant::Module(name) => format!("Module {}", utf8(class, name)),
+        Constant::Package(name) => format!("Package {}", utf8(class, name)),
+        Constant::Unusable => String::new(),
+    }
+}
+
+/// How many times each constant is in the pool
+fn constants(class: &ClassFile) -> BTreeMap<String, usize> {
+    let mut constants = BTreeMap::new();
+    for (i, constant) in class.constant_pool.iter().enumerate() {
+        if *constant != Constant::Unusable {
+            *constants.entry(describe(class, i as u16)).or_default() += 1;
+        }
+    }
+    constants
+}
+
+fn members<'a>(class: &'a ClassFile, members: &'a [Member]) -> BTreeMap<String, &'a Member> {
+    members
+        .iter()
+        .map(|m| {
+            let key = format!(
+                "{} {}",
+                utf8(class, m.name_index),
+                utf8(class, m.descriptor_index)
+            );
+            (key, m)
+        })
+        .collect()
+}
+
+fn attributes<'a>(class: &ClassFile, attributes: &'a [Attribute]) -> Vec<(String, &'a [u8])> {
+    attributes
+        .iter()
+        .map(|a| (utf8(class, a.name_index), a.info.as_slice()))
+        .collect()
+}
+
+/// The attributes that were added, removed or changed, by their names. The
+/// ones that refer to the constant pool show up as changed when only the
+/// indices moved, there's no telling that apart without parsing all of them
+fn attribute_differences(
+    old: (&ClassFile, &[Attribute]),
+    new: (&ClassFile, &[Attribute]),
+    owner: &str,
+    out: &mut Vec<String>,
+) {
+    let (old, new) = (attributes(old.0, old.1), attributes(new.0, new.1));
+    for (name, info) in &old {
+        match new.iter().find(|(n, _)| n == name) {
+            None => out.push(format!("{}removed attribute {}", owner, name)),
+            Some((_, new_info)) if new_info != info => {
+                out.push(format!("{}changed attribute {}", owner, name))
+            }
+            _ => {}
+        }
+    }
+    for (name, _) in &new {
+        if !old.iter().any(|(n, _)| n == name) {
+            out.push(format!("{}added attribute {}", owner, name));
+        }
+    }
+}
+
+fn member_differences(
+    old: (&ClassFile, &[Member]),
+    new: (&ClassFile, &[Member]),
+    kind: &str,
+    out: &mut Vec<String>,
+) {
+    let (old_members, new_members) = (members(old.0, old.1), members(new.0, new.1));
+    for (key, member) in &old_members {
+        let new_member = match new_members.get(key) {
+            Some(new_member) => new_member,
+            None => {
+                out.push(format!("removed {} {}", kind, key));
+                continue;
+            }
+        };
+        if member.access_flags != new_member.access_flags {
+            out.push(format!(
+                "{} {}: access flags {:#06x} -> {:#06x}",
+                kind, key, member.access_flags, new_member.access_flags
+            ));
+        }
+        attribute_differences(
+            (old.0, &member.attributes),
+            (new.0, &new_member.attributes),
+            &format!("{} {}: ", kind, key),
+            out,
+        );
+    }
+    for key in new_members.keys() {
+        if !old_members.contains_key(key) {
+            out.push(format!("added {} {}", kind, key));
+        }
+    }
+}
+
+/// What changed between the two versions of a class, in a readable form
+pub fn class_differences(old: &ClassFile, new: &ClassFile) -> Vec<String> {
+    let mut out = Vec::new();
+    let (old_version, new_version) = (
+        (old.major_version, old.minor_version),
+        (new.major_version, new.minor_version),
+    );
+    if old_version != new_version {
+        out.push(format!(
+            "version {}.{} -> {}.{}",
+            old_version.0, old_version.1, new_version.0, new_version.1
+        ));
+    }
+    if old.access_flags != new.access_flags {
+        out.push(format!(
+            "access flags {:#06x} -> {:#06x}",
+            old.access_flags, new.access_flags
+        ));
+    }
+    let names = |class: &ClassFile| {
+        (
+            describe(class, class.this_class),
+            describe(class, class.super_class),
+        )
+    };
+    let (old_names, new_names) = (names(old), names(new));
+    if old_names.0 != new_names.0 {
+        out.push(format!("this class {} -> {}", old_names.0, new_names.0));
+    }
+    if old_names.1 != new_names.1 {
+        out.push(format!("super class {} -> {}", old_names.1, new_names.1));
+    }
+    let interfaces = |class: &ClassFile| {
+        class
+            .interfaces
+            .iter()
+            .map(|&i| describe(class, i))
+            .collect::<Vec<_>>()
+    };
+    let (old_interfaces, new_interfaces) = (interfaces(old), interfaces(new));
+    if old_interfaces != new_interfaces {
+        out.push(format!(
+            "interfaces [{}] -> [{}]",
+            old_interfaces.join(", "),
+            new_interfaces.join(", ")
+        ));
+    }
+
+    let (old_constants, new_constants) = (constants(old), constants(new));
+    for (constant, &count) in &old_constants {
+        let new_count = new_constants.get(constant).copied().unwrap_or_default();
+        for _ in new_count..count {
+            out.push(format!("removed constant {}", constant));
+        }
+    }
+    for (constant, &count) in &new_constants {
+        let old_count = old_constants.get(constant).copied().unwrap_or_default();
+        for _ in old_count..count {
+            out.push(format!("added constant {}", constant));
+        }
+    }
+
+    member_differences((old, &old.fields), (new, &new.fields), "field", &mut out);
+    member_differences((old, &old.methods), (new, &new.methods), "method", &mut out);
+    attribute_differences((old, &old.attributes), (new, &new.attributes), "", &mut out);
+    if old.trailing != new.trailing {
+        out.push(format!(
+            "{} bytes after the end of the class -> {}",
+            old.trailing.len(),
+            new.trailing.len()
+        ));
+    }
+    out
+}
@@ -0,0 +1,71 @@
+//! Where the files we keep around (the config, the registry, the journals)
+//! go: the usual places of the platform, or next to the executable with
+//! --portable.
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+const APP: &str = "starsector-fixer";
+
+pub fn home() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+/// The XDG variable if it's set to an absolute path (the spec says to
+/// ignore the relative ones), otherwise the default under the home
+fn xdg(var: &str, default: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| Some(home()?.join(default)))
+}
+
+fn beside_exe() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.parent()?.to_owned())
+}
+
+fn config_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        std::env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join(APP))
+    } else if cfg!(target_os = "macos") {
+        Some(home()?.join("Library/Application Support").join(APP))
+    } else {
+        Some(xdg("XDG_CONFIG_HOME", ".config")?.join(APP))
+    }
+}
+
+fn data_dir() -> Option<PathBuf> {
+    if cfg!(windows) || cfg!(target_os = "macos") {
+        config_dir()
+    } else {
+        Some(xdg("XDG_DATA_HOME", ".local/share")?.join(APP))
+    }
+}
+
+/// The trash of the user, as in the freedesktop.org spec
+pub fn trash() -> Option<PathBuf> {
+    Some(xdg("XDG_DATA_HOME", ".local/share")?.join("Trash"))
+}
+
+/// Where the config is looked for when it's not given explicitly
+pub fn config_file(portable: bool) -> Option<PathBuf> {
+    match portable {
+        true => Some(beside_exe()?.join(format!("{}.json", APP))),
+        false => Some(config_dir()?.join("config.json")),
+    }
+}
+
+/// A file we write to, with its directory created if needed
+pub fn data_file(portable: bool, name: &str) -> Result<PathBuf> {
+    let dir = match portable {
+        true => beside_exe(),
+        false => data_dir(),
+    };
+    let dir = dir.context("Could not find a directory to keep the files in, use --portable")?;
+    std::fs::create_dir_all(&dir).with_context(|| format!("Creating {}", dir.display()))?;
+    Ok(dir.join(name))
+}
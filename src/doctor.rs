@@ -0,0 +1,211 @@
+//! Looking at an install of the game as a whole, for everything that can
+//! get in the way of it running on a VM that cares about the names, or of
+//! fixing it.
+
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{Context, Result};
+use regex::bytes::{Regex, RegexBuilder};
+
+use crate::{check, fix::NameUse, info, limits::Limits};
+
+/// The VM the game runs on
+#[derive(Debug)]
+pub struct Jre {
+    pub home: PathBuf,
+    /// Like 1.7.0_79 or 17.0.2
+    pub version: String,
+    pub vendor: String,
+}
+
+impl Jre {
+    pub fn is_strict(&self) -> bool {
+        is_strict(&self.vendor, &self.version)
+    }
+}
+
+/// The major version, 7 for 1.7.0_79
+fn major(version: &str) -> Option<u32> {
+    let mut parts = version.split(['.', '_', '-', '+']);
+    match parts.next()? {
+        "1" => parts.next()?.parse().ok(),
+        major => major.parse().ok(),
+    }
+}
+
+/// Whether the VM rejects the methods with dots in their names, which only
+/// the old Oracle ones (like the one the game comes with) don't
+pub fn is_strict(vendor: &str, version: &str) -> bool {
+    let oracle = vendor.starts_with("Oracle") && !vendor.contains("OpenJDK");
+    !(oracle && major(version).is_some_and(|major| major <= 8))
+}
+
+/// The JRE that comes with the game (or was put in place of it), if any,
+/// in one of the directories the launchers expect it in
+pub fn find_jre(game_dir: &Path, jre_dirs: &[PathBuf]) -> Option<Jre> {
+    let home = jre_dirs
+        .iter()
+        .map(|dir| game_dir.join(dir))
+        .find(|home| java(home).is_file())?;
+    match describe_jre(&home) {
+        Ok(jre) => Some(jre),
+        Err(e) => {
+            log::warn!("{:#}", e);
+            None
+        }
+    }
+}
+
+pub fn java(home: &Path) -> PathBuf {
+    match cfg!(windows) {
+        true => home.join("bin/java.exe"),
+        false => home.join("bin/java"),
+    }
+}
+
+/// From the release file, or by asking java itself if there's none
+fn describe_jre(home: &Path) -> Result<Jre> {
+    if let Ok(release) = std::fs::read_to_string(home.join("release")) {
+        let value = |key: &str| {
+            release.lines().find_map(|line| {
+                let value = line.strip_prefix(key)?.strip_prefix('=')?;
+                Some(value.trim().trim_matches('"').to_owned())
+            })
+        };
+        if let Some(version) = value("JAVA_VERSION") {
+            return Ok(Jre {
+                home: home.to_owned(),
+                version,
+                vendor: value("IMPLEMENTOR").unwrap_or_else(|| "Oracle Corporation".to_owned()),
+            });
+        }
+    }
+    let (version, vendor) = describe_java(&java(home))?;
+    Ok(Jre {
+        home: home.to_owned(),
+        version,
+        vendor,
+    })
+}
+
+/// The version and the vendor, by asking java itself
+pub fn describe_java(java: &Path) -> Result<(String, String)> {
+    let output = Command::new(java)
+        .arg("-version")
+        .output()
+        .with_context(|| format!("Running {}", java.display()))?;
+    // like `java version "1.7.0_79"` or `openjdk version "17.0.2" 2022-01-18`
+    let text = String::from_utf8_lossy(&output.stderr);
+    let first = text.lines().next().unwrap_or_default();
+    let version = first.split('"').nth(1).unwrap_or_default().to_owned();
+    let vendor = match first.starts_with("java version") {
+        true => "Oracle Corporation",
+        false => "OpenJDK",
+    };
+    Ok((version, vendor.to_owned()))
+}
+
+/// What a jar of the game (or of a mod) is like
+#[derive(Debug)]
+pub struct JarState {
+    pub size: u64,
+    pub bad_names: usize,
+    /// The version of the fixer that fixed it
+    pub fixed_with: Option<String>,
+    pub signed: bool,
+    /// Either the jar or the directory it's in
+    pub read_only: bool,
+}
+
+pub fn jar_state(path: &Path, limits: &Limits) -> Result<JarState> {
+    let file = File::open(path).with_context(|| format!("Reading archive {}", path.display()))?;
+    let size = file.metadata()?.len();
+    let info = info::jar_info(BufReader::new(file), limits)
+        .with_context(|| format!("Reading archive {}", path.display()))?;
+    let read_only = |path: &Path| std::fs::metadata(path).is_ok_and(|m| m.permissions().readonly());
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+    Ok(JarState {
+        size,
+        bad_names: info.bad_names,
+        fixed_with: info.fixed_with,
+        signed: !info.signatures.is_empty(),
+        read_only: read_only(path) || dir.is_some_and(read_only),
+    })
+}
+
+/// The bad names of the fields in the jar, which is what the saves have,
+/// they are the game objects written out field by field
+pub fn bad_field_names(path: &Path, limits: &Limits, names: &mut BTreeSet<String>) -> Result<()> {
+    let file = File::open(path).with_context(|| format!("Reading archive {}", path.display()))?;
+    let report = check::check_jar(BufReader::new(file), limits, false)?;
+    let fields = report.classes.into_iter().flat_map(|(_, names)| names);
+    names.extend(
+        fields
+            .filter(|bad| bad.name_use == NameUse::Field)
+            .map(|bad| bad.name),
+    );
+    Ok(())
+}
+
+/// The saves (their files) that have any of the fields in them, with the
+/// first one found in each
+pub fn saves_with(saves_dir: &Path, names: &BTreeSet<String>) -> Result<Vec<(PathBuf, String)>> {
+    if names.is_empty() || !saves_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    // as the XML elements, like <foo.bar>
+    let alternatives = names
+        .iter()
+        .map(|name| regex::escape(name))
+        .collect::<Vec<_>>()
+        .join("|");
+    let pattern = RegexBuilder::new(&format!(r"<({})[\s/>]", alternatives))
+        .size_limit(64 * 1024 * 1024)
+        .build()?;
+    let longest = names.iter().map(String::len).max().unwrap_or_default() + 2;
+
+    let mut found = Vec::new();
+    for save in std::fs::read_dir(saves_dir)? {
+        let save = save?.path();
+        if !save.is_dir() {
+            continue;
+        }
+        let mut files = std::fs::read_dir(&save)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        files.sort();
+        for file in files {
+            if file.extension().is_none_or(|ext| ext != "xml") {
+                continue;
+            }
+            if let Some(name) = find_in_file(&file, &pattern, longest)? {
+                found.push((file, name));
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// A chunk at a time, the saves can be hundreds of megabytes, with a bit of
+/// the previous chunk kept for the names on the boundary
+fn find_in_file(path: &Path, pattern: &Regex, overlap: usize) -> Result<Option<String>> {
+    const CHUNK: usize = 1024 * 1024;
+    let mut file = File::open(path).with_context(|| format!("Reading {}", path.display()))?;
+    let mut buf = Vec::with_capacity(CHUNK + overlap);
+    loop {
+        let read = (&mut file).take(CHUNK as u64).read_to_end(&mut buf)?;
+        if let Some(found) = pattern.captures(&buf).and_then(|c| c.get(1)) {
+            return Ok(Some(String::from_utf8_lossy(found.as_bytes()).into_owned()));
+        }
+        if read < CHUNK {
+            return Ok(None);
+        }
+        buf.drain(..buf.len().saturating_sub(overlap));
+    }
+}
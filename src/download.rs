@@ -0,0 +1,82 @@
+//! Fetching the input from http(s) URLs.
+//!
+//! There's no point in pulling in a whole HTTP+TLS stack for this, so it's
+//! just delegated to curl, which is present on pretty much every Linux and
+//! macOS machine and ships with Windows 10 and later.
+
+use std::{path::Path, process::Command};
+
+use anyhow::{bail, Context, Result};
+
+use crate::temp::{self, TempPath};
+
+pub fn as_url(input: &Path) -> Option<&str> {
+    input
+        .to_str()
+        .filter(|s| s.starts_with("http://") || s.starts_with("https://"))
+}
+
+/// The file name a URL would be saved under, which is the last path segment,
+/// with what the file systems don't allow in the names taken out
+pub fn file_name(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next()?;
+    let (_, name) = path.split_once("://")?.1.rsplit_once('/')?;
+    // backslashes separate the directories on Windows
+    let name = name.rsplit('\\').next()?;
+    let name = name
+        .chars()
+        .filter(|&c| !c.is_control() && !matches!(c, ':' | '*' | '?' | '"' | '<' | '>' | '|'))
+        .collect::<String>();
+    let name = name.trim_end_matches(['.', ' ']);
+    (!name.is_empty()).then(|| name.to_owned())
+}
+
+/// Downloads into a new temporary file, which is removed when this is dropped
+pub fn download(url: &str) -> Result<TempPath> {
+    let name = file_name(url).unwrap_or_else(|| "download.jar".to_owned());
+    // named the same in the end, the tarballs are told by the extension
+    let (file, path) = temp::create_ending(&format!("-{}", name))?;
+    drop(file);
+
+    log::info!("Downloading {}", url);
+    let status = Command::new("curl")
+        .args(["--fail", "--location", "--progress-bar", "--output"])
+        .arg(path.path())
+        .arg(url)
+        .status()
+        .context("Running curl, is it installed?")?;
+    if !status.success() {
+        bail!("Downloading {} failed ({})", url, status);
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names() {
+        let name = |url| file_name(url);
+        assert_eq!(
+            name("https://example.com/a/mod.jar").as_deref(),
+            Some("mod.jar")
+        );
+        assert_eq!(
+            name("https://example.com/mod.zip?x=/y#z").as_deref(),
+            Some("mod.zip")
+        );
+        assert_eq!(
+            name("https://example.com/a\\..\\..\\evil.jar").as_deref(),
+            Some("evil.jar")
+        );
+        assert_eq!(
+            name("https://example.com/c:x<y>.jar").as_deref(),
+            Some("cxy.jar")
+        );
+        assert_eq!(name("https://example.com/..").as_deref(), None);
+        assert_eq!(name("https://example.com/a\\").as_deref(), None);
+        assert_eq!(name("https://example.com/").as_deref(), None);
+        assert_eq!(name("https://example.com").as_deref(), None);
+    }
+}
@@ -0,0 +1,144 @@
+//! Telling what went wrong from a crash log of the VM (an hs_err_pid file)
+//! or a log with the stack trace of the game not starting, by the errors
+//! that are known to happen with it.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+use Fix::{Elsewhere, Fixer};
+
+/// What can be done about a known error
+#[derive(Debug, Clone, Copy)]
+pub enum Fix {
+    /// Fixing the jars, with these flags on top of the usual ones
+    Fixer(&'static str),
+    /// Something else, which this can't do, the advice being the message
+    /// `explain-<id>-advice`
+    Elsewhere,
+}
+
+#[derive(Debug)]
+pub struct Known {
+    /// What the messages about it are by, `explain-<id>` being what it is
+    pub id: &'static str,
+    pattern: &'static str,
+    pub fix: Fix,
+}
+
+/// The errors, the more specific ones first, as the first one that matches
+/// a line is the one it's reported as
+const KNOWN: &[Known] = &[
+    Known {
+        id: "dotted-names",
+        pattern: r#"ClassFormatError: Illegal (field|method) name ""#,
+        fix: Fixer(""),
+    },
+    Known {
+        id: "ref-kinds",
+        pattern: r"IncompatibleClassChangeError: (Found (interface|class) .*, but (class|interface) was expected|Method .* must be (Interface)?Methodref constant)",
+        fix: Fixer("--repair-ref-kinds"),
+    },
+    Known {
+        id: "duplicate-members",
+        pattern: r"ClassFormatError: Duplicate (field|method) name",
+        fix: Elsewhere,
+    },
+    Known {
+        id: "class-version",
+        pattern: r"UnsupportedClassVersionError",
+        fix: Elsewhere,
+    },
+    Known {
+        id: "class-format",
+        pattern: r"ClassFormatError",
+        fix: Elsewhere,
+    },
+    Known {
+        id: "verify",
+        pattern: r"VerifyError",
+        fix: Elsewhere,
+    },
+    Known {
+        id: "heap-size",
+        pattern: r"Could not reserve enough space for .*object heap|Invalid maximum heap size",
+        fix: Elsewhere,
+    },
+    Known {
+        id: "out-of-memory",
+        pattern: r"OutOfMemoryError",
+        fix: Elsewhere,
+    },
+    Known {
+        id: "lwjgl",
+        pattern: r"UnsatisfiedLinkError|no lwjgl in java\.library\.path|Could not initialize class org\.lwjgl",
+        fix: Elsewhere,
+    },
+    Known {
+        id: "graphics-driver",
+        pattern: r"(?i)problematic frame:.*(opengl|atio|nvoglv|ig[0-9a-z]*icd|libgl)",
+        fix: Elsewhere,
+    },
+    Known {
+        id: "vm-crash",
+        pattern: r"(?i)EXCEPTION_ACCESS_VIOLATION|SIGSEGV|SIGBUS",
+        fix: Elsewhere,
+    },
+];
+
+/// A known error, found in the log
+#[derive(Debug)]
+pub struct Finding {
+    pub known: &'static Known,
+    pub line_number: usize,
+    pub line: String,
+}
+
+/// The known errors in the log, each one only the first time it's there
+pub fn explain(path: &Path) -> Result<Vec<Finding>> {
+    let patterns = KNOWN
+        .iter()
+        .map(|known| Regex::new(known.pattern).unwrap())
+        .collect::<Vec<_>>();
+    let file = File::open(path).with_context(|| format!("Reading {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let mut findings: Vec<Finding> = Vec::new();
+    let mut line = Vec::new();
+    let mut line_number = 0;
+    loop {
+        line.clear();
+        if reader
+            .read_until(b'\n', &mut line)
+            .with_context(|| format!("Reading {}", path.display()))?
+            == 0
+        {
+            break;
+        }
+        line_number += 1;
+        // the logs are in whatever encoding the system has
+        let text = String::from_utf8_lossy(&line);
+        let found = patterns.iter().position(|pattern| pattern.is_match(&text));
+        if let Some(i) = found {
+            let known = &KNOWN[i];
+            if !findings.iter().any(|f| std::ptr::eq(f.known, known)) {
+                findings.push(Finding {
+                    known,
+                    line_number,
+                    line: text.trim().to_owned(),
+                });
+            }
+        }
+    }
+    Ok(findings)
+}
+
+/// The directory of the game, if the log is in it (or in a directory of
+/// it, like starsector-core)
+pub fn game_dir(log: &Path, is_game_dir: impl Fn(&Path) -> bool) -> Option<&Path> {
+    log.ancestors().skip(1).take(3).find(|dir| is_game_dir(dir))
+}
@@ -0,0 +1,239 @@
+//! Guessing which obfuscator a jar went through, from the traces they are
+//! known to leave. It's all heuristics, so it only tells what to look into.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+
+use crate::{
+    class::{ClassFile, Constant},
+    fix,
+};
+
+/// The attributes of the spec, and the ones the usual compilers add
+const KNOWN_ATTRIBUTES: &[&str] = &[
+    "AnnotationDefault",
+    "BootstrapMethods",
+    "Code",
+    "CompilationID",
+    "ConstantValue",
+    "Deprecated",
+    "EnclosingMethod",
+    "Exceptions",
+    "InnerClasses",
+    "LineNumberTable",
+    "LocalVariableTable",
+    "LocalVariableTypeTable",
+    "MethodParameters",
+    "Module",
+    "ModuleMainClass",
+    "ModulePackages",
+    "NestHost",
+    "NestMembers",
+    "PermittedSubclasses",
+    "Record",
+    "RuntimeInvisibleAnnotations",
+    "RuntimeInvisibleParameterAnnotations",
+    "RuntimeInvisibleTypeAnnotations",
+    "RuntimeVisibleAnnotations",
+    "RuntimeVisibleParameterAnnotations",
+    "RuntimeVisibleTypeAnnotations",
+    "Signature",
+    "SourceDebugExtension",
+    "SourceFile",
+    "SourceID",
+    "StackMapTable",
+    "Synthetic",
+];
+
+/// An obfuscator that likely touched the jar, with why it's thought so
+#[derive(Debug, Clone)]
+pub struct Guess {
+    pub obfuscator: &'static str,
+    pub evidence: Vec<String>,
+}
+
+/// Collects the traces from the classes of the jar
+#[derive(Debug, Default)]
+pub struct Detector {
+    classes: usize,
+    /// Like `a/b/c`, which is what ProGuard names things by default
+    short_class_names: usize,
+    no_source_file: usize,
+    /// Made of only I, l and i, like `IiIlIIl`, which Allatori likes
+    confusable_names: usize,
+    allatori_strings: usize,
+    /// The static String[] fields named z, which is where Zelix KlassMaster
+    /// keeps the encrypted strings
+    zelix_string_arrays: usize,
+    illegal_names: usize,
+    odd_attributes: BTreeSet<String>,
+    manifest_hints: Vec<String>,
+}
+
+fn is_confusable(name: &str) -> bool {
+    name.len() >= 4 && name.chars().all(|c| matches!(c, 'I' | 'l' | 'i' | '1'))
+}
+
+impl Detector {
+    pub fn add_manifest(&mut self, manifest: &[u8]) {
+        for line in String::from_utf8_lossy(manifest).lines() {
+            let lowercase = line.to_ascii_lowercase();
+            let is_hint = [
+                "obfuscat",
+                "protected-by",
+                "proguard",
+                "allatori",
+                "zkm",
+                "zelix",
+            ]
+            .iter()
+            .any(|hint| lowercase.contains(hint));
+            if is_hint {
+                self.manifest_hints.push(line.trim().to_owned());
+            }
+        }
+    }
+
+    pub fn add_class(&mut self, class: &ClassFile) -> Result<()> {
+        self.classes += 1;
+
+        let name = class.class_name(class.this_class)?;
+        let simple = name.rsplit('/').next().unwrap_or(&name);
+        if simple.len() <= 2 && simple.chars().all(|c| c.is_ascii_lowercase()) {
+            self.short_class_names += 1;
+        }
+        if is_confusable(simple) {
+            self.confusable_names += 1;
+        }
+
+        let mut has_source_file = false;
+        for attribute in &class.attributes {
+            let attribute_name = class.attribute_name(attribute)?;
+            has_source_file |= attribute_name == "SourceFile";
+            self.check_attribute(&attribute_name);
+        }
+        if !has_source_file {
+            self.no_source_file += 1;
+        }
+
+        for (member_type, members) in [("field", &class.fields), ("method", &class.methods)] {
+            for member in members {
+                let member_name = class.utf8(member.name_index)?;
+                if is_confusable(&member_name) {
+                    self.confusable_names += 1;
+                }
+                if fix::fixed_name(&member_name).is_some() {
+                    self.illegal_names += 1;
+                }
+                if member_type == "field"
+                    && member.access_flags & crate::class::ACC_STATIC != 0
+                    && member_name.eq_ignore_ascii_case("z")
+                    && class
+                        .utf8(member.descriptor_index)?
+                        .starts_with("[Ljava/lang/String;")
+                {
+                    self.zelix_string_arrays += 1;
+                }
+                for attribute in &member.attributes {
+                    self.check_attribute(&class.attribute_name(attribute)?);
+                }
+            }
+        }
+
+        for constant in &class.constant_pool {
+            if let Constant::String(index) = *constant {
+                // the strings don't have to be valid, so not decoded
+                if class
+                    .utf8_bytes(index)?
+                    .windows(8)
+                    .any(|w| w == b"ALLATORI")
+                {
+                    self.allatori_strings += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_attribute(&mut self, name: &str) {
+        if !KNOWN_ATTRIBUTES.contains(&name) {
+            self.odd_attributes.insert(name.to_owned());
+        }
+    }
+
+    /// The obfuscators the traces point to, the most likely ones first
+    pub fn guesses(&self) -> Vec<Guess> {
+        let mut guesses: BTreeMap<&'static str, Vec<String>> = BTreeMap::new();
+        let mut add = |obfuscator, evidence: String| {
+            guesses.entry(obfuscator).or_default().push(evidence);
+        };
+        let most = |count: usize| self.classes > 0 && count * 2 > self.classes;
+
+        if self.allatori_strings > 0 {
+            add(
+                "Allatori",
+                format!("{} strings mentioning ALLATORI", self.allatori_strings),
+            );
+        }
+        if self.confusable_names > 0 {
+            add(
+                "Allatori",
+                format!("{} names made of only I, l and i", self.confusable_names),
+            );
+        }
+        if self.zelix_string_arrays > 0 {
+            add(
+                "Zelix KlassMaster",
+                format!(
+                    "{} static String[] fields named z, where it keeps the encrypted strings",
+                    self.zelix_string_arrays
+                ),
+            );
+        }
+        if most(self.short_class_names) {
+            add(
+                "ProGuard (or another renamer)",
+                format!(
+                    "{} of {} classes have names like a or ab",
+                    self.short_class_names, self.classes
+                ),
+            );
+            if most(self.no_source_file) {
+                add(
+                    "ProGuard (or another renamer)",
+                    format!(
+                        "{} of {} classes have no SourceFile",
+                        self.no_source_file, self.classes
+                    ),
+                );
+            }
+        }
+        for hint in &self.manifest_hints {
+            add("Named in the manifest", hint.clone());
+        }
+        if self.illegal_names > 0 {
+            add(
+                "One that uses names only old VMs accept",
+                format!("{} member names against the spec", self.illegal_names),
+            );
+        }
+        if !self.odd_attributes.is_empty() {
+            let names: Vec<_> = self.odd_attributes.iter().map(String::as_str).collect();
+            add(
+                "One that adds its own attributes",
+                format!("attributes {}", names.join(", ")),
+            );
+        }
+
+        let mut guesses: Vec<_> = guesses
+            .into_iter()
+            .map(|(obfuscator, evidence)| Guess {
+                obfuscator,
+                evidence,
+            })
+            .collect();
+        guesses.sort_by_key(|guess| std::cmp::Reverse(guess.evidence.len()));
+        guesses
+    }
+}
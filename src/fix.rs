@@ -0,0 +1,1354 @@
+//! The actual fixing of the class files.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{BufReader, BufWriter, Read, Write},
+    str::FromStr,
+    sync::{Arc, Mutex, PoisonError},
+    time::SystemTime,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::{
+    bundle::FailedClasses,
+    bytecode::{self, INVOKEINTERFACE, INVOKESPECIAL, INVOKESTATIC, INVOKEVIRTUAL},
+    class::{ClassFile, Code, Constant, ACC_INTERFACE},
+    glob::Glob,
+    index::{ClassInfo, JarIndex, MemberInfo},
+    jobs::Jobs,
+    known::KnownHashes,
+    limits::Limits,
+    mapping::{Mapped, MappedMembers, Mapping},
+    memory::Budget,
+    registry::Registry,
+    report::{Rename, Renames},
+    rules::Rules,
+    version,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct FixOptions {
+    /// Change the major version of every class to this one
+    pub class_version: Option<u16>,
+    pub source_file: Option<SourceFilePolicy>,
+    /// The renames to reuse and to add the new ones to
+    pub registry: Option<Arc<Mutex<Registry>>>,
+    /// Work around the attributes with wrong lengths instead of failing
+    pub lenient: bool,
+    /// What to do with the bytes after the end of the classes and jars
+    pub trailing_garbage: TrailingGarbage,
+    /// Where to put the classes that could not be fixed, for the debug bundle
+    pub failed_classes: Option<FailedClasses>,
+    pub limits: Limits,
+    /// Make method refs to the interfaces in the jar into interface method
+    /// refs and the other way around, where the code allows it
+    pub repair_ref_kinds: bool,
+    /// Fix the names in all of the NameAndType constants, not only in the
+    /// ones of the member refs
+    pub all_name_and_type: bool,
+    /// Check that the refs to the classes in the jar still resolve after
+    /// the renames, which needs the index of the jar
+    pub verify_refs: bool,
+    /// Replace the control characters in the member names with printable
+    /// placeholders
+    pub sanitize_names: bool,
+    /// The renames and the removals from a rules file
+    pub rules: Option<Arc<Rules>>,
+    /// The renames from a mapping file, done instead of the usual fixing of
+    /// the names in it
+    pub mapping: Option<Arc<Mapping>>,
+    /// Where the renamed members go, for writing the mapping file
+    pub mapped: Option<MappedMembers>,
+    pub unknown_tags: UnknownTagPolicy,
+    pub on_collision: CollisionPolicy,
+    /// What the names that would collide are renamed to instead, found for
+    /// the whole jar by the scan, or for each class on its own without it
+    pub collisions: Option<Arc<Collisions>>,
+    /// Also fix the jars and the zips inside of the jars, with the same
+    /// options
+    pub recurse_archives: bool,
+    /// Only the classes with the paths matching one of these are fixed, all
+    /// of them if there are none
+    pub only_packages: Vec<Glob>,
+    /// The results to compare the fixed files to
+    pub known_hashes: Option<Arc<KnownHashes>>,
+    /// Embed a report of what was done into the fixed jars
+    pub embed_report: bool,
+    /// The time the report is made at, the current one (or the
+    /// SOURCE_DATE_EPOCH) if `None`
+    pub now: Option<SystemTime>,
+    /// Where the renames go, for the report
+    pub renames: Option<Renames>,
+    /// Record the fixer and the original jar in the manifests
+    pub provenance: bool,
+    /// Leave the signatures of the signed jars in them, broken
+    pub keep_signatures: bool,
+    pub memory: Budget,
+    /// The threads to fix the classes with, only the one it's called from
+    /// if `None`
+    pub jobs: Option<Arc<Jobs>>,
+    /// For the reads and the writes of the archives, 0 for the default
+    pub buffer_size: usize,
+    /// Keep the fixed classes in a temporary file instead of in memory, and
+    /// only read the classes whole if they have something to rename
+    pub low_memory: bool,
+}
+
+/// Big enough to not be dominated by the round trips to network drives
+const DEFAULT_BUFFER_SIZE: usize = 256 * 1024;
+
+impl FixOptions {
+    /// Whether the result is the same as the one with the default options
+    /// and --no-provenance, the only one the known hashes are for, since the
+    /// provenance has the fixer version in it
+    pub fn is_standard(&self) -> bool {
+        self.class_version.is_none()
+            && self.source_file.is_none()
+            && self.registry.is_none()
+            && !self.repair_ref_kinds
+            && !self.all_name_and_type
+            && !self.sanitize_names
+            && self.rules.is_none()
+            && self.mapping.is_none()
+            && !self.lenient
+            && self.trailing_garbage == TrailingGarbage::Preserve
+            && self.on_collision == CollisionPolicy::Suffix
+            && self.only_packages.is_empty()
+            && !self.embed_report
+            && !self.keep_signatures
+            && !self.recurse_archives
+            && !self.provenance
+    }
+
+    /// Whether renaming the members is the only thing done to the classes, so
+    /// that the ones `renames_anything` is false for are left as they are
+    pub fn only_renames(&self) -> bool {
+        self.class_version.is_none()
+            && self.source_file.is_none()
+            && !self.repair_ref_kinds
+            && self.rules.is_none()
+            && self.mapping.is_none()
+            && !self.lenient
+            && self.trailing_garbage == TrailingGarbage::Preserve
+    }
+
+    /// Whether the name could be renamed, whatever it's used as
+    fn may_rename(&self, name: &str) -> bool {
+        fixed_name(name).is_some()
+            || (self.sanitize_names && sanitized_name(name).is_some())
+            || self
+                .collisions
+                .as_ref()
+                .is_some_and(|collisions| collisions.contains_key(name))
+            || self.registry.as_ref().is_some_and(|registry| {
+                registry
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .has_fixed_name(name)
+            })
+    }
+
+    pub fn reader<R: Read>(&self, inner: R) -> BufReader<R> {
+        BufReader::with_capacity(self.buffer_capacity(), inner)
+    }
+
+    pub fn writer<W: Write>(&self, inner: W) -> BufWriter<W> {
+        BufWriter::with_capacity(self.buffer_capacity(), inner)
+    }
+
+    fn buffer_capacity(&self) -> usize {
+        match self.buffer_size {
+            0 => DEFAULT_BUFFER_SIZE,
+            size => size,
+        }
+    }
+
+    /// Whether the names in all of the NameAndType constants are looked at
+    fn all_name_and_type(&self) -> bool {
+        self.all_name_and_type
+            || self
+                .rules
+                .as_ref()
+                .is_some_and(|rules| rules.wants_all_name_and_type())
+    }
+
+    /// Whether the class with this path in the jar should be fixed
+    pub fn is_included(&self, path: &str) -> bool {
+        self.only_packages.is_empty() || self.only_packages.iter().any(|glob| glob.is_match(path))
+    }
+}
+
+/// What to do with the SourceFile attributes with weird values in them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceFilePolicy {
+    /// Replace the bad values with the name javac would use
+    Normalize,
+    /// Remove all of the SourceFile attributes, bad or not
+    Strip,
+}
+
+impl FromStr for SourceFilePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "normalize" => Ok(Self::Normalize),
+            "strip" => Ok(Self::Strip),
+            _ => bail!("Expected 'normalize' or 'strip'"),
+        }
+    }
+}
+
+/// What to do with the bytes after the end of a class or a jar, which
+/// nothing reads but which are sometimes there
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrailingGarbage {
+    #[default]
+    Preserve,
+    Strip,
+}
+
+impl FromStr for TrailingGarbage {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "preserve" => Ok(Self::Preserve),
+            "strip" => Ok(Self::Strip),
+            _ => bail!("Expected 'preserve' or 'strip'"),
+        }
+    }
+}
+
+/// What to do with the classes that have constants with unknown tags
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownTagPolicy {
+    /// Fail, like with any other broken class
+    #[default]
+    Abort,
+    /// Copy the class as it is, with a warning
+    Skip,
+}
+
+impl FromStr for UnknownTagPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "abort" => Ok(Self::Abort),
+            "skip" => Ok(Self::Skip),
+            _ => bail!("Expected 'abort' or 'skip'"),
+        }
+    }
+}
+
+/// What to do when fixing a name makes it the same as the name of another
+/// member of the class, with the same descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollisionPolicy {
+    Error,
+    /// Add `$fx1` (or the next number that's free) to the fixed name
+    #[default]
+    Suffix,
+    /// Leave the name as it is, bad as it is
+    Skip,
+}
+
+impl FromStr for CollisionPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "error" => Ok(Self::Error),
+            "suffix" => Ok(Self::Suffix),
+            "skip" => Ok(Self::Skip),
+            _ => bail!("Expected 'error', 'suffix' or 'skip'"),
+        }
+    }
+}
+
+/// The names that would collide when fixed, with what they are renamed to
+/// instead, `None` for not renaming them
+pub type Collisions = BTreeMap<String, Option<String>>;
+
+/// The replacement for a name that is not allowed by the spec, if it's not.
+/// Some obfuscators put slashes in the names as well as the dots, and old
+/// VMs let those slide all the same
+pub fn fixed_name(name: &str) -> Option<String> {
+    name.contains(['.', '/'])
+        .then(|| name.replace(['.', '/'], "_"))
+}
+
+/// The new name for the bad one, the same one every time with the registry
+fn new_name(
+    options: &FixOptions,
+    collisions: &Collisions,
+    name: &str,
+    name_use: NameUse,
+) -> Option<String> {
+    if let Some(instead) = collisions.get(name) {
+        return instead.clone();
+    }
+    let mapped = options.mapping.as_ref().and_then(|m| m.rename(name));
+    let fixed = match (mapped, &options.registry) {
+        (Some(mapped), _) => Some(mapped),
+        (None, Some(registry)) => registry
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .fixed_name(name),
+        (None, None) => fixed_name(name),
+    };
+    let fixed = match options.sanitize_names {
+        true => sanitized_name(fixed.as_deref().unwrap_or(name)).or(fixed),
+        false => fixed,
+    };
+    match &options.rules {
+        Some(rules) => rules
+            .rename(fixed.as_deref().unwrap_or(name), name_use)
+            .or(fixed),
+        None => fixed,
+    }
+}
+
+/// The names and the descriptors of the members and of the NameAndType
+/// constants, in the order they are in the class
+fn names_and_descriptors(class: &ClassFile) -> Result<Vec<(String, String)>> {
+    let mut result = Vec::new();
+    for member in class.fields.iter().chain(&class.methods) {
+        result.push((
+            class.utf8(member.name_index)?.into_owned(),
+            class.utf8(member.descriptor_index)?.into_owned(),
+        ));
+    }
+    for constant in &class.constant_pool {
+        if let Constant::NameAndType { name, descriptor } = *constant {
+            result.push((
+                class.utf8(name)?.into_owned(),
+                class.utf8(descriptor)?.into_owned(),
+            ));
+        }
+    }
+    Ok(result)
+}
+
+/// Makes sure that the renames only changed the names that were meant to be
+/// changed, into what they were meant to be, and that all of the renamed
+/// ones kept their descriptors. The constants being shared in some weird
+/// way is what could break this
+fn check_renames(
+    before: &[(String, String)],
+    after: &[(String, String)],
+    renamed: &BTreeMap<String, String>,
+) -> Result<()> {
+    ensure!(before.len() == after.len(), "The member count changed");
+    for ((old_name, old_descriptor), (new_name, new_descriptor)) in before.iter().zip(after) {
+        ensure!(
+            old_descriptor == new_descriptor,
+            "The descriptor of {} changed from {} to {}",
+            old_name,
+            old_descriptor,
+            new_descriptor
+        );
+        if old_name != new_name {
+            ensure!(
+                renamed.get(old_name) == Some(new_name),
+                "{}{} was renamed to {} by mistake",
+                old_name,
+                old_descriptor,
+                new_name
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Makes sure that every ref to a member of a class in the jar that found
+/// it before the renames still finds it after them
+fn check_refs(
+    class: &ClassFile,
+    index: &JarIndex,
+    options: &FixOptions,
+    collisions: &Collisions,
+    renamed: &BTreeMap<String, String>,
+) -> Result<()> {
+    // what the names of the members in the jar become, the classes that
+    // are not fixed keep theirs
+    let name_after = |owner: &str, name: &str, name_use: NameUse| -> String {
+        match options.is_included(&format!("{}.class", owner)) {
+            true => {
+                new_name(options, collisions, name, name_use).unwrap_or_else(|| name.to_owned())
+            }
+            false => name.to_owned(),
+        }
+    };
+    let unrenamed: BTreeMap<&str, &str> = renamed
+        .iter()
+        .map(|(from, to)| (to.as_str(), from.as_str()))
+        .collect();
+
+    for constant in &class.constant_pool {
+        let (owner, name_and_type, is_field) = match *constant {
+            Constant::FieldRef {
+                class,
+                name_and_type,
+            } => (class, name_and_type, true),
+            Constant::MethodRef {
+                class,
+                name_and_type,
+            }
+            | Constant::InterfaceMethodRef {
+                class,
+                name_and_type,
+            } => (class, name_and_type, false),
+            _ => continue,
+        };
+        let (name, descriptor) = match *class.constant(name_and_type)? {
+            Constant::NameAndType { name, descriptor } => {
+                (class.utf8(name)?, class.utf8(descriptor)?)
+            }
+            _ => bail!("Constant #{} is not a NAME_AND_TYPE", name_and_type),
+        };
+        let owner = class.class_name(owner)?;
+        let old_name = unrenamed.get(&*name).copied().unwrap_or(&name);
+
+        let found_before = resolve(index, &owner, is_field, |_, member| {
+            member.name == old_name && member.descriptor == descriptor
+        });
+        let name_use = match is_field {
+            true => NameUse::Field,
+            false => NameUse::Method,
+        };
+        let found_after = resolve(index, &owner, is_field, |member_owner, member| {
+            name_after(member_owner, &member.name, name_use) == name
+                && member.descriptor == descriptor
+        });
+        if found_before == Some(true) && found_after != Some(true) {
+            bail!(
+                "The ref to {}.{}{} (which was {}) does not find the member anymore",
+                owner,
+                name,
+                descriptor,
+                old_name
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Whether there's a member matching the ref in the class or in one of its
+/// supertypes, `None` meaning that it can't be known, since some of them are
+/// not in the jar
+fn resolve(
+    index: &JarIndex,
+    owner: &str,
+    is_field: bool,
+    matches: impl Fn(&str, &MemberInfo) -> bool,
+) -> Option<bool> {
+    let mut queue = vec![owner];
+    let mut seen = BTreeSet::new();
+    let mut complete = true;
+    while let Some(name) = queue.pop() {
+        if !seen.insert(name) {
+            continue;
+        }
+        let info = match index.classes.get(name) {
+            Some(info) => info,
+            None => {
+                complete = false;
+                continue;
+            }
+        };
+        let members = match is_field {
+            true => &info.fields,
+            false => &info.methods,
+        };
+        let found = members.iter().any(|member| matches(&info.name, member));
+        if found {
+            return Some(true);
+        }
+        queue.extend(info.supertypes());
+    }
+    complete.then_some(false)
+}
+
+/// The name with the control characters (NULs included) replaced with
+/// placeholders like `_u0000`, if there are any. The VM is fine with them,
+/// but most of the tools that show or process the names are not
+pub fn sanitized_name(name: &str) -> Option<String> {
+    if !name.chars().any(char::is_control) {
+        return None;
+    }
+    let mut sanitized = String::with_capacity(name.len() + 8);
+    for c in name.chars() {
+        match c.is_control() {
+            true => sanitized.push_str(&format!("_u{:04x}", c as u32)),
+            false => sanitized.push(c),
+        }
+    }
+    Some(sanitized)
+}
+
+/// Finds the members of the classes that fixing the names would make the
+/// same as the other ones, and what to do with their names instead. The
+/// name is renamed the same way everywhere, so that the refs to it from
+/// the other classes stay right
+pub fn find_collisions<'a>(
+    classes: impl IntoIterator<Item = &'a ClassInfo>,
+    options: &FixOptions,
+) -> Result<Collisions> {
+    let classes: Vec<_> = classes
+        .into_iter()
+        .filter(|class| options.is_included(&format!("{}.class", class.name)))
+        .collect();
+    // the suffixed names must not be the same as any of the others either
+    let mut taken = BTreeSet::new();
+    for class in &classes {
+        for member in class.fields.iter().chain(&class.methods) {
+            taken.insert(member.name.clone());
+        }
+    }
+
+    let mut collisions = Collisions::new();
+    for class in &classes {
+        for (members, name_use) in [
+            (&class.fields, NameUse::Field),
+            (&class.methods, NameUse::Method),
+        ] {
+            let mut fixed = BTreeMap::<_, BTreeSet<&str>>::new();
+            for member in members {
+                let name = new_name(options, &Collisions::new(), &member.name, name_use)
+                    .unwrap_or_else(|| member.name.clone());
+                taken.insert(name.clone());
+                fixed
+                    .entry((name, member.descriptor.as_str()))
+                    .or_default()
+                    .insert(&member.name);
+            }
+            for ((name, descriptor), originals) in fixed {
+                if originals.len() < 2 {
+                    continue;
+                }
+                // the one that had the name already keeps it, or the first
+                // one of the renamed ones if none of them did
+                let keeps_first = !originals.contains(name.as_str());
+                let renamed = originals.iter().filter(|&&original| original != name);
+                for original in renamed.skip(keeps_first as usize) {
+                    if collisions.contains_key(*original) {
+                        continue;
+                    }
+                    let original_name = original.escape_debug();
+                    let kind = member_kind(name_use);
+                    let instead = match options.on_collision {
+                        CollisionPolicy::Error => bail!(
+                            "{}",
+                            tr!(
+                                "fix-collision",
+                                name = original_name,
+                                class = class.name,
+                                kind = kind,
+                                new = name.escape_debug(),
+                                descriptor = descriptor
+                            )
+                        ),
+                        CollisionPolicy::Skip => None,
+                        CollisionPolicy::Suffix => {
+                            let suffixed = (1..)
+                                .map(|i| format!("{}$fx{}", name, i))
+                                .find(|suffixed| !taken.contains(suffixed))
+                                .unwrap();
+                            taken.insert(suffixed.clone());
+                            Some(suffixed)
+                        }
+                    };
+                    let message = match &instead {
+                        Some(instead) => tr!(
+                            "fix-collision-suffixed",
+                            name = original_name,
+                            class = class.name,
+                            kind = kind,
+                            new = instead.escape_debug()
+                        ),
+                        None => tr!(
+                            "fix-collision-skipped",
+                            name = original_name,
+                            class = class.name,
+                            kind = kind
+                        ),
+                    };
+                    log::warn!("{}", message);
+                    // so that the jars fixed with the registry later refer
+                    // to it by the same name
+                    if let (Some(registry), Some(instead)) = (&options.registry, &instead) {
+                        registry
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .set_fixed_name(original, instead);
+                    }
+                    collisions.insert(original.to_string(), instead);
+                }
+            }
+        }
+    }
+    Ok(collisions)
+}
+
+/// The members that have the same name and descriptor as some other member
+/// of the class. Old VMs let that slide, newer ones reject the class
+fn duplicate_members(class: &ClassFile) -> Result<BTreeSet<(NameUse, String, String)>> {
+    let mut seen = BTreeSet::new();
+    let mut duplicates = BTreeSet::new();
+    for (name_use, members) in [
+        (NameUse::Field, &class.fields),
+        (NameUse::Method, &class.methods),
+    ] {
+        for member in members {
+            let key = (
+                name_use,
+                class.utf8(member.name_index)?.into_owned(),
+                class.utf8(member.descriptor_index)?.into_owned(),
+            );
+            if !seen.insert(key.clone()) {
+                duplicates.insert(key);
+            }
+        }
+    }
+    Ok(duplicates)
+}
+
+/// What a name constant is used as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NameUse {
+    Field,
+    Method,
+    /// Only used in the refs, to the members of other classes
+    RefOnly,
+    /// Only used in NameAndType constants that are not for member refs
+    Other,
+    /// The names of the local variables and the parameters, in the debug
+    /// info and the MethodParameters
+    Local,
+}
+
+impl NameUse {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::Field => "field",
+            Self::Method => "method",
+            Self::RefOnly => "ref-only",
+            Self::Other => "other",
+            Self::Local => "local",
+        }
+    }
+}
+
+/// Whether the member is a field or a method, in the language of the user
+fn member_kind(name_use: NameUse) -> String {
+    match name_use {
+        NameUse::Field => tr!("fix-field"),
+        _ => tr!("fix-method"),
+    }
+}
+
+/// The constants with the names of the members of the class and of the
+/// members referred to from it, by their index. The same constant is usually
+/// shared between the member definition and all of the refs to it. With
+/// `all_name_and_type`, the names in the rest of the NameAndType constants
+/// are there too
+fn member_names(class: &ClassFile, all_name_and_type: bool) -> Result<BTreeMap<u16, NameUse>> {
+    let mut names = BTreeMap::new();
+
+    for (member_type, members, name_use) in [
+        ("Field", &class.fields, NameUse::Field),
+        ("Method", &class.methods, NameUse::Method),
+    ] {
+        log::debug!("{} count is {}", member_type, members.len());
+        for member in members {
+            log::debug!(
+                "{} name is a constant #{} -> {:?}",
+                member_type,
+                member.name_index,
+                class.utf8(member.name_index)?,
+            );
+            names.entry(member.name_index).or_insert(name_use);
+        }
+    }
+
+    for constant in &class.constant_pool {
+        if let Constant::FieldRef { name_and_type, .. }
+        | Constant::MethodRef { name_and_type, .. }
+        | Constant::InterfaceMethodRef { name_and_type, .. } = constant
+        {
+            if let Constant::NameAndType { name, .. } = class.constant(*name_and_type)? {
+                names.entry(*name).or_insert(NameUse::RefOnly);
+            }
+        }
+    }
+
+    // the names of the call sites are the names of the interface methods
+    // for the lambdas, and the arguments of the bootstrap methods are the
+    // method handles to the refs above and the values, so these are all
+    // that BootstrapMethods has to rename
+    for constant in &class.constant_pool {
+        if let Constant::InvokeDynamic { name_and_type, .. }
+        | Constant::Dynamic { name_and_type, .. } = constant
+        {
+            if let Constant::NameAndType { name, .. } = class.constant(*name_and_type)? {
+                names.entry(*name).or_insert(NameUse::RefOnly);
+            }
+        }
+    }
+
+    // javac shares the constants between everything with the same text,
+    // so the name can be a string literal or a class name as well, and
+    // those must stay as they are
+    let mut other_uses = BTreeSet::new();
+    for constant in &class.constant_pool {
+        if let Constant::Class(index)
+        | Constant::String(index)
+        | Constant::MethodType(index)
+        | Constant::Module(index)
+        | Constant::Package(index) = constant
+        {
+            other_uses.insert(*index);
+        }
+    }
+    for (name, name_use) in attribute_names(class)? {
+        if names.contains_key(&name) {
+            continue;
+        }
+        if other_uses.contains(&name) {
+            log::debug!(
+                "Not touching the name in constant #{}, it's also used as a value",
+                name
+            );
+            continue;
+        }
+        names.insert(name, name_use);
+    }
+
+    if all_name_and_type {
+        for constant in &class.constant_pool {
+            if let Constant::NameAndType { name, .. } = constant {
+                if names.contains_key(name) {
+                    continue;
+                }
+                if other_uses.contains(name) {
+                    log::debug!(
+                        "Not touching the name in constant #{}, it's also used as a value",
+                        name
+                    );
+                    continue;
+                }
+                names.insert(*name, NameUse::Other);
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// The names that the attributes have, by the index of their constant: the
+/// enclosing methods, the record components, the parameters and the local
+/// variables. The signatures are left alone, they are made of the class
+/// names, where the dots are the separators of the inner classes
+fn attribute_names(class: &ClassFile) -> Result<Vec<(u16, NameUse)>> {
+    let u16_at = |info: &[u8], pos: usize| {
+        info.get(pos..pos + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .context("The attribute is too short")
+    };
+    let mut names = Vec::new();
+    for attribute in &class.attributes {
+        let info = &attribute.info;
+        match &*class.attribute_name(attribute)? {
+            "EnclosingMethod" => {
+                // zero when the class is not in a method, but in an initializer
+                let method = u16_at(info, 2).context("Reading EnclosingMethod")?;
+                if method != 0 {
+                    if let Constant::NameAndType { name, .. } = class.constant(method)? {
+                        names.push((*name, NameUse::RefOnly));
+                    }
+                }
+            }
+            "Record" => {
+                let mut pos = 2;
+                for _ in 0..u16_at(info, 0).context("Reading Record")? {
+                    names.push((u16_at(info, pos)?, NameUse::Field));
+                    let attributes = u16_at(info, pos + 4)?;
+                    pos += 6;
+                    // the attributes of the component, skipped over
+                    for _ in 0..attributes {
+                        let length = info
+                            .get(pos + 2..pos + 6)
+                            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+                            .context("Reading Record")?;
+                        pos += 6 + length as usize;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    for method in &class.methods {
+        for attribute in &method.attributes {
+            match &*class.attribute_name(attribute)? {
+                "MethodParameters" => {
+                    let info = &attribute.info;
+                    let count = *info.first().context("Reading MethodParameters")?;
+                    for i in 0..count as usize {
+                        // zero for the parameters without a name
+                        match u16_at(info, 1 + i * 4).context("Reading MethodParameters")? {
+                            0 => {}
+                            name => names.push((name, NameUse::Local)),
+                        }
+                    }
+                }
+                "Code" => {
+                    let code = Code::parse(&attribute.info)?;
+                    for attribute in &code.attributes {
+                        let table = class.attribute_name(attribute)?;
+                        if table != "LocalVariableTable" && table != "LocalVariableTypeTable" {
+                            continue;
+                        }
+                        let info = &attribute.info;
+                        let count =
+                            u16_at(info, 0).with_context(|| format!("Reading {}", table))?;
+                        for i in 0..count as usize {
+                            let name = u16_at(info, 2 + i * 10 + 4)
+                                .with_context(|| format!("Reading {}", table))?;
+                            names.push((name, NameUse::Local));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// A name in the class that needs fixing
+#[derive(Debug, Clone)]
+pub struct BadName {
+    /// The index of the constant with the name
+    pub index: u16,
+    pub name: String,
+    pub name_use: NameUse,
+}
+
+/// Whether fixing the class would rename any of the names in it, which
+/// works with the classes from `ClassFile::read_names` too
+pub fn renames_anything(class: &ClassFile, options: &FixOptions) -> Result<bool> {
+    for (index, _) in member_names(class, options.all_name_and_type())? {
+        if options.may_rename(&class.utf8(index)?) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Whether fixing would rename any of the members of the indexed class
+pub fn renames_any_member(class: &ClassInfo, options: &FixOptions) -> bool {
+    let none = Collisions::new();
+    let renames = |members: &[MemberInfo], name_use| {
+        members
+            .iter()
+            .any(|member| new_name(options, &none, &member.name, name_use).is_some())
+    };
+    renames(&class.fields, NameUse::Field) || renames(&class.methods, NameUse::Method)
+}
+
+pub fn bad_names(class: &ClassFile, all_name_and_type: bool) -> Result<Vec<BadName>> {
+    names_where(class, all_name_and_type, |name| fixed_name(name).is_some())
+}
+
+/// The names with control characters in them, which are not against the
+/// spec, but are trouble all the same
+pub fn control_char_names(class: &ClassFile, all_name_and_type: bool) -> Result<Vec<BadName>> {
+    names_where(class, all_name_and_type, |name| {
+        sanitized_name(name).is_some()
+    })
+}
+
+fn names_where(
+    class: &ClassFile,
+    all_name_and_type: bool,
+    is_bad: impl Fn(&str) -> bool,
+) -> Result<Vec<BadName>> {
+    let mut bad = Vec::new();
+    for (index, name_use) in member_names(class, all_name_and_type)? {
+        let name = class.utf8(index)?;
+        if is_bad(&name) {
+            bad.push(BadName {
+                index,
+                name: name.into_owned(),
+                name_use,
+            });
+        }
+    }
+    Ok(bad)
+}
+
+/// Fixes the class, given the index of the jar it's from for the passes that
+/// need to know about the other classes. Returns the fixed class if anything
+/// was changed
+pub fn fix_class(
+    bytecode: &[u8],
+    filename: &str,
+    options: &FixOptions,
+    index: Option<&JarIndex>,
+) -> Result<Option<Vec<u8>>> {
+    let (mut class, anomalies) = if options.lenient {
+        ClassFile::parse_lenient(bytecode)?
+    } else {
+        (ClassFile::parse(bytecode)?, Vec::new())
+    };
+    for anomaly in &anomalies {
+        log::warn!("{} in {}", anomaly, filename);
+    }
+    // writing the class back is what fixes the lengths
+    let mut changed = !anomalies.is_empty();
+
+    if !class.trailing.is_empty() {
+        let key = match options.trailing_garbage {
+            TrailingGarbage::Preserve => "fix-trailing-garbage-kept",
+            TrailingGarbage::Strip => "fix-trailing-garbage-stripped",
+        };
+        let count = class.trailing.len();
+        log::warn!("{}", tr!(key, count = count, class = filename));
+        if options.trailing_garbage == TrailingGarbage::Strip {
+            class.trailing.clear();
+            changed = true;
+        }
+    }
+
+    let duplicates = duplicate_members(&class)?;
+    for (name_use, name, descriptor) in &duplicates {
+        let kind = member_kind(*name_use);
+        let message = tr!(
+            "fix-duplicate",
+            kind = kind,
+            name = name,
+            descriptor = descriptor,
+            class = filename
+        );
+        log::warn!("{}", message);
+    }
+
+    let local;
+    let collisions = match &options.collisions {
+        Some(collisions) => collisions.as_ref(),
+        None => {
+            local = find_collisions([&ClassInfo::from_class(&class)?], options)
+                .with_context(|| format!("Renaming the members of {}", filename))?;
+            &local
+        }
+    };
+    let before = names_and_descriptors(&class)?;
+    let mut renamed = BTreeMap::new();
+    for (idx, name_use) in member_names(&class, options.all_name_and_type())? {
+        let name = class.utf8(idx)?;
+        if let Some(fixed) = new_name(options, collisions, &name, name_use) {
+            let mapped = options.mapping.as_ref().and_then(|m| m.rename(&name));
+            if let Some(mapped) = &mapped {
+                let (name, new) = (name.escape_debug(), mapped.escape_debug());
+                let message = tr!("fix-mapped-name", name = name, new = new, class = filename);
+                log::info!("{}", message);
+            } else if fixed_name(&name).is_some() {
+                let name = name.escape_debug();
+                log::info!("{}", tr!("fix-bad-name", name = name, class = filename));
+            }
+            if options.sanitize_names && sanitized_name(&name).is_some() {
+                let name = name.escape_debug();
+                log::info!(
+                    "{}",
+                    tr!("fix-control-chars", name = name, class = filename)
+                );
+            }
+            if options.rules.is_some() && fixed_name(&name).is_none() && mapped.is_none() {
+                let (name, new) = (name.escape_debug(), fixed.escape_debug());
+                let message = tr!("fix-rules-name", name = name, new = new, class = filename);
+                log::info!("{}", message);
+            }
+            if let Some(renames) = &options.renames {
+                renames
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(Rename {
+                        class: filename.to_owned(),
+                        from: name.clone().into_owned(),
+                        to: fixed.clone(),
+                    });
+            }
+            renamed.insert(name.into_owned(), fixed.clone());
+            class.set_utf8(idx, &fixed)?;
+            changed = true;
+        }
+    }
+    if !renamed.is_empty() {
+        let after = names_and_descriptors(&class)?;
+        check_renames(&before, &after, &renamed)
+            .with_context(|| format!("Renaming the members of {}", filename))?;
+        if let Some(mapped) = &options.mapped {
+            let this = class.class_name(class.this_class)?;
+            let members = class.fields.len() + class.methods.len();
+            let mut mapped = mapped.lock().unwrap_or_else(PoisonError::into_inner);
+            for ((from, descriptor), (to, _)) in before.iter().zip(&after).take(members) {
+                if from != to {
+                    mapped.push(Mapped {
+                        class: this.clone().into_owned(),
+                        descriptor: descriptor.clone(),
+                        from: from.clone(),
+                        to: to.clone(),
+                    });
+                }
+            }
+        }
+        if let (true, Some(index)) = (options.verify_refs, index) {
+            check_refs(&class, index, options, collisions, &renamed)
+                .with_context(|| format!("Renaming the members of {}", filename))?;
+        }
+    }
+
+    // things like `a.b` and `a_b` in the same class end up being the same
+    for (name_use, name, descriptor) in duplicate_members(&class)?.difference(&duplicates) {
+        let kind = member_kind(*name_use);
+        let message = tr!(
+            "fix-made-duplicate",
+            kind = kind,
+            name = name,
+            descriptor = descriptor,
+            class = filename
+        );
+        log::warn!("{}", message);
+    }
+
+    if let (true, Some(index)) = (options.repair_ref_kinds, index) {
+        changed |= repair_ref_kinds(&mut class, index, filename)?;
+    }
+
+    if let Some(policy) = options.source_file {
+        changed |= sanitize_source_file(&mut class, policy, filename)?;
+    }
+
+    if let Some(rules) = &options.rules {
+        changed |= rules.strip_attributes(&mut class, filename)?;
+    }
+
+    if class.minor_version == version::PREVIEW_MINOR {
+        log::debug!(
+            "{} uses the preview features of {}",
+            filename,
+            version::release_name(class.major_version)
+        );
+    }
+
+    if let Some(target) = options.class_version {
+        // the preview flag stays, as the class can still need the features
+        let minor = match class.minor_version {
+            version::PREVIEW_MINOR => version::PREVIEW_MINOR,
+            _ => 0,
+        };
+        if (class.major_version, class.minor_version) != (target, minor) {
+            version::check_target(&class, target)
+                .with_context(|| format!("Cannot change the version to {}", target))?;
+            log::debug!(
+                "Changing the version of {} from {}.{} to {}.{}",
+                filename,
+                class.major_version,
+                class.minor_version,
+                target,
+                minor
+            );
+            class.major_version = target;
+            class.minor_version = minor;
+            changed = true;
+        }
+    }
+
+    Ok(changed.then(|| class.to_bytes()))
+}
+
+/// Something a compiler could have written, and that won't mess up stack
+/// traces or the decompiler output
+fn is_sane_source_file(name: &str) -> bool {
+    name.len() <= 128
+        && !name.is_empty()
+        && !name
+            .chars()
+            .any(|c| c.is_control() || matches!(c, '"' | '\'' | '\\' | '/' | '<' | '>' | '|'))
+}
+
+/// The source file name javac would use, from the outermost class name
+fn default_source_file(class: &ClassFile) -> Result<String> {
+    let name = class.class_name(class.this_class)?;
+    let simple = name.rsplit('/').next().unwrap_or(&name);
+    let outer = simple.split('$').find(|s| !s.is_empty()).unwrap_or(simple);
+    Ok(format!("{}.java", outer))
+}
+
+fn sanitize_source_file(
+    class: &mut ClassFile,
+    policy: SourceFilePolicy,
+    filename: &str,
+) -> Result<bool> {
+    let mut changed = false;
+    let mut i = 0;
+    while i < class.attributes.len() {
+        if class.attribute_name(&class.attributes[i])? != "SourceFile" {
+            i += 1;
+            continue;
+        }
+        let info = &class.attributes[i].info;
+        ensure!(info.len() == 2, "SourceFile attribute has the wrong length");
+        let value = class.utf8(u16::from_be_bytes([info[0], info[1]]))?;
+
+        if policy == SourceFilePolicy::Strip {
+            log::info!(
+                "Removing SourceFile '{}' from {}",
+                value.escape_debug(),
+                filename
+            );
+            class.attributes.remove(i);
+            changed = true;
+            continue;
+        }
+
+        if !is_sane_source_file(&value) {
+            let replacement = default_source_file(class)?;
+            log::info!(
+                "Replacing SourceFile '{}' with '{}' in {}",
+                value.escape_debug(),
+                replacement,
+                filename
+            );
+            // a new constant, in case the old one is also used for something
+            let index = class.add_utf8(&replacement)?;
+            class.attributes[i].info = index.to_be_bytes().to_vec();
+            changed = true;
+        }
+        i += 1;
+    }
+    Ok(changed)
+}
+
+/// Old VMs did not care if a method ref to an interface method was a plain
+/// method ref (or vice versa), newer ones do. Since the instructions other
+/// than invokestatic and invokespecial dictate the kind of the ref, only the
+/// refs used by those two can be changed without rewriting the code
+fn repair_ref_kinds(class: &mut ClassFile, index: &JarIndex, filename: &str) -> Result<bool> {
+    // (constant index, owner, name and type, whether it should be an
+    // interface method ref, what it refers to)
+    let mut mismatched = Vec::new();
+    for (i, constant) in class.constant_pool.iter().enumerate() {
+        let (owner, name_and_type, is_interface_ref) = match *constant {
+            Constant::MethodRef {
+                class,
+                name_and_type,
+            } => (class, name_and_type, false),
+            Constant::InterfaceMethodRef {
+                class,
+                name_and_type,
+            } => (class, name_and_type, true),
+            _ => continue,
+        };
+        // classes outside of the jar can't be checked
+        let info = match index.classes.get(&*class.class_name(owner)?) {
+            Some(info) => info,
+            None => continue,
+        };
+        let is_interface = info.access_flags & ACC_INTERFACE != 0;
+        if is_interface != is_interface_ref {
+            let member = match *class.constant(name_and_type)? {
+                Constant::NameAndType { name, descriptor } => format!(
+                    "{}.{}{}",
+                    info.name,
+                    class.utf8(name)?,
+                    class.utf8(descriptor)?
+                ),
+                _ => bail!("Constant #{} is not a NAME_AND_TYPE", name_and_type),
+            };
+            mismatched.push((i as u16, owner, name_and_type, is_interface, member));
+        }
+    }
+    if mismatched.is_empty() {
+        return Ok(false);
+    }
+
+    // the refs that are used in ways that need the kind they have now
+    let mut stuck = BTreeSet::new();
+    for method in &class.methods {
+        for attribute in &method.attributes {
+            if class.attribute_name(attribute)? != "Code" {
+                continue;
+            }
+            let code = Code::parse(&attribute.info)?;
+            for instruction in bytecode::instructions(&code.code)? {
+                let needs_kind = match instruction.opcode {
+                    INVOKEVIRTUAL | INVOKEINTERFACE => true,
+                    // calling interface methods with those is new in 52
+                    INVOKESTATIC | INVOKESPECIAL => class.major_version < 52,
+                    _ => false,
+                };
+                if needs_kind {
+                    let pc = instruction.pc;
+                    stuck.insert(u16::from_be_bytes([code.code[pc + 1], code.code[pc + 2]]));
+                }
+            }
+        }
+    }
+    for constant in &class.constant_pool {
+        // same for the method handles of the kinds other than static/special
+        if let Constant::MethodHandle { kind, reference } = *constant {
+            if !matches!(kind, 6 | 7) || class.major_version < 52 {
+                stuck.insert(reference);
+            }
+        }
+    }
+
+    let mut changed = false;
+    for (i, owner, name_and_type, to_interface, member) in mismatched {
+        let (from, to) = match to_interface {
+            true => ("a method ref", "an interface method ref"),
+            false => ("an interface method ref", "a method ref"),
+        };
+        if stuck.contains(&i) {
+            log::warn!(
+                "{} in {} is {} but should be {}, and can't be changed without changing the code",
+                member,
+                filename,
+                from,
+                to
+            );
+            continue;
+        }
+        log::info!(
+            "Changing {} in {} from {} to {}",
+            member,
+            filename,
+            from,
+            to
+        );
+        class.constant_pool[i as usize] = match to_interface {
+            true => Constant::InterfaceMethodRef {
+                class: owner,
+                name_and_type,
+            },
+            false => Constant::MethodRef {
+                class: owner,
+                name_and_type,
+            },
+        };
+        changed = true;
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::class::{tests::sample, Attribute, Member};
+
+    /// The sample with an `int a_b` next to its `int a.b`
+    fn colliding() -> ClassFile {
+        let mut class = sample();
+        let name_index = class.add_utf8("a_b").unwrap();
+        let descriptor_index = class.add_utf8("I").unwrap();
+        class.fields.push(Member {
+            access_flags: 0,
+            name_index,
+            descriptor_index,
+            attributes: Vec::new(),
+        });
+        class
+    }
+
+    fn fix(class: &ClassFile, options: &FixOptions) -> Result<Option<ClassFile>> {
+        let fixed = fix_class(&class.to_bytes(), "Test.class", options, None)?;
+        fixed.map(|bytes| ClassFile::parse(&bytes)).transpose()
+    }
+
+    fn field_names(class: &ClassFile) -> Vec<String> {
+        let names = class.fields.iter().map(|f| class.utf8(f.name_index));
+        names.map(|name| name.unwrap().into_owned()).collect()
+    }
+
+    fn with_policy(on_collision: CollisionPolicy) -> FixOptions {
+        FixOptions {
+            on_collision,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn collisions_get_a_suffix() {
+        let fixed = fix(&colliding(), &with_policy(CollisionPolicy::Suffix))
+            .unwrap()
+            .unwrap();
+        assert_eq!(field_names(&fixed), ["a_b$fx1", "a_b"]);
+    }
+
+    #[test]
+    fn collisions_are_errors() {
+        let error = fix(&colliding(), &with_policy(CollisionPolicy::Error)).unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("--on-collision"),
+            "{:#}",
+            error
+        );
+    }
+
+    #[test]
+    fn collisions_are_skipped() {
+        let fixed = fix(&colliding(), &with_policy(CollisionPolicy::Skip)).unwrap();
+        assert!(fixed.is_none());
+    }
+
+    #[test]
+    fn attribute_names_are_fixed() {
+        let mut class = sample();
+        let u16s = |values: &[u16]| values.iter().flat_map(|v| v.to_be_bytes()).collect();
+        let int = class.add_utf8("I").unwrap();
+
+        let name = class.add_utf8("x.y").unwrap();
+        let descriptor = class.add_utf8("()V").unwrap();
+        class
+            .constant_pool
+            .push(Constant::NameAndType { name, descriptor });
+        let method = (class.constant_pool.len() - 1) as u16;
+        let name_index = class.add_utf8("EnclosingMethod").unwrap();
+        let info = u16s(&[class.this_class, method]);
+        class.attributes.push(Attribute { name_index, info });
+
+        let name_index = class.add_utf8("Record").unwrap();
+        let info = u16s(&[1, class.add_utf8("r.c").unwrap(), int, 0]);
+        class.attributes.push(Attribute { name_index, info });
+
+        let name_index = class.add_utf8("MethodParameters").unwrap();
+        let mut info = vec![1];
+        info.extend(u16s(&[class.add_utf8("p.q").unwrap(), 0]));
+        class.methods[0]
+            .attributes
+            .push(Attribute { name_index, info });
+
+        let mut code = Code::parse(&class.methods[0].attributes[0].info).unwrap();
+        let name_index = class.add_utf8("LocalVariableTable").unwrap();
+        let info = u16s(&[1, 0, 1, class.add_utf8("l.v").unwrap(), int, 0]);
+        code.attributes.push(Attribute { name_index, info });
+        class.methods[0].attributes[0].info = code.to_bytes();
+
+        let fixed = fix(&class, &FixOptions::default()).unwrap().unwrap();
+        let names: Vec<_> = (1..fixed.constant_pool.len() as u16)
+            .filter_map(|i| fixed.utf8(i).ok())
+            .collect();
+        for (bad, good) in [
+            ("x.y", "x_y"),
+            ("r.c", "r_c"),
+            ("p.q", "p_q"),
+            ("l.v", "l_v"),
+        ] {
+            assert!(!names.contains(&bad.into()), "{} is still there", bad);
+            assert!(names.contains(&good.into()), "{} is not there", good);
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Gitignore-style globs over `/`-separated paths: `*` and `?` don't match
+//! the slashes, `**` matches any number of directories, and `[...]` is a set
+//! of characters, negated with a leading `!` or `^`.
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+
+#[derive(Debug, Clone)]
+pub struct Glob {
+    glob: String,
+    regex: Regex,
+}
+
+impl Glob {
+    pub fn new(glob: &str) -> Result<Self> {
+        let mut regex = String::from("^");
+        let mut chars = glob.chars().peekable();
+        // whether the last thing was the start or a slash, for the `**`
+        let mut at_dir_start = true;
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    match chars.peek() {
+                        Some('/') if at_dir_start => {
+                            chars.next();
+                            regex.push_str("(?:.*/)?");
+                            continue;
+                        }
+                        None if at_dir_start => regex.push_str(".*"),
+                        // not a whole path component, so just a star
+                        _ => regex.push_str("[^/]*"),
+                    }
+                }
+                '*' => regex.push_str("[^/]*"),
+                '?' => regex.push_str("[^/]"),
+                '[' => {
+                    regex.push('[');
+                    if let Some('!' | '^') = chars.peek() {
+                        chars.next();
+                        regex.push('^');
+                    }
+                    let mut first = true;
+                    loop {
+                        match chars.next() {
+                            Some(']') if !first => break,
+                            Some('\\') => match chars.next() {
+                                Some(c) => regex.push_str(&regex::escape(&c.to_string())),
+                                None => bail!("The glob '{}' ends with a \\", glob),
+                            },
+                            Some(c @ ('[' | ']' | '^' | '&' | '~')) => {
+                                regex.push('\\');
+                                regex.push(c);
+                            }
+                            Some(c) => regex.push(c),
+                            None => bail!("Unclosed [ in the glob '{}'", glob),
+                        }
+                        first = false;
+                    }
+                    regex.push(']');
+                }
+                '\\' => match chars.next() {
+                    Some(c) => regex.push_str(&regex::escape(&c.to_string())),
+                    None => bail!("The glob '{}' ends with a \\", glob),
+                },
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            }
+            at_dir_start = c == '/';
+        }
+        regex.push('$');
+        let regex = Regex::new(&regex).with_context(|| format!("Bad glob '{}'", glob))?;
+        Ok(Self {
+            glob: glob.to_owned(),
+            regex,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.glob
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        self.regex.is_match(path)
+    }
+}
@@ -0,0 +1,73 @@
+//! Graphviz graphs of which classes depend on which, mostly for seeing what
+//! uses the members that get renamed.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+};
+
+use anyhow::Result;
+
+use crate::{fix, index::JarIndex};
+
+/// The inside of a DOT string literal
+fn escaped(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The edges from the classes to the classes declaring the members with bad
+/// names they use, labeled with those names. With `all`, any reference from
+/// one class in the jar to another is an edge
+pub fn dependencies(index: &JarIndex, all: bool) -> BTreeMap<(&str, &str), BTreeSet<&str>> {
+    let mut edges: BTreeMap<_, BTreeSet<_>> = BTreeMap::new();
+    for class in index.classes.values() {
+        for member_ref in &class.refs {
+            if fix::fixed_name(&member_ref.name).is_none() {
+                continue;
+            }
+            let owner = index
+                .resolve(member_ref)
+                .map_or(member_ref.owner.as_str(), |owner| owner.name.as_str());
+            if owner != class.name {
+                edges
+                    .entry((class.name.as_str(), owner))
+                    .or_default()
+                    .insert(member_ref.name.as_str());
+            }
+        }
+        if all {
+            for other in &class.class_refs {
+                if index.classes.contains_key(other) {
+                    edges
+                        .entry((class.name.as_str(), other.as_str()))
+                        .or_default();
+                }
+            }
+        }
+    }
+    edges
+}
+
+pub fn write_dot(
+    mut out: impl Write,
+    edges: &BTreeMap<(&str, &str), BTreeSet<&str>>,
+) -> Result<()> {
+    writeln!(out, "digraph dependencies {{")?;
+    writeln!(out, "    node [shape=box];")?;
+    for ((from, to), names) in edges {
+        let (from, to) = (escaped(from), escaped(to));
+        match names.is_empty() {
+            true => writeln!(out, "    \"{}\" -> \"{}\";", from, to)?,
+            false => {
+                let label = names
+                    .iter()
+                    .map(|name| escaped(name))
+                    .collect::<Vec<_>>()
+                    .join("\\n");
+                writeln!(out, "    \"{}\" -> \"{}\" [label=\"{}\"];", from, to, label)?
+            }
+        }
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
@@ -0,0 +1,63 @@
+//! Searching the UTF8 constants of all the classes in a jar, which is where
+//! all the names and the strings are.
+
+use std::io::{Read, Seek};
+
+use anyhow::Result;
+use regex::Regex;
+use zip::ZipArchive;
+
+use crate::{
+    class::{ClassFile, Constant},
+    limits::Limits,
+};
+
+#[derive(Debug)]
+pub struct Match {
+    /// The path of the class in the jar
+    pub class: String,
+    pub index: u16,
+    pub value: String,
+}
+
+pub fn grep_jar(input: impl Read + Seek, limits: &Limits, pattern: &Regex) -> Result<Vec<Match>> {
+    let mut zip = ZipArchive::new(input)?;
+    let mut matches = Vec::new();
+    let mut buf = Vec::new();
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        if !file.name().ends_with(".class") {
+            continue;
+        }
+        limits.check_time()?;
+        limits.prepare_buffer(&mut buf, file.size());
+        let class = limits
+            .read_class(&mut file, &mut buf)
+            .and_then(|_| ClassFile::parse(&buf));
+        let class = match class {
+            Ok(class) => class,
+            Err(e) => {
+                log::warn!("Skipping {}, could not read it: {:#}", file.name(), e);
+                continue;
+            }
+        };
+        for (index, constant) in class.constant_pool.iter().enumerate() {
+            if !matches!(constant, Constant::Utf8(_)) {
+                continue;
+            }
+            // the malformed ones can still be found by what's readable in them
+            let value = match class.utf8(index as u16) {
+                Ok(value) => value.into_owned(),
+                Err(_) => String::from_utf8_lossy(class.utf8_bytes(index as u16)?).into_owned(),
+            };
+            if pattern.is_match(&value) {
+                matches.push(Match {
+                    class: file.name().to_owned(),
+                    index: index as u16,
+                    value,
+                });
+            }
+        }
+    }
+    Ok(matches)
+}
@@ -0,0 +1,35 @@
+use std::{fs::File, io::Read, path::Path};
+
+use anyhow::{ensure, Result};
+use sha2::{Digest, Sha256};
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+pub fn sha256_file(path: &Path) -> Result<String> {
+    sha256_reader(File::open(path)?)
+}
+
+pub fn sha256_reader(mut input: impl Read) -> Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut input, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Checks that the file has the given SHA-256 hash
+pub fn verify_sha256(path: &Path, expected: &str) -> Result<()> {
+    let actual = sha256_file(path)?;
+    ensure!(
+        actual.eq_ignore_ascii_case(expected),
+        "SHA-256 mismatch for {}: expected {}, got {}",
+        path.display(),
+        expected,
+        actual
+    );
+    Ok(())
+}
@@ -0,0 +1,182 @@
+//! The messages for the players in their language: the reports of doctor,
+//! status and explain, and what fixing the game logs. The ones for the
+//! modders (check, info, diff and such) stay in English.
+//!
+//! The messages are in src/messages/*.ftl, in the simplest bit of Fluent:
+//! `key = text`, with `{ $name }` for the values, the lines starting with
+//! a space continuing the previous one. A message missing from a language
+//! is shown in English.
+
+use std::{collections::HashMap, fmt::Display, sync::OnceLock};
+
+use anyhow::{bail, Result};
+
+/// Like format!, with the message of the key in the language of the user
+#[macro_export]
+macro_rules! tr {
+    ($key:expr $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::i18n::message(
+            $key,
+            &[$((stringify!($name), &$value as &dyn std::fmt::Display)),*],
+        )
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    En,
+    Ru,
+}
+
+impl Lang {
+    fn parse(lang: &str) -> Option<Self> {
+        // like ru_RU.UTF-8 or en-US
+        match lang.get(..2)?.to_ascii_lowercase().as_str() {
+            "en" => Some(Self::En),
+            "ru" => Some(Self::Ru),
+            _ => None,
+        }
+    }
+
+    fn catalog(self) -> &'static HashMap<&'static str, String> {
+        static EN: OnceLock<HashMap<&str, String>> = OnceLock::new();
+        static RU: OnceLock<HashMap<&str, String>> = OnceLock::new();
+        match self {
+            Self::En => EN.get_or_init(|| parse(include_str!("messages/en.ftl"))),
+            Self::Ru => RU.get_or_init(|| parse(include_str!("messages/ru.ftl"))),
+        }
+    }
+}
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+/// Picks the language, the given one or the one of the system
+pub fn init(lang: Option<&str>) -> Result<()> {
+    let lang = match lang {
+        Some(lang) => match Lang::parse(lang) {
+            Some(lang) => lang,
+            None => bail!("Unknown language '{}', there's en and ru", lang),
+        },
+        None => detect().unwrap_or(Lang::En),
+    };
+    log::debug!("The messages are in {:?}", lang);
+    let _ = LANG.set(lang);
+    Ok(())
+}
+
+/// The first of the locale variables that's set decides, like gettext does
+#[cfg(not(windows))]
+fn detect() -> Option<Lang> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| Lang::parse(&value))
+}
+
+#[cfg(windows)]
+fn detect() -> Option<Lang> {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetUserDefaultUILanguage() -> u16;
+    }
+    // the primary language of the LANGID
+    match unsafe { GetUserDefaultUILanguage() } & 0x3ff {
+        0x09 => Some(Lang::En),
+        0x19 => Some(Lang::Ru),
+        _ => None,
+    }
+}
+
+/// The message with the values put in
+pub fn message(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let lang = *LANG.get().unwrap_or(&Lang::En);
+    let template = match lang
+        .catalog()
+        .get(key)
+        .or_else(|| Lang::En.catalog().get(key))
+    {
+        Some(template) => template,
+        // a bug, but not worth losing the message over
+        None => return key.to_owned(),
+    };
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template.as_str();
+    while let Some(start) = rest.find("{ $") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 3..];
+        let end = match after.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+        let name = after[..end].trim();
+        match args.iter().find(|(n, _)| *n == name) {
+            Some((_, value)) => out.push_str(&value.to_string()),
+            None => out.push_str(&rest[start..start + 3 + end + 1]),
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Whether the answer to a question is a yes, in any of the languages
+pub fn is_yes(answer: &str) -> bool {
+    matches!(
+        answer.trim().to_lowercase().as_str(),
+        "y" | "yes" | "д" | "да"
+    )
+}
+
+fn parse(catalog: &'static str) -> HashMap<&'static str, String> {
+    let mut messages: HashMap<&str, String> = HashMap::new();
+    let mut last: Option<&str> = None;
+    for line in catalog.lines() {
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        if line.starts_with(' ') {
+            if let Some(message) = last.and_then(|key| messages.get_mut(key)) {
+                if !message.is_empty() {
+                    message.push(' ');
+                }
+                message.push_str(line.trim());
+            }
+            continue;
+        }
+        if let Some((key, text)) = line.split_once('=') {
+            let key = key.trim();
+            messages.insert(key, text.trim().to_owned());
+            last = Some(key);
+        }
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translations_have_the_same_keys() {
+        let en = Lang::En.catalog();
+        let ru = Lang::Ru.catalog();
+        for key in ru.keys() {
+            assert!(en.contains_key(key), "{} is only in ru.ftl", key);
+        }
+        for key in en.keys() {
+            assert!(ru.contains_key(key), "{} is not translated", key);
+        }
+    }
+
+    #[test]
+    fn messages() {
+        assert_eq!(
+            message("fix-known-bad", &[("jar", &"a.jar"), ("version", &"0.98a")]),
+            "Your fixed a.jar does not match the known-good result for 0.98a, it could be broken"
+        );
+        // the values that aren't given are left as they are
+        assert_eq!(message("fix-processed", &[]), "Processed { $class }");
+        assert_eq!(message("no-such-key", &[]), "no-such-key");
+    }
+}
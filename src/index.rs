@@ -0,0 +1,295 @@
+//! A summary of every class in a jar, for the things that need to know about
+//! more than one class at a time.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{Read, Seek},
+};
+
+use anyhow::Result;
+use zip::ZipArchive;
+
+use crate::{
+    class::{ClassFile, Constant, UnknownTag},
+    limits::Limits,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RefKind {
+    Field,
+    Method,
+    InterfaceMethod,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MemberRef {
+    pub kind: RefKind,
+    pub owner: String,
+    pub name: String,
+    pub descriptor: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct MemberInfo {
+    pub access_flags: u16,
+    pub name: String,
+    pub descriptor: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClassInfo {
+    pub name: String,
+    pub access_flags: u16,
+    pub super_class: Option<String>,
+    pub interfaces: Vec<String>,
+    pub fields: Vec<MemberInfo>,
+    pub methods: Vec<MemberInfo>,
+    pub refs: BTreeSet<MemberRef>,
+    /// Every other class mentioned in the constant pool or the descriptors
+    pub class_refs: BTreeSet<String>,
+}
+
+impl ClassInfo {
+    pub fn from_class(class: &ClassFile) -> Result<Self> {
+        let class_name = |index: u16| -> Result<String> {
+            match class.constant(index)? {
+                Constant::Class(name) => Ok(class.utf8(*name)?.into_owned()),
+                _ => anyhow::bail!("Constant #{} is not a CLASS_INFO", index),
+            }
+        };
+
+        let name = class_name(class.this_class)?;
+        let super_class = match class.super_class {
+            0 => None,
+            index => Some(class_name(index)?),
+        };
+        let interfaces = class
+            .interfaces
+            .iter()
+            .map(|&i| class_name(i))
+            .collect::<Result<_>>()?;
+
+        let mut class_refs = BTreeSet::new();
+
+        let mut members = |members: &[crate::class::Member]| -> Result<Vec<MemberInfo>> {
+            members
+                .iter()
+                .map(|m| {
+                    let descriptor = class.utf8(m.descriptor_index)?.into_owned();
+                    class_refs.extend(descriptor_classes(&descriptor));
+                    Ok(MemberInfo {
+                        access_flags: m.access_flags,
+                        name: class.utf8(m.name_index)?.into_owned(),
+                        descriptor,
+                    })
+                })
+                .collect()
+        };
+        let fields = members(&class.fields)?;
+        let methods = members(&class.methods)?;
+
+        let mut refs = BTreeSet::new();
+        for constant in &class.constant_pool {
+            let (kind, owner, name_and_type) = match *constant {
+                Constant::Class(index) => {
+                    let referenced = class.utf8(index)?;
+                    // array classes are descriptors
+                    if referenced.starts_with('[') {
+                        class_refs.extend(descriptor_classes(&referenced));
+                    } else {
+                        class_refs.insert(referenced.into_owned());
+                    }
+                    continue;
+                }
+                Constant::FieldRef {
+                    class,
+                    name_and_type,
+                } => (RefKind::Field, class, name_and_type),
+                Constant::MethodRef {
+                    class,
+                    name_and_type,
+                } => (RefKind::Method, class, name_and_type),
+                Constant::InterfaceMethodRef {
+                    class,
+                    name_and_type,
+                } => (RefKind::InterfaceMethod, class, name_and_type),
+                _ => continue,
+            };
+            if let Constant::NameAndType { name, descriptor } = *class.constant(name_and_type)? {
+                let descriptor = class.utf8(descriptor)?.into_owned();
+                class_refs.extend(descriptor_classes(&descriptor));
+                refs.insert(MemberRef {
+                    kind,
+                    owner: class_name(owner)?,
+                    name: class.utf8(name)?.into_owned(),
+                    descriptor,
+                });
+            }
+        }
+        class_refs.remove(&name);
+
+        Ok(Self {
+            name,
+            access_flags: class.access_flags,
+            super_class,
+            interfaces,
+            fields,
+            methods,
+            refs,
+            class_refs,
+        })
+    }
+
+    /// The direct supertypes of the class
+    pub fn supertypes(&self) -> impl Iterator<Item = &str> {
+        self.super_class
+            .iter()
+            .chain(&self.interfaces)
+            .map(String::as_str)
+    }
+}
+
+/// The names of the classes mentioned in a field or method descriptor
+pub fn descriptor_classes(descriptor: &str) -> Vec<String> {
+    let mut classes = Vec::new();
+    let mut rest = descriptor;
+    // everything other than class names is single characters
+    while let Some(pos) = rest.find('L') {
+        match rest[pos + 1..].split_once(';') {
+            Some((name, tail)) => {
+                classes.push(name.to_owned());
+                rest = tail;
+            }
+            None => break,
+        }
+    }
+    classes
+}
+
+#[derive(Debug, Default)]
+pub struct JarIndex {
+    pub classes: BTreeMap<String, ClassInfo>,
+}
+
+impl JarIndex {
+    /// Indexes the classes in the jar, leaving out the ones with unknown
+    /// constant tags with `skip_unknown_tags`
+    pub fn from_jar(
+        input: impl Read + Seek,
+        limits: &Limits,
+        skip_unknown_tags: bool,
+    ) -> Result<Self> {
+        Self::read(input, limits, skip_unknown_tags, false)
+    }
+
+    /// Same, but only with the members of the classes, without the refs,
+    /// which is all the collisions need. The classes are read without the
+    /// code of their methods, so it's much cheaper
+    pub fn members_from_jar(
+        input: impl Read + Seek,
+        limits: &Limits,
+        skip_unknown_tags: bool,
+    ) -> Result<Self> {
+        Self::read(input, limits, skip_unknown_tags, true)
+    }
+
+    fn read(
+        input: impl Read + Seek,
+        limits: &Limits,
+        skip_unknown_tags: bool,
+        members_only: bool,
+    ) -> Result<Self> {
+        let mut zip = ZipArchive::new(input)?;
+        let mut index = Self::default();
+        let mut buf = Vec::new();
+
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i)?;
+            if !file.is_file() || !file.name().ends_with(".class") {
+                continue;
+            }
+            let info = match members_only {
+                true => {
+                    let max = limits.max_class_size.unwrap_or(u64::MAX);
+                    ClassFile::read_names((&mut file).take(max))
+                        .and_then(|class| ClassInfo::from_class(&class))
+                        .map(|info| ClassInfo {
+                            refs: BTreeSet::new(),
+                            class_refs: BTreeSet::new(),
+                            ..info
+                        })
+                }
+                false => {
+                    limits.prepare_buffer(&mut buf, file.size());
+                    limits
+                        .read_class(&mut file, &mut buf)
+                        .and_then(|_| limits.check_constant_pool(&buf))
+                        .and_then(|_| ClassFile::parse(&buf))
+                        .and_then(|class| ClassInfo::from_class(&class))
+                }
+            };
+            let info = match info {
+                Ok(info) => info,
+                Err(e) if skip_unknown_tags && UnknownTag::find(&e).is_some() => {
+                    log::debug!("Leaving {} out of the index: {:#}", file.name(), e);
+                    continue;
+                }
+                Err(e) => return Err(e.context(format!("Processing {}", file.name()))),
+            };
+            index.classes.insert(info.name.clone(), info);
+        }
+        Ok(index)
+    }
+
+    /// Finds the class declaring the referenced member, going up the
+    /// hierarchy the same way the VM resolves refs. `None` if it's not
+    /// declared anywhere in the jar
+    pub fn resolve(&self, member_ref: &MemberRef) -> Option<&ClassInfo> {
+        let mut queue = vec![member_ref.owner.as_str()];
+        let mut seen = BTreeSet::new();
+        while let Some(name) = queue.pop() {
+            if !seen.insert(name) {
+                continue;
+            }
+            let class = match self.classes.get(name) {
+                Some(class) => class,
+                None => continue,
+            };
+            let members = match member_ref.kind {
+                RefKind::Field => &class.fields,
+                _ => &class.methods,
+            };
+            if members
+                .iter()
+                .any(|m| m.name == member_ref.name && m.descriptor == member_ref.descriptor)
+            {
+                return Some(class);
+            }
+            queue.extend(class.supertypes());
+        }
+        None
+    }
+
+    /// All of the supertypes of the class, direct or not, that are in the jar,
+    /// and whether there were some that were not
+    pub fn ancestors(&self, class: &ClassInfo) -> (Vec<&ClassInfo>, bool) {
+        let mut result = Vec::new();
+        let mut external = false;
+        let mut queue: Vec<&str> = class.supertypes().collect();
+        let mut seen = BTreeSet::new();
+        while let Some(name) = queue.pop() {
+            if !seen.insert(name) {
+                continue;
+            }
+            match self.classes.get(name) {
+                Some(ancestor) => {
+                    queue.extend(ancestor.supertypes());
+                    result.push(ancestor);
+                }
+                None if name != "java/lang/Object" => external = true,
+                None => {}
+            }
+        }
+        (result, external)
+    }
+}
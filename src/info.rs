@@ -0,0 +1,105 @@
+//! An overview of what's in a jar, to tell what it needs before fixing it.
+
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek},
+};
+
+use anyhow::Result;
+use zip::{CompressionMethod, ZipArchive};
+
+use crate::{
+    class::ClassFile,
+    fingerprint::{Detector, Guess},
+    fix, jar,
+    limits::Limits,
+    manifest,
+};
+
+#[derive(Debug, Default)]
+pub struct JarInfo {
+    pub entries: usize,
+    pub directories: usize,
+    pub classes: usize,
+    /// The classes that could not be read, by their path in the jar
+    pub unreadable: Vec<String>,
+    /// How many classes have each (major, minor) version
+    pub versions: BTreeMap<(u16, u16), usize>,
+    /// How many entries are stored with each compression method
+    pub compression: BTreeMap<String, usize>,
+    /// The signature files in META-INF, if the jar is signed
+    pub signatures: Vec<String>,
+    /// The archives inside of the jar
+    pub nested: Vec<String>,
+    pub bad_names: usize,
+    pub classes_with_bad_names: usize,
+    pub obfuscators: Vec<Guess>,
+    /// The version of the fixer, if the manifest says the jar was fixed
+    pub fixed_with: Option<String>,
+}
+
+pub fn jar_info(input: impl Read + Seek, limits: &Limits) -> Result<JarInfo> {
+    let mut zip = ZipArchive::new(input)?;
+    let mut info = JarInfo::default();
+    let mut buf = Vec::new();
+    let mut detector = Detector::default();
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        info.entries += 1;
+        if file.is_dir() {
+            info.directories += 1;
+            continue;
+        }
+        let method = match file.compression() {
+            CompressionMethod::Stored => "stored".to_owned(),
+            CompressionMethod::Deflated => "deflated".to_owned(),
+            other => format!("{:?}", other).to_ascii_lowercase(),
+        };
+        *info.compression.entry(method).or_default() += 1;
+
+        let name = file.name().to_owned();
+        if jar::is_signature(&name) {
+            info.signatures.push(name);
+            continue;
+        }
+        let lowercase = name.to_ascii_lowercase();
+        if [".jar", ".zip"].iter().any(|ext| lowercase.ends_with(ext)) {
+            info.nested.push(name);
+            continue;
+        }
+        limits.prepare_buffer(&mut buf, file.size());
+        if name == manifest::PATH {
+            limits.read_class(&mut file, &mut buf)?;
+            detector.add_manifest(&buf);
+            info.fixed_with = manifest::fixed_with(&buf);
+            continue;
+        }
+        if !name.ends_with(".class") {
+            continue;
+        }
+        info.classes += 1;
+        let class = limits
+            .read_class(&mut file, &mut buf)
+            .and_then(|_| ClassFile::parse(&buf));
+        let bad = class.and_then(|class| {
+            detector.add_class(&class)?;
+            let bad = fix::bad_names(&class, false)?.len();
+            Ok((class.major_version, class.minor_version, bad))
+        });
+        let (major, minor, bad) = match bad {
+            Ok(result) => result,
+            Err(e) => {
+                log::debug!("Could not read {}: {:#}", name, e);
+                info.unreadable.push(name);
+                continue;
+            }
+        };
+        *info.versions.entry((major, minor)).or_default() += 1;
+        if bad != 0 {
+            info.bad_names += bad;
+            info.classes_with_bad_names += 1;
+        }
+    }
+    info.obfuscators = detector.guesses();
+    Ok(info)
+}
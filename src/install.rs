@@ -0,0 +1,73 @@
+//! Finding the installs of the game without being told where they are, in
+//! the places the installers and the players usually put them.
+
+use std::path::{Path, PathBuf};
+
+use crate::{dirs, paths, profile::Profile};
+
+/// The directories the game could be in on this platform, the current one
+/// and the one with the fixer first, as it's often dropped into the game
+fn candidates() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    dirs.extend(std::env::current_dir().ok());
+    if let Some(exe_dir) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_owned))
+    {
+        // the exe can also be in starsector-core or such
+        dirs.extend(exe_dir.parent().map(Path::to_owned));
+        dirs.push(exe_dir);
+    }
+    let home = dirs::home();
+    if cfg!(windows) {
+        for var in ["ProgramFiles(x86)", "ProgramFiles"] {
+            if let Some(dir) = std::env::var_os(var) {
+                dirs.push(
+                    PathBuf::from(dir)
+                        .join("Fractal Softworks")
+                        .join("Starsector"),
+                );
+            }
+        }
+        dirs.extend(
+            home.iter()
+                .map(|home| home.join("Games").join("Starsector")),
+        );
+        dirs.push(r"C:\Games\Starsector".into());
+    } else if cfg!(target_os = "macos") {
+        dirs.push("/Applications/Starsector.app".into());
+        dirs.extend(
+            home.iter()
+                .map(|home| home.join("Applications/Starsector.app")),
+        );
+    } else {
+        for dir in [
+            "starsector",
+            "Starsector",
+            "Games/starsector",
+            "Games/Starsector",
+            ".local/share/starsector",
+        ] {
+            dirs.extend(home.iter().map(|home| home.join(dir)));
+        }
+        dirs.push("/opt/starsector".into());
+        dirs.push("/usr/local/games/starsector".into());
+    }
+    dirs
+}
+
+/// The installs of the game in the usual places, each one once
+pub fn find(profile: &Profile) -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = Vec::new();
+    for dir in candidates() {
+        log::debug!("Looking for {} in {}", profile.name, dir.display());
+        if !matches!(profile.find_launcher(&dir), Ok(Some(_))) {
+            continue;
+        }
+        let dir = paths::absolute(&dir).unwrap_or(dir);
+        if !found.iter().any(|other| paths::same_file(other, &dir)) {
+            found.push(dir);
+        }
+    }
+    found
+}
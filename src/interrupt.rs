@@ -0,0 +1,137 @@
+//! Ctrl-C and the like. With nothing being written the program just stops,
+//! as it would anyway. Otherwise it stops at the next class or entry, with
+//! the temp files removed and the locks released on the way out, so that
+//! the original jar and its backup stay as they were. A file that is past
+//! the point of being written is still put in place, the journal having
+//! already been told about it. A second Ctrl-C stops it right away.
+
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+
+use anyhow::{bail, Result};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static EXIT_CODE: AtomicI32 = AtomicI32::new(130);
+/// How many of the things that have to be cleaned up there are
+static WORKING: AtomicUsize = AtomicUsize::new(0);
+
+/// Held by the temp files and the locks, the program is not stopped until
+/// the last one is dropped
+#[derive(Debug)]
+pub struct Working(());
+
+pub fn working() -> Working {
+    WORKING.fetch_add(1, Ordering::SeqCst);
+    Working(())
+}
+
+impl Drop for Working {
+    fn drop(&mut self) {
+        // the other way around from the handler, so that one of the two
+        // always sees what the other did
+        if WORKING.fetch_sub(1, Ordering::SeqCst) == 1 && INTERRUPTED.load(Ordering::SeqCst) {
+            log::warn!("Interrupted, stopped without leaving anything half-written");
+            std::process::exit(EXIT_CODE.load(Ordering::SeqCst));
+        }
+    }
+}
+
+/// Fails once the program got interrupted, for the work to stop and clean up
+pub fn check() -> Result<()> {
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        bail!("Interrupted");
+    }
+    Ok(())
+}
+
+/// Called from the signal handler, so only the async-signal-safe things
+fn interrupted(exit_code: i32) {
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+        imp::exit_now(exit_code);
+    }
+    EXIT_CODE.store(exit_code, Ordering::SeqCst);
+    if WORKING.load(Ordering::SeqCst) == 0 {
+        imp::exit_now(exit_code);
+    }
+    imp::say("Stopping after cleaning up, interrupt again to stop right away\n");
+}
+
+pub fn install() {
+    if let Err(e) = imp::install() {
+        log::debug!("Could not handle the interrupts: {}", e);
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::io;
+
+    extern "C" fn on_signal(signal: libc::c_int) {
+        super::interrupted(128 + signal);
+    }
+
+    pub fn install() -> io::Result<()> {
+        for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as usize;
+                action.sa_flags = libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+                if libc::sigaction(signal, &action, std::ptr::null_mut()) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn say(message: &str) {
+        unsafe { libc::write(2, message.as_ptr().cast(), message.len()) };
+    }
+
+    pub fn exit_now(code: i32) -> ! {
+        unsafe { libc::_exit(code) }
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::{io, time::Duration};
+
+    const CTRL_CLOSE_EVENT: u32 = 2;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleCtrlHandler(
+            handler: Option<unsafe extern "system" fn(u32) -> i32>,
+            add: i32,
+        ) -> i32;
+    }
+
+    /// Runs on a thread of its own, so anything goes here
+    unsafe extern "system" fn on_event(event: u32) -> i32 {
+        super::interrupted(130);
+        // the process is killed as soon as this returns for these, so it
+        // waits for the work to clean up and exit instead
+        if event >= CTRL_CLOSE_EVENT {
+            loop {
+                std::thread::sleep(Duration::from_secs(1));
+            }
+        }
+        1
+    }
+
+    pub fn install() -> io::Result<()> {
+        match unsafe { SetConsoleCtrlHandler(Some(on_event), 1) } {
+            0 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn say(message: &str) {
+        eprint!("{}", message);
+    }
+
+    pub fn exit_now(code: i32) -> ! {
+        std::process::exit(code)
+    }
+}
@@ -0,0 +1,993 @@
+//! Fixing the jars, and the tarballs full of them, entry by entry.
+
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+};
+
+use anyhow::{anyhow, Context, Result};
+use zip::{write::FileOptions, ZipArchive, ZipWriter};
+
+use crate::{
+    class::{self, ClassFile},
+    fix::{self, CollisionPolicy, FixOptions, TrailingGarbage, UnknownTagPolicy},
+    hash, index, interrupt, json, manifest,
+    memory::{Reservation, Spool},
+    raw_names::{self, RawNames},
+    report, tar, unfix,
+};
+
+/// What fixing a jar did to it
+#[derive(Debug, Clone, Default)]
+pub struct FixReport {
+    /// Whether a new jar was written, as opposed to the original copied
+    pub changed: bool,
+    /// The paths of the fixed classes in the jar
+    pub classes: Vec<String>,
+}
+
+/// What the scan of a jar found needs to be changed in it
+pub struct JarFixes {
+    /// The fixed classes, by the index of their entry
+    classes: BTreeMap<usize, Fixed>,
+    /// Where the fixed classes are with --low-memory
+    spill: Option<Spool>,
+    /// The fixed archives inside of the jar, with --recurse-archives
+    nested: BTreeMap<usize, Spool>,
+    /// The paths of the fixed classes in the jar
+    pub names: Vec<String>,
+    /// What goes after the end of the new jar
+    trailing: Vec<u8>,
+    original_sha256: Option<String>,
+    pub report: Option<json::Value>,
+    /// What the original had, with the provenance
+    unfix: Option<unfix::Original>,
+    /// The signature files to remove, which the fixed classes would break
+    signatures: Vec<String>,
+    /// Held for the fixed classes until they are written
+    _memory: Vec<Reservation>,
+}
+
+/// A fixed class, waiting for the new jar to be written
+enum Fixed {
+    Memory(Vec<u8>),
+    /// Where it is in the spill file
+    Spilled {
+        start: u64,
+        len: u64,
+    },
+}
+
+/// Fixes the jar, or all of the jars inside of a tarball, returning whether
+/// anything was changed
+pub fn fix_file(
+    input: &Path,
+    output: impl Read + Write + Seek,
+    options: &FixOptions,
+) -> Result<bool> {
+    let file = File::open(input).with_context(|| format!("Reading archive {}", input.display()))?;
+
+    let compression = match tar::Compression::detect(input) {
+        Some(compression) => compression,
+        None => return fix_jar(options.reader(file), output, options).map(|report| report.changed),
+    };
+
+    let mut writer = compression.writer(options.writer(output))?;
+    let changed = tar::fix_tarball(
+        compression.reader(options.reader(file))?,
+        &mut writer,
+        &options.memory,
+        |name, jar| {
+            log::info!("Fixing {} in {}", name, input.display());
+            options.limits.check_time()?;
+            let fixes = match scan_jar(&mut *jar, options, 1)? {
+                Some(fixes) => fixes,
+                None => return Ok(None),
+            };
+            let mut fixed = Spool::new(&options.memory, jar.len()?)?;
+            write_jar(jar, &mut fixed, options, fixes)?;
+            Ok(Some(fixed))
+        },
+    )?;
+    writer.finish()?.flush()?;
+    Ok(changed)
+}
+
+/// Fixes the jar, copying it as is if there is nothing to fix
+pub fn fix_jar(
+    mut input: impl Read + Seek,
+    mut output: impl Read + Write + Seek,
+    options: &FixOptions,
+) -> Result<FixReport> {
+    match scan_jar(&mut input, options, 0)? {
+        Some(fixes) => {
+            let classes = fixes.names.clone();
+            write_jar(input, output, options, fixes)?;
+            Ok(FixReport {
+                changed: true,
+                classes,
+            })
+        }
+        None => {
+            io::copy(&mut input, &mut output)?;
+            output.flush()?;
+            Ok(FixReport::default())
+        }
+    }
+}
+
+/// Goes through the jar, which is `depth` archives deep inside of the input,
+/// fixing everything in memory, but only the classes that need it are kept.
+/// Returns `None` if there is nothing to write a new jar for, and leaves the
+/// input at the start either way
+pub fn scan_jar(
+    mut input: impl Read + Seek,
+    options: &FixOptions,
+    depth: usize,
+) -> Result<Option<JarFixes>> {
+    options.limits.check_depth(depth)?;
+
+    // every jar, even the ones in a tarball, gets its own report
+    let with_renames;
+    let options = match options.embed_report {
+        true => {
+            with_renames = FixOptions {
+                renames: Some(Default::default()),
+                ..options.clone()
+            };
+            &with_renames
+        }
+        false => options,
+    };
+
+    // only built for the passes that need it, since it's a whole extra read,
+    // the collisions being found in the whole jar for the refs to the
+    // renamed members to be renamed the same way. The collisions only need
+    // the names of the members, so the refs are only read for the passes
+    // that check them
+    let full_index = options.repair_ref_kinds || options.verify_refs;
+    let index = if full_index || options.on_collision != CollisionPolicy::Error {
+        let skip_unknown_tags = options.unknown_tags == UnknownTagPolicy::Skip;
+        let index = match full_index {
+            true => index::JarIndex::from_jar(&mut input, &options.limits, skip_unknown_tags)?,
+            false => {
+                index::JarIndex::members_from_jar(&mut input, &options.limits, skip_unknown_tags)?
+            }
+        };
+        input.rewind()?;
+        Some(index)
+    } else {
+        None
+    };
+    // nothing can collide if nothing is renamed
+    let index = index.filter(|index| {
+        full_index
+            || index
+                .classes
+                .values()
+                .any(|class| fix::renames_any_member(class, options))
+    });
+    let with_collisions;
+    let options = match &index {
+        Some(index) if options.collisions.is_none() => {
+            let collisions = fix::find_collisions(index.classes.values(), options)?;
+            with_collisions = FixOptions {
+                collisions: Some(Arc::new(collisions)),
+                ..options.clone()
+            };
+            &with_collisions
+        }
+        _ => options,
+    };
+
+    let mut trailing = trailing_garbage(&mut input)?;
+    let mut stripped = None;
+    let mut changed = false;
+    if !trailing.is_empty() {
+        log::warn!(
+            "{} bytes of garbage after the end of the jar, {}",
+            trailing.len(),
+            match options.trailing_garbage {
+                TrailingGarbage::Preserve => "keeping them",
+                TrailingGarbage::Strip => "removing them",
+            }
+        );
+        if options.trailing_garbage == TrailingGarbage::Strip {
+            stripped = Some(std::mem::take(&mut trailing));
+            changed = true;
+        }
+    }
+
+    let mut zip = ZipArchive::new(&mut input)?;
+    let mut scanned = Scanned {
+        unfix: match options.provenance {
+            // the one from fixing it before knows what the original was
+            true => Some(match zip.by_name(unfix::PATH) {
+                Ok(mut file) => {
+                    let mut text = String::new();
+                    // it comes with the input, so a broken one is as good as none
+                    match file
+                        .read_to_string(&mut text)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|_| unfix::Original::parse(&text))
+                    {
+                        Ok(original) => original,
+                        Err(e) => {
+                            log::warn!("Ignoring {}: {:#}", unfix::PATH, e);
+                            unfix::Original::default()
+                        }
+                    }
+                }
+                Err(_) => unfix::Original::default(),
+            }),
+            false => None,
+        },
+        ..Scanned::default()
+    };
+    if let (Some(unfix), Some(stripped)) = (&mut scanned.unfix, stripped) {
+        unfix.trailing.get_or_insert(stripped);
+    }
+    let mut signatures = zip
+        .file_names()
+        .filter(|name| is_signature(name))
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    signatures.sort();
+    // the classes are read one by one, but fixed a batch at a time, by as
+    // many threads as there are for it
+    let max_batch = match options.jobs {
+        Some(_) => MAX_BATCH,
+        None => 1,
+    };
+    let mut batch = Vec::new();
+    let mut batch_bytes = 0;
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        if options.recurse_archives && file.is_file() && is_archive(file.name()) {
+            scanned.fix_nested(i, &mut file, options, depth)?;
+            continue;
+        }
+        if !file.is_file() || !file.name().ends_with(".class") || !options.is_included(file.name())
+        {
+            continue;
+        }
+        // what the logs and the report call it
+        let name = raw_names::display_name(&file);
+        options.limits.check_time()?;
+        // most of the classes having nothing to fix, that's found out first
+        // without having the whole class in memory
+        let mut file = match options.low_memory && options.only_renames() {
+            true => {
+                let max = options.limits.max_class_size.unwrap_or(u64::MAX);
+                let renames = ClassFile::read_names((&mut file).take(max))
+                    .and_then(|class| fix::renames_anything(&class, options));
+                // the fixing is what tells about the broken ones
+                if let Ok(false) = renames {
+                    log::debug!("Nothing to rename in {}", name);
+                    continue;
+                }
+                drop(file);
+                zip.by_index(i)?
+            }
+            false => file,
+        };
+        let mut buf = match scanned.buffers.pop() {
+            Some(buf) => buf,
+            None => Buffer {
+                bytes: Vec::new(),
+                memory: options.memory.reserve(0, &name)?,
+            },
+        };
+        // the class and what it's fixed into, as big as the jar says until
+        // it's read
+        let size = file.size().saturating_mul(2);
+        let size = size.max(buf.bytes.capacity() as u64);
+        match options.memory.resize(&mut buf.memory, size, &name) {
+            // what's waiting in the batch can make room for it, as long as
+            // its buffers are not kept around
+            Err(_) if !batch.is_empty() => {
+                scanned.fix_batch(&mut batch, options, index.as_ref())?;
+                scanned.buffers.clear();
+                batch_bytes = 0;
+                options.memory.resize(&mut buf.memory, size, &name)?
+            }
+            resized => resized?,
+        }
+        options.limits.prepare_buffer(&mut buf.bytes, file.size());
+        options
+            .limits
+            .read_class(&mut file, &mut buf.bytes)
+            .and_then(|_| options.limits.check_constant_pool(&buf.bytes))
+            .with_context(|| format!("Reading {}", name))?;
+        // what it really takes, now that it's read
+        let size = buf.bytes.capacity() + buf.bytes.len();
+        options.memory.resize(&mut buf.memory, size as u64, &name)?;
+        batch_bytes += buf.bytes.len();
+        batch.push(Pending {
+            index: i,
+            path: file.name().to_owned(),
+            name,
+            bytecode: buf,
+        });
+        if batch.len() >= max_batch || batch_bytes >= MAX_BATCH_BYTES {
+            scanned.fix_batch(&mut batch, options, index.as_ref())?;
+            batch_bytes = 0;
+        }
+    }
+    scanned.fix_batch(&mut batch, options, index.as_ref())?;
+    drop(zip);
+    let Scanned {
+        classes,
+        spill,
+        nested,
+        names,
+        skipped,
+        memory,
+        unfix,
+        ..
+    } = scanned;
+
+    // the signatures are only broken by the changed classes and archives,
+    // the entries staying the same otherwise
+    if classes.is_empty() && nested.is_empty() {
+        signatures.clear();
+    }
+    if !signatures.is_empty() {
+        let files = signatures.join(", ");
+        match options.keep_signatures {
+            true => {
+                log::warn!("{}", tr!("fix-signed-kept", files = files));
+                signatures.clear();
+            }
+            false => log::warn!("{}", tr!("fix-signed", files = files)),
+        }
+    }
+
+    // the renames are only collected for the report, even if it's not going
+    // to be embedded
+    let report = options.renames.as_ref().map(|renames| {
+        let renames = renames.lock().unwrap_or_else(PoisonError::into_inner);
+        report::build(options, &renames, &skipped)
+    });
+    // the report is only for the jars that change, a clean one is left as is
+    if !changed && classes.is_empty() && nested.is_empty() {
+        input.rewind()?;
+        return Ok(None);
+    }
+
+    let original_sha256 = match options.provenance {
+        true => Some(hash::sha256_reader(&mut input)?),
+        false => None,
+    };
+    input.rewind()?;
+    Ok(Some(JarFixes {
+        classes,
+        spill,
+        nested,
+        names,
+        trailing,
+        original_sha256,
+        report,
+        unfix,
+        signatures,
+        _memory: memory,
+    }))
+}
+
+/// The most classes fixed at a time, and the most bytes of them
+const MAX_BATCH: usize = 256;
+const MAX_BATCH_BYTES: usize = 32 * 1024 * 1024;
+
+/// A class read out of the jar, waiting for the rest of its batch
+struct Pending {
+    index: usize,
+    path: String,
+    name: String,
+    /// With the memory for what it's fixed into
+    bytecode: Buffer,
+}
+
+/// A buffer to read the classes into, with its memory taken from the budget
+/// for as long as it's kept around
+struct Buffer {
+    bytes: Vec<u8>,
+    memory: Reservation,
+}
+
+/// What the scan found so far
+#[derive(Default)]
+struct Scanned {
+    classes: BTreeMap<usize, Fixed>,
+    spill: Option<Spool>,
+    nested: BTreeMap<usize, Spool>,
+    names: Vec<String>,
+    skipped: Vec<report::Skipped>,
+    memory: Vec<Reservation>,
+    unfix: Option<unfix::Original>,
+    /// The buffers of the fixed batches, to read the next ones into
+    buffers: Vec<Buffer>,
+}
+
+impl Scanned {
+    /// Fixes the jar (or the zip) in the jar, with everything in it
+    fn fix_nested(
+        &mut self,
+        index: usize,
+        file: &mut zip::read::ZipFile,
+        options: &FixOptions,
+        depth: usize,
+    ) -> Result<()> {
+        let name = raw_names::display_name(file);
+        options.limits.check_time()?;
+        // not trusting the size, deflate can't do better than about 1032:1
+        let size = file.size().min(file.compressed_size().saturating_mul(1032));
+        let mut original = Spool::new(&options.memory, size)?;
+        io::copy(&mut file.take(size), &mut original)
+            .with_context(|| format!("Reading {}", name))?;
+        original.rewind()?;
+
+        log::debug!("Checking the archive {}", name);
+        // what the original was is kept whole by the jar it's in
+        let options = &FixOptions {
+            provenance: false,
+            ..options.clone()
+        };
+        let result = scan_jar(&mut original, options, depth + 1).and_then(|fixes| {
+            let fixes = match fixes {
+                Some(fixes) => fixes,
+                None => return Ok(None),
+            };
+            let mut fixed = Spool::new(&options.memory, size)?;
+            write_jar(&mut original, &mut fixed, options, fixes)?;
+            Ok(Some(fixed))
+        });
+        let fixed = match result.with_context(|| format!("Processing {}", name)) {
+            Ok(Some(fixed)) => fixed,
+            Ok(None) => return Ok(()),
+            Err(e) if options.lenient => {
+                log::warn!("{:#}, copying it as is", e);
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        log::info!("{}", tr!("fix-processed", class = name));
+        if let Some(unfix) = &mut self.unfix {
+            unfix.nested.insert(file.name().to_owned());
+        }
+        self.nested.insert(index, fixed);
+        Ok(())
+    }
+
+    /// Fixes the classes of the batch, and empties it
+    fn fix_batch(
+        &mut self,
+        batch: &mut Vec<Pending>,
+        options: &FixOptions,
+        index: Option<&index::JarIndex>,
+    ) -> Result<()> {
+        let results = fix_classes(batch, options, index);
+        for (pending, result) in batch.drain(..).zip(results) {
+            self.add(pending, result, options)?;
+        }
+        Ok(())
+    }
+
+    fn add(
+        &mut self,
+        pending: Pending,
+        result: Result<Option<Vec<u8>>>,
+        options: &FixOptions,
+    ) -> Result<()> {
+        let name = pending.name;
+        if let (Err(_), Some(failed)) = (&result, &options.failed_classes) {
+            let mut failed = failed.lock().unwrap_or_else(PoisonError::into_inner);
+            failed.push((name.clone(), pending.bytecode.bytes.clone()));
+        }
+        let unknown_tag = result.as_ref().err().and_then(class::UnknownTag::find);
+        let fixed = match (result, unknown_tag) {
+            (Ok(fixed), _) => fixed,
+            (Err(e), Some(unknown)) if options.unknown_tags == UnknownTagPolicy::Skip => {
+                log::warn!("{:#}, copying it as is. Please report it!", e);
+                self.skipped.push(report::Skipped {
+                    class: name.clone(),
+                    tag: unknown.tag,
+                    offset: unknown.offset,
+                });
+                None
+            }
+            (Err(e), _) if options.lenient => {
+                log::warn!("{:#}, copying it as is", e);
+                None
+            }
+            (Err(e), _) => return Err(e),
+        };
+        if let Some(updated_bytecode) = fixed {
+            log::info!("{}", tr!("fix-processed", class = name));
+            if let Some(unfix) = &mut self.unfix {
+                unfix.record(&pending.path, &pending.bytecode.bytes, &updated_bytecode)?;
+            }
+            let fixed = match options.low_memory {
+                true => {
+                    let spill = match &mut self.spill {
+                        Some(spill) => spill,
+                        spill @ None => spill.insert(Spool::file()?),
+                    };
+                    let start = spill.seek(SeekFrom::End(0))?;
+                    spill.write_all(&updated_bytecode)?;
+                    Fixed::Spilled {
+                        start,
+                        len: updated_bytecode.len() as u64,
+                    }
+                }
+                false => {
+                    self.memory.push(
+                        options
+                            .memory
+                            .reserve(updated_bytecode.len() as u64, &name)?,
+                    );
+                    Fixed::Memory(updated_bytecode)
+                }
+            };
+            self.classes.insert(pending.index, fixed);
+            self.names.push(pending.path);
+        }
+        // the fixed one has its own memory now, if it's kept at all
+        let mut buf = pending.bytecode;
+        let capacity = buf.bytes.capacity() as u64;
+        options.memory.resize(&mut buf.memory, capacity, &name)?;
+        self.buffers.push(buf);
+        Ok(())
+    }
+}
+
+/// Fixes the classes on as many threads as there are free, the results
+/// being in the same order
+fn fix_classes(
+    batch: &[Pending],
+    options: &FixOptions,
+    index: Option<&index::JarIndex>,
+) -> Vec<Result<Option<Vec<u8>>>> {
+    let fix = |pending: &Pending| {
+        log::debug!("Checking {}", pending.name);
+        // a bug with one weird class should not lose the whole run
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            fix::fix_class(&pending.bytecode.bytes, &pending.name, options, index)
+        }))
+        .unwrap_or_else(|panic| Err(anyhow!("Panicked: {}", panic_message(&*panic))))
+        .with_context(|| format!("Processing {}", pending.name))
+    };
+    let helpers = match &options.jobs {
+        Some(jobs) => jobs.take_up_to(batch.len().saturating_sub(1)),
+        None => Vec::new(),
+    };
+    if helpers.is_empty() {
+        return batch.iter().map(fix).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results = batch.iter().map(|_| Mutex::new(None)).collect::<Vec<_>>();
+    let work = || loop {
+        let i = next.fetch_add(1, Ordering::Relaxed);
+        match batch.get(i) {
+            Some(pending) => {
+                *results[i].lock().unwrap_or_else(PoisonError::into_inner) = Some(fix(pending))
+            }
+            None => break,
+        }
+    };
+    std::thread::scope(|scope| {
+        for _ in &helpers {
+            scope.spawn(work);
+        }
+        work();
+    });
+    results
+        .into_iter()
+        .map(|result| {
+            result
+                .into_inner()
+                .unwrap_or_else(PoisonError::into_inner)
+                .expect("every class has a result")
+        })
+        .collect()
+}
+
+/// Writes the new jar with what the scan found, copying the rest of the
+/// entries without recompressing them
+pub fn write_jar(
+    input: impl Read + Seek,
+    mut output: impl Read + Write + Seek,
+    options: &FixOptions,
+    mut fixes: JarFixes,
+) -> Result<()> {
+    let mut writer = ZipWriter::new(options.writer(&mut output));
+    let mut zip = ZipArchive::new(input)?;
+    let mut raw_names = RawNames::default();
+    let mut written = 0;
+
+    // the manifest is expected to be one of the first entries
+    if let Some(original) = &fixes.original_sha256 {
+        if zip.by_name(manifest::PATH).is_err() {
+            // not the current time, so that the same input gives the same jar
+            let options = FileOptions::default().last_modified_time(Default::default());
+            writer.start_file(manifest::PATH, options)?;
+            writer.write_all(&manifest::with_provenance(None, original))?;
+            written += 1;
+            if let Some(unfix) = &mut fixes.unfix {
+                unfix.record_manifest(None);
+            }
+        }
+    }
+
+    for i in 0..zip.len() {
+        options.limits.check_time()?;
+        let mut file = zip.by_index(i)?;
+        // the one from the last time it was fixed
+        if fixes.report.is_some() && file.name() == report::PATH {
+            continue;
+        }
+        if fixes.unfix.is_some() && file.name() == unfix::PATH {
+            continue;
+        }
+        if fixes.signatures.iter().any(|name| name == file.name()) {
+            if let Some(unfix) = &mut fixes.unfix {
+                let mut buf = Vec::new();
+                options
+                    .limits
+                    .read_class(&mut file, &mut buf)
+                    .with_context(|| format!("Reading {}", file.name()))?;
+                unfix.removed.insert(file.name().to_owned(), buf);
+            }
+            continue;
+        }
+        let index = written;
+        written += 1;
+        let rewrites_manifest = fixes.original_sha256.is_some() || !fixes.signatures.is_empty();
+        if rewrites_manifest && file.name() == manifest::PATH {
+            let mut buf = Vec::new();
+            options
+                .limits
+                .read_class(&mut file, &mut buf)
+                .with_context(|| format!("Reading {}", manifest::PATH))?;
+            let mut manifest = match fixes.signatures.is_empty() {
+                true => buf.clone(),
+                false => manifest::without_digests(&buf),
+            };
+            if let Some(original) = &fixes.original_sha256 {
+                manifest = manifest::with_provenance(Some(&manifest), original);
+            }
+            writer.start_file(manifest::PATH, entry_options(&file))?;
+            writer.write_all(&manifest)?;
+            if let Some(unfix) = &mut fixes.unfix {
+                unfix.record_manifest(Some(&buf));
+            }
+            continue;
+        }
+        if let Some(class) = fixes.classes.remove(&i) {
+            let name = raw_names.name_for(&file, index);
+            writer.start_file(name, entry_options(&file))?;
+            match class {
+                Fixed::Memory(bytes) => writer.write_all(&bytes)?,
+                Fixed::Spilled { start, len } => {
+                    let spill = fixes.spill.as_mut().expect("spilled with the spill file");
+                    spill.seek(SeekFrom::Start(start))?;
+                    io::copy(&mut Read::by_ref(spill).take(len), &mut writer)?;
+                }
+            }
+        } else if let Some(mut nested) = fixes.nested.remove(&i) {
+            let name = raw_names.name_for(&file, index);
+            // the fixed one growing past what the plain zips can have
+            let size = nested.len()?.max(file.size()).max(file.compressed_size());
+            writer.start_file(
+                name,
+                entry_options(&file).large_file(size > u32::MAX as u64),
+            )?;
+            nested.rewind()?;
+            io::copy(&mut nested, &mut writer)?;
+        } else {
+            drop(file); // release the `&mut zip` used by `file`
+            let file = zip.by_index_raw(i)?;
+            let name = raw_names.name_for(&file, index);
+            writer.raw_copy_file_rename(file, name)?;
+        }
+    }
+    if let Some(report) = &fixes.report {
+        writer.start_file(
+            report::PATH,
+            FileOptions::default().last_modified_time(Default::default()),
+        )?;
+        writer.write_all(report.to_pretty_string().as_bytes())?;
+    }
+    if let Some(unfix) = &fixes.unfix {
+        writer.start_file(
+            unfix::PATH,
+            FileOptions::default().last_modified_time(Default::default()),
+        )?;
+        writer.write_all(unfix.to_json().to_pretty_string().as_bytes())?;
+    }
+    let output = writer.finish()?.into_inner().map_err(|e| e.into_error())?;
+    raw_names.restore(&mut *output)?;
+    output.write_all(&fixes.trailing)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Writes the original jar out of the fixed one, returning how many classes
+/// were restored
+pub fn unfix_jar(
+    mut input: impl Read + Seek,
+    mut output: impl Read + Write + Seek,
+    original: &unfix::Original,
+) -> Result<usize> {
+    let trailing = trailing_garbage(&mut input)?;
+    let mut writer = ZipWriter::new(io::BufWriter::new(&mut output));
+    let mut zip = ZipArchive::new(input)?;
+    let mut raw_names = RawNames::default();
+    let (mut written, mut restored) = (0, 0);
+    for i in 0..zip.len() {
+        interrupt::check()?;
+        let mut file = zip.by_index(i)?;
+        if matches!(file.name(), unfix::PATH | report::PATH) {
+            continue;
+        }
+        let change = original.classes.get(file.name());
+        if file.name() == manifest::PATH || change.is_some() {
+            let mut buf = Vec::new();
+            file.read_to_end(&mut buf)
+                .with_context(|| format!("Reading {}", file.name()))?;
+            let bytes = match change {
+                Some(change) => {
+                    restored += 1;
+                    change
+                        .undo(&buf)
+                        .with_context(|| format!("Restoring {}", file.name()))?
+                }
+                None => match &original.manifest {
+                    Some(manifest) => manifest.clone(),
+                    // the fixing added it
+                    None => continue,
+                },
+            };
+            let name = raw_names.name_for(&file, written);
+            writer.start_file(name, entry_options(&file))?;
+            writer.write_all(&bytes)?;
+        } else {
+            drop(file);
+            let file = zip.by_index_raw(i)?;
+            let name = raw_names.name_for(&file, written);
+            writer.raw_copy_file_rename(file, name)?;
+        }
+        written += 1;
+    }
+    // at the end, which is fine for the JarFile the VM checks them with
+    for (path, bytes) in &original.removed {
+        if zip.by_name(path).is_ok() {
+            continue;
+        }
+        writer.start_file(path.as_str(), FileOptions::default())?;
+        writer.write_all(bytes)?;
+    }
+    if !original.nested.is_empty() {
+        let nested = original.nested.iter().cloned().collect::<Vec<_>>();
+        log::warn!(
+            "The archives in the jar stay fixed, there's nothing to unfix them with: {}",
+            nested.join(", ")
+        );
+    }
+    if restored != original.classes.len() {
+        log::warn!(
+            "{} of the fixed classes are not in the jar anymore",
+            original.classes.len() - restored
+        );
+    }
+    let output = writer.finish()?.into_inner().map_err(|e| e.into_error())?;
+    raw_names.restore(&mut *output)?;
+    output.write_all(original.trailing.as_deref().unwrap_or(&trailing))?;
+    output.flush()?;
+    Ok(restored)
+}
+
+/// Whether the entry is an archive that --recurse-archives goes into
+fn is_archive(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name.ends_with(".jar") || name.ends_with(".zip")
+}
+
+/// Whether the entry is one of the files a jar is signed with
+pub fn is_signature(name: &str) -> bool {
+    name.strip_prefix("META-INF/")
+        .filter(|rest| !rest.contains('/'))
+        .is_some_and(|rest| {
+            let rest = rest.to_ascii_uppercase();
+            [".SF", ".RSA", ".DSA", ".EC"]
+                .iter()
+                .any(|ext| rest.ends_with(ext))
+        })
+}
+
+fn entry_options(file: &zip::read::ZipFile) -> FileOptions {
+    let mut options = FileOptions::default()
+        .large_file(file.compressed_size().max(file.size()) > u32::MAX as u64)
+        .last_modified_time(file.last_modified())
+        .compression_method(file.compression());
+    if let Some(perms) = file.unix_mode() {
+        options = options.unix_permissions(perms);
+    }
+    options
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match panic.downcast_ref::<String>() {
+        Some(message) => message,
+        None => panic
+            .downcast_ref::<&str>()
+            .copied()
+            .unwrap_or("unknown error"),
+    }
+}
+
+/// The bytes after the end of central directory record (and its comment)
+fn trailing_garbage(input: &mut (impl Read + Seek)) -> Result<Vec<u8>> {
+    // same as the zip crate, the record has to be in the last 64K or so
+    const EOCD_SIZE: u64 = 22;
+    let len = input.seek(SeekFrom::End(0))?;
+    let tail_start = len.saturating_sub(EOCD_SIZE + u16::MAX as u64);
+    input.seek(SeekFrom::Start(tail_start))?;
+    let mut tail = Vec::new();
+    input.read_to_end(&mut tail)?;
+    input.rewind()?;
+
+    let positions = (0..tail.len().saturating_sub(EOCD_SIZE as usize - 1)).rev();
+    for pos in positions {
+        if tail[pos..pos + 4] != [b'P', b'K', 5, 6] {
+            continue;
+        }
+        let comment_len = u16::from_le_bytes([tail[pos + 20], tail[pos + 21]]) as usize;
+        let end = pos + EOCD_SIZE as usize + comment_len;
+        if end <= tail.len() {
+            return Ok(tail.split_off(end));
+        }
+    }
+    // not a zip at all, reading it will tell that better than us
+    Ok(Vec::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use zip::ZipWriter;
+
+    use super::*;
+    use crate::{class::tests::sample, memory::Budget};
+
+    fn jar(entries: &[(&str, &[u8])]) -> Cursor<Vec<u8>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        let mut jar = writer.finish().unwrap();
+        jar.rewind().unwrap();
+        jar
+    }
+
+    fn fix(input: &mut Cursor<Vec<u8>>, options: &FixOptions) -> Result<Option<Vec<u8>>> {
+        input.rewind()?;
+        let mut output = Cursor::new(Vec::new());
+        let report = fix_jar(&mut *input, &mut output, options)?;
+        Ok(report.changed.then(|| output.into_inner()))
+    }
+
+    fn class_in(jar: Vec<u8>, name: &str) -> ClassFile {
+        let mut zip = ZipArchive::new(Cursor::new(jar)).unwrap();
+        let mut bytes = Vec::new();
+        zip.by_name(name).unwrap().read_to_end(&mut bytes).unwrap();
+        ClassFile::parse(&bytes).unwrap()
+    }
+
+    #[test]
+    fn fixes_the_classes() {
+        let class = sample().to_bytes();
+        let mut input = jar(&[("Test.class", &class), ("readme.txt", b"hi")]);
+        let fixed = fix(&mut input, &FixOptions::default()).unwrap().unwrap();
+        let class = class_in(fixed.clone(), "Test.class");
+        assert_eq!(class.utf8(class.fields[0].name_index).unwrap(), "a_b");
+
+        let mut fixed = Cursor::new(fixed);
+        assert_eq!(fix(&mut fixed, &FixOptions::default()).unwrap(), None);
+    }
+
+    #[test]
+    fn same_input_same_jar() {
+        let class = sample().to_bytes();
+        let mut input = jar(&[("Test.class", &class)]);
+        let at = |secs| FixOptions {
+            provenance: true,
+            now: Some(UNIX_EPOCH + Duration::from_secs(secs)),
+            ..FixOptions::default()
+        };
+        let first = fix(&mut input, &at(1_000_000_000)).unwrap().unwrap();
+        let second = fix(&mut input, &at(2_000_000_000)).unwrap().unwrap();
+        assert!(first == second);
+
+        // only the report has the time in it
+        let report = FixOptions {
+            embed_report: true,
+            renames: Some(Default::default()),
+            ..at(1_000_000_000)
+        };
+        let fixed = fix(&mut input, &report).unwrap().unwrap();
+        let mut zip = ZipArchive::new(Cursor::new(fixed)).unwrap();
+        let mut text = String::new();
+        zip.by_name(report::PATH)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        let report = crate::json::parse(&text).unwrap();
+        assert_eq!(
+            report.get("timestamp").and_then(|t| t.as_str()),
+            Some("2001-09-09T01:46:40Z")
+        );
+    }
+
+    #[test]
+    fn broken_unfix_json_is_ignored() {
+        let class = sample().to_bytes();
+        let unfix = "[".repeat(1_000_000);
+        let mut input = jar(&[("Test.class", &class), (unfix::PATH, unfix.as_bytes())]);
+        let options = FixOptions {
+            provenance: true,
+            ..FixOptions::default()
+        };
+        let fixed = fix(&mut input, &options).unwrap().unwrap();
+
+        let mut zip = ZipArchive::new(Cursor::new(fixed)).unwrap();
+        let mut text = String::new();
+        zip.by_name(unfix::PATH)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        unfix::Original::parse(&text).unwrap();
+    }
+
+    #[test]
+    fn buffers_are_in_the_budget() {
+        let class = sample().to_bytes();
+        let names = (0..50)
+            .map(|i| format!("Test{}.class", i))
+            .collect::<Vec<_>>();
+        let entries = names
+            .iter()
+            .map(|name| (name.as_str(), &class[..]))
+            .collect::<Vec<_>>();
+        let mut input = jar(&entries);
+
+        let options = FixOptions {
+            memory: Budget::new(Some(64 * 1024)),
+            ..FixOptions::default()
+        };
+        fix(&mut input, &options).unwrap().unwrap();
+        // everything is given back in the end
+        let reservation = options.memory.reserve(64 * 1024, "everything").unwrap();
+        drop(reservation);
+
+        // not enough for even one class and what it's fixed into
+        let options = FixOptions {
+            memory: Budget::new(Some(class.len() as u64)),
+            ..FixOptions::default()
+        };
+        assert!(fix(&mut input, &options).is_err());
+    }
+}
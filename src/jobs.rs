@@ -0,0 +1,58 @@
+//! The threads that the fixing can use, shared by the jars fixed at the same
+//! time and the classes of each of them, so that there are never more of
+//! them working than --jobs says. Nothing waits for them: the work is done
+//! by the threads that are free, the one it's started from always being one.
+
+use std::{
+    num::NonZeroUsize,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+#[derive(Debug)]
+pub struct Jobs {
+    /// How many more threads can be started
+    free: Arc<Mutex<usize>>,
+}
+
+/// A thread that can be started, given back when dropped
+#[derive(Debug)]
+pub struct Job {
+    free: Arc<Mutex<usize>>,
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        *self.free.lock().unwrap_or_else(PoisonError::into_inner) += 1;
+    }
+}
+
+impl Jobs {
+    /// Up to `count` threads, 0 being as many as there are CPUs
+    pub fn new(count: usize) -> Self {
+        let count = match count {
+            0 => std::thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            count => count,
+        };
+        log::debug!("Using up to {} threads", count);
+        Self {
+            // the thread it's started from is the first one
+            free: Arc::new(Mutex::new(count - 1)),
+        }
+    }
+
+    /// Another thread, if there's room for it
+    pub fn try_take(&self) -> Option<Job> {
+        let mut free = self.free.lock().unwrap_or_else(PoisonError::into_inner);
+        (*free > 0).then(|| {
+            *free -= 1;
+            Job {
+                free: self.free.clone(),
+            }
+        })
+    }
+
+    /// As many more threads as there is room for, up to `max`
+    pub fn take_up_to(&self, max: usize) -> Vec<Job> {
+        (0..max).map_while(|_| self.try_take()).collect()
+    }
+}
@@ -0,0 +1,88 @@
+//! The record of the inputs that were already fixed, so that an interrupted
+//! run over many of them can pick up where it stopped.
+//!
+//! Each line is the hash of the result, the input and the file the result
+//! went to. An input is done if that file still has that hash, so the ones
+//! that were replaced with something else since (like after a game update)
+//! are redone. The line is written before the result replaces the input, so
+//! if we crash right between the two, it's the hash that tells which happened.
+
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{hash, paths};
+
+pub struct Journal {
+    file: File,
+    /// The input -> (the hash of the result, where the result is)
+    done: BTreeMap<String, (String, PathBuf)>,
+}
+
+/// How the input is recorded, URLs as they are and paths as absolute ones
+pub fn key(input: &Path) -> String {
+    match crate::download::as_url(input) {
+        Some(url) => url.to_owned(),
+        None => paths::absolute(input)
+            .unwrap_or_else(|_| input.to_owned())
+            .display()
+            .to_string(),
+    }
+}
+
+impl Journal {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut done = BTreeMap::new();
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line.with_context(|| format!("Reading {}", path.display()))?;
+                    let mut parts = line.splitn(3, '\t');
+                    // a line cut off by a crash is simply not there
+                    if let (Some(hash), Some(input), Some(result)) =
+                        (parts.next(), parts.next(), parts.next())
+                    {
+                        done.insert(input.to_owned(), (hash.to_owned(), result.into()));
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("Reading {}", path.display())),
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Opening {}", path.display()))?;
+        Ok(Self { file, done })
+    }
+
+    /// Whether the input was already fixed, and the result is still there
+    pub fn is_done(&self, input: &Path) -> Result<bool> {
+        let (expected, result) = match self.done.get(&key(input)) {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+        if !result.exists() {
+            return Ok(false);
+        }
+        Ok(hash::sha256_file(result)? == *expected)
+    }
+
+    /// Records that the input was fixed, the result of it being `file`
+    /// (which will be at `result`, if it's not already)
+    pub fn record(&mut self, input: &Path, file: &Path, result: &Path) -> Result<()> {
+        let hash = hash::sha256_file(file)?;
+        let result = paths::absolute(result)?;
+        writeln!(self.file, "{}\t{}\t{}", hash, key(input), result.display())?;
+        // the whole point is to survive crashes and power loss
+        self.file.sync_data()?;
+        self.done.insert(key(input), (hash, result));
+        Ok(())
+    }
+}
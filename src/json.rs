@@ -0,0 +1,370 @@
+//! A small JSON reader and writer, for the few files we keep around and the
+//! machine-readable reports.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use anyhow::{bail, Context, Result};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(BTreeMap<String, Value>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&BTreeMap<String, Value>> {
+        match self {
+            Self::Object(o) => Some(o),
+            _ => None,
+        }
+    }
+
+    /// The value of the key, if this is an object and it has the key
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.as_object()?.get(key)
+    }
+
+    /// Formats the value with two-space indentation, like everyone else does
+    pub fn to_pretty_string(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, Some(0));
+        out.push('\n');
+        out
+    }
+
+    fn write(&self, out: &mut String, indent: Option<usize>) {
+        let newline = |out: &mut String, level: usize| {
+            if indent.is_some() {
+                out.push('\n');
+                out.extend(std::iter::repeat_n("  ", level));
+            }
+        };
+        let inner = indent.map(|i| i + 1);
+        match self {
+            Self::Null => out.push_str("null"),
+            Self::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Self::Number(n) if n.is_finite() => {
+                let _ = write!(out, "{}", n);
+            }
+            Self::Number(_) => out.push_str("null"),
+            Self::String(s) => write_string(out, s),
+            Self::Array(items) if items.is_empty() => out.push_str("[]"),
+            Self::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        out.push(',');
+                    }
+                    newline(out, inner.unwrap_or(0));
+                    item.write(out, inner);
+                }
+                newline(out, indent.unwrap_or(0));
+                out.push(']');
+            }
+            Self::Object(map) if map.is_empty() => out.push_str("{}"),
+            Self::Object(map) => {
+                out.push('{');
+                for (i, (key, value)) in map.iter().enumerate() {
+                    if i != 0 {
+                        out.push(',');
+                    }
+                    newline(out, inner.unwrap_or(0));
+                    write_string(out, key);
+                    out.push_str(if indent.is_some() { ": " } else { ":" });
+                    value.write(out, inner);
+                }
+                newline(out, indent.unwrap_or(0));
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// How deep the arrays and objects can nest, so that a file of nothing but
+/// `[` is an error and not a stack overflow
+const MAX_DEPTH: usize = 128;
+
+pub fn parse(input: &str) -> Result<Value> {
+    let mut parser = Parser {
+        input: input.as_bytes(),
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.value().with_context(|| {
+        let line = input[..parser.pos.min(input.len())].matches('\n').count() + 1;
+        format!("Bad JSON at line {}", line)
+    })?;
+    parser.skip_whitespace();
+    if parser.pos != parser.input.len() {
+        bail!("Trailing characters after the JSON value");
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while matches!(self.input.get(self.pos), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.input.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<()> {
+        if self.peek() != Some(c) {
+            bail!("Expected '{}'", c as char);
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value> {
+        if !self.input[self.pos..].starts_with(word.as_bytes()) {
+            bail!("Unexpected character");
+        }
+        self.pos += word.len();
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        let open = match self.peek() {
+            Some(c @ (b'{' | b'[')) => c,
+            _ => return self.scalar(),
+        };
+        if self.depth == MAX_DEPTH {
+            bail!("Nested deeper than {} levels", MAX_DEPTH);
+        }
+        self.depth += 1;
+        let value = self.nested(open);
+        self.depth -= 1;
+        value
+    }
+
+    fn nested(&mut self, open: u8) -> Result<Value> {
+        match open {
+            b'{' => {
+                self.pos += 1;
+                let mut map = BTreeMap::new();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Value::Object(map));
+                }
+                loop {
+                    if self.peek() != Some(b'"') {
+                        bail!("Expected a key");
+                    }
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    map.insert(key, self.value()?);
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Value::Object(map));
+                        }
+                        _ => bail!("Expected ',' or '}}'"),
+                    }
+                }
+            }
+            _ => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Value::Array(items));
+                        }
+                        _ => bail!("Expected ',' or ']'"),
+                    }
+                }
+            }
+        }
+    }
+
+    fn scalar(&mut self) -> Result<Value> {
+        match self.peek() {
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.pos;
+                while matches!(
+                    self.input.get(self.pos),
+                    Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+                ) {
+                    self.pos += 1;
+                }
+                let s = std::str::from_utf8(&self.input[start..self.pos])?;
+                Ok(Value::Number(s.parse().context("Bad number")?))
+            }
+            Some(_) => bail!("Unexpected character"),
+            None => bail!("Unexpected end of input"),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let digits = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .context("Truncated \\u escape")?;
+        self.pos += 4;
+        Ok(u32::from_str_radix(std::str::from_utf8(digits)?, 16)?)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.pos += 1; // the opening quote
+        let mut bytes = Vec::new();
+        loop {
+            let c = *self.input.get(self.pos).context("Unterminated string")?;
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let escape = *self.input.get(self.pos).context("Unterminated string")?;
+                    self.pos += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            // surrogate pairs for the characters outside the BMP
+                            if (0xD800..0xDC00).contains(&code)
+                                && self.input[self.pos..].starts_with(b"\\u")
+                            {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                code = 0x10000
+                                    + ((code - 0xD800) << 10)
+                                    + (low.wrapping_sub(0xDC00) & 0x3FF);
+                            }
+                            char::from_u32(code).context("Bad \\u escape")?
+                        }
+                        _ => bail!("Bad escape"),
+                    };
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                c => bytes.push(c),
+            }
+        }
+        Ok(String::from_utf8(bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = r#"{
+  "array": [
+    1,
+    -2.5,
+    true,
+    null
+  ],
+  "empty": {},
+  "string": "quote \" backslash \\ newline \n tab \t bell \u0007"
+}
+"#;
+        let value = parse(text).unwrap();
+        assert_eq!(value.to_pretty_string(), text);
+        assert_eq!(parse(&value.to_pretty_string()).unwrap(), value);
+    }
+
+    #[test]
+    fn escapes() {
+        let value = parse(r#""\u00e9\/\ud83d\ude00""#).unwrap();
+        assert_eq!(value.as_str(), Some("é/😀"));
+    }
+
+    #[test]
+    fn errors() {
+        for text in [
+            "",
+            "[1,]",
+            "{\"a\" 1}",
+            "{1: 2}",
+            "\"unterminated",
+            "\"\\x\"",
+            "\"\\u12\"",
+            "tru",
+            "1 2",
+            "-",
+        ] {
+            assert!(parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let ok = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(parse(&ok).is_ok());
+
+        let deep = format!("{}{}", "[".repeat(MAX_DEPTH + 1), "]".repeat(MAX_DEPTH + 1));
+        assert!(parse(&deep).is_err());
+
+        // would overflow the stack without the limit
+        assert!(parse(&"[".repeat(1_000_000)).is_err());
+        assert!(parse(&"{\"a\":".repeat(1_000_000)).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+//! The hashes of the jars as they come out of known-good runs, to tell the
+//! people whose results match them that everything went fine, and to warn
+//! the ones whose results don't.
+//!
+//! The table is keyed by the hash of the original jar, which is also how the
+//! game version is found out:
+//!
+//! ```json
+//! {"jars": {"<sha256 of the original>": {"version": "0.98a", "fixed": "<sha256 of the result>"}}}
+//! ```
+
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{bail, Context, Result};
+
+use crate::json;
+
+/// The table that comes with the fixer, the results only count with the
+/// default options and --no-provenance. It's empty until there are results
+/// checked well enough to vouch for
+const EMBEDDED: &str = include_str!("known-hashes.json");
+
+#[derive(Debug)]
+pub struct KnownJar {
+    /// The game version the original jar is from
+    pub version: String,
+    pub fixed: String,
+}
+
+#[derive(Debug, Default)]
+pub struct KnownHashes {
+    by_original: BTreeMap<String, KnownJar>,
+}
+
+impl KnownHashes {
+    /// The embedded table, with the entries from the file on top of it
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut known = Self::default();
+        known.add(EMBEDDED).context("Reading the embedded hashes")?;
+        if let Some(path) = path {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Reading {}", path.display()))?;
+            known
+                .add(&contents)
+                .with_context(|| format!("Reading {}", path.display()))?;
+        }
+        Ok(known)
+    }
+
+    fn add(&mut self, contents: &str) -> Result<()> {
+        let root = json::parse(contents)?;
+        let jars = match root.get("jars").and_then(json::Value::as_object) {
+            Some(jars) => jars,
+            None => bail!("There is no \"jars\" object"),
+        };
+        for (original, jar) in jars {
+            let field = |name: &str| {
+                jar.get(name)
+                    .and_then(json::Value::as_str)
+                    .with_context(|| format!("The jar {} has no \"{}\" string", original, name))
+            };
+            let jar = KnownJar {
+                version: field("version")?.to_owned(),
+                fixed: field("fixed")?.to_ascii_lowercase(),
+            };
+            self.by_original.insert(original.to_ascii_lowercase(), jar);
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_original.is_empty()
+    }
+
+    /// Tells the user whether the result matches the known-good one
+    pub fn check(&self, name: &str, original_hash: &str, fixed_hash: &str) {
+        let known = match self.by_original.get(original_hash) {
+            Some(known) => known,
+            None => {
+                log::debug!("{} is not a known jar, nothing to compare it to", name);
+                return;
+            }
+        };
+        if known.fixed == fixed_hash {
+            log::info!(
+                "{}",
+                tr!("fix-known-good", jar = name, version = known.version)
+            );
+        } else {
+            log::warn!(
+                "{}",
+                tr!("fix-known-bad", jar = name, version = known.version)
+            );
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Running the game after fixing it, and telling the launchers how to do
+//! that.
+
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::profile::Profile;
+
+/// Quotes the argument for the shell the launch options and the wrapper
+/// scripts go through: sh everywhere but Windows, and cmd on it
+pub fn quote(arg: &str) -> String {
+    if cfg!(windows) {
+        // paths can't have quotes in them on Windows, so that's all it takes
+        format!("\"{}\"", arg)
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// The launch options that make Steam run the game through `wrap`
+pub fn steam_launch_options(exe: &Path, inputs: &[&Path]) -> String {
+    let mut options = quote(&exe.display().to_string());
+    options.push_str(" wrap");
+    for input in inputs {
+        options.push(' ');
+        options.push_str(&quote(&input.display().to_string()));
+    }
+    // steam replaces it with the command that runs the game
+    options.push_str(" -- %command%");
+    options
+}
+
+/// Runs the game, failing if it does
+pub fn run(command: &[OsString]) -> Result<()> {
+    let (program, args) = match command.split_first() {
+        Some(split) => split,
+        None => bail!("No command to run after fixing"),
+    };
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .with_context(|| format!("Running {}", Path::new(program).display()))?;
+    if !status.success() {
+        bail!("{} exited with {}", Path::new(program).display(), status);
+    }
+    Ok(())
+}
+
+/// What to fix in an install of the game and what starts it, relative to
+/// the game directory
+#[derive(Debug)]
+pub struct Layout {
+    pub inputs: Vec<PathBuf>,
+    /// None with the profiles that don't know what starts it
+    pub launcher: Option<PathBuf>,
+}
+
+/// Figures out which of the platforms the game in the directory is for,
+/// since all of them have their files in different places
+pub fn detect_layout(game_dir: &Path, profile: &Profile) -> Result<Layout> {
+    let launcher = profile.find_launcher(game_dir)?;
+    let inputs = profile.find_jars(game_dir)?;
+    Ok(Layout { inputs, launcher })
+}
+
+/// The script that fixes the game quietly (with the --profile, if it's not
+/// the default one) and then starts it, and the extension it should have on
+/// this platform
+pub fn wrapper_script(
+    exe: &Path,
+    game_dir: &Path,
+    profile: Option<&OsStr>,
+    inputs: &[PathBuf],
+    launcher: &Path,
+) -> (&'static str, String) {
+    let quoted = |path: &Path| quote(&path.display().to_string());
+    let inputs = inputs
+        .iter()
+        .map(|input| quoted(input))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut fixer = quoted(exe);
+    if let Some(profile) = profile {
+        fixer.push_str(" --profile ");
+        fixer.push_str(&quoted(Path::new(profile)));
+    }
+    if cfg!(windows) {
+        // pushd and not cd, since cmd can't be in a directory on a network
+        // share otherwise, and in batch files even the quoted percents are
+        // variables
+        let script = format!(
+            "@echo off\r\n\
+             rem Fixes the game with starsector-fixer, then starts it\r\n\
+             pushd {}\r\n\
+             set RUST_LOG=warn\r\n\
+             {} wrap {} -- {} %*\r\n",
+            quoted(game_dir),
+            fixer,
+            inputs,
+            quoted(launcher),
+        )
+        .replace('%', "%%")
+        .replace("%%*", "%*");
+        ("bat", script)
+    } else {
+        let script = format!(
+            "#!/bin/sh\n\
+             # Fixes the game with starsector-fixer, then starts it\n\
+             cd {} || exit 1\n\
+             RUST_LOG=warn exec {} wrap {} -- {} \"$@\"\n",
+            quoted(game_dir),
+            fixer,
+            inputs,
+            quoted(&Path::new(".").join(launcher)),
+        );
+        // double-clicking the .command files runs them in the terminal
+        (
+            if cfg!(target_os = "macos") {
+                "command"
+            } else {
+                "sh"
+            },
+            script,
+        )
+    }
+}
+
+/// Shows a desktop notification, if there's a way to do that here
+pub fn notify(message: &str) {
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("msg");
+        command.args(["*", message]);
+        command
+    } else if cfg!(target_os = "macos") {
+        let mut command = Command::new("osascript");
+        let message = message.replace('\\', "\\\\").replace('"', "\\\"");
+        command.args([
+            "-e",
+            &format!(
+                "display notification \"{}\" with title \"starsector-fixer\"",
+                message
+            ),
+        ]);
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.args(["starsector-fixer", message]);
+        command
+    };
+    if let Err(e) = command.status() {
+        log::debug!("Could not show the notification: {}", e);
+    }
+}
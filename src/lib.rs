@@ -0,0 +1,79 @@
+//! The fixing of the class files and the jars, for the launchers and the mod
+//! managers to do it themselves instead of running the fixer.
+//!
+//! ```no_run
+//! use std::fs::File;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let input = File::open("starsector-core/starfarer_obf.jar")?;
+//! let output = File::options()
+//!     .read(true)
+//!     .write(true)
+//!     .create(true)
+//!     .truncate(true)
+//!     .open("starfarer_obf.fixed.jar")?;
+//! let options = starsector_fixer::FixOptions::default();
+//! let report = starsector_fixer::fix_jar(options.reader(input), output, &options)?;
+//! println!("Fixed {} classes", report.classes.len());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The work stops with an error when the process is interrupted, once
+//! [`interrupt::install`] is called, and the messages are in English unless
+//! [`i18n::init`] is called.
+
+use anyhow::Result;
+
+pub mod bundle;
+pub mod bytecode;
+pub mod class;
+#[macro_use]
+pub mod console;
+#[macro_use]
+pub mod i18n;
+pub mod fix;
+pub mod glob;
+pub mod hash;
+pub mod index;
+pub mod interrupt;
+pub mod jar;
+pub mod jobs;
+pub mod json;
+pub mod known;
+pub mod limits;
+pub mod manifest;
+pub mod mapping;
+pub mod memory;
+pub mod raw_names;
+pub mod recovery;
+pub mod registry;
+pub mod report;
+pub mod rules;
+pub mod tar;
+pub mod temp;
+pub mod toml;
+pub mod unfix;
+pub mod version;
+
+pub use fix::FixOptions;
+pub use jar::{fix_file, fix_jar, unfix_jar, FixReport};
+
+/// Fixes the class with the default options, `None` if there is nothing to
+/// fix in it
+pub fn fix_class(class: &[u8]) -> Result<Option<Vec<u8>>> {
+    fix::fix_class(class, "the class", &FixOptions::default(), None)
+}
+
+/// The --version, with everything needed to tell which build it is
+pub fn long_version() -> String {
+    format!(
+        "{} (commit {}, built on {})\nfeatures: {}\nclass files up to version {} ({})",
+        env!("CARGO_PKG_VERSION"),
+        env!("BUILD_COMMIT"),
+        env!("BUILD_DATE"),
+        env!("BUILD_FEATURES"),
+        version::MAX_KNOWN_MAJOR,
+        version::release_name(version::MAX_KNOWN_MAJOR),
+    )
+}
@@ -0,0 +1,89 @@
+//! Caps on how much work the input can make us do, for when the jars come
+//! from someone who can't be trusted.
+
+use std::{io::Read, time::Instant};
+
+use anyhow::{bail, ensure, Result};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    /// In bytes, after decompression
+    pub max_class_size: Option<u64>,
+    pub max_constant_pool: Option<u16>,
+    /// How deep the jars can be nested in other archives, with 0 being the
+    /// input jar itself
+    pub max_depth: Option<usize>,
+    pub deadline: Option<Instant>,
+}
+
+impl Limits {
+    /// Also where the work stops when interrupted, it's checked often enough
+    pub fn check_time(&self) -> Result<()> {
+        crate::interrupt::check()?;
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() > deadline)
+        {
+            bail!("Ran out of the time limit");
+        }
+        Ok(())
+    }
+
+    pub fn check_depth(&self, depth: usize) -> Result<()> {
+        if let Some(max) = self.max_depth {
+            ensure!(
+                depth <= max,
+                "The jar is nested at depth {}, which is more than the limit of {}",
+                depth,
+                max
+            );
+        }
+        Ok(())
+    }
+
+    pub fn check_constant_pool(&self, bytecode: &[u8]) -> Result<()> {
+        if let (Some(max), Some(count)) = (self.max_constant_pool, bytecode.get(8..10)) {
+            let count = u16::from_be_bytes([count[0], count[1]]);
+            ensure!(
+                count <= max,
+                "The class has {} constants, which is more than the limit of {}",
+                count,
+                max
+            );
+        }
+        Ok(())
+    }
+
+    /// Empties the buffer (which is reused for all of the classes) for the
+    /// next one, making room for the size the jar says it has, but not more
+    /// than what would be read anyway
+    pub fn prepare_buffer(&self, buf: &mut Vec<u8>, size: u64) {
+        // what a lying jar can make us allocate without a limit
+        const MAX_RESERVE: u64 = 16 * 1024 * 1024;
+        let cap = self
+            .max_class_size
+            .map_or(MAX_RESERVE, |max| max.min(MAX_RESERVE));
+        buf.clear();
+        buf.reserve(size.min(cap) as usize);
+    }
+
+    /// Reads a class out of the jar, without trusting the size the jar says
+    /// it has, since that's what zip bombs lie about
+    pub fn read_class(&self, input: impl Read, buf: &mut Vec<u8>) -> Result<()> {
+        match self.max_class_size {
+            Some(max) => {
+                input.take(max + 1).read_to_end(buf)?;
+                ensure!(
+                    buf.len() as u64 <= max,
+                    "The class is bigger than the limit of {} bytes",
+                    max
+                );
+            }
+            None => {
+                let mut input = input;
+                input.read_to_end(buf)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+//! Lock files next to the jars being written, so that two runs at once (say,
+//! the watcher and a manual one) don't trample each other's temp files and
+//! backups.
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::interrupt::{self, Working};
+
+/// Held while the file is being worked on, removes the lock file when dropped
+#[derive(Debug)]
+pub struct Lock {
+    path: PathBuf,
+    _file: File,
+    _working: Working,
+}
+
+pub fn lock(target: &Path) -> Result<Lock> {
+    let path = crate::with_suffix(target, ".lock");
+    // the lock file gets removed when unlocking, so someone who opened it
+    // right before that could lock the removed one, hence the check and
+    // the retries
+    for _ in 0..3 {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| tr!("lock-creating", file = path.display()))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                bail!("{}", tr!("lock-busy", file = target.display()))
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| tr!("lock-locking", file = path.display()))
+            }
+        }
+        if is_same_file(&file, &path) {
+            return Ok(Lock {
+                path,
+                _file: file,
+                _working: interrupt::working(),
+            });
+        }
+    }
+    bail!("{}", tr!("lock-failed", file = path.display()))
+}
+
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(a), Ok(b)) => (a.dev(), a.ino()) == (b.dev(), b.ino()),
+        _ => false,
+    }
+}
+
+/// Files that are open can't be removed on Windows, so it's always the same
+#[cfg(not(unix))]
+fn is_same_file(_: &File, path: &Path) -> bool {
+    path.exists()
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // removed while still locked, the file handle is closed after this
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            log::warn!(
+                "Could not remove the lock file {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
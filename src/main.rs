@@ -1,259 +1,2956 @@
 use std::{
-    borrow::Cow,
+    collections::BTreeSet,
+    ffi::OsString,
     fs::File,
-    io::{Cursor, Read, Seek, SeekFrom, Write},
-    path::PathBuf,
+    io::{self, BufReader, Cursor, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
-use anyhow::{bail, ensure, Context, Result};
-use byteorder::{ReadBytesExt, BE};
+use anyhow::{bail, Context, Result};
 use log::LevelFilter;
 use structopt::StructOpt;
 
-use zip::{write::FileOptions, ZipArchive, ZipWriter};
+use zip::ZipArchive;
 
-/// A simple program that remaps Java method names to not have dots in them.
+#[macro_use]
+extern crate starsector_fixer;
+
+use starsector_fixer::{
+    bundle, bytecode, class, console, fix, glob, hash, i18n, index, interrupt, jar, jobs, json,
+    known, limits, long_version, manifest, mapping, memory, registry, report, rules, tar, temp,
+    toml, unfix, version,
+};
+
+mod asm;
+mod attrs;
+mod check;
+mod config;
+mod decompile;
+mod diff;
+mod dirs;
+mod doctor;
+mod download;
+mod explain;
+mod fingerprint;
+mod graph;
+mod grep;
+mod info;
+mod install;
+mod journal;
+mod launch;
+mod lock;
+mod metrics;
+mod migrate;
+mod patch;
+mod paths;
+mod priority;
+mod profile;
+mod queue;
+mod scan;
+mod serve;
+mod smoke;
+mod space;
+mod trash;
+mod unused;
+mod usages;
+mod verify;
+mod watch;
+
+use fix::{CollisionPolicy, FixOptions, SourceFilePolicy, TrailingGarbage, UnknownTagPolicy};
+use glob::Glob;
+use jar::{fix_file, scan_jar, unfix_jar, write_jar};
+use journal::Journal;
+use known::KnownHashes;
+use limits::Limits;
+use memory::Budget;
+use profile::Profile;
+use registry::Registry;
+
+/// A simple program that remaps Java method names to not have dots (or
+/// slashes) in them.
 ///
 /// Old Oracle VMs allow that, which is against the spec, and some obfuscation
 /// methods (cough-cough, starsector) use that, making the resulting program
-/// not runnable on VMs with a stricter implementation, such as OpenJDK
-#[derive(Debug, StructOpt)]
+/// not runnable on VMs with a stricter implementation, such as OpenJDK.
+///
+/// Every option can also be set with a STARSECTOR_FIXER_<NAME> environment
+/// variable (like STARSECTOR_FIXER_NO_CLOBBER=1), which the command line
+/// overrides and which overrides the config file
+#[derive(Debug, Clone, StructOpt)]
 struct Opt {
-    /// The path to the JAR file to be processed
-    input: PathBuf,
+    /// The paths to the JAR files to be processed, or http(s) URLs to download
+    /// them from, in which case the output is written to the current
+    /// directory. Tarballs (.tar, .tar.gz and .tar.zst) are processed by
+    /// fixing all of the jars inside of them. Directories are searched for
+    /// jars and tarballs, skipping the things listed in the .fixerignore
+    /// files (which are like .gitignore) in them. An argument like @file
+    /// is replaced with the lines of the file, one argument per line
+    #[structopt(parse(from_os_str), env = "STARSECTOR_FIXER_INPUTS")]
+    inputs: Vec<PathBuf>,
     /// The output file. Without this option, a backup is created for the input
     /// file and the input file gets replaced with the fixed one
-    #[structopt(short, long)]
+    #[structopt(
+        short,
+        long,
+        global = true,
+        parse(from_os_str),
+        env = "STARSECTOR_FIXER_OUTPUT"
+    )]
     output: Option<PathBuf>,
+    /// The directory to write the fixed files to, with the same layout the
+    /// inputs have, instead of replacing the inputs
+    #[structopt(
+        long,
+        value_name = "dir",
+        conflicts_with = "output",
+        parse(from_os_str),
+        env = "STARSECTOR_FIXER_OUTPUT_DIR"
+    )]
+    output_dir: Option<PathBuf>,
+    /// Name the fixed files after the inputs using this template, like
+    /// "{stem}-fixed.{ext}", with {name} being the whole file name, {stem}
+    /// the part before the last dot and {ext} the one after it. The files
+    /// are written next to the inputs, or into --output-dir
+    #[structopt(
+        long,
+        value_name = "template",
+        conflicts_with = "output",
+        env = "STARSECTOR_FIXER_OUTPUT_NAME"
+    )]
+    output_name: Option<String>,
+    /// Keep fixing the jars dropped into the first directory into the
+    /// second one, with the report of each next to it (as <name>.report.json).
+    /// The ones that could not be fixed go to the failed directory in the
+    /// second one, with the error. The jars are removed from the first
+    /// directory once they are dealt with. Runs until it's killed
+    #[structopt(
+        long,
+        number_of_values = 2,
+        value_names = &["in", "out"],
+        parse(from_os_str),
+        conflicts_with_all = &["output", "output-dir", "output-name"]
+    )]
+    queue: Vec<PathBuf>,
     /// Use this flag if you don't want the backup to be created. Does
-    /// nothing if -o is present
-    #[structopt(short, long)]
+    /// nothing if the output goes to another file
+    #[structopt(short, long, global = true)]
     force: bool,
+    /// Refuse to overwrite the output file or the backup if they already
+    /// exist, so that a backup of the original jar can't be replaced with
+    /// the one of an already fixed jar. Can be made the default in the
+    /// config file
+    #[structopt(long, global = true)]
+    no_clobber: bool,
+    /// Send the original jars to the recycle bin (or the trash) when they
+    /// are replaced, as well as making the backup, or instead of it with -f
+    #[structopt(long, global = true)]
+    trash: bool,
+    /// Make the read-only jars (and the read-only directories with them)
+    /// writable for the time of fixing them, and read-only again after,
+    /// instead of refusing to replace them
+    #[structopt(long, global = true)]
+    force_writable: bool,
+    /// Overwrite the existing files even if the config says not to
+    #[structopt(long, global = true, conflicts_with = "no-clobber")]
+    clobber: bool,
+    /// Instead of fixing the input, write a binary patch (in VCDIFF format,
+    /// same as xdelta3) that turns the original jar into the fixed one.
+    /// The fixed jar is still written if -o is present
+    #[structopt(
+        long,
+        value_name = "patch",
+        parse(from_os_str),
+        env = "STARSECTOR_FIXER_EMIT_PATCH"
+    )]
+    emit_patch: Option<PathBuf>,
+    /// Check that the input (downloaded or not) has this SHA-256 hash before
+    /// doing anything with it
+    #[structopt(long, value_name = "hash", env = "STARSECTOR_FIXER_SHA256")]
+    sha256: Option<String>,
+    /// Change the class file version of all of the classes to the given one,
+    /// either as the major version (52) or the Java release (8). Fails if a
+    /// class uses something that the version does not support
+    #[structopt(
+        long,
+        value_name = "version",
+        parse(try_from_str = version::parse_version),
+        env = "STARSECTOR_FIXER_SET_CLASS_VERSION"
+    )]
+    set_class_version: Option<u16>,
+    /// Fix the SourceFile attributes with newlines, quotes and other garbage
+    /// in them, which break stack traces and decompilers. 'normalize'
+    /// replaces such values with the ones javac would write, while 'strip'
+    /// removes the attributes altogether
+    #[structopt(
+        long,
+        value_name = "policy",
+        possible_values = &["normalize", "strip"],
+        env = "STARSECTOR_FIXER_SANITIZE_SOURCE_FILE"
+    )]
+    sanitize_source_file: Option<SourceFilePolicy>,
+    /// A JSON file to remember every rename in, created if it does not
+    /// exist. The names already in it are always fixed the same way, so
+    /// jars fixed at different times (and the saves made with them) stay
+    /// compatible with each other. The fixed jars are remembered in it too,
+    /// for `status`. Without the file, the one in the data directory (like
+    /// ~/.local/share/starsector-fixer) is used
+    #[structopt(long, value_name = "file", env = "STARSECTOR_FIXER_REGISTRY")]
+    registry: Option<Option<PathBuf>>,
+    /// Fix the method refs to interface methods that are not marked as such,
+    /// and the other way around, which old VMs did not care about. Only the
+    /// classes in the same jar are checked
+    #[structopt(long)]
+    repair_ref_kinds: bool,
+    /// Also fix the names in every NameAndType constant, not just the ones
+    /// used by the fields, the methods and the refs to them, which catches
+    /// the names used from the attributes this does not know about
+    #[structopt(long)]
+    fix_all_name_and_type: bool,
+    /// Check that every ref to a member of the classes in the jar which
+    /// found it before the renames still does after them, failing the class
+    /// if not. It takes a whole extra read of the jar
+    #[structopt(long)]
+    verify_refs: bool,
+    /// Replace the control characters (and NULs) in the member names with
+    /// placeholders like _u0000. The VM takes them, but a lot of the tools
+    /// break on them, and they can hide other names in the logs
+    #[structopt(long)]
+    sanitize_names: bool,
+    /// A TOML file with more renames (of the names matching a regex, with
+    /// the characters replaced or with a regex replacement) and attributes
+    /// to remove, for the kinds of bad names this doesn't know about, done
+    /// after the usual fixing. See the rules module for how it's written
+    #[structopt(
+        long,
+        value_name = "file",
+        parse(from_os_str),
+        env = "STARSECTOR_FIXER_RULES"
+    )]
+    rules: Option<PathBuf>,
+    /// Also rename the names matching a regex, given with the replacement
+    /// after an `=`, like '^access\$(\d+)$=access_$1' ($1 and such being
+    /// the groups). The new names can be of any length. Can be given several
+    /// times, the renames being done in turn after the ones of --rules
+    #[structopt(
+        long,
+        value_name = "regex=replace",
+        number_of_values = 1,
+        parse(try_from_str = rules::RenamePattern::new),
+        env = "STARSECTOR_FIXER_RENAME_PATTERN"
+    )]
+    rename_pattern: Vec<rules::RenamePattern>,
+    /// A ProGuard mapping file with the new names for the fields and the
+    /// methods, used instead of the usual fixing for the names in it (the
+    /// ones not in it are fixed as usual). A name has to be renamed the same
+    /// way in all of the classes, and the classes are not renamed
+    #[structopt(
+        long,
+        value_name = "file",
+        parse(from_os_str),
+        env = "STARSECTOR_FIXER_APPLY_MAPPING"
+    )]
+    apply_mapping: Option<PathBuf>,
+    /// Write every rename of a field or a method into this file, in the
+    /// ProGuard mapping format, for the tools that deobfuscate the stack
+    /// traces and such. It has the renames of all of the inputs
+    #[structopt(
+        long,
+        value_name = "file",
+        parse(from_os_str),
+        env = "STARSECTOR_FIXER_WRITE_MAPPING"
+    )]
+    write_mapping: Option<PathBuf>,
+    /// Only fix the classes in the jar with the paths matching this glob,
+    /// like com/fs/** (** going into the subpackages, * not). Can be given
+    /// several times. The references to the fixed names from the other
+    /// classes are left as they were
+    #[structopt(
+        long,
+        value_name = "glob",
+        number_of_values = 1,
+        parse(try_from_str = Glob::new),
+        env = "STARSECTOR_FIXER_ONLY_PACKAGE"
+    )]
+    only_package: Vec<Glob>,
+    /// Don't fail on the classes with made up attribute lengths (a trick
+    /// to break the tools that read classes), find out where the attributes
+    /// really end and write the correct lengths instead. The classes that
+    /// still can't be fixed are copied as they are, with a warning
+    #[structopt(long)]
+    lenient: bool,
+    /// What to do with the bytes after the end of the classes and after the
+    /// end of the jars, which nothing reads but which are sometimes there
+    #[structopt(
+        long,
+        value_name = "policy",
+        possible_values = &["preserve", "strip"],
+        default_value = "preserve",
+        env = "STARSECTOR_FIXER_TRAILING_GARBAGE"
+    )]
+    trailing_garbage: TrailingGarbage,
+    /// What to do with the classes that have constants with tags this does
+    /// not know about (from a newer Java, most likely): fail, or copy them
+    /// as they are with a warning. The tags found are put into the
+    /// --embed-report, so they can be reported and supported
+    #[structopt(
+        long,
+        value_name = "policy",
+        possible_values = &["abort", "skip"],
+        default_value = "abort",
+        env = "STARSECTOR_FIXER_UNKNOWN_CONSTANT_TAG"
+    )]
+    unknown_constant_tag: UnknownTagPolicy,
+    /// What to do when fixing a name makes it the same as the one of another
+    /// member of the class (like with both foo.bar and foo_bar there): fail,
+    /// add $fx1 to the fixed name (or the next number that's free), or leave
+    /// the name as it is. The same name is renamed the same way in the whole
+    /// jar, and with --registry in the jars fixed after it too
+    #[structopt(
+        long,
+        value_name = "policy",
+        possible_values = &["error", "suffix", "skip"],
+        default_value = "suffix",
+        env = "STARSECTOR_FIXER_ON_COLLISION"
+    )]
+    on_collision: CollisionPolicy,
+    /// The biggest class (in bytes, after decompression) to process, for
+    /// not being zip-bombed by untrusted jars
+    #[structopt(long, value_name = "bytes", env = "STARSECTOR_FIXER_MAX_CLASS_SIZE")]
+    max_class_size: Option<u64>,
+    /// The most constants a class can have
+    #[structopt(long, value_name = "count", env = "STARSECTOR_FIXER_MAX_CONSTANT_POOL")]
+    max_constant_pool: Option<u16>,
+    /// How deep the jars can be inside of other archives, 0 being the input
+    /// itself, 1 being the jars in a tarball
+    #[structopt(long, value_name = "depth", env = "STARSECTOR_FIXER_MAX_DEPTH")]
+    max_depth: Option<usize>,
+    /// How many directories deep to look for jars in the directories given
+    /// as inputs, 0 being only the files right in them
+    #[structopt(long, value_name = "depth", env = "STARSECTOR_FIXER_MAX_SCAN_DEPTH")]
+    max_scan_depth: Option<usize>,
+    /// Follow the symlinks when looking for jars in directories, instead of
+    /// skipping them. The places linked to several times are only gone
+    /// through once, so the loops are fine
+    #[structopt(long)]
+    follow_symlinks: bool,
+    /// Only take the archives matching this glob from the directories given
+    /// as inputs, by the name (like starfarer*.jar), or with a / in it, by
+    /// the path in the directory (like mods/**/jars/*.jar). Can be given
+    /// several times
+    #[structopt(
+        long,
+        value_name = "glob",
+        number_of_values = 1,
+        parse(try_from_str = Glob::new),
+        env = "STARSECTOR_FIXER_INCLUDE"
+    )]
+    include: Vec<Glob>,
+    /// Leave out the archives and the directories matching this glob when
+    /// going through the directories, the same way as --include. Can be
+    /// given several times
+    #[structopt(
+        long,
+        value_name = "glob",
+        number_of_values = 1,
+        parse(try_from_str = Glob::new),
+        env = "STARSECTOR_FIXER_EXCLUDE"
+    )]
+    exclude: Vec<Glob>,
+    /// Roughly how much of the jars (in bytes) to keep in memory at once.
+    /// The jars inside of tarballs that don't fit go into temporary files,
+    /// and the classes and patches that don't fit fail instead of eating
+    /// the memory of the game
+    #[structopt(long, value_name = "bytes", env = "STARSECTOR_FIXER_MAX_MEMORY")]
+    max_memory: Option<u64>,
+    /// For the machines short on memory and the huge archives: keep the
+    /// fixed classes in a temporary file, only read the classes whole when
+    /// they have something to rename (skipping over the code of the methods
+    /// to find out), index only the members of the classes for the
+    /// collisions, and fix on one thread and with --max-memory of 64M, if
+    /// those are not given. The jars come out the same
+    #[structopt(long)]
+    low_memory: bool,
+    /// How many bytes of the archives to read ahead and to write at once,
+    /// 256K by default. Bigger is better for the ones on network or slow
+    /// drives
+    #[structopt(long, value_name = "bytes", env = "STARSECTOR_FIXER_BUFFER_SIZE")]
+    buffer_size: Option<usize>,
+    /// How many threads to fix the classes and the jars with, as many as
+    /// there are CPUs by default. Each thread that's free takes the next
+    /// jar, or helps with the classes of one
+    #[structopt(long, value_name = "n", env = "STARSECTOR_FIXER_JOBS")]
+    jobs: Option<usize>,
+    /// Give up if the whole thing takes more than this many seconds
+    #[structopt(long, value_name = "seconds", env = "STARSECTOR_FIXER_TIME_LIMIT")]
+    time_limit: Option<u64>,
+    /// Remember the inputs that were fixed in this file, and skip them when
+    /// running again, so that a run over lots of jars that was interrupted
+    /// can be continued without redoing (and re-backing-up) the finished ones
+    #[structopt(
+        long,
+        value_name = "file",
+        parse(from_os_str),
+        env = "STARSECTOR_FIXER_JOURNAL"
+    )]
+    journal: Option<PathBuf>,
+    /// Write a report into the fixed jars (as META-INF/starsector-fixer/
+    /// report.json), with the version of the fixer, the time, the options
+    /// and every rename, so that the jars tell what was done to them. The
+    /// jars with nothing to fix are left as they are, without one
+    #[structopt(long)]
+    embed_report: bool,
+    /// Don't record the fixer, its version and the hash of the original jar
+    /// in the manifest of the fixed jar (as X-Fixed-By, X-Fixer-Version and
+    /// X-Original-SHA256), nor the original bytes of what was changed (in
+    /// META-INF/starsector-fixer/unfix.json), which `unfix` needs
+    #[structopt(long)]
+    no_provenance: bool,
+    /// Also fix the jars and the zips inside of the jars (like the libraries
+    /// of the fat jars), however deep, putting them back compressed the
+    /// same way. The ones that are not fixed are copied as they are, and
+    /// the ones that are can't be unfixed with `unfix`
+    #[structopt(long)]
+    recurse_archives: bool,
+    /// Keep the signatures of the signed jars, which the fixed classes
+    /// break, so that the VM refuses to load them. They are removed by
+    /// default, along with the digests of the entries in the manifest
+    #[structopt(long)]
+    keep_signatures: bool,
+    /// A JSON file with the hashes of the known-good results to compare the
+    /// fixed jars to. The fixer comes with none of them yet, so nothing is
+    /// compared without this. That's only done with the default fixing
+    /// options and --no-provenance, which the hashes have to be made with
+    #[structopt(
+        long,
+        value_name = "file",
+        parse(from_os_str),
+        env = "STARSECTOR_FIXER_KNOWN_HASHES"
+    )]
+    known_hashes: Option<PathBuf>,
+    /// After fixing, run this decompiler (the jar or the executable of CFR,
+    /// Vineflower or another Fernflower fork) on the classes that were
+    /// changed, for looking at what was done to them. Not for tarballs
+    #[structopt(
+        long,
+        value_name = "decompiler",
+        parse(from_os_str),
+        env = "STARSECTOR_FIXER_DECOMPILE_WITH"
+    )]
+    decompile_with: Option<PathBuf>,
+    /// Where the sources from --decompile-with go, <name of the fixed
+    /// jar>-src next to it by default
+    #[structopt(
+        long,
+        value_name = "dir",
+        parse(from_os_str),
+        env = "STARSECTOR_FIXER_DECOMPILE_INTO"
+    )]
+    decompile_into: Option<PathBuf>,
+    /// After fixing, load some of the fixed classes in a VM that rejects the
+    /// bad names (the JRE of the game if it's one, or the java on the PATH),
+    /// failing if they don't verify. Skipped if there's no such java, and
+    /// not for tarballs
+    #[structopt(long)]
+    smoke_test: bool,
+    /// Read the fixed jar back before it replaces anything, checking that
+    /// every class in it parses and writes back the same, that the constants
+    /// refer to the right kinds of constants, and that the fixed classes
+    /// have no bad names left. Done without asking when fixing in place
+    #[structopt(long)]
+    verify: bool,
+    /// Don't read the jar back when fixing in place
+    #[structopt(long, conflicts_with = "verify")]
+    no_verify: bool,
+    /// Write a zip with the log, the classes that failed to be fixed and
+    /// some information about the system, to attach to bug reports
+    #[structopt(
+        long,
+        value_name = "zip",
+        global = true,
+        parse(from_os_str),
+        env = "STARSECTOR_FIXER_DEBUG_BUNDLE"
+    )]
+    debug_bundle: Option<PathBuf>,
+    /// The config file with the defaults for some of the options, by default
+    /// config.json in the config directory (like ~/.config/starsector-fixer
+    /// or %APPDATA%\starsector-fixer), or starsector-fixer.json next to this
+    /// executable with --portable, if it's there
+    #[structopt(
+        long,
+        value_name = "file",
+        global = true,
+        parse(from_os_str),
+        env = "STARSECTOR_FIXER_CONFIG"
+    )]
+    config: Option<PathBuf>,
+    /// Keep the config, the registry and the journals next to this
+    /// executable instead, for when it's carried around on a USB stick or
+    /// kept in the game directory
+    #[structopt(long, global = true)]
+    portable: bool,
+    /// Run at the lowest CPU and disk priority, so that fixing in the
+    /// background (like with the daemon) doesn't get in the way of anything
+    #[structopt(long, global = true)]
+    nice: bool,
+    /// What the commands that look at the whole install (gen-wrapper and
+    /// doctor) are for: starsector, generic (all the jars in the directory)
+    /// or a TOML file describing some other game or tool. It also decides
+    /// where the backups go and which of the fixing flags are on
+    #[structopt(
+        long,
+        value_name = "profile",
+        global = true,
+        parse(from_os_str),
+        env = "STARSECTOR_FIXER_PROFILE"
+    )]
+    profile: Option<OsString>,
+    /// The language of the reports and the messages for the players, en or
+    /// ru, the one of the system by default. The details of the errors and
+    /// the reports for looking into the jars are in English either way
+    #[structopt(
+        long,
+        value_name = "lang",
+        global = true,
+        possible_values = &["en", "ru"],
+        env = "STARSECTOR_FIXER_LANG"
+    )]
+    lang: Option<String>,
+    #[structopt(skip)]
+    loaded_profile: Profile,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+impl Opt {
+    fn scan_options(&self) -> scan::ScanOptions {
+        scan::ScanOptions {
+            max_depth: self.max_scan_depth,
+            follow_symlinks: self.follow_symlinks,
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
+        }
+    }
+
+    /// Clap takes the values of the options from the environment, but not
+    /// the flags, so those are done here
+    fn apply_env_flags(&mut self) {
+        let flags = [
+            ("FORCE", &mut self.force),
+            ("CLOBBER", &mut self.clobber),
+            ("FORCE_WRITABLE", &mut self.force_writable),
+            ("TRASH", &mut self.trash),
+            ("REPAIR_REF_KINDS", &mut self.repair_ref_kinds),
+            ("FIX_ALL_NAME_AND_TYPE", &mut self.fix_all_name_and_type),
+            ("VERIFY_REFS", &mut self.verify_refs),
+            ("SANITIZE_NAMES", &mut self.sanitize_names),
+            ("LENIENT", &mut self.lenient),
+            ("FOLLOW_SYMLINKS", &mut self.follow_symlinks),
+            ("EMBED_REPORT", &mut self.embed_report),
+            ("NO_PROVENANCE", &mut self.no_provenance),
+            ("KEEP_SIGNATURES", &mut self.keep_signatures),
+            ("RECURSE_ARCHIVES", &mut self.recurse_archives),
+            ("SMOKE_TEST", &mut self.smoke_test),
+            ("VERIFY", &mut self.verify),
+            ("NO_VERIFY", &mut self.no_verify),
+            ("PORTABLE", &mut self.portable),
+            ("NICE", &mut self.nice),
+            ("LOW_MEMORY", &mut self.low_memory),
+        ];
+        for (name, flag) in flags {
+            *flag |= env_flag(name).unwrap_or(false);
+        }
+    }
+}
+
+/// The value of the `STARSECTOR_FIXER_<name>` variable for a flag
+fn env_flag(name: &str) -> Option<bool> {
+    let var = format!("STARSECTOR_FIXER_{}", name);
+    let value = std::env::var(&var).ok()?;
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "" | "0" | "false" | "no" | "off" => Some(false),
+        _ => usage_error(&format!("{} should be true or false, not '{}'", var, value)),
+    }
+}
+
+#[derive(Debug, Clone, StructOpt)]
+enum Command {
+    /// Fix the jars, same as with no subcommand.
+    ///
+    /// Takes the inputs and all of the options that `starsector-fixer
+    /// --help` lists, for the scripts to say what they do
+    #[structopt(
+        setting = structopt::clap::AppSettings::TrailingVarArg,
+        setting = structopt::clap::AppSettings::AllowLeadingHyphen
+    )]
+    Fix {
+        /// The inputs and the options, as they would be given without `fix`
+        #[structopt(parse(from_os_str), allow_hyphen_values = true)]
+        args: Vec<OsString>,
+    },
+    /// Put the backups made by the fixing back in place of the jars.
+    ///
+    /// The backup is checked to be a readable archive first, and the jar
+    /// is replaced at once, so it's either the fixed one or the original.
+    /// The backup is removed after
+    Restore {
+        /// The jars (or their backups) to restore, or the directories to
+        /// restore all of the jars with backups in
+        #[structopt(parse(from_os_str), required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Apply a patch made with --emit-patch to the original jar.
+    ///
+    /// Both the original and the result are checked against the hashes
+    /// stored in the patch, so it is not possible to end up with a broken jar
+    /// by applying the patch to the wrong file. The -o and -f options work
+    /// the same way as when fixing
+    ApplyPatch {
+        /// The original, unfixed, JAR file
+        #[structopt(parse(from_os_str))]
+        original: PathBuf,
+        /// The patch file
+        #[structopt(parse(from_os_str))]
+        patch: PathBuf,
+    },
+    /// List the classes and members that are never referenced from
+    /// anywhere in the jar.
+    ///
+    /// In obfuscated jars those are likely to be decoys. This is only a
+    /// heuristic, things used through reflection or from other jars are
+    /// listed as well. Nothing is changed
+    Unused {
+        /// The JAR file to analyze
+        #[structopt(parse(from_os_str))]
+        jar: PathBuf,
+    },
+    /// Give an overview of the jar: what's in it, which Java versions the
+    /// classes are for, whether it's signed, and whether it has any names
+    /// that need fixing
+    Info {
+        /// The JAR file to look at
+        #[structopt(parse(from_os_str))]
+        jar: PathBuf,
+    },
+    /// Compare two jars entry by entry, showing the files that were added,
+    /// removed or changed, and for the classes what changed in them.
+    ///
+    /// For checking what a fix, or a game update, actually did
+    Diff {
+        /// The old JAR file
+        #[structopt(parse(from_os_str))]
+        a: PathBuf,
+        /// The new JAR file
+        #[structopt(parse(from_os_str))]
+        b: PathBuf,
+    },
+    /// Find every reference to the fields and methods with the given name
+    /// in all of the jars, which tells whether renaming it is safe
+    Usages {
+        /// The name of the field or method
+        name: String,
+        /// The JAR files to look in, the first one having a class wins, as
+        /// on the classpath. Can be given many times, or as one list with
+        /// the system path separator like java's -cp
+        #[structopt(
+            short = "c",
+            long = "cp",
+            alias = "classpath",
+            required = true,
+            parse(from_os_str)
+        )]
+        classpath: Vec<OsString>,
+        /// Only the references to the member of this class, in the internal
+        /// form (com/fs/starfarer/Something)
+        #[structopt(long)]
+        owner: Option<String>,
+    },
+    /// Write a Graphviz graph of the classes that use the members with bad
+    /// names, with edges to the classes declaring them.
+    ///
+    /// Shows what depends on the members that will be renamed
+    Graph {
+        /// The JAR file to look at
+        #[structopt(parse(from_os_str))]
+        jar: PathBuf,
+        /// Where to write the DOT file, instead of the standard output
+        #[structopt(long, parse(from_os_str))]
+        out: Option<PathBuf>,
+        /// Include every reference from one class in the jar to another
+        #[structopt(long)]
+        all: bool,
+    },
+    /// Search the UTF8 constants of all the classes in the jar, which hold
+    /// all of the names and the strings, printing the class, the index of
+    /// the constant and its value for each match
+    Grep {
+        /// The JAR file to search
+        #[structopt(parse(from_os_str))]
+        jar: PathBuf,
+        /// The text to look for
+        pattern: String,
+        /// Treat the pattern as a regular expression
+        #[structopt(short = "e", long)]
+        regex: bool,
+        /// Ignore the case when matching
+        #[structopt(short, long)]
+        ignore_case: bool,
+    },
+    /// Tell which classes in the jar have names that need fixing, without
+    /// changing anything, exiting with 2 if there are any (with 1 being
+    /// the errors), for the CI
+    Check {
+        /// The JAR file to check
+        #[structopt(parse(from_os_str))]
+        jar: PathBuf,
+        /// List every bad name, with what it's the name of and the index of
+        /// the constant it's in, instead of just counting them per class
+        #[structopt(long)]
+        details: bool,
+        /// Also go through the code of every method, reporting the bad names
+        /// that the instructions can still get to in any way. Nothing being
+        /// found in a fixed jar means that it was fixed completely
+        #[structopt(long)]
+        deep: bool,
+        /// 'json' writes everything found, like with --details, as JSON
+        /// for the scripts
+        #[structopt(
+            long,
+            value_name = "format",
+            possible_values = &["text", "json"],
+            default_value = "text"
+        )]
+        format: check::Format,
+    },
+    /// Turn the fixed jar back into the original one, with what the fixing
+    /// recorded in it, for when the backup is gone.
+    ///
+    /// The classes and the manifest come out exactly as they were, the jar
+    /// itself can differ in how the entries are compressed. The archives
+    /// fixed with --recurse-archives stay fixed. Replaces the jar (keeping
+    /// a backup), or writes the -o file
+    Unfix {
+        /// The fixed JAR file
+        #[structopt(parse(from_os_str))]
+        jar: PathBuf,
+    },
+    /// Write the class file out as text (a subset of YAML) with every part
+    /// of it there to be edited, for `asm` to make the class out of again.
+    ///
+    /// Goes to the -o file, or to the standard output. The text is checked
+    /// to make the very same class before it's written
+    Disasm {
+        /// The class file
+        #[structopt(parse(from_os_str))]
+        class: PathBuf,
+    },
+    /// Make the class file out of the text written by `disasm`.
+    ///
+    /// Goes to the -o file, or next to the text with the .class extension
+    Asm {
+        /// The text file
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+    /// Fix the jars, then run the command that starts the game.
+    ///
+    /// The game is started even if fixing fails. The fixed jars are recorded
+    /// in a journal (in the data directory, unless --journal is given), so
+    /// only the ones replaced by a game update are fixed again
+    Wrap {
+        /// The jars, or the directories with them, to fix
+        #[structopt(required = true, parse(from_os_str))]
+        inputs: Vec<PathBuf>,
+        /// The command that starts the game, after --
+        #[structopt(last = true, required = true, parse(from_os_str))]
+        command: Vec<OsString>,
+    },
+    /// Keep watching the jars, and fix them again whenever they are replaced
+    /// with the unfixed ones, like when the game is updated.
+    ///
+    /// Runs until it's killed, so it's meant to be started from a user
+    /// service or the startup folder. The jars are checked every few
+    /// seconds, and recorded in a journal like with wrap
+    Daemon {
+        /// The jars, or the directories with them, to watch
+        #[structopt(required = true, parse(from_os_str))]
+        inputs: Vec<PathBuf>,
+        /// How often to check the jars, in seconds
+        #[structopt(long, value_name = "seconds", default_value = "10")]
+        interval: u64,
+        /// Show a desktop notification when something gets fixed
+        #[structopt(long)]
+        notify: bool,
+        /// Keep the status (the jars watched, the fixes, the failures and
+        /// how the last run went) in this file, in the Prometheus text
+        /// format, for monitoring that the fixing actually works
+        #[structopt(
+            long,
+            value_name = "file",
+            parse(from_os_str),
+            env = "STARSECTOR_FIXER_STATUS_FILE"
+        )]
+        status_file: Option<PathBuf>,
+    },
+    /// Run an HTTP service that fixes the jars POSTed to /fix, sending the
+    /// fixed jar back, or its report with /fix?report.
+    ///
+    /// For hosting the fixer somewhere for the people who can't run it.
+    /// There's no TLS and the requests are handled one at a time, so it's
+    /// meant to be behind a proper web server. The fixing options apply to
+    /// every upload, and --time-limit to each one of them
+    Serve {
+        /// The address and the port to listen on
+        #[structopt(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+        /// Refuse the uploads bigger than this many bytes
+        #[structopt(long, value_name = "bytes", default_value = "268435456")]
+        max_upload: u64,
+    },
+    /// Print the launch options that make Steam fix the game whenever it's
+    /// started, with the game added to Steam as a non-Steam game.
+    ///
+    /// Paste them into the launch options in the properties of the game in
+    /// Steam. They are not written into the Steam config, since Steam
+    /// overwrites it while running and keeps the non-Steam games in a binary
+    /// file that's not safe to edit from outside
+    SteamHook {
+        /// The jars, or the directories with them, to fix, like the
+        /// starsector-core directory
+        #[structopt(required = true, parse(from_os_str))]
+        inputs: Vec<PathBuf>,
+    },
+    /// Look at everything about the install of the game that matters for
+    /// running it fixed: the JRE it uses and whether that one cares about
+    /// the names, which jars (of the game and the mods) still need fixing,
+    /// the saves with the original names, the space and the permissions
+    /// for fixing, and the signed jars. Ends with what to do about it, the
+    /// most important first, offering to fix the jars
+    Doctor {
+        /// The directory the game is installed in
+        #[structopt(default_value = ".", parse(from_os_str))]
+        install: PathBuf,
+        /// Fix the jars that need it without asking
+        #[structopt(short, long)]
+        yes: bool,
+    },
+    /// Tell what went wrong from a crash log of the VM (an hs_err_pid file)
+    /// or the log of the game, by the errors known to stop it from starting,
+    /// whether this fixes them, and the command to do it with
+    Explain {
+        /// The log, like starsector-core/starsector.log
+        #[structopt(parse(from_os_str))]
+        log: PathBuf,
+    },
+    /// Tell whether the jars fixed with the registry are still the fixed
+    /// ones, or were replaced, like by an update of the game, which makes it
+    /// stop starting until they are fixed again
+    Status,
+    /// Rename the fields in the saves of a campaign like the registry says,
+    /// for the saves made with the unfixed game (or one fixed without the
+    /// registry) to load in the fixed one.
+    ///
+    /// Every .xml in the directory is done, backed up first like the jars
+    /// are. Needs the registry the jars were fixed with, the --registry
+    /// file or the one in the data directory
+    MigrateSave {
+        /// The directory of the campaign, like saves/save_Name_123, or a
+        /// single file of it
+        #[structopt(parse(from_os_str))]
+        campaign: PathBuf,
+    },
+    /// Find the game and fix it, for running it on a newer Java.
+    ///
+    /// Looks in the places the game is usually installed in (and in the
+    /// current directory and the one of the fixer), fixes the jars of the
+    /// game and the mods that need it, keeping the backups, and tells what
+    /// was done. `restore` puts the backups back
+    Starsector {
+        /// The directory the game is installed in, instead of looking for it
+        #[structopt(long, parse(from_os_str))]
+        game_dir: Option<PathBuf>,
+    },
+    /// Write a script into the game directory that fixes the game and then
+    /// starts it, to start the game with instead of the usual launcher.
+    ///
+    /// It's a .bat on Windows, a .command on macOS and a .sh elsewhere.
+    /// The -f and --no-clobber options decide what happens when the script
+    /// is already there
+    GenWrapper {
+        /// The directory the game is installed in
+        #[structopt(parse(from_os_str))]
+        game_dir: PathBuf,
+        /// What starts the game, relative to the game directory, for when
+        /// it's not the usual launcher of the platform
+        #[structopt(long, value_name = "file", parse(from_os_str))]
+        launcher: Option<PathBuf>,
+    },
 }
 
 fn main() -> Result<()> {
-    env_logger::builder()
+    let long_version = long_version();
+    let app = Opt::clap().long_version(long_version.as_str());
+    let args = expand_response_files(std::env::args_os());
+    let mut opt = Opt::from_clap(&app.clone().get_matches_from(&args));
+    // the options of `fix` are the ones without a subcommand, it's parsed
+    // again without it for them to be taken wherever they are
+    if let Some(Command::Fix { args: after }) = &opt.command {
+        // the global options like -o are not in the args of `fix` even when
+        // they are after it, so it's looked for anywhere before its own ones
+        let mut args = args.clone();
+        let before = args.len().saturating_sub(after.len());
+        if let Some(i) = args[1..before].iter().position(|arg| arg == "fix") {
+            args.remove(i + 1);
+        }
+        opt = Opt::from_clap(&app.get_matches_from(args));
+    }
+
+    let bundle = opt
+        .debug_bundle
+        .as_ref()
+        .map(|_| bundle::Collector::default());
+
+    let mut logger = env_logger::builder();
+    logger
         .format_timestamp(None)
         .format_target(false)
         .filter_level(LevelFilter::Info)
-        .parse_env(env_logger::Env::default())
-        .init();
-
-    let opt = Opt::from_args();
-
-    let in_place = opt.output.is_none();
-    let work_file = opt
-        .output
-        .unwrap_or_else(|| opt.input.with_extension("jar.temp"));
-
-    let input = File::open(&opt.input)
-        .with_context(|| format!("Reading archive {}", opt.input.display()))?;
-    let mut output = ZipWriter::new(File::create(&work_file)?);
-    let mut zip = ZipArchive::new(input)?;
-
-    for i in 0..zip.len() {
-        let mut file = zip.by_index(i)?;
-        if !file.is_file() || !file.name().ends_with(".class") {
-            drop(file); // release the `&mut zip` used by `file`
-            output.raw_copy_file(zip.by_index_raw(i)?)?;
-            continue;
-        }
-        let mut buf = Vec::with_capacity(8096);
-        file.read_to_end(&mut buf)?;
+        .parse_env(env_logger::Env::default());
+    match &bundle {
+        Some(bundle) => bundle.init_logger(logger.build()),
+        None => logger.init(),
+    }
+
+    interrupt::install();
+    i18n::init(opt.lang.as_deref())?;
+    opt.apply_env_flags();
+    let config = config::Config::load(opt.config.as_deref(), opt.portable)?;
+    let no_clobber = env_flag("NO_CLOBBER").unwrap_or(config.no_clobber);
+    opt.no_clobber |= no_clobber && !opt.clobber;
+    if let Some(profile) = &opt.profile {
+        opt.loaded_profile = Profile::load(profile)?;
+    }
+    let passes = opt.loaded_profile.passes.clone();
+    opt.repair_ref_kinds |= passes.repair_ref_kinds;
+    opt.fix_all_name_and_type |= passes.fix_all_name_and_type;
+    opt.verify_refs |= passes.verify_refs;
+    opt.sanitize_names |= passes.sanitize_names;
 
-        log::debug!("Checking {}", file.name());
-        if let Some(updated_bytecode) =
-            fix_class(&buf, file.name()).with_context(|| format!("Processing {}", file.name()))?
-        {
-            log::info!("Processed {}", file.name());
-            let mut options = FileOptions::default()
-                .large_file(file.compressed_size().max(file.size()) > u32::MAX as u64)
-                .last_modified_time(file.last_modified())
-                .compression_method(file.compression());
-            if let Some(perms) = file.unix_mode() {
-                options = options.unix_permissions(perms);
+    if opt.nice {
+        match &opt.command {
+            // children get the priority too, and the game is one
+            Some(Command::Wrap { .. }) => {
+                log::warn!("Not lowering the priority with wrap, the game would run with it too")
+            }
+            _ => {
+                if let Err(e) = priority::lower() {
+                    log::warn!("{:#}", e);
+                }
             }
-            output.start_file(file.name(), options)?;
-            output.write_all(&updated_bytecode)?;
-        } else {
-            drop(file); // ditto
-            output.raw_copy_file(zip.by_index_raw(i)?)?
         }
     }
 
-    if in_place {
-        if !opt.force {
-            std::fs::copy(&opt.input, opt.input.with_extension("jar.bak"))
-                .context("Creating backup")?;
+    if opt.command.is_some() && !opt.inputs.is_empty() {
+        usage_error("The input should not be given together with a subcommand");
+    }
+
+    let mut needs_fixing = false;
+    let result = match &opt.command {
+        Some(Command::ApplyPatch { original, patch }) => apply_patch(&opt, original, patch),
+        Some(Command::Unused { jar }) => report_unused(jar),
+        Some(Command::Info { jar }) => report_info(jar),
+        Some(Command::Diff { a, b }) => report_diff(a, b),
+        Some(Command::Usages {
+            name,
+            classpath,
+            owner,
+        }) => report_usages(name, classpath, owner.as_deref()),
+        Some(Command::Graph { jar, out, all }) => write_graph(jar, out.as_deref(), *all),
+        Some(Command::Grep {
+            jar,
+            pattern,
+            regex,
+            ignore_case,
+        }) => report_grep(jar, pattern, *regex, *ignore_case),
+        Some(Command::Check {
+            jar,
+            details,
+            deep,
+            format,
+        }) => report_check(jar, *details, *deep, *format).map(|found| needs_fixing = found),
+        Some(Command::Unfix { jar }) => unfix(&opt, jar),
+        Some(Command::Restore { paths }) => restore(&opt, paths),
+        Some(Command::Disasm { class }) => disassemble(&opt, class),
+        Some(Command::Asm { file }) => assemble(&opt, file),
+        Some(Command::Wrap { inputs, command }) => wrap(&opt, inputs, command, bundle.as_ref()),
+        Some(Command::SteamHook { inputs }) => print_steam_hook(inputs),
+        Some(Command::Daemon {
+            inputs,
+            interval,
+            notify,
+            status_file,
+        }) => daemon(
+            &opt,
+            inputs,
+            *interval,
+            *notify,
+            status_file.as_deref(),
+            bundle.as_ref(),
+        ),
+        Some(Command::Serve { listen, max_upload }) => serve(&opt, listen, *max_upload),
+        Some(Command::Doctor { install, yes }) => doctor(&opt, install, *yes),
+        Some(Command::Explain { log }) => report_explain(&opt, log),
+        Some(Command::Status) => report_status(&opt),
+        Some(Command::MigrateSave { campaign }) => migrate_save(&opt, campaign),
+        Some(Command::Starsector { game_dir }) => fix_starsector(&opt, game_dir.as_deref()),
+        Some(Command::GenWrapper { game_dir, launcher }) => {
+            gen_wrapper(&opt, game_dir, launcher.as_deref())
         }
-        std::fs::rename(work_file, opt.input)
-            .context("Moving the file that was worked on in place of the original")?;
+        None if !opt.queue.is_empty() => match opt.inputs.is_empty() {
+            true => run_queue(&opt, &opt.queue[0], &opt.queue[1]),
+            false => usage_error("The inputs can't be given with --queue"),
+        },
+        None if opt.inputs.is_empty() => usage_error("The input file is required"),
+        // parsed again into no subcommand above
+        Some(Command::Fix { .. }) => unreachable!(),
+        None => fix_all(&opt, bundle.as_ref()).map(drop),
+    };
+
+    if let (Some(bundle), Some(path)) = (&bundle, &opt.debug_bundle) {
+        if let Err(e) = bundle.write(path, &opt, &result) {
+            log::error!("Could not write the debug bundle: {:#}", e);
+        }
+    }
+    if needs_fixing && result.is_ok() {
+        std::process::exit(2);
     }
+    result
+}
 
-    Ok(())
+/// Replaces the `@file` arguments with the lines of the file, one argument
+/// per line, since the command line length on Windows is too short for
+/// lots of jars
+fn expand_response_files(args: impl Iterator<Item = OsString>) -> Vec<OsString> {
+    let mut expanded = Vec::new();
+    for arg in args {
+        let path = match arg.to_str().and_then(|arg| arg.strip_prefix('@')) {
+            Some(path) => path,
+            None => {
+                expanded.push(arg);
+                continue;
+            }
+        };
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) => usage_error(&format!(
+                "Could not read the arguments from {}: {}",
+                path, e
+            )),
+        };
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            // the paths copied from the explorer come quoted
+            let line = match line.strip_prefix('"').and_then(|l| l.strip_suffix('"')) {
+                Some(unquoted) => unquoted,
+                None => line,
+            };
+            expanded.push(line.into());
+        }
+    }
+    expanded
 }
 
-fn fix_class(bytecode: &[u8], filename: &str) -> Result<Option<Vec<u8>>> {
-    let mut stream = Cursor::new(bytecode);
+fn usage_error(message: &str) -> ! {
+    structopt::clap::Error::with_description(
+        message,
+        structopt::clap::ErrorKind::MissingRequiredArgument,
+    )
+    .exit()
+}
 
-    ensure!(stream.read_u32::<BE>()? == 0xCAFEBABE, "Bad magic number");
+/// What fixing one of the inputs did, for the summary
+#[derive(Debug, Clone, Copy)]
+enum Outcome {
+    /// With how many classes were fixed, when that's known (it's not for
+    /// the tarballs and the patches)
+    Fixed(Option<usize>),
+    NothingToFix,
+    AlreadyFixed,
+}
 
-    // skip u2 minor_version, u2 major_version
-    stream.seek(SeekFrom::Current(4))?;
+/// Fixes every input, returning how many of them were rewritten
+fn fix_all(opt: &Opt, bundle: Option<&bundle::Collector>) -> Result<usize> {
+    let inputs = scan::expand(&opt.inputs, &opt.scan_options())?;
+    if inputs.is_empty() {
+        log::warn!("{}", tr!("fix-no-archives"));
+    }
+    if inputs.len() > 1 {
+        let single_only = [
+            ("-o", opt.output.is_some()),
+            ("--emit-patch", opt.emit_patch.is_some()),
+            ("--sha256", opt.sha256.is_some()),
+        ];
+        if let Some((option, _)) = single_only.iter().find(|(_, given)| *given) {
+            usage_error(&format!("{} only works with a single input", option));
+        }
+    }
 
-    let constant_pool_count = stream.read_u16::<BE>()? as usize;
-    log::debug!("Constant pool entry count is {}", constant_pool_count);
+    let registry = match &opt.registry {
+        Some(Some(path)) => Some(Arc::new(Mutex::new(Registry::load(path)?))),
+        Some(None) => {
+            let path = dirs::data_file(opt.portable, "registry.json")?;
+            Some(Arc::new(Mutex::new(Registry::load(&path)?)))
+        }
+        None => None,
+    };
+    let options = fix_options(opt, registry.clone(), bundle)?;
+    let journal = opt
+        .journal
+        .as_deref()
+        .map(Journal::open)
+        .transpose()?
+        .map(Mutex::new);
+    let base = common_base(&opt.inputs);
 
-    let mut constant_pool = Vec::with_capacity(constant_pool_count);
-    // take up unused zeroeth index
-    constant_pool.push(ConstantItem::Ignored);
+    let fix_input = |input: &PathBuf| -> Result<Outcome> {
+        // so that the registry is saved for the jar that was written
+        let _working = interrupt::working();
+        interrupt::check()?;
+        if let Some(journal) = &journal {
+            let done = journal
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_done(input)?;
+            if done {
+                log::info!("{}", tr!("fix-skipping", jar = input.display()));
+                return Ok(Outcome::AlreadyFixed);
+            }
+        }
+        let output = output_path(opt, input, &base)?;
+        if let Some(parent) = output.as_deref().and_then(Path::parent) {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Creating {}", parent.display()))?;
+        }
+        let result = output.clone().unwrap_or_else(|| input.clone());
+        let outcome = fix(opt, input, output, &options, journal.as_ref())
+            .with_context(|| format!("Fixing {}", input.display()))?;
 
-    while constant_pool.len() < constant_pool_count {
-        let constant_item = ConstantItem::read(&mut stream)?;
-        log::debug!("Constant #{} is {:?}", constant_pool.len(), constant_item);
-        if matches!(constant_item, ConstantItem::DoubleEntry) {
-            // longs and doubles take up two indices
-            constant_pool.push(ConstantItem::Ignored);
+        // after every input, so the renames in it are all real, and none
+        // are lost if a later one fails
+        if let Some(registry) = &registry {
+            let mut registry = registry.lock().unwrap_or_else(PoisonError::into_inner);
+            if result.is_file() {
+                registry.record_jar(&paths::absolute(&result)?, hash::sha256_file(&result)?);
+            }
+            registry.save()?;
+        }
+        Ok(outcome)
+    };
+
+    // each thread that's free takes the next jar, but with a registry they
+    // go one by one, for the renames in it to be real and the same every
+    // time. The first failure stops the jars after it from being started
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let results = inputs.iter().map(|_| Mutex::new(None)).collect::<Vec<_>>();
+    let work = || {
+        while !failed.load(Ordering::SeqCst) {
+            let i = next.fetch_add(1, Ordering::SeqCst);
+            let input = match inputs.get(i) {
+                Some(input) => input,
+                None => break,
+            };
+            let result = fix_input(input);
+            failed.fetch_or(result.is_err(), Ordering::SeqCst);
+            *results[i].lock().unwrap_or_else(PoisonError::into_inner) = Some(result);
+        }
+    };
+    let helpers = match (&options.jobs, &registry) {
+        (Some(jobs), None) => jobs.take_up_to(inputs.len().saturating_sub(1)),
+        _ => Vec::new(),
+    };
+    std::thread::scope(|scope| {
+        for job in helpers {
+            scope.spawn(move || {
+                let _job = job;
+                work()
+            });
+        }
+        work();
+    });
+    let mut outcomes = Vec::with_capacity(inputs.len());
+    for (input, result) in inputs.iter().zip(results) {
+        // the ones not started are after a failure
+        if let Some(result) = result.into_inner().unwrap_or_else(PoisonError::into_inner) {
+            outcomes.push((input, result?));
         }
-        constant_pool.push(constant_item);
     }
 
-    // u2 access_flags, u2 this_class, u2 super_class
-    stream.seek(SeekFrom::Current(6))?;
+    if let (Some(path), Some(mapped)) = (&opt.write_mapping, &options.mapped) {
+        let mapped = mapped.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut out = Vec::new();
+        mapping::write(&mapped, &mut out)?;
+        std::fs::write(path, out).with_context(|| format!("Writing {}", path.display()))?;
+        log::info!(
+            "Wrote the mapping of {} renames to {}",
+            mapped.len(),
+            path.display()
+        );
+    }
 
-    let interfaces_count = stream.read_u16::<BE>()?;
+    let fixed = outcomes
+        .iter()
+        .filter(|(_, outcome)| matches!(outcome, Outcome::Fixed(_)))
+        .count();
+    // the logs of a lot of jars are too long to see what happened to each
+    if outcomes.len() > 1 {
+        for (input, outcome) in &outcomes {
+            let jar = input.display();
+            match outcome {
+                Outcome::Fixed(Some(count)) => {
+                    outln!("{}", tr!("fix-summary-fixed", jar = jar, count = count))
+                }
+                Outcome::Fixed(None) => outln!("{}", tr!("fix-summary-changed", jar = jar)),
+                Outcome::NothingToFix => outln!("{}", tr!("fix-summary-nothing", jar = jar)),
+                Outcome::AlreadyFixed => outln!("{}", tr!("fix-summary-already", jar = jar)),
+            }
+        }
+        outln!(
+            "{}",
+            tr!("fix-summary", fixed = fixed, total = outcomes.len())
+        );
+    }
+    Ok(fixed)
+}
 
-    // skip u2 interfaces[interfaces_count]
-    stream.seek(SeekFrom::Current(2 * interfaces_count as i64))?;
+/// The --max-memory of --low-memory
+const LOW_MEMORY: u64 = 64 * 1024 * 1024;
 
-    let mut member_name_indices = Vec::new();
+/// The options for fixing, as given on the command line
+fn fix_options(
+    opt: &Opt,
+    registry: Option<Arc<Mutex<Registry>>>,
+    bundle: Option<&bundle::Collector>,
+) -> Result<FixOptions> {
+    let mut options = FixOptions {
+        class_version: opt.set_class_version,
+        source_file: opt.sanitize_source_file,
+        registry,
+        repair_ref_kinds: opt.repair_ref_kinds,
+        all_name_and_type: opt.fix_all_name_and_type,
+        verify_refs: opt.verify_refs,
+        sanitize_names: opt.sanitize_names,
+        rules: match (&opt.rules, opt.rename_pattern.is_empty()) {
+            (None, true) => None,
+            (path, _) => {
+                let mut rules = match path {
+                    Some(path) => rules::Rules::load(path)?,
+                    None => rules::Rules::empty(),
+                };
+                rules.add_patterns(&opt.rename_pattern);
+                Some(Arc::new(rules))
+            }
+        },
+        mapping: match &opt.apply_mapping {
+            Some(path) => Some(Arc::new(mapping::Mapping::load(path)?)),
+            None => None,
+        },
+        mapped: opt.write_mapping.as_ref().map(|_| Default::default()),
+        unknown_tags: opt.unknown_constant_tag,
+        on_collision: opt.on_collision,
+        collisions: None,
+        recurse_archives: opt.recurse_archives,
+        only_packages: opt.only_package.clone(),
+        known_hashes: None,
+        embed_report: opt.embed_report,
+        now: None,
+        renames: None,
+        provenance: !opt.no_provenance,
+        keep_signatures: opt.keep_signatures,
+        memory: Budget::new(match opt.low_memory {
+            true => opt.max_memory.or(Some(LOW_MEMORY)),
+            false => opt.max_memory,
+        }),
+        jobs: Some(Arc::new(jobs::Jobs::new(match opt.low_memory {
+            true => opt.jobs.unwrap_or(1),
+            false => opt.jobs.unwrap_or_default(),
+        }))),
+        buffer_size: opt.buffer_size.unwrap_or_default(),
+        low_memory: opt.low_memory,
+        lenient: opt.lenient,
+        trailing_garbage: opt.trailing_garbage,
+        failed_classes: bundle.map(|b| b.failed_classes.clone()),
+        limits: Limits {
+            max_class_size: opt.max_class_size,
+            max_constant_pool: opt.max_constant_pool,
+            max_depth: opt.max_depth,
+            deadline: opt
+                .time_limit
+                .map(|secs| Instant::now() + Duration::from_secs(secs)),
+        },
+    };
+    let known = KnownHashes::load(opt.known_hashes.as_deref())?;
+    if !known.is_empty() && options.is_standard() {
+        options.known_hashes = Some(Arc::new(known));
+    }
+    Ok(options)
+}
 
-    let mut read_member_name_indices = |member_type: &str| -> Result<()> {
-        let count = stream.read_u16::<BE>()?;
-        log::debug!("{} count is {}", member_type, count);
-        for _ in 0..count {
-            stream.seek(SeekFrom::Current(2))?; // u2 access_flags;
-            let name_index = stream.read_u16::<BE>()?;
-            log::debug!(
-                "{} name is a constant #{} -> {:?}",
-                member_type,
-                name_index,
-                constant_pool[name_index as usize],
-            );
-            member_name_indices.push(name_index);
-            stream.seek(SeekFrom::Current(2))?; // u2 descriptor_index;
+/// The deepest directory with all of the (local) inputs in it, which the
+/// layout in --output-dir mirrors
+fn common_base(inputs: &[PathBuf]) -> PathBuf {
+    let mut base: Option<PathBuf> = None;
+    for input in inputs.iter().filter(|i| download::as_url(i).is_none()) {
+        let dir = paths::absolute(input)
+            .ok()
+            .and_then(|path| match path.is_dir() {
+                true => Some(path),
+                false => path.parent().map(Path::to_owned),
+            })
+            .unwrap_or_default();
+        base = Some(match base {
+            None => dir,
+            Some(base) => base
+                .components()
+                .zip(dir.components())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect(),
+        });
+    }
+    base.unwrap_or_default()
+}
 
-            // yeah all of the below is just a complicated skip
-            let attributes_count = stream.read_u16::<BE>()?;
-            for _ in 0..attributes_count {
-                stream.seek(SeekFrom::Current(2))?; // u2 attribute_name_index;
-                let attribute_length = stream.read_u32::<BE>()?;
-                stream.seek(SeekFrom::Current(attribute_length as i64))?; // u1 info[attribute_length];
+/// Where the fixed input goes, `None` meaning in place
+fn output_path(opt: &Opt, input: &Path, base: &Path) -> Result<Option<PathBuf>> {
+    if let Some(output) = &opt.output {
+        return Ok(Some(output.clone()));
+    }
+    // downloaded inputs are fixed into a local file, by default named
+    // the same as the one in the URL
+    let relative = match download::as_url(input) {
+        Some(url) => PathBuf::from(
+            download::file_name(url).context("Could not get the file name from the URL, use -o")?,
+        ),
+        None => match &opt.output_dir {
+            Some(_) => {
+                // not just joined, since an absolute path (on another drive
+                // than the rest) would replace the directory
+                paths::relative_to(&paths::absolute(input)?, base)
             }
+            None if opt.output_name.is_some() => input.to_owned(),
+            None => return Ok(None),
+        },
+    };
+    let relative = match &opt.output_name {
+        Some(template) => relative.with_file_name(expand_name(template, &relative)?),
+        None => relative,
+    };
+    let output = match &opt.output_dir {
+        Some(dir) => dir.join(relative),
+        None => relative,
+    };
+    // a template that keeps the name is the same as fixing in place, which
+    // makes the backup
+    if output == input {
+        return Ok(None);
+    }
+    Ok(Some(output))
+}
+
+/// Fills in the --output-name template with the parts of the file name
+fn expand_name(template: &str, path: &Path) -> Result<OsString> {
+    // the parts as they are, a name that's not valid UTF-8 is still a name
+    let mut name = OsString::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        name.push(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .with_context(|| format!("Unclosed {{ in the output name '{}'", template))?;
+        match &rest[start + 1..start + end] {
+            "name" => name.push(path.file_name().unwrap_or_default()),
+            "stem" => name.push(path.file_stem().unwrap_or_default()),
+            "ext" => name.push(path.extension().unwrap_or_default()),
+            other => bail!("Unknown placeholder {{{}}} in the output name", other),
         }
-        Ok(())
+        rest = &rest[start + end + 1..];
+    }
+    name.push(rest);
+    Ok(name)
+}
+
+/// What the user knows the input by
+fn display_name(input: &Path) -> String {
+    input.file_name().map_or_else(
+        || input.display().to_string(),
+        |name| name.to_string_lossy().into(),
+    )
+}
+
+/// Fixes the input into the output, or in place if there is none
+fn fix(
+    opt: &Opt,
+    input: &Path,
+    output: Option<PathBuf>,
+    options: &FixOptions,
+    journal: Option<&Mutex<Journal>>,
+) -> Result<Outcome> {
+    let original_input = input;
+    let downloaded;
+    let input = match download::as_url(input) {
+        Some(url) => {
+            downloaded = download::download(url)?;
+            downloaded.path()
+        }
+        None => input,
+    };
+
+    if let Some(expected) = &opt.sha256 {
+        hash::verify_sha256(input, expected)?;
+    }
+    // the name is what the user knows the jar by, not the temp download
+    let name = display_name(original_input);
+    let known = match &options.known_hashes {
+        Some(known) => Some((known, hash::sha256_file(input)?)),
+        None => None,
     };
 
-    read_member_name_indices("Field")?;
-    read_member_name_indices("Method")?;
+    if let Some(patch_file) = &opt.emit_patch {
+        // the original, the fixed one and the patch are all in memory
+        let size = std::fs::metadata(input).map_or(0, |m| m.len());
+        let _memory = options
+            .memory
+            .reserve(size.saturating_mul(3), "Making the patch")?;
+        let original =
+            std::fs::read(input).with_context(|| format!("Reading archive {}", input.display()))?;
 
-    let mut updated = None;
+        let mut fixed = Cursor::new(Vec::with_capacity(original.len()));
+        let changed = fix_file(input, &mut fixed, options)?;
+        let fixed = fixed.into_inner();
+        if let Some((known, original_hash)) = &known {
+            known.check(&name, original_hash, &hash::sha256_hex(&fixed));
+        }
 
-    let mut fix_name = |idx: usize| -> Result<()> {
-        if let ConstantItem::Utf8(s, class_offset) = &constant_pool[idx] {
-            if let Some(idx) = s.find('.') {
-                let owned = updated.get_or_insert_with(|| bytecode.to_owned());
-                let char = &mut owned[class_offset + idx];
-                // could've already been fixed by field/method def or other ref
-                if *char == '.' as u8 {
-                    log::info!("Fixing bad name '{}' in {}", s, filename);
-                    *char = '_' as u8;
+        if paths::same_file(input, patch_file) {
+            bail!("The patch {} would replace the input", patch_file.display());
+        }
+        let mut patch = Vec::new();
+        patch::encode(&original, &fixed, &mut patch).context("Generating the patch")?;
+        std::fs::write(patch_file, &patch)
+            .with_context(|| format!("Writing patch {}", patch_file.display()))?;
+        log::info!(
+            "Written a {} byte patch to {}",
+            patch.len(),
+            patch_file.display()
+        );
+
+        // the patch is the output, but the jar itself can be wanted as well
+        if let Some(output) = &output {
+            write_output(input, Some(output), opt, |work_file| {
+                std::fs::write(work_file, &fixed)
+                    .with_context(|| format!("Writing {}", output.display()))?;
+                match opt.verify && tar::Compression::detect(input).is_none() {
+                    true => verify_output(work_file, input, &[], options),
+                    false => Ok(()),
+                }
+            })?;
+        }
+        return Ok(match changed {
+            true => Outcome::Fixed(None),
+            false => Outcome::NothingToFix,
+        });
+    }
+
+    let output = match output {
+        Some(output) if paths::same_file(input, &output) => same_output(input, output),
+        output => output,
+    };
+    let result = output.as_deref().unwrap_or(input);
+    // tarballs are scanned jar by jar as they are rewritten
+    let fixes = match tar::Compression::detect(input) {
+        Some(_) if opt.decompile_with.is_some() || opt.smoke_test => {
+            log::warn!("Not decompiling or smoke testing anything from a tarball");
+            None
+        }
+        Some(_) => None,
+        None => {
+            let file = File::open(input)
+                .with_context(|| format!("Reading archive {}", input.display()))?;
+            match scan_jar(options.reader(file), options, 0)? {
+                Some(fixes) => Some(fixes),
+                None => {
+                    keep_as_is(opt, input, original_input, output, &known, journal)?;
+                    return Ok(Outcome::NothingToFix);
                 }
             }
-            Ok(())
-        } else {
-            bail!("Constant #{} is not a UTF8_INFO", idx);
         }
     };
+    let fixed_classes = fixes.as_ref().map(|fixes| fixes.names.clone());
+    // the tarballs are written as they are read, there's no reading them back
+    let verify = fixes.is_some() && (opt.verify || (output.is_none() && !opt.no_verify));
+    let mut changed = true;
+    write_output(input, output.as_deref(), opt, |work_file| {
+        let output = create_output(work_file)?;
+        match fixes {
+            Some(fixes) => write_jar(options.reader(File::open(input)?), output, options, fixes)?,
+            None => changed = fix_file(input, output, options)?,
+        }
+        if let (true, Some(classes)) = (verify, &fixed_classes) {
+            verify_output(work_file, input, classes, options)?;
+        }
+        if let Some((known, original_hash)) = &known {
+            known.check(&name, original_hash, &hash::sha256_file(work_file)?);
+        }
+        if let Some(journal) = journal {
+            journal
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .record(original_input, work_file, result)?;
+        }
+        Ok(())
+    })?;
 
-    for member_name_idx in member_name_indices {
-        fix_name(member_name_idx as usize)?;
+    if let (Some(decompiler), Some(classes)) = (&opt.decompile_with, &fixed_classes) {
+        let into = match &opt.decompile_into {
+            Some(dir) => dir.clone(),
+            None => with_suffix(&result.with_extension(""), "-src"),
+        };
+        // not worth failing the whole thing over, the jar is fixed already
+        if let Err(e) = decompile::decompile(decompiler, result, classes, &into) {
+            log::error!("Could not decompile the fixed classes: {:#}", e);
+        }
+    }
+    if let (true, Some(classes)) = (opt.smoke_test, &fixed_classes) {
+        match smoke::find_java(result, &opt.loaded_profile.jre_dirs) {
+            Some(java) => smoke::smoke_test(&java, result, classes)?,
+            None => log::warn!("Not smoke testing, there's no java that rejects the bad names"),
+        }
+    }
+    Ok(match (changed, &fixed_classes) {
+        (false, _) => Outcome::NothingToFix,
+        (true, classes) => Outcome::Fixed(classes.as_ref().map(Vec::len)),
+    })
+}
+
+/// Reads the fixed jar back with --verify, before it replaces anything
+fn verify_output(
+    work_file: &Path,
+    input: &Path,
+    fixed: &[String],
+    options: &FixOptions,
+) -> Result<()> {
+    let classes = verify::verify_jar(work_file, fixed, &options.limits).with_context(|| {
+        format!(
+            "The fixed {} is broken, so nothing was replaced with it",
+            input.display()
+        )
+    })?;
+    log::info!("Read back the {} classes of the fixed jar", classes);
+    Ok(())
+}
+
+/// The input has nothing to fix, so nothing is written, unless it's wanted
+/// somewhere else
+fn keep_as_is(
+    opt: &Opt,
+    input: &Path,
+    original_input: &Path,
+    output: Option<PathBuf>,
+    known: &Option<(&Arc<KnownHashes>, String)>,
+    journal: Option<&Mutex<Journal>>,
+) -> Result<()> {
+    log::info!("{}", tr!("fix-nothing", jar = original_input.display()));
+    if let Some(output) = &output {
+        write_output(input, Some(output), opt, |work_file| {
+            std::fs::copy(input, work_file)?;
+            Ok(())
+        })?;
     }
+    if let Some((known, original_hash)) = known {
+        known.check(&display_name(original_input), original_hash, original_hash);
+    }
+    if let Some(journal) = journal {
+        journal
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record(original_input, input, output.as_deref().unwrap_or(input))?;
+    }
+    Ok(())
+}
 
-    for constant in &constant_pool {
-        if let ConstantItem::Ref(ref_idx) = constant {
-            if let ConstantItem::NameAndType(name_idx) = constant_pool[*ref_idx as usize] {
-                fix_name(name_idx as usize)?;
+fn apply_patch(opt: &Opt, original: &Path, patch: &Path) -> Result<()> {
+    let source = std::fs::read(original)
+        .with_context(|| format!("Reading archive {}", original.display()))?;
+    let patch_bytes =
+        std::fs::read(patch).with_context(|| format!("Reading patch {}", patch.display()))?;
+
+    let result = patch::apply(&source, &patch_bytes).context("Applying the patch")?;
+
+    write_output(original, opt.output.as_deref(), opt, |work_file| {
+        std::fs::write(work_file, &result)?;
+        Ok(())
+    })?;
+    log::info!(
+        "Patched {}",
+        opt.output.as_deref().unwrap_or(original).display()
+    );
+    Ok(())
+}
+
+fn report_unused(jar: &Path) -> Result<()> {
+    let input = File::open(jar).with_context(|| format!("Reading archive {}", jar.display()))?;
+    let index = index::JarIndex::from_jar(BufReader::new(input), &Limits::default(), false)?;
+    let report = unused::find_unused(&index);
+
+    outln!("Unused classes ({}):", report.classes.len());
+    for class in &report.classes {
+        outln!("  {}", console::escaped(class));
+    }
+    outln!("Unused fields ({}):", report.fields.len());
+    for (owner, name, descriptor) in &report.fields {
+        outln!(
+            "  {}.{} {}",
+            console::escaped(owner),
+            console::escaped(name),
+            console::escaped(descriptor)
+        );
+    }
+    outln!("Unused methods ({}):", report.methods.len());
+    for (owner, name, descriptor) in &report.methods {
+        outln!(
+            "  {}.{}{}",
+            console::escaped(owner),
+            console::escaped(name),
+            console::escaped(descriptor)
+        );
+    }
+    Ok(())
+}
+
+fn wrap(
+    opt: &Opt,
+    inputs: &[PathBuf],
+    command: &[OsString],
+    bundle: Option<&bundle::Collector>,
+) -> Result<()> {
+    let opt = Opt {
+        inputs: inputs.to_owned(),
+        journal: opt
+            .journal
+            .clone()
+            .map_or_else(|| dirs::data_file(opt.portable, "journal.txt"), Ok)?
+            .into(),
+        ..opt.clone()
+    };
+    if let Err(e) = fix_all(&opt, bundle) {
+        log::error!("{:#}", e);
+        log::error!("{}", tr!("wrap-starting-anyway"));
+    }
+    launch::run(command)
+}
+
+fn daemon(
+    opt: &Opt,
+    inputs: &[PathBuf],
+    interval: u64,
+    notify: bool,
+    status_file: Option<&Path>,
+    bundle: Option<&bundle::Collector>,
+) -> Result<()> {
+    let journal = match &opt.journal {
+        Some(journal) => journal.clone(),
+        None => dirs::data_file(opt.portable, "journal.txt")?,
+    };
+    let scan_options = opt.scan_options();
+    let mut watcher = watch::Watcher::default();
+    let mut metrics = metrics::Metrics::default();
+    for input in inputs {
+        log::info!("Watching {}", input.display());
+    }
+    loop {
+        // the directories can be gone for a bit in the middle of an update
+        let files = scan::expand(inputs, &scan_options).unwrap_or_else(|e| {
+            log::warn!("{:#}", e);
+            Vec::new()
+        });
+        let changed = watcher.poll(&files);
+        metrics.jars_watched = files.len();
+        metrics.checks += 1;
+        if !changed.is_empty() {
+            for file in &changed {
+                log::info!("{} changed", file.display());
+            }
+            let opt = Opt {
+                inputs: changed.clone(),
+                journal: Some(journal.clone()),
+                ..opt.clone()
+            };
+            let started = Instant::now();
+            let result = fix_all(&opt, bundle);
+            metrics.last_run_duration = started.elapsed();
+            metrics.last_run = Some((result.is_ok(), SystemTime::now()));
+            match result {
+                Ok(fixed) => metrics.fixes += fixed as u64,
+                Err(_) => metrics.failures += 1,
+            }
+            match result {
+                Ok(_) if notify => launch::notify(&tr!("daemon-fixed")),
+                Ok(_) => {}
+                Err(e) => {
+                    log::error!("{:#}", e);
+                    if notify {
+                        let error = format!("{:#}", e);
+                        launch::notify(&tr!("daemon-failed", error = error));
+                    }
+                }
             }
+            // failing again every few seconds wouldn't help anyone
+            watcher.mark_handled(&changed);
         }
+        if let Some(status_file) = status_file {
+            if let Err(e) = metrics.write(status_file) {
+                log::warn!("{:#}", e);
+            }
+        }
+        std::thread::sleep(Duration::from_secs(interval));
     }
+}
+
+fn serve(opt: &Opt, listen: &str, max_upload: u64) -> Result<()> {
+    // the registry is for one user's own game, not for everyone's uploads
+    let options = fix_options(opt, None, None)?;
+    serve::run(listen, max_upload, |request| {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/") => serve::Response::text(
+                200,
+                "POST a jar to /fix to get it fixed, or to /fix?report for the report",
+            ),
+            ("POST", "/fix") => {
+                let report = request.query.split('&').any(|param| param == "report");
+                fix_upload(&request.body, &options, opt.time_limit, report).unwrap_or_else(|e| {
+                    log::warn!("Could not fix the upload: {:#}", e);
+                    serve::Response::text(422, format!("Could not fix the jar: {:#}", e))
+                })
+            }
+            (_, "/" | "/fix") => serve::Response::text(405, "Wrong method"),
+            _ => serve::Response::text(404, "Nothing here"),
+        }
+    })
+}
 
-    Ok(updated)
+/// The fixed jar, or the report of fixing it
+fn fix_upload(
+    jar: &[u8],
+    options: &FixOptions,
+    time_limit: Option<u64>,
+    report: bool,
+) -> Result<serve::Response> {
+    let options = FixOptions {
+        embed_report: options.embed_report || report,
+        limits: Limits {
+            deadline: time_limit.map(|secs| Instant::now() + Duration::from_secs(secs)),
+            ..options.limits
+        },
+        ..options.clone()
+    };
+    // the upload and the fixed one
+    let _memory = options
+        .memory
+        .reserve((jar.len() as u64).saturating_mul(2), "Fixing the upload")?;
+    let mut input = Cursor::new(jar);
+    let fixes = scan_jar(&mut input, &options, 0)?;
+    if report {
+        let report = match fixes.and_then(|fixes| fixes.report) {
+            Some(report) => report,
+            None => report::build(&options, &[], &[]),
+        };
+        let report = report.to_pretty_string().into_bytes();
+        return Ok(serve::Response::new(200, "application/json", report));
+    }
+    let fixed = match fixes {
+        Some(fixes) => {
+            let mut output = Cursor::new(Vec::with_capacity(jar.len()));
+            write_jar(input, &mut output, &options, fixes)?;
+            output.into_inner()
+        }
+        None => jar.to_vec(),
+    };
+    Ok(serve::Response::new(200, "application/java-archive", fixed))
 }
 
-const UTF_8: u8 = 1;
-const INTEGER: u8 = 3;
-const FLOAT: u8 = 4;
-const LONG: u8 = 5;
-const DOUBLE: u8 = 6;
-const CLASS: u8 = 7;
-const STRING: u8 = 8;
-const FIELD_REF: u8 = 9;
-const METHOD_REF: u8 = 10;
-const INTERFACE_METHOD_REF: u8 = 11;
-const NAME_AND_TYPE: u8 = 12;
-const METHOD_HANDLE: u8 = 15;
-const METHOD_TYPE: u8 = 16;
-const INVOKE_DYNAMIC: u8 = 18;
-const MODULE: u8 = 19;
-const PACKAGE: u8 = 20;
+fn run_queue(opt: &Opt, input_dir: &Path, output_dir: &Path) -> Result<()> {
+    // how often to look, the jars are picked up on the look after they stop
+    // changing
+    const INTERVAL: Duration = Duration::from_secs(2);
 
-#[derive(Debug)]
-enum ConstantItem<'a> {
-    Utf8(Cow<'a, str>, usize),
-    Ref(u16),
-    NameAndType(u16),
-    DoubleEntry,
-    Ignored,
+    if opt.registry.is_some() {
+        log::warn!("The registry is not used with --queue");
+    }
+    let options = fix_options(opt, None, None)?;
+    std::fs::create_dir_all(output_dir)
+        .with_context(|| format!("Creating {}", output_dir.display()))?;
+    let failed_dir = output_dir.join("failed");
+    let mut watcher = watch::Watcher::default();
+    log::info!(
+        "Fixing the jars put into {} into {}",
+        input_dir.display(),
+        output_dir.display()
+    );
+    loop {
+        let jars = queue::waiting(input_dir).unwrap_or_else(|e| {
+            log::warn!("{:#}", e);
+            Vec::new()
+        });
+        let ready = watcher.poll(&jars);
+        for jar in &ready {
+            let result = fix_queued(opt, &options, jar, output_dir)
+                .with_context(|| format!("Fixing {}", jar.display()));
+            let result = match result {
+                Ok(()) => std::fs::remove_file(jar)
+                    .with_context(|| format!("Removing {} from the queue", jar.display())),
+                Err(e) => {
+                    log::error!("{:#}", e);
+                    queue::fail(jar, &failed_dir, &e)
+                }
+            };
+            if let Err(e) = result {
+                log::error!("{:#}", e);
+            }
+        }
+        // the ones that could not be removed are not retried forever
+        watcher.mark_handled(&ready);
+        std::thread::sleep(INTERVAL);
+    }
 }
 
-impl<'a> ConstantItem<'a> {
-    fn read(stream: &mut Cursor<&'a [u8]>) -> Result<Self> {
-        Ok(match stream.read_u8()? {
-            UTF_8 => {
-                let len = stream.read_u16::<BE>()? as usize;
-                let pos = stream.position() as usize;
-                let slice = &stream.get_ref()[pos..pos + len];
-                stream.seek(SeekFrom::Current(len as i64))?;
-                Self::Utf8(cesu8::from_java_cesu8(slice)?, pos)
+/// Fixes the jar into the output directory, with the report next to it
+fn fix_queued(opt: &Opt, options: &FixOptions, jar: &Path, output_dir: &Path) -> Result<()> {
+    let name = jar.file_name().context("The jar has no name")?;
+    let output = output_dir.join(name);
+    let options = FixOptions {
+        // for the report, it gets its own ones with --embed-report
+        renames: Some(Default::default()),
+        limits: Limits {
+            deadline: opt
+                .time_limit
+                .map(|secs| Instant::now() + Duration::from_secs(secs)),
+            ..options.limits
+        },
+        ..options.clone()
+    };
+    let mut report = None;
+    write_output(jar, Some(&output), opt, |work_file| {
+        let mut input = options.reader(File::open(jar)?);
+        let mut output = create_output(work_file)?;
+        match scan_jar(&mut input, &options, 0)? {
+            Some(mut fixes) => {
+                report = match opt.embed_report {
+                    true => fixes.report.clone(),
+                    false => fixes.report.take(),
+                };
+                write_jar(input, output, &options, fixes)
             }
-            FIELD_REF | METHOD_REF | INTERFACE_METHOD_REF => {
-                stream.seek(SeekFrom::Current(2))?; // skip u2 class_index
-                let name_and_type_index = stream.read_u16::<BE>()?;
-                Self::Ref(name_and_type_index)
+            None => {
+                log::info!("Nothing to fix in {}", jar.display());
+                io::copy(&mut input, &mut output)?;
+                Ok(output.flush()?)
             }
-            NAME_AND_TYPE => {
-                let name_index = stream.read_u16::<BE>()?;
-                stream.seek(SeekFrom::Current(2))?; // skip u2 descriptor_index
-                Self::NameAndType(name_index)
+        }
+    })?;
+    let report = report.unwrap_or_else(|| report::build(&options, &[], &[]));
+    queue::write_file(
+        &with_suffix(&output, ".report.json"),
+        report.to_pretty_string().as_bytes(),
+    )?;
+    log::info!("Fixed {} into {}", jar.display(), output.display());
+    Ok(())
+}
+
+fn print_steam_hook(inputs: &[PathBuf]) -> Result<()> {
+    let exe = paths::simplified(&std::env::current_exe().context("Finding this executable")?);
+    // the game is started from who knows where
+    let inputs = inputs
+        .iter()
+        .map(|input| paths::absolute(input))
+        .collect::<Result<Vec<_>, _>>()?;
+    let inputs = inputs.iter().map(PathBuf::as_path).collect::<Vec<_>>();
+    outln!("{}", launch::steam_launch_options(&exe, &inputs));
+    Ok(())
+}
+
+fn gen_wrapper(opt: &Opt, game_dir: &Path, launcher: Option<&Path>) -> Result<()> {
+    let exe = paths::simplified(&std::env::current_exe().context("Finding this executable")?);
+    let game_dir = paths::absolute(game_dir)?;
+    let profile = &opt.loaded_profile;
+    let layout = match (launch::detect_layout(&game_dir, profile), launcher) {
+        (Ok(layout), _) => layout,
+        // fixing everything in the directory is the best guess there is
+        (Err(_), Some(_)) => launch::Layout {
+            inputs: vec![".".into()],
+            launcher: None,
+        },
+        (Err(e), None) => return Err(e),
+    };
+    let launcher = match launcher.or(layout.launcher.as_deref()) {
+        Some(launcher) => launcher,
+        None => bail!(
+            "The {} profile doesn't know what starts it, give the --launcher",
+            profile.name
+        ),
+    };
+    let (extension, script) = launch::wrapper_script(
+        &exe,
+        &game_dir,
+        profile.non_default_arg(),
+        &layout.inputs,
+        launcher,
+    );
+
+    let path = game_dir.join(format!("starsector-fixed.{}", extension));
+    if path.exists() && (opt.no_clobber || !opt.force) {
+        bail!("{} already exists, use -f to overwrite it", path.display());
+    }
+    std::fs::write(&path, script).with_context(|| format!("Writing {}", path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    log::info!(
+        "Written {}, start the game with it to have it fixed first",
+        path.display()
+    );
+    Ok(())
+}
+
+fn doctor(opt: &Opt, install: &Path, yes: bool) -> Result<()> {
+    let game_dir = paths::absolute(install)?;
+    let profile = &opt.loaded_profile;
+    let layout = launch::detect_layout(&game_dir, profile)?;
+    let limits = Limits::default();
+    let shown = |path: &Path| paths::relative_to(path, &game_dir).display().to_string();
+    outln!("{}", tr!("doctor-game", dir = game_dir.display()));
+
+    let jre = doctor::find_jre(&game_dir, &profile.jre_dirs);
+    // a JRE that's not known is as good as a strict one
+    let strict = jre.as_ref().is_none_or(doctor::Jre::is_strict);
+    match &jre {
+        Some(jre) => outln!(
+            "{}",
+            tr!(
+                match jre.is_strict() {
+                    true => "doctor-jre-strict",
+                    false => "doctor-jre-lenient",
+                },
+                vendor = jre.vendor,
+                version = jre.version,
+                dir = shown(&jre.home),
+            )
+        ),
+        None => outln!("{}", tr!("doctor-no-jre")),
+    }
+
+    let inputs: Vec<_> = layout.inputs.iter().map(|i| game_dir.join(i)).collect();
+    let mut unfixed = Vec::new();
+    let (mut read_only, mut signed, mut needed) = (false, 0, 0);
+    outln!("{}", tr!("doctor-jars"));
+    for jar in scan::expand(&inputs, &opt.scan_options())? {
+        // the tarballs are for fixing, not for running the game from
+        if tar::Compression::detect(&jar).is_some() {
+            continue;
+        }
+        let state = match doctor::jar_state(&jar, &limits) {
+            Ok(state) => state,
+            Err(e) => {
+                let error = format!("{:#}", e);
+                outln!(
+                    "  {}",
+                    tr!("doctor-jar-unreadable", jar = shown(&jar), error = error)
+                );
+                continue;
             }
-            CLASS | STRING | METHOD_TYPE | MODULE | PACKAGE => {
-                stream.seek(SeekFrom::Current(2))?;
-                Self::Ignored
+        };
+        let mut status = match (state.bad_names, &state.fixed_with) {
+            (0, Some(version)) => tr!("doctor-fixed-with", version = version),
+            (0, None) => tr!("doctor-nothing-to-fix"),
+            (bad, _) => tr!("doctor-bad-names", count = bad),
+        };
+        if state.signed {
+            status.push_str(&tr!("doctor-signed"));
+            signed += 1;
+        }
+        if state.bad_names != 0 {
+            if state.read_only {
+                status.push_str(&tr!("doctor-read-only"));
+                read_only = true;
             }
-            METHOD_HANDLE => {
-                stream.seek(SeekFrom::Current(3))?;
-                Self::Ignored
+            // the temp file and the backup
+            needed += state.size * 2;
+            unfixed.push(jar.clone());
+        }
+        outln!("  {}: {}", shown(&jar), status);
+    }
+
+    let mut fields = BTreeSet::new();
+    for jar in &unfixed {
+        if let Err(e) = doctor::bad_field_names(jar, &limits, &mut fields) {
+            log::warn!("{:#}", e);
+        }
+    }
+    let saves_dir = profile.saves.as_ref().map(|saves| game_dir.join(saves));
+    let saves = match &saves_dir {
+        Some(saves_dir) => doctor::saves_with(saves_dir, &fields)?,
+        None => Vec::new(),
+    };
+    match saves.as_slice() {
+        // the profile doesn't know of any
+        _ if saves_dir.is_none() => {}
+        [] => outln!("{}", tr!("doctor-no-saves")),
+        saves => {
+            outln!("{}", tr!("doctor-saves", count = saves.len()));
+            for (file, name) in saves {
+                let name = console::escaped(name);
+                outln!("  {}", tr!("doctor-save", file = shown(file), name = name));
             }
-            INTEGER | FLOAT | INVOKE_DYNAMIC => {
-                stream.seek(SeekFrom::Current(4))?;
-                Self::Ignored
+        }
+    }
+
+    let available = space::available(&game_dir);
+    if let Some(available) = available {
+        outln!(
+            "{}",
+            tr!("doctor-space", available = available, needed = needed)
+        );
+    }
+    let enough_space = available.is_none_or(|available| available >= needed);
+
+    let flags: &[&str] = match read_only {
+        true => &["--force-writable"],
+        false => &[],
+    };
+    let command = fix_command(opt, flags, &unfixed);
+    let mut actions = Vec::new();
+    if !enough_space {
+        actions.push(tr!(
+            "doctor-free-space",
+            bytes = needed - available.unwrap_or_default(),
+            dir = game_dir.display(),
+        ));
+    }
+    if let (Some(saves_dir), false) = (&saves_dir, saves.is_empty() || unfixed.is_empty()) {
+        actions.push(tr!("doctor-back-up-saves", dir = saves_dir.display()));
+    }
+    if !unfixed.is_empty() {
+        actions.push(match strict {
+            true => tr!("doctor-fix-strict", command = command),
+            false => tr!("doctor-fix-lenient", command = command),
+        });
+    }
+    if signed != 0 && !unfixed.is_empty() {
+        actions.push(tr!("doctor-signed-jars", count = signed));
+    }
+    if actions.is_empty() {
+        outln!("{}", tr!("doctor-nothing-to-do"));
+        return Ok(());
+    }
+    outln!("{}", tr!("doctor-what-to-do"));
+    for (i, action) in actions.iter().enumerate() {
+        outln!("  {}. {}", i + 1, action);
+    }
+
+    if unfixed.is_empty() || !enough_space {
+        return Ok(());
+    }
+    if !yes && !console::confirm(&tr!("doctor-confirm")) {
+        return Ok(());
+    }
+    let opt = Opt {
+        inputs: unfixed,
+        registry: opt.registry.clone().or(Some(None)),
+        force_writable: opt.force_writable || read_only,
+        ..opt.clone()
+    };
+    fix_all(&opt, None).map(drop)
+}
+
+/// Finds the installs of the game, and fixes the jars in them that need it
+fn fix_starsector(opt: &Opt, game_dir: Option<&Path>) -> Result<()> {
+    let profile = &opt.loaded_profile;
+    let installs = match game_dir {
+        Some(game_dir) => {
+            let game_dir = paths::absolute(game_dir)?;
+            profile.find_launcher(&game_dir)?;
+            vec![game_dir]
+        }
+        None => install::find(profile),
+    };
+    if installs.is_empty() {
+        bail!("{}", tr!("starsector-not-found"));
+    }
+
+    let limits = Limits::default();
+    let mut unfixed = Vec::new();
+    let mut saves = Vec::new();
+    let mut read_only = false;
+    for game_dir in &installs {
+        outln!("{}", tr!("doctor-game", dir = game_dir.display()));
+        let layout = launch::detect_layout(game_dir, profile)?;
+        let inputs: Vec<_> = layout.inputs.iter().map(|i| game_dir.join(i)).collect();
+        let mut fields = BTreeSet::new();
+        for jar in scan::expand(&inputs, &opt.scan_options())? {
+            if tar::Compression::detect(&jar).is_some() {
+                continue;
             }
-            LONG | DOUBLE => {
-                stream.seek(SeekFrom::Current(8))?;
-                Self::DoubleEntry
+            let shown = paths::relative_to(&jar, game_dir);
+            match doctor::jar_state(&jar, &limits) {
+                Ok(state) if state.bad_names != 0 => {
+                    let status = tr!("doctor-bad-names", count = state.bad_names);
+                    outln!("  {}: {}", shown.display(), status);
+                    if let Err(e) = doctor::bad_field_names(&jar, &limits, &mut fields) {
+                        log::warn!("{:#}", e);
+                    }
+                    read_only |= state.read_only;
+                    unfixed.push(jar);
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    let error = format!("{:#}", e);
+                    let jar = shown.display();
+                    outln!(
+                        "  {}",
+                        tr!("doctor-jar-unreadable", jar = jar, error = error)
+                    );
+                }
+            }
+        }
+        if let Some(saves_dir) = &profile.saves {
+            let saves_dir = game_dir.join(saves_dir);
+            if !doctor::saves_with(&saves_dir, &fields)?.is_empty() {
+                saves.push(saves_dir);
+            }
+        }
+    }
+    if unfixed.is_empty() {
+        outln!("{}", tr!("doctor-nothing-to-do"));
+        return Ok(());
+    }
+
+    let count = unfixed.len();
+    let fixing = Opt {
+        inputs: unfixed,
+        // for the saves to be migrated after
+        registry: opt.registry.clone().or(Some(None)),
+        // like the installs in Program Files, it's what the player wants
+        force_writable: opt.force_writable || read_only,
+        ..opt.clone()
+    };
+    fix_all(&fixing, None)?;
+    outln!("{}", tr!("starsector-fixed", count = count));
+    if !opt.force {
+        outln!("{}", tr!("starsector-backups"));
+    }
+    for saves_dir in &saves {
+        outln!("{}", tr!("starsector-saves", dir = saves_dir.display()));
+    }
+    Ok(())
+}
+
+/// The command that fixes the jars like the given options, with the flags,
+/// for the reports to tell to run
+fn fix_command(opt: &Opt, flags: &[&str], jars: &[PathBuf]) -> String {
+    let mut command = "starsector-fixer".to_owned();
+    if let Some(profile) = opt.loaded_profile.non_default_arg() {
+        command.push_str(" --profile ");
+        command.push_str(&launch::quote(&Path::new(profile).display().to_string()));
+    }
+    for flag in flags {
+        command.push(' ');
+        command.push_str(flag);
+    }
+    for jar in jars {
+        command.push(' ');
+        command.push_str(&launch::quote(&jar.display().to_string()));
+    }
+    // last, it would take a jar for its file otherwise
+    match &opt.registry {
+        Some(Some(path)) => {
+            command.push_str(" --registry=");
+            command.push_str(&launch::quote(&path.display().to_string()));
+        }
+        _ => command.push_str(" --registry"),
+    }
+    command
+}
+
+fn report_explain(opt: &Opt, log: &Path) -> Result<()> {
+    let findings = explain::explain(log)?;
+    if findings.is_empty() {
+        outln!("{}", tr!("explain-nothing", log = log.display()));
+        return Ok(());
+    }
+    let profile = &opt.loaded_profile;
+    let log = paths::absolute(log)?;
+    let game_dir = explain::game_dir(&log, |dir| {
+        matches!(profile.find_launcher(dir), Ok(Some(_)))
+    });
+    for finding in &findings {
+        let line = console::escaped(&finding.line);
+        outln!(
+            "{}",
+            tr!("explain-line", number = finding.line_number, line = line)
+        );
+        let id = finding.known.id;
+        outln!("  {}", tr!(&format!("explain-{}", id)));
+        let flags = match finding.known.fix {
+            explain::Fix::Fixer(flags) => flags,
+            explain::Fix::Elsewhere => {
+                let advice = tr!(&format!("explain-{}-advice", id));
+                outln!("  {}", tr!("explain-not-fixed", advice = advice));
+                continue;
             }
-            x => bail!("Unknown constant tag: {}", x),
+        };
+        let flags: Vec<_> = flags.split_whitespace().collect();
+        let command = match game_dir {
+            Some(game_dir) => {
+                let layout = launch::detect_layout(game_dir, profile)?;
+                let jars: Vec<_> = layout.inputs.iter().map(|i| game_dir.join(i)).collect();
+                fix_command(opt, &flags, &jars)
+            }
+            // doctor finds the jars
+            None => {
+                let mut command = "starsector-fixer".to_owned();
+                if let Some(profile) = profile.non_default_arg() {
+                    command.push_str(" --profile ");
+                    command.push_str(&launch::quote(&Path::new(profile).display().to_string()));
+                }
+                for flag in &flags {
+                    command.push(' ');
+                    command.push_str(flag);
+                }
+                command.push_str(" doctor ");
+                command.push_str(&tr!("explain-game-dir"));
+                command
+            }
+        };
+        outln!("  {}", tr!("explain-fixes", command = command));
+    }
+    Ok(())
+}
+
+fn report_status(opt: &Opt) -> Result<()> {
+    let path = match &opt.registry {
+        Some(Some(path)) => path.clone(),
+        _ => dirs::data_file(opt.portable, "registry.json")?,
+    };
+    let registry = Registry::load(&path)?;
+    if registry.jars().next().is_none() {
+        outln!("{}", tr!("status-no-jars", registry = path.display()));
+        return Ok(());
+    }
+    let limits = Limits::default();
+    let mut changed = Vec::new();
+    for (jar, sha256) in registry.jars() {
+        let status = match hash::sha256_file(jar) {
+            Err(_) if !jar.exists() => tr!("status-gone"),
+            Err(e) => tr!("status-unreadable", error = format!("{:#}", e)),
+            Ok(current) if current == sha256 => tr!("status-fixed"),
+            Ok(_) => match doctor::jar_state(jar, &limits) {
+                Ok(state) if state.bad_names != 0 => {
+                    changed.push(jar);
+                    tr!("status-changed", count = state.bad_names)
+                }
+                Ok(_) => tr!("status-changed-fixed"),
+                Err(e) => tr!("status-changed-unreadable", error = format!("{:#}", e)),
+            },
+        };
+        outln!("{}: {}", jar.display(), status);
+    }
+    if changed.is_empty() {
+        return Ok(());
+    }
+    let changed: Vec<_> = changed.into_iter().map(Path::to_owned).collect();
+    let command = fix_command(opt, &[], &changed);
+    let names = changed
+        .iter()
+        .map(|jar| {
+            let name = jar.file_name().unwrap_or_default().to_string_lossy();
+            console::escaped(&name).into_owned()
         })
+        .collect::<Vec<_>>()
+        .join(", ");
+    outln!("{}", tr!("status-updated", jars = names, command = command));
+    Ok(())
+}
+
+fn migrate_save(opt: &Opt, campaign: &Path) -> Result<()> {
+    let path = match &opt.registry {
+        Some(Some(path)) => path.clone(),
+        _ => dirs::data_file(opt.portable, "registry.json")?,
+    };
+    let registry = Registry::load(&path)?;
+    if registry.renames().next().is_none() {
+        bail!(
+            "There are no renames in {}, the jars were not fixed with it",
+            path.display()
+        );
+    }
+    let migration = migrate::Migration::new(registry.renames())?;
+
+    let files = match campaign.is_dir() {
+        true => {
+            let mut files = std::fs::read_dir(campaign)
+                .with_context(|| format!("Reading {}", campaign.display()))?
+                .map(|entry| Ok(entry?.path()))
+                .collect::<Result<Vec<_>>>()?;
+            files.retain(|file| file.extension().is_some_and(|ext| ext == "xml"));
+            files.sort();
+            files
+        }
+        false => vec![campaign.to_owned()],
+    };
+    let mut total = 0;
+    for file in &files {
+        interrupt::check()?;
+        let (found, escaped) = migration.scan(file)?;
+        if found == 0 {
+            log::info!("{}", tr!("migrate-nothing", file = file.display()));
+            continue;
+        }
+        let mut renamed = 0;
+        write_output(file, None, opt, |work_file| {
+            let output = std::fs::File::create(work_file)?;
+            renamed = migration.rewrite(file, escaped, std::io::BufWriter::new(output))?;
+            Ok(())
+        })?;
+        log::info!(
+            "{}",
+            tr!("migrate-renamed", file = file.display(), count = renamed)
+        );
+        total += renamed;
+    }
+    match total {
+        0 => log::info!(
+            "{}",
+            tr!("migrate-nothing-at-all", dir = campaign.display())
+        ),
+        _ => log::info!(
+            "{}",
+            tr!("migrate-total", dir = campaign.display(), count = total)
+        ),
+    }
+    Ok(())
+}
+
+fn disassemble(opt: &Opt, path: &Path) -> Result<()> {
+    let bytecode = std::fs::read(path).with_context(|| format!("Reading {}", path.display()))?;
+    let class = class::ClassFile::parse(&bytecode)
+        .with_context(|| format!("Reading class {}", path.display()))?;
+    let text = asm::disassemble(&class);
+    let again = asm::assemble(&text).map(|class| class.to_bytes());
+    if again.as_ref().ok() != Some(&bytecode) {
+        bail!(
+            "The text does not make the same class again, which is a bug: {}",
+            match again {
+                Ok(_) => "the bytes are different".to_owned(),
+                Err(e) => format!("{:#}", e),
+            }
+        );
+    }
+    match &opt.output {
+        Some(output) => {
+            if opt.no_clobber {
+                check_clobber(output)?;
+            }
+            queue::write_file(output, text.as_bytes())?;
+            log::info!("Disassembled {} into {}", path.display(), output.display());
+        }
+        None => {
+            // lines and not at once, for the closed pipes
+            for line in text.lines() {
+                outln!("{}", line);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn assemble(opt: &Opt, path: &Path) -> Result<()> {
+    let text =
+        std::fs::read_to_string(path).with_context(|| format!("Reading {}", path.display()))?;
+    let class = asm::assemble(&text).with_context(|| format!("Reading {}", path.display()))?;
+    let output = match &opt.output {
+        Some(output) => output.clone(),
+        None => path.with_extension("class"),
+    };
+    if opt.no_clobber {
+        check_clobber(&output)?;
+    }
+    queue::write_file(&output, &class.to_bytes())?;
+    log::info!("Assembled {} into {}", path.display(), output.display());
+    Ok(())
+}
+
+/// Returns whether the jar has anything that fixing it would change
+fn report_check(jar: &Path, details: bool, deep: bool, format: check::Format) -> Result<bool> {
+    let input = File::open(jar).with_context(|| format!("Reading archive {}", jar.display()))?;
+    let report = check::check_jar(BufReader::new(input), &Limits::default(), deep)?;
+    let needs_fixing = report.bad_names() != 0;
+    if format == check::Format::Json {
+        outln!("{}", report.to_json(deep).to_pretty_string());
+        return Ok(needs_fixing);
+    }
+
+    for (class, names) in &report.classes {
+        outln!("{}: {} bad names", console::escaped(class), names.len());
+        if details {
+            for bad in names {
+                outln!(
+                    "  {} '{}' (constant #{})",
+                    bad.name_use.describe(),
+                    console::escaped(&bad.name),
+                    bad.index
+                );
+            }
+        }
+    }
+    outln!(
+        "{} bad names in {} of {} classes",
+        report.bad_names(),
+        report.classes.len(),
+        report.total_classes
+    );
+
+    // not against the spec, so not counted as bad names
+    for (class, names) in &report.control_chars {
+        outln!(
+            "{}: {} names with control characters",
+            console::escaped(class),
+            names.len()
+        );
+        if details {
+            for bad in names {
+                outln!(
+                    "  {} '{}' (constant #{})",
+                    bad.name_use.describe(),
+                    console::escaped(&bad.name),
+                    bad.index
+                );
+            }
+        }
+    }
+    if !report.control_chars.is_empty() {
+        let count: usize = report.control_chars.iter().map(|(_, n)| n.len()).sum();
+        outln!(
+            "{} names with control characters in {} classes, --sanitize-names replaces them",
+            count,
+            report.control_chars.len()
+        );
+    }
+
+    if !deep {
+        return Ok(needs_fixing);
+    }
+    for (class, names) in &report.reachable {
+        outln!(
+            "{}: {} bad names reachable from the code",
+            console::escaped(class),
+            names.len()
+        );
+        for reachable in names {
+            outln!(
+                "  '{}' from {} at {}",
+                console::escaped(&reachable.name),
+                console::escaped(&reachable.method),
+                reachable.pc
+            );
+        }
+    }
+    match report.reachable.len() {
+        0 => outln!("No bad names are reachable from the code"),
+        classes => outln!(
+            "Bad names are reachable from the code of {} of {} classes",
+            classes,
+            report.total_classes
+        ),
+    }
+    Ok(needs_fixing)
+}
+
+fn report_info(jar: &Path) -> Result<()> {
+    let input = File::open(jar).with_context(|| format!("Reading archive {}", jar.display()))?;
+    let info = info::jar_info(BufReader::new(input), &Limits::default())?;
+
+    outln!(
+        "{} entries ({} directories), {} classes",
+        info.entries,
+        info.directories,
+        info.classes
+    );
+    if !info.versions.is_empty() {
+        outln!("Class versions:");
+        for ((major, minor), count) in &info.versions {
+            let preview = match *minor {
+                version::PREVIEW_MINOR => ", preview features",
+                _ => "",
+            };
+            outln!(
+                "  {}.{} ({}{}): {}",
+                major,
+                minor,
+                version::release_name(*major),
+                preview,
+                count
+            );
+        }
+    }
+    if !info.unreadable.is_empty() {
+        outln!("Unreadable classes: {}", info.unreadable.len());
+        for class in &info.unreadable {
+            outln!("  {}", console::escaped(class));
+        }
+    }
+    outln!("Compression:");
+    for (method, count) in &info.compression {
+        outln!("  {}: {}", method, count);
+    }
+    match info.signatures.is_empty() {
+        true => outln!("Not signed"),
+        false => outln!(
+            "Signed ({}), fixing it removes the signature, which it would break",
+            info.signatures.join(", ")
+        ),
+    }
+    if !info.nested.is_empty() {
+        outln!("Nested archives: {}", info.nested.len());
+        for nested in &info.nested {
+            outln!("  {}", console::escaped(nested));
+        }
+    }
+    if let Some(version) = &info.fixed_with {
+        outln!("Fixed with {} {}", env!("CARGO_PKG_NAME"), version);
+    }
+    match info.bad_names {
+        0 => outln!("No names that need fixing"),
+        bad => outln!(
+            "{} names that need fixing in {} classes",
+            bad,
+            info.classes_with_bad_names
+        ),
+    }
+    match info.obfuscators.as_slice() {
+        [] => outln!("No traces of known obfuscators"),
+        guesses => {
+            outln!("Likely obfuscated with:");
+            for guess in guesses {
+                outln!("  {}: {}", guess.obfuscator, guess.evidence.join("; "));
+            }
+        }
     }
+    Ok(())
+}
+
+fn report_usages(name: &str, classpath: &[OsString], owner: Option<&str>) -> Result<()> {
+    let mut jars = Vec::new();
+    for jar in classpath.iter().flat_map(std::env::split_paths) {
+        let input =
+            File::open(&jar).with_context(|| format!("Reading archive {}", jar.display()))?;
+        let index = index::JarIndex::from_jar(BufReader::new(input), &Limits::default(), false)
+            .with_context(|| format!("Indexing {}", jar.display()))?;
+        jars.push((jar, index));
+    }
+    let usages = usages::find_usages(&jars, name, owner);
+
+    let mut last_jar = None;
+    for usage in &usages {
+        if last_jar != Some(usage.jar) {
+            outln!("{}:", usage.jar.display());
+            last_jar = Some(usage.jar);
+        }
+        outln!("  {}", console::escaped(&usage.describe()));
+    }
+    if usages.is_empty() {
+        log::info!("No usages of {} found", name);
+    }
+    Ok(())
+}
+
+fn write_graph(jar: &Path, out: Option<&Path>, all: bool) -> Result<()> {
+    let input = File::open(jar).with_context(|| format!("Reading archive {}", jar.display()))?;
+    let index = index::JarIndex::from_jar(BufReader::new(input), &Limits::default(), false)?;
+    let edges = graph::dependencies(&index, all);
+    match out {
+        Some(out) => {
+            let file = File::create(out).with_context(|| format!("Creating {}", out.display()))?;
+            let mut file = io::BufWriter::new(file);
+            graph::write_dot(&mut file, &edges)?;
+            file.flush()?;
+            log::info!("Wrote {} edges to {}", edges.len(), out.display());
+        }
+        None => graph::write_dot(io::stdout().lock(), &edges)?,
+    }
+    Ok(())
+}
+
+fn report_grep(jar: &Path, pattern: &str, regex: bool, ignore_case: bool) -> Result<()> {
+    let pattern = match regex {
+        true => pattern.to_owned(),
+        false => regex::escape(pattern),
+    };
+    let pattern = regex::RegexBuilder::new(&pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .context("Invalid pattern")?;
+    let input = File::open(jar).with_context(|| format!("Reading archive {}", jar.display()))?;
+    let matches = grep::grep_jar(BufReader::new(input), &Limits::default(), &pattern)?;
+
+    for m in &matches {
+        // quoted, since the obfuscated names are full of invisible things
+        outln!(
+            "{} #{} {}",
+            console::escaped(&m.class),
+            m.index,
+            console::escaped(&format!("{:?}", m.value))
+        );
+    }
+    if matches.is_empty() {
+        log::info!("No matches");
+    }
+    Ok(())
+}
+
+fn report_diff(a: &Path, b: &Path) -> Result<()> {
+    let open = |jar: &Path| {
+        File::open(jar)
+            .map(BufReader::new)
+            .with_context(|| format!("Reading archive {}", jar.display()))
+    };
+    let report = diff::diff_jars(open(a)?, open(b)?, &Limits::default())?;
+
+    for entry in &report.entries {
+        match entry {
+            diff::EntryDiff::Added { name, size } => {
+                outln!("+ {} ({} bytes)", console::escaped(name), size)
+            }
+            diff::EntryDiff::Removed { name, size } => {
+                outln!("- {} ({} bytes)", console::escaped(name), size)
+            }
+            diff::EntryDiff::Changed {
+                name,
+                sizes,
+                details,
+            } => {
+                outln!(
+                    "~ {} ({} -> {} bytes)",
+                    console::escaped(name),
+                    sizes.0,
+                    sizes.1
+                );
+                for detail in details {
+                    outln!("    {}", console::escaped(detail));
+                }
+            }
+        }
+    }
+    match report.entries.len() {
+        0 => outln!("The jars have the same {} files", report.same),
+        changed => outln!("{} files differ, {} are the same", changed, report.same),
+    }
+    Ok(())
+}
+
+/// Writes the output to a temporary file which then replaces the -o file or
+/// the input, creating the backup of the input unless -f was given. The file
+/// being written is locked for the time of it
+fn write_output(
+    input: &Path,
+    output: Option<&Path>,
+    opt: &Opt,
+    write: impl FnOnce(&Path) -> Result<()>,
+) -> Result<()> {
+    let in_place = output.is_none();
+    // found out before the work and not at the end of it
+    let _writable = make_writable(opt, output.unwrap_or(input))?;
+    let _lock = lock::lock(output.unwrap_or(input))?;
+    let backup = in_place && !opt.force;
+    check_space(input, output.unwrap_or(input), backup)?;
+    if opt.no_clobber {
+        match output {
+            Some(output) => check_clobber(output)?,
+            None if !opt.force => check_clobber(&opt.loaded_profile.backup_path(input))?,
+            None => {}
+        }
+    }
+    let target = output.unwrap_or(input);
+    temp::remove_stale(target);
+    let (file, work_file) = temp::next_to(target)?;
+    drop(file);
+
+    write(work_file.path())?;
+
+    if backup {
+        let backup = opt.loaded_profile.backup_path(input);
+        if let Some(dir) = backup.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).with_context(|| tr!("write-creating-backup"))?;
+        }
+        std::fs::copy(input, backup).with_context(|| tr!("write-creating-backup"))?;
+    }
+    // the replaced file keeps being what it was to whoever uses it
+    if target.exists() {
+        if let Err(e) = attrs::copy(target, work_file.path()) {
+            log::warn!("{:#}", e);
+        }
+    }
+    // moved out of the way first, there's no going back from the trash
+    if in_place && opt.trash {
+        trash::put(input)?;
+    }
+    work_file
+        .persist(target)
+        .with_context(|| match in_place && opt.trash {
+            true => tr!("write-moving-trashed", file = input.display()),
+            false => tr!("write-moving"),
+        })?;
+
+    Ok(())
+}
+
+/// Fails if there's clearly not enough space for the output (which is about
+/// as big as the input) and the backup
+fn check_space(input: &Path, target: &Path, backup: bool) -> Result<()> {
+    let size = match std::fs::metadata(input) {
+        Ok(metadata) => metadata.len(),
+        Err(_) => return Ok(()),
+    };
+    let needed = match backup {
+        true => size * 2,
+        false => size,
+    };
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let key = match backup {
+        true => "write-no-space-backup",
+        false => "write-no-space",
+    };
+    match space::available(dir) {
+        Some(available) if available < needed => bail!(
+            "{}",
+            tr!(
+                key,
+                dir = dir.display(),
+                file = target.display(),
+                needed = needed,
+                available = available
+            )
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that the target (and its directory) can be replaced, making them
+/// writable for the time of it with --force-writable
+fn make_writable(opt: &Opt, target: &Path) -> Result<Vec<attrs::Writable>> {
+    let dir = match target.parent() {
+        Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+        Some(dir) => dir,
+        None => return Ok(Vec::new()),
+    };
+    let mut writable = Vec::new();
+    for path in [dir, target] {
+        let read_only = std::fs::metadata(path).is_ok_and(|m| m.permissions().readonly());
+        if !read_only {
+            continue;
+        }
+        if !opt.force_writable {
+            match path == target {
+                true => bail!("{}", tr!("write-read-only", file = target.display())),
+                false => bail!(
+                    "{}",
+                    tr!(
+                        "write-dir-read-only",
+                        dir = path.display(),
+                        file = target.display()
+                    )
+                ),
+            }
+        }
+        log::info!("{}", tr!("write-making-writable", path = path.display()));
+        writable.push(attrs::writable(path)?);
+    }
+    Ok(writable)
+}
+
+fn check_clobber(path: &Path) -> Result<()> {
+    if path.exists() {
+        bail!("{}", tr!("write-no-clobber", file = path.display()));
+    }
+    Ok(())
+}
+
+/// Where the output goes when -o is the input itself. It's written to a temp
+/// file and moved in place either way, so the input is never truncated
+/// before it's read
+fn same_output(input: &Path, output: PathBuf) -> Option<PathBuf> {
+    let same_entry = matches!(
+        (input.canonicalize(), output.canonicalize()),
+        (Ok(a), Ok(b)) if a == b
+    );
+    match same_entry {
+        // so it's in place, with the backup
+        true => {
+            log::info!(
+                "{} is the input itself, fixing it in place",
+                output.display()
+            );
+            None
+        }
+        false => {
+            log::warn!(
+                "{} is a hard link to {}, which will keep the unfixed jar",
+                output.display(),
+                input.display()
+            );
+            Some(output)
+        }
+    }
+}
+
+/// `foo.jar` -> `foo.jar.bak`, as opposed to `with_extension` replacing it
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+/// Puts the backups back in place of the jars
+fn restore(opt: &Opt, paths: &[PathBuf]) -> Result<()> {
+    let profile = &opt.loaded_profile;
+    let mut jars = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let found = scan::expand(std::slice::from_ref(path), &opt.scan_options())?;
+            jars.extend(
+                found
+                    .into_iter()
+                    .filter(|jar| profile.backup_path(jar).is_file()),
+            );
+            continue;
+        }
+        // the backup itself can be given, it's next to the jar
+        let name = path.file_name().and_then(|name| name.to_str());
+        match name.and_then(|name| name.strip_suffix(".bak")) {
+            Some(jar) if !profile.backup_path(path).is_file() => {
+                let mut dir = path.parent();
+                for _ in profile.backups.iter().flat_map(|dir| dir.components()) {
+                    dir = dir.and_then(Path::parent);
+                }
+                jars.push(dir.unwrap_or(Path::new("")).join(jar));
+            }
+            _ => jars.push(path.clone()),
+        }
+    }
+    if jars.is_empty() {
+        log::warn!("{}", tr!("restore-none"));
+    }
+
+    for jar in &jars {
+        interrupt::check()?;
+        let _working = interrupt::working();
+        let backup = profile.backup_path(jar);
+        if !backup.is_file() {
+            bail!(
+                "There is no backup of {} at {}",
+                jar.display(),
+                backup.display()
+            );
+        }
+        // a broken backup is left for a look, the jar is no worse than it
+        if tar::Compression::detect(jar).is_none() {
+            let file = File::open(&backup)
+                .with_context(|| format!("Reading the backup {}", backup.display()))?;
+            zip::ZipArchive::new(BufReader::new(file)).with_context(|| {
+                format!(
+                    "The backup {} is not a readable archive, leaving everything as it is",
+                    backup.display()
+                )
+            })?;
+        }
+
+        let _writable = make_writable(opt, jar)?;
+        let _lock = lock::lock(jar)?;
+        temp::remove_stale(jar);
+        let (file, work_file) = temp::next_to(jar)?;
+        drop(file);
+        std::fs::copy(&backup, work_file.path())
+            .with_context(|| format!("Copying the backup {}", backup.display()))?;
+        work_file
+            .persist(jar)
+            .with_context(|| format!("Moving the backup in place of {}", jar.display()))?;
+        std::fs::remove_file(&backup)
+            .with_context(|| format!("Removing the backup {}", backup.display()))?;
+        log::info!("{}", tr!("restore-done", jar = jar.display()));
+    }
+    Ok(())
+}
+
+/// Like File::create, but the jar gets read back after it's written
+fn create_output(path: &Path) -> io::Result<File> {
+    File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+}
+
+fn unfix(opt: &Opt, jar: &Path) -> Result<()> {
+    let open = || File::open(jar).with_context(|| format!("Reading archive {}", jar.display()));
+    let mut zip = ZipArchive::new(BufReader::new(open()?))?;
+    let mut text = String::new();
+    match zip.by_name(unfix::PATH) {
+        Ok(mut file) => file.read_to_string(&mut text)?,
+        Err(_) => bail!(
+            "{} has nothing about the original in it, it was not fixed or fixed with --no-provenance or by an older version",
+            jar.display()
+        ),
+    };
+    let original =
+        unfix::Original::parse(&text).with_context(|| format!("Reading {}", unfix::PATH))?;
+    let recorded_sha256 = match zip.by_name(manifest::PATH) {
+        Ok(mut file) => {
+            let mut buf = Vec::new();
+            file.read_to_end(&mut buf)?;
+            manifest::original_sha256(&buf)
+        }
+        Err(_) => None,
+    };
+    drop(zip);
+
+    let output = opt.output.as_deref();
+    let result = output.unwrap_or(jar);
+    let mut same_jar = false;
+    write_output(jar, output, opt, |work_file| {
+        let count = unfix_jar(open()?, create_output(work_file)?, &original)?;
+        log::info!(
+            "{}",
+            tr!("unfix-restored", count = count, jar = jar.display())
+        );
+        same_jar = recorded_sha256 == Some(hash::sha256_file(work_file)?);
+        Ok(())
+    })?;
+    match same_jar {
+        true => log::info!("{}", tr!("unfix-same-jar", jar = result.display())),
+        false => log::info!("{}", tr!("unfix-same-entries", jar = result.display())),
+    }
+    Ok(())
 }
@@ -0,0 +1,174 @@
+//! Marking the fixed jars in their MANIFEST.MF, so that they are easy to
+//! tell apart from the unfixed ones.
+
+pub const PATH: &str = "META-INF/MANIFEST.MF";
+
+/// The longest a manifest line can be, in bytes, without the line break
+const MAX_LINE: usize = 72;
+
+const ORIGINAL_SHA256: &str = "X-Original-SHA256";
+
+/// The manifest with the fixer and the original jar recorded in it
+pub fn with_provenance(manifest: Option<&[u8]>, original_sha256: &str) -> Vec<u8> {
+    // fixing a fixed jar again should not lose what the original was
+    let original = manifest
+        .and_then(|manifest| main_attribute(manifest, ORIGINAL_SHA256))
+        .unwrap_or_else(|| original_sha256.to_owned());
+    set_main_attributes(
+        manifest,
+        &[
+            ("X-Fixed-By", env!("CARGO_PKG_NAME")),
+            ("X-Fixer-Version", env!("CARGO_PKG_VERSION")),
+            (ORIGINAL_SHA256, &original),
+        ],
+    )
+}
+
+/// The version of the fixer that fixed the jar with this manifest, if it was
+pub fn fixed_with(manifest: &[u8]) -> Option<String> {
+    main_attribute(manifest, "X-Fixed-By")?;
+    Some(main_attribute(manifest, "X-Fixer-Version").unwrap_or_else(|| "?".to_owned()))
+}
+
+/// The SHA-256 of the jar before it was first fixed, if it's recorded
+pub fn original_sha256(manifest: &[u8]) -> Option<String> {
+    main_attribute(manifest, ORIGINAL_SHA256)
+}
+
+/// The manifest without the digests of the entries that the signatures
+/// put in it, and without the sections that were only there for them
+pub fn without_digests(manifest: &[u8]) -> Vec<u8> {
+    let text = String::from_utf8_lossy(manifest);
+    let mut lines = text.split_inclusive('\n').peekable();
+    let mut out = String::with_capacity(text.len());
+    let is_blank = |line: &str| line.trim_end_matches(['\r', '\n']).is_empty();
+    // the main section has none of them
+    for line in lines.by_ref() {
+        out.push_str(line);
+        if is_blank(line) {
+            break;
+        }
+    }
+    while lines.peek().is_some() {
+        // the attributes of the section, with their continuation lines
+        let mut attributes: Vec<String> = Vec::new();
+        let mut end = "";
+        for line in lines.by_ref() {
+            if is_blank(line) {
+                end = line;
+                break;
+            }
+            match (line.starts_with(' '), attributes.last_mut()) {
+                (true, Some(last)) => last.push_str(line),
+                _ => attributes.push(line.to_owned()),
+            }
+        }
+        let key = |attribute: &str| attribute.split(':').next().unwrap_or_default().to_owned();
+        // like SHA-256-Digest or SHA1-Digest
+        attributes.retain(|a| !key(a).to_ascii_lowercase().ends_with("-digest"));
+        if attributes
+            .iter()
+            .any(|a| !key(a).eq_ignore_ascii_case("Name"))
+        {
+            out.extend(attributes);
+            out.push_str(end);
+        }
+    }
+    out.into_bytes()
+}
+
+/// The value of the attribute in the main section, if it's there
+fn main_attribute(manifest: &[u8], name: &str) -> Option<String> {
+    let text = String::from_utf8_lossy(manifest);
+    let mut lines = text.lines().take_while(|line| !line.is_empty()).peekable();
+    while let Some(line) = lines.next() {
+        let (key, value) = match line.split_once(':') {
+            Some(split) => split,
+            None => continue,
+        };
+        if key.eq_ignore_ascii_case(name) {
+            let mut value = value.strip_prefix(' ').unwrap_or(value).to_owned();
+            while let Some(continuation) = lines.peek().and_then(|l| l.strip_prefix(' ')) {
+                value.push_str(continuation);
+                lines.next();
+            }
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Puts the attributes at the end of the main section of the manifest,
+/// replacing the ones with the same names, or makes a new manifest with
+/// them if there is none
+fn set_main_attributes(manifest: Option<&[u8]>, attributes: &[(&str, &str)]) -> Vec<u8> {
+    let manifest = manifest.unwrap_or(b"Manifest-Version: 1.0\r\n");
+    let newline: &[u8] = match manifest.windows(2).any(|w| w == b"\r\n") {
+        true => b"\r\n",
+        false => b"\n",
+    };
+    let text = String::from_utf8_lossy(manifest);
+    let mut lines = text.split_inclusive('\n');
+
+    let mut out = Vec::with_capacity(manifest.len() + 256);
+    let mut written = false;
+    let mut skipping = false;
+    for line in lines.by_ref() {
+        if line.trim_end_matches(['\r', '\n']).is_empty() {
+            // the end of the main section, the other sections come after
+            write_attributes(&mut out, attributes, newline);
+            written = true;
+            out.extend_from_slice(line.as_bytes());
+            break;
+        }
+        if line.starts_with(' ') {
+            // continuing the previous line
+            if !skipping {
+                out.extend_from_slice(line.as_bytes());
+            }
+            continue;
+        }
+        let key = line.split(':').next().unwrap_or_default();
+        skipping = attributes
+            .iter()
+            .any(|(name, _)| key.eq_ignore_ascii_case(name));
+        if !skipping {
+            out.extend_from_slice(line.as_bytes());
+            // the last line might have no line break
+            if !line.ends_with('\n') {
+                out.extend_from_slice(newline);
+            }
+        }
+    }
+    if !written {
+        write_attributes(&mut out, attributes, newline);
+    }
+    for line in lines {
+        out.extend_from_slice(line.as_bytes());
+    }
+    out
+}
+
+fn write_attributes(out: &mut Vec<u8>, attributes: &[(&str, &str)], newline: &[u8]) {
+    for (name, value) in attributes {
+        let line = format!("{}: {}", name, value);
+        let mut rest = line.as_bytes();
+        let mut first = true;
+        while !rest.is_empty() || first {
+            // the continuation lines start with a space, which counts
+            let max = if first { MAX_LINE } else { MAX_LINE - 1 };
+            let mut end = rest.len().min(max);
+            // not splitting the multibyte characters
+            while end < rest.len() && (rest[end] & 0xC0) == 0x80 {
+                end -= 1;
+            }
+            if !first {
+                out.push(b' ');
+            }
+            out.extend_from_slice(&rest[..end]);
+            out.extend_from_slice(newline);
+            rest = &rest[end..];
+            first = false;
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! The renames in the format of the ProGuard mapping files, for the
+//! deobfuscators and the stack trace tools that take them, and the other
+//! way around, renaming the members as a mapping file says instead of the
+//! usual fixing. It goes like this:
+//!
+//! ```text
+//! com.fs.starfarer.Foo -> com.fs.starfarer.Foo:
+//!     int some.field -> some_field
+//!     void some.method(java.lang.String,int[]) -> some_method
+//! ```
+//!
+//! The classes are never renamed, and as the names are fixed the same way
+//! everywhere, a mapping is taken only if every name in it is renamed to
+//! the same one in all of the classes.
+
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{bail, Context, Result};
+
+/// A field or a method that was renamed
+#[derive(Debug, Clone)]
+pub struct Mapped {
+    /// The internal name of the class, like com/fs/starfarer/Foo
+    pub class: String,
+    pub descriptor: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// The renamed members of the jars being fixed, for --write-mapping
+pub type MappedMembers = Arc<Mutex<Vec<Mapped>>>;
+
+/// Writes the renamed members, the classes sorted by name and the members
+/// in the order they are in the class
+pub fn write(members: &[Mapped], mut out: impl Write) -> Result<()> {
+    let mut classes = BTreeMap::<_, Vec<_>>::new();
+    for member in members {
+        classes
+            .entry(member.class.as_str())
+            .or_default()
+            .push(member);
+    }
+    for (class, members) in classes {
+        let class = class.replace('/', ".");
+        writeln!(out, "{} -> {}:", class, class)?;
+        for member in members {
+            match member.descriptor.strip_prefix('(') {
+                Some(method) => {
+                    let (params, ret) = method
+                        .split_once(')')
+                        .with_context(|| format!("Bad method descriptor {}", member.descriptor))?;
+                    let params = java_types(params)?.join(",");
+                    let ret = java_types(ret)?.join("");
+                    writeln!(
+                        out,
+                        "    {} {}({}) -> {}",
+                        ret, member.from, params, member.to
+                    )?;
+                }
+                None => writeln!(
+                    out,
+                    "    {} {} -> {}",
+                    java_types(&member.descriptor)?.join(""),
+                    member.from,
+                    member.to
+                )?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The types in the descriptor as they are written in Java, like int[] and
+/// java.lang.String
+fn java_types(descriptor: &str) -> Result<Vec<String>> {
+    let mut types = Vec::new();
+    let mut dimensions = 0;
+    let mut rest = descriptor;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        let name = match c {
+            '[' => {
+                dimensions += 1;
+                continue;
+            }
+            'B' => "byte".to_owned(),
+            'C' => "char".to_owned(),
+            'D' => "double".to_owned(),
+            'F' => "float".to_owned(),
+            'I' => "int".to_owned(),
+            'J' => "long".to_owned(),
+            'S' => "short".to_owned(),
+            'Z' => "boolean".to_owned(),
+            'V' => "void".to_owned(),
+            'L' => match rest.split_once(';') {
+                Some((name, tail)) => {
+                    rest = tail;
+                    name.replace('/', ".")
+                }
+                None => bail!("Bad descriptor {}", descriptor),
+            },
+            _ => bail!("Bad descriptor {}", descriptor),
+        };
+        types.push(name + &"[]".repeat(dimensions));
+        dimensions = 0;
+    }
+    Ok(types)
+}
+
+/// The renames from a mapping file
+#[derive(Debug)]
+pub struct Mapping {
+    /// Where they are from, for the report
+    pub source: String,
+    renames: BTreeMap<String, String>,
+}
+
+impl Mapping {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents =
+            std::fs::read_to_string(path).with_context(|| format!("Reading {}", path.display()))?;
+        Self::parse(&contents)
+            .with_context(|| format!("Reading {}", path.display()))
+            .map(|mapping| Self {
+                source: path.display().to_string(),
+                ..mapping
+            })
+    }
+
+    fn parse(contents: &str) -> Result<Self> {
+        let mut renames = BTreeMap::<String, String>::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line_number = i + 1;
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let (from, to) = line
+                .split_once(" -> ")
+                .with_context(|| format!("No ' -> ' on line {}", line_number))?;
+            // the classes are the lines that don't start with a space
+            if !line.starts_with(char::is_whitespace) {
+                let to = to.trim_end().trim_end_matches(':');
+                if from.trim() != to {
+                    log::warn!(
+                        "Line {} renames the class {} to {}, which this doesn't do, keeping the name",
+                        line_number,
+                        from.trim(),
+                        to
+                    );
+                }
+                continue;
+            }
+            // like `1:5:void name(int):12:16`, the line numbers only being
+            // there for the methods
+            let member = from
+                .trim()
+                .trim_start_matches(|c: char| c.is_ascii_digit() || c == ':');
+            let (_, member) = member
+                .split_once(' ')
+                .with_context(|| format!("No type on line {}", line_number))?;
+            let from = match member.find('(') {
+                Some(end) => &member[..end],
+                None => member,
+            };
+            let to = to.trim();
+            if from.is_empty() || to.is_empty() {
+                bail!("No name on line {}", line_number);
+            }
+            match renames.get(from) {
+                Some(was) if was != to => bail!(
+                    "Line {} renames '{}' to '{}', but it's renamed to '{}' before, and the names are fixed the same way in all of the classes",
+                    line_number,
+                    from,
+                    to,
+                    was
+                ),
+                _ => {
+                    renames.insert(from.to_owned(), to.to_owned());
+                }
+            }
+        }
+        log::debug!("Loaded {} renames", renames.len());
+        Ok(Self {
+            source: String::new(),
+            renames,
+        })
+    }
+
+    /// The new name, if the mapping changes it
+    pub fn rename(&self, name: &str) -> Option<String> {
+        self.renames.get(name).filter(|to| *to != name).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapped(class: &str, descriptor: &str, from: &str, to: &str) -> Mapped {
+        Mapped {
+            class: class.to_owned(),
+            descriptor: descriptor.to_owned(),
+            from: from.to_owned(),
+            to: to.to_owned(),
+        }
+    }
+
+    #[test]
+    fn write_and_parse() {
+        let members = [
+            mapped("com/fs/Foo", "I", "some.field", "some_field"),
+            mapped("a/Bar", "[[Ljava/lang/String;", "x.y", "x_y"),
+            mapped(
+                "com/fs/Foo",
+                "(Ljava/lang/String;[IJ)V",
+                "some.method",
+                "some_method",
+            ),
+        ];
+        let mut out = Vec::new();
+        write(&members, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(
+            text,
+            "a.Bar -> a.Bar:
+    java.lang.String[][] x.y -> x_y
+com.fs.Foo -> com.fs.Foo:
+    int some.field -> some_field
+    void some.method(java.lang.String,int[],long) -> some_method
+"
+        );
+
+        let mapping = Mapping::parse(&text).unwrap();
+        assert_eq!(mapping.rename("some.field").as_deref(), Some("some_field"));
+        assert_eq!(
+            mapping.rename("some.method").as_deref(),
+            Some("some_method")
+        );
+        assert_eq!(mapping.rename("x.y").as_deref(), Some("x_y"));
+        assert_eq!(mapping.rename("other"), None);
+    }
+
+    #[test]
+    fn proguard_line_numbers_and_comments() {
+        let mapping = Mapping::parse(
+            "# compiler: R8
+a.A -> a.A:
+    12:15:void a.b(int):30:33 -> a_b
+    # a comment
+    java.lang.Object same -> same
+
+b.B -> renamed.B:
+    int c.d -> c_d
+",
+        )
+        .unwrap();
+        assert_eq!(mapping.rename("a.b").as_deref(), Some("a_b"));
+        assert_eq!(mapping.rename("c.d").as_deref(), Some("c_d"));
+        // mapped to itself is not a rename
+        assert_eq!(mapping.rename("same"), None);
+    }
+
+    #[test]
+    fn errors() {
+        for text in [
+            "a.A -> a.A:\n    int a.b",
+            "a.A -> a.A:\n    a_b -> c",
+            "a.A -> a.A:\n    int  -> c",
+            "a.A -> a.A:\n    int a.b -> ",
+            "a.A -> a.A:\n    int a.b -> a_b\nb.B -> b.B:\n    int a.b -> other",
+        ] {
+            assert!(Mapping::parse(text).is_err(), "{}", text);
+        }
+        // the same rename in several classes is fine
+        Mapping::parse("a.A -> a.A:\n    int a.b -> a_b\nb.B -> b.B:\n    long a.b -> a_b")
+            .unwrap();
+    }
+
+    #[test]
+    fn bad_descriptors() {
+        assert!(java_types("Ljava/lang/String").is_err());
+        assert!(java_types("Q").is_err());
+        let mut out = Vec::new();
+        assert!(write(&[mapped("A", "(I", "a.b", "a_b")], &mut out).is_err());
+    }
+}
@@ -0,0 +1,230 @@
+//! Keeping the amount of data held in memory under --max-memory, by putting
+//! the bigger things into temporary files instead of failing.
+//!
+//! Nothing waits for the memory to be freed, even with the classes and the
+//! jars fixed in parallel: what fits is kept in memory, what doesn't goes to
+//! a file, and the few things that have to be in memory fail if they don't
+//! fit, the classes waiting to be fixed making room by being fixed first.
+
+use std::{
+    fs::File,
+    io::{self, Cursor, Read, Seek, SeekFrom, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::{ensure, Result};
+
+use crate::temp::{self, TempPath};
+
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    max: Option<u64>,
+    used: Arc<AtomicU64>,
+}
+
+/// The memory taken from the budget, given back when dropped
+#[derive(Debug)]
+pub struct Reservation {
+    used: Arc<AtomicU64>,
+    bytes: u64,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.used.fetch_sub(self.bytes, Ordering::SeqCst);
+    }
+}
+
+impl Budget {
+    pub fn new(max: Option<u64>) -> Self {
+        Self {
+            max,
+            used: Arc::default(),
+        }
+    }
+
+    fn take(&self, bytes: u64) -> Reservation {
+        self.used.fetch_add(bytes, Ordering::SeqCst);
+        Reservation {
+            used: self.used.clone(),
+            bytes,
+        }
+    }
+
+    fn available(&self) -> u64 {
+        match self.max {
+            Some(max) => max.saturating_sub(self.used.load(Ordering::SeqCst)),
+            None => u64::MAX,
+        }
+    }
+
+    /// Takes the memory for something that has to be in memory
+    pub fn reserve(&self, bytes: u64, what: &str) -> Result<Reservation> {
+        ensure!(
+            bytes <= self.available(),
+            "{} needs {} bytes of memory, which is more than --max-memory leaves",
+            what,
+            bytes
+        );
+        Ok(self.take(bytes))
+    }
+
+    /// Makes the reservation the given size, for the things that turned out
+    /// to need more (or less) memory than was taken for them
+    pub fn resize(&self, reservation: &mut Reservation, bytes: u64, what: &str) -> Result<()> {
+        if let Some(more) = bytes.checked_sub(reservation.bytes) {
+            ensure!(
+                more <= self.available(),
+                "{} needs {} bytes of memory, which is more than --max-memory leaves",
+                what,
+                bytes
+            );
+            reservation.used.fetch_add(more, Ordering::SeqCst);
+        } else {
+            reservation
+                .used
+                .fetch_sub(reservation.bytes - bytes, Ordering::SeqCst);
+        }
+        reservation.bytes = bytes;
+        Ok(())
+    }
+
+    /// Takes the memory if it's not too much of what's left, since the things
+    /// that have to be in memory need some room too
+    fn try_reserve(&self, bytes: u64) -> Option<Reservation> {
+        (self.max.is_none() || bytes <= self.available() / 4).then(|| self.take(bytes))
+    }
+}
+
+/// A buffer that's in memory if it fits into the budget, and in a
+/// temporary file otherwise
+#[derive(Debug)]
+pub enum Spool {
+    Memory {
+        cursor: Cursor<Vec<u8>>,
+        _reservation: Reservation,
+    },
+    File {
+        file: File,
+        _path: TempPath,
+    },
+}
+
+impl Spool {
+    /// A spool for about `size` bytes
+    pub fn new(budget: &Budget, size: u64) -> Result<Self> {
+        if let Some(reservation) = budget.try_reserve(size) {
+            let buf = Vec::with_capacity(size as usize);
+            return Ok(Self::Memory {
+                cursor: Cursor::new(buf),
+                _reservation: reservation,
+            });
+        }
+        log::debug!("Putting {} bytes into a temporary file", size);
+        Self::file()
+    }
+
+    /// A spool that's always in a temporary file, for the things that grow
+    /// with no size known up front
+    pub fn file() -> Result<Self> {
+        let (file, path) = temp::create()?;
+        Ok(Self::File { file, _path: path })
+    }
+
+    /// Goes back to the start, for reading what was written
+    pub fn rewind(&mut self) -> io::Result<()> {
+        self.seek(SeekFrom::Start(0)).map(|_| ())
+    }
+
+    pub fn len(&mut self) -> io::Result<u64> {
+        match self {
+            Self::Memory { cursor, .. } => Ok(cursor.get_ref().len() as u64),
+            Self::File { file, .. } => file.metadata().map(|m| m.len()),
+        }
+    }
+
+    pub fn is_empty(&mut self) -> io::Result<bool> {
+        self.len().map(|len| len == 0)
+    }
+}
+
+impl Read for Spool {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Memory { cursor, .. } => cursor.read(buf),
+            Self::File { file, .. } => file.read(buf),
+        }
+    }
+}
+
+impl Write for Spool {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Memory { cursor, .. } => cursor.write(buf),
+            Self::File { file, .. } => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Memory { cursor, .. } => cursor.flush(),
+            Self::File { file, .. } => file.flush(),
+        }
+    }
+}
+
+impl Seek for Spool {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::Memory { cursor, .. } => cursor.seek(pos),
+            Self::File { file, .. } => file.seek(pos),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reservations() {
+        let budget = Budget::new(Some(100));
+        let mut a = budget.reserve(60, "a").unwrap();
+        assert!(budget.reserve(50, "b").is_err());
+
+        budget.resize(&mut a, 90, "a").unwrap();
+        assert!(budget.resize(&mut a, 101, "a").is_err());
+        assert_eq!(budget.available(), 10);
+
+        budget.resize(&mut a, 20, "a").unwrap();
+        let b = budget.reserve(80, "b").unwrap();
+        drop(a);
+        drop(b);
+        assert_eq!(budget.available(), 100);
+    }
+
+    #[test]
+    fn spools() {
+        let budget = Budget::new(Some(1000));
+        assert!(matches!(
+            Spool::new(&budget, 100).unwrap(),
+            Spool::Memory { .. }
+        ));
+        // it leaves the room for the things that have to be in memory
+        assert!(matches!(
+            Spool::new(&budget, 500).unwrap(),
+            Spool::File { .. }
+        ));
+
+        let mut spool = Spool::file().unwrap();
+        spool.write_all(b"data").unwrap();
+        spool.rewind().unwrap();
+        let mut read = String::new();
+        spool.read_to_string(&mut read).unwrap();
+        assert_eq!(read, "data");
+        assert_eq!(spool.len().unwrap(), 4);
+    }
+}
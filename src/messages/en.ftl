@@ -0,0 +1,172 @@
+# The messages for the players, see src/i18n.rs. The keys are the same in
+# every language, the ones missing from a translation are shown in English.
+
+## fixing
+
+fix-bad-name = Fixing bad name '{ $name }' in { $class }
+fix-processed = Processed { $class }
+fix-nothing = Nothing to fix in { $jar }
+fix-skipping = Skipping { $jar }, it was already fixed
+fix-signed = The jar is signed ({ $files }), removing the signatures, as the VM would refuse
+    the fixed classes with them
+fix-signed-kept = The jar is signed ({ $files }), the VM will refuse the fixed classes, as they
+    don't match the signatures anymore
+fix-no-archives = There are no archives to fix in the given directories
+fix-summary-fixed = { $jar }: fixed { $count } classes
+fix-summary-changed = { $jar }: fixed
+fix-summary-nothing = { $jar }: nothing to fix
+fix-summary-already = { $jar }: skipped, it was already fixed
+fix-summary = Fixed { $fixed } of { $total } archives
+fix-known-good = Your fixed { $jar } matches the known-good result for { $version }
+fix-known-bad = Your fixed { $jar } does not match the known-good result for { $version }, it
+    could be broken
+fix-trailing-garbage-kept = { $count } bytes of garbage after the end of { $class }, keeping them
+fix-trailing-garbage-stripped = { $count } bytes of garbage after the end of { $class }, removing
+    them
+fix-duplicate = Duplicate { $kind } { $name } { $descriptor } in { $class }, strict VMs will
+    reject the class
+fix-made-duplicate = Fixing the names made a duplicate { $kind } { $name } { $descriptor } in
+    { $class }
+fix-mapped-name = Renaming '{ $name }' to '{ $new }' in { $class }, as the mapping says
+fix-rules-name = Renaming '{ $name }' to '{ $new }' in { $class }, as the rules say
+fix-control-chars = Replacing the control characters in '{ $name }' in { $class }
+fix-collision = Fixing '{ $name }' in { $class } makes it the same as the { $kind } { $new }
+    { $descriptor }, see --on-collision
+fix-collision-suffixed = Fixing '{ $name }' in { $class } makes it the same as another { $kind },
+    renaming it to '{ $new }' instead
+fix-collision-skipped = Fixing '{ $name }' in { $class } makes it the same as another { $kind },
+    leaving it as it is
+fix-field = field
+fix-method = method
+wrap-starting-anyway = Could not fix the game, starting it anyway
+
+## writing
+
+write-read-only = { $file } is read-only. Make it writable, or use --force-writable to have it
+    made writable for the time of fixing
+write-dir-read-only = The directory { $dir } is read-only, so { $file } can't be replaced. Make
+    it writable, or use --force-writable to have it made writable for the time of fixing
+write-making-writable = Making { $path } writable for the time of fixing
+write-no-clobber = { $file } already exists, not overwriting it because of --no-clobber
+write-no-space = Not enough space in { $dir }: writing { $file } needs { $needed } bytes, but
+    only { $available } are left
+write-no-space-backup = Not enough space in { $dir }: writing { $file } needs { $needed } bytes
+    with the backup, but only { $available } are left
+write-creating-backup = Creating backup
+write-moving = Moving the file that was worked on in place of the target
+write-moving-trashed = Moving the file that was worked on in place of the target, the original
+    { $file } is in the trash
+lock-creating = Creating the lock file { $file }
+lock-busy = { $file } is being worked on by another run
+lock-locking = Locking { $file }
+lock-failed = Could not lock { $file }
+
+## daemon
+
+daemon-fixed = The game was fixed after an update
+daemon-failed = Could not fix the game: { $error }
+
+## doctor
+
+doctor-game = Game: { $dir }
+doctor-jre-strict = JRE: { $vendor } { $version } in { $dir }, which rejects the bad names
+doctor-jre-lenient = JRE: { $vendor } { $version } in { $dir }, which allows the bad names
+doctor-no-jre = JRE: none in the game directory
+doctor-jars = Jars:
+doctor-jar-unreadable = { $jar }: could not be read: { $error }
+doctor-fixed-with = fixed with { $version }
+doctor-nothing-to-fix = nothing to fix
+doctor-bad-names = { $count } bad names
+doctor-signed = , signed
+doctor-read-only = , read-only
+doctor-no-saves = Saves with the bad names: none
+doctor-saves = Saves with the bad names: { $count }
+doctor-save = { $file }: like { $name }
+doctor-space = Space: { $available } bytes free, fixing needs { $needed }
+doctor-free-space = Free up { $bytes } bytes on the drive of { $dir }, fixing the jars needs them
+doctor-back-up-saves = Back up { $dir }, the saves with the bad names won't load in the fixed game
+    until starsector-fixer migrate-save is run on them after fixing
+doctor-fix-strict = Fix the jars, the JRE won't run the game otherwise: { $command }
+doctor-fix-lenient = Fix the jars for running the game on a newer JRE, the one it has doesn't
+    need it: { $command }
+doctor-signed-jars = { $count } jars are signed, fixing removes the signatures, which matters only
+    if something checks them
+doctor-nothing-to-do = Nothing to do, the game is ready to run
+doctor-what-to-do = What to do, the most important first:
+doctor-confirm = Fix the jars now?
+
+## starsector
+
+starsector-not-found = Could not find the game, give the directory it's in with --game-dir
+starsector-fixed = Fixed { $count } jars, the game is ready to run
+starsector-backups = The originals are kept next to them as .bak, starsector-fixer restore puts
+    them back
+starsector-saves = Some of the saves in { $dir } won't load in the fixed game until
+    starsector-fixer migrate-save is run on them
+
+## status
+
+status-no-jars = No jars were fixed with the registry at { $registry }, fix them with --registry
+    for this to know about them
+status-gone = gone
+status-unreadable = could not be read: { $error }
+status-fixed = fixed
+status-changed = changed, { $count } bad names
+status-changed-fixed = changed, but nothing to fix
+status-changed-unreadable = changed, and could not be read: { $error }
+status-updated = The game was updated since the last fix, { $jars } changed, run `{ $command }` again
+
+## explain
+
+explain-nothing = Nothing in { $log } that this knows about
+explain-line = Line { $number }: { $line }
+explain-not-fixed = This is not something this fixes. { $advice }
+explain-fixes = This fixes it: { $command }
+explain-game-dir = <the game directory>
+explain-dotted-names = The game has the members with dots in their names, which this VM rejects
+explain-ref-kinds = A ref to a method is of the other kind (interface or not) than the method,
+    which old VMs did not care about
+explain-duplicate-members = A class has two members with the same name and type, which newer VMs
+    reject
+explain-duplicate-members-advice = Use the JRE the game came with, the class can't be run on a
+    newer one without changing the code
+explain-class-version = A class (likely of a mod) needs a newer Java than the one the game runs on
+explain-class-version-advice = Run the game on a newer JRE, fixing it first, or use a version of
+    the mod made for this one
+explain-class-format = A class is broken in some other way than the bad names
+explain-class-format-advice = Find out which jar it's from and reinstall it, this doesn't know how
+    to fix it
+explain-verify = The code of a class does not pass the checks of the VM
+explain-verify-advice = Run the game with -noverify in the vmparams, or on the JRE the game came
+    with
+explain-heap-size = The VM can't get as much memory as the -Xmx in the vmparams asks for
+explain-heap-size-advice = Lower the -Xmx (and -Xms) in the vmparams file, or use a 64-bit JRE
+explain-out-of-memory = The game ran out of the memory it's allowed
+explain-out-of-memory-advice = Raise the -Xmx (and -Xms) in the vmparams file
+explain-lwjgl = The native libraries of LWJGL are not found or don't fit the JRE
+explain-lwjgl-advice = Check the -Djava.library.path in the vmparams, and that the JRE is as 32-
+    or 64-bit as the libraries
+explain-graphics-driver = The VM crashed in the graphics driver
+explain-graphics-driver-advice = Update the graphics driver
+explain-vm-crash = The VM itself crashed
+explain-vm-crash-advice = Look at the problematic frame in the crash log, it's usually a driver or
+    the JRE
+
+## migrate-save
+
+migrate-nothing = { $file }: nothing to rename
+migrate-renamed = { $file }: renamed { $count } names
+migrate-nothing-at-all = Nothing to rename in { $dir }
+migrate-total = Renamed { $count } names in { $dir }
+
+## restore
+
+restore-done = Restored { $jar } from the backup
+restore-none = There are no backups to restore
+
+## unfix
+
+unfix-restored = Restored { $count } classes of { $jar }
+unfix-same-jar = { $jar } is the very same jar as the original
+unfix-same-entries = { $jar } has the same entries as the original, but is not the same file byte
+    for byte
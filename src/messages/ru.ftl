@@ -0,0 +1,170 @@
+# Сообщения для игроков, см. src/i18n.rs. Ключи те же, что в en.ftl.
+
+## исправление
+
+fix-bad-name = Исправляю плохое имя '{ $name }' в { $class }
+fix-processed = Обработан { $class }
+fix-nothing = В { $jar } нечего исправлять
+fix-skipping = Пропускаю { $jar }, он уже исправлен
+fix-signed = Jar-файл подписан ({ $files }), удаляю подписи, с ними виртуальная машина
+    не загрузит исправленные классы
+fix-signed-kept = Jar-файл подписан ({ $files }), виртуальная машина не загрузит исправленные
+    классы, так как они больше не совпадают с подписями
+fix-no-archives = В указанных папках нет архивов для исправления
+fix-summary-fixed = { $jar }: исправлено классов: { $count }
+fix-summary-changed = { $jar }: исправлен
+fix-summary-nothing = { $jar }: нечего исправлять
+fix-summary-already = { $jar }: пропущен, он уже исправлен
+fix-summary = Исправлено архивов: { $fixed } из { $total }
+fix-known-good = Ваш исправленный { $jar } совпадает с проверенным результатом для { $version }
+fix-known-bad = Ваш исправленный { $jar } не совпадает с проверенным результатом для { $version },
+    он может быть сломан
+fix-trailing-garbage-kept = { $count } байт мусора после конца { $class }, оставляю их
+fix-trailing-garbage-stripped = { $count } байт мусора после конца { $class }, удаляю их
+fix-duplicate = Повтор: { $kind } { $name } { $descriptor } в { $class }, строгие виртуальные
+    машины не загрузят такой класс
+fix-made-duplicate = Из-за исправления имён появился повтор: { $kind } { $name } { $descriptor }
+    в { $class }
+fix-mapped-name = Переименовываю '{ $name }' в { $class } в '{ $new }', как указано в маппинге
+fix-rules-name = Переименовываю '{ $name }' в { $class } в '{ $new }' по правилам
+fix-control-chars = Заменяю управляющие символы в '{ $name }' в { $class }
+fix-collision = После исправления '{ $name }' в { $class } совпадёт с другим членом класса
+    ({ $kind } { $new } { $descriptor }), см. --on-collision
+fix-collision-suffixed = После исправления '{ $name }' в { $class } совпадёт с другим членом
+    класса ({ $kind }), поэтому переименовываю его в '{ $new }'
+fix-collision-skipped = После исправления '{ $name }' в { $class } совпадёт с другим членом
+    класса ({ $kind }), поэтому оставляю его как есть
+fix-field = поле
+fix-method = метод
+wrap-starting-anyway = Не удалось исправить игру, запускаю как есть
+
+## writing
+
+write-read-only = { $file } только для чтения. Разрешите запись в него или используйте
+    --force-writable, чтобы запись была разрешена на время исправления
+write-dir-read-only = Папка { $dir } только для чтения, поэтому { $file } нельзя заменить.
+    Разрешите запись в неё или используйте --force-writable, чтобы запись была разрешена на
+    время исправления
+write-making-writable = Разрешаю запись в { $path } на время исправления
+write-no-clobber = { $file } уже существует, не перезаписываю его из-за --no-clobber
+write-no-space = Недостаточно места в { $dir }: для записи { $file } нужно байт: { $needed },
+    а свободно только { $available }
+write-no-space-backup = Недостаточно места в { $dir }: для записи { $file } вместе с резервной
+    копией нужно байт: { $needed }, а свободно только { $available }
+write-creating-backup = Создание резервной копии
+write-moving = Перемещение обработанного файла на место исходного
+write-moving-trashed = Перемещение обработанного файла на место исходного, исходный { $file }
+    в корзине
+lock-creating = Создание файла блокировки { $file }
+lock-busy = С { $file } уже работает другой запуск
+lock-locking = Блокировка { $file }
+lock-failed = Не удалось заблокировать { $file }
+
+## daemon
+
+daemon-fixed = Игра исправлена после обновления
+daemon-failed = Не удалось исправить игру: { $error }
+
+## doctor
+
+doctor-game = Игра: { $dir }
+doctor-jre-strict = JRE: { $vendor } { $version } в { $dir }, не пропускает плохие имена
+doctor-jre-lenient = JRE: { $vendor } { $version } в { $dir }, пропускает плохие имена
+doctor-no-jre = JRE: в папке игры нет
+doctor-jars = Jar-файлы:
+doctor-jar-unreadable = { $jar }: не читается: { $error }
+doctor-fixed-with = исправлен версией { $version }
+doctor-nothing-to-fix = нечего исправлять
+doctor-bad-names = плохих имён: { $count }
+doctor-signed = , подписан
+doctor-read-only = , только для чтения
+doctor-no-saves = Сохранений с плохими именами: нет
+doctor-saves = Сохранений с плохими именами: { $count }
+doctor-save = { $file }: например, { $name }
+doctor-space = Место: свободно { $available } байт, для исправления нужно { $needed }
+doctor-free-space = Освободите { $bytes } байт на диске с { $dir }, они нужны для исправления
+doctor-back-up-saves = Сделайте копию { $dir }: сохранения с плохими именами не загрузятся в
+    исправленной игре, пока после исправления не запустить для них starsector-fixer migrate-save
+doctor-fix-strict = Исправьте jar-файлы, иначе JRE не запустит игру: { $command }
+doctor-fix-lenient = Исправьте jar-файлы, чтобы запускать игру на новой JRE (текущей это не
+    нужно): { $command }
+doctor-signed-jars = Подписанных jar-файлов: { $count }. Исправление удаляет подписи, но это
+    важно, только если их кто-то проверяет
+doctor-nothing-to-do = Делать ничего не нужно, игра готова к запуску
+doctor-what-to-do = Что сделать, от самого важного:
+doctor-confirm = Исправить jar-файлы сейчас?
+
+## starsector
+
+starsector-not-found = Не удалось найти игру, укажите её папку с --game-dir
+starsector-fixed = Исправлено jar-файлов: { $count }, игра готова к запуску
+starsector-backups = Оригиналы сохранены рядом с ними как .bak, starsector-fixer restore
+    возвращает их на место
+starsector-saves = Некоторые сохранения в { $dir } не загрузятся в исправленной игре, пока
+    для них не запущен starsector-fixer migrate-save
+
+## status
+
+status-no-jars = Через реестр { $registry } не исправлялось ни одного jar-файла. Исправляйте с
+    --registry, чтобы они здесь отслеживались
+status-gone = удалён
+status-unreadable = не читается: { $error }
+status-fixed = исправлен
+status-changed = изменился, плохих имён: { $count }
+status-changed-fixed = изменился, но исправлять нечего
+status-changed-unreadable = изменился и не читается: { $error }
+status-updated = Игра обновилась после исправления, изменились: { $jars }. Запустите
+    `{ $command }` ещё раз
+
+## explain
+
+explain-nothing = В { $log } нет ничего знакомого
+explain-line = Строка { $number }: { $line }
+explain-not-fixed = Эта программа такое не исправляет. { $advice }
+explain-fixes = Исправляется так: { $command }
+explain-game-dir = <папка игры>
+explain-dotted-names = В игре есть поля и методы с точками в именах, а эта JVM их не пропускает
+explain-ref-kinds = Ссылка на метод не того вида (интерфейсный или нет), что сам метод, на что
+    старые JVM не обращали внимания
+explain-duplicate-members = В классе два члена с одинаковыми именем и типом, новые JVM такое не
+    пропускают
+explain-duplicate-members-advice = Запускайте на JRE, которая шла с игрой: на новой этот класс
+    не заработает без изменения кода
+explain-class-version = Классу (скорее всего из мода) нужна Java новее той, на которой идёт игра
+explain-class-version-advice = Запустите игру на новой JRE, сначала исправив её, или возьмите
+    версию мода для этой
+explain-class-format = Класс испорчен как-то иначе, чем плохими именами
+explain-class-format-advice = Найдите, из какого он jar-файла, и переустановите его, это
+    исправлять программа не умеет
+explain-verify = Код класса не проходит проверку JVM
+explain-verify-advice = Запустите игру с -noverify в vmparams или на JRE, которая шла с игрой
+explain-heap-size = JVM не может получить столько памяти, сколько просит -Xmx в vmparams
+explain-heap-size-advice = Уменьшите -Xmx (и -Xms) в файле vmparams или возьмите 64-битную JRE
+explain-out-of-memory = Игре не хватило отведённой памяти
+explain-out-of-memory-advice = Увеличьте -Xmx (и -Xms) в файле vmparams
+explain-lwjgl = Нативные библиотеки LWJGL не найдены или не подходят к JRE
+explain-lwjgl-advice = Проверьте -Djava.library.path в vmparams и что JRE той же разрядности
+    (32 или 64 бита), что и библиотеки
+explain-graphics-driver = JVM упала в драйвере видеокарты
+explain-graphics-driver-advice = Обновите драйвер видеокарты
+explain-vm-crash = Упала сама JVM
+explain-vm-crash-advice = Посмотрите на problematic frame в логе падения, обычно виноват драйвер
+    или JRE
+
+## migrate-save
+
+migrate-nothing = { $file }: нечего переименовывать
+migrate-renamed = { $file }: переименовано имён: { $count }
+migrate-nothing-at-all = В { $dir } нечего переименовывать
+migrate-total = В { $dir } переименовано имён: { $count }
+
+## restore
+
+restore-done = { $jar } восстановлен из резервной копии
+restore-none = Нет резервных копий для восстановления
+
+## unfix
+
+unfix-restored = Восстановлено классов в { $jar }: { $count }
+unfix-same-jar = { $jar } в точности совпадает с оригиналом
+unfix-same-entries = Содержимое { $jar } совпадает с оригиналом, но сам файл побайтно отличается
@@ -0,0 +1,93 @@
+//! The status of the daemon, written to a file in the Prometheus text format
+//! after every check, so that it can be picked up by the textfile collector
+//! of the node exporter, or just looked at.
+
+use std::{
+    io::Write,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+
+use crate::temp;
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub jars_watched: usize,
+    pub checks: u64,
+    /// The jars that were rewritten since the daemon started, not
+    /// counting the changed ones that had nothing to fix
+    pub fixes: u64,
+    pub failures: u64,
+    /// Whether the last run succeeded, and when it ended, if there was one
+    pub last_run: Option<(bool, SystemTime)>,
+    pub last_run_duration: Duration,
+}
+
+impl Metrics {
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+            out.push_str(&format!(
+                "# HELP starsector_fixer_{name} {help}\n\
+                 # TYPE starsector_fixer_{name} {kind}\n\
+                 starsector_fixer_{name} {value}\n"
+            ));
+        };
+        metric(
+            "jars_watched",
+            "gauge",
+            "The jars being watched",
+            self.jars_watched.to_string(),
+        );
+        metric(
+            "checks_total",
+            "counter",
+            "How many times the jars were checked",
+            self.checks.to_string(),
+        );
+        metric(
+            "fixes_total",
+            "counter",
+            "The jars fixed since the start",
+            self.fixes.to_string(),
+        );
+        metric(
+            "failures_total",
+            "counter",
+            "The runs that failed since the start",
+            self.failures.to_string(),
+        );
+        if let Some((success, ended)) = self.last_run {
+            let ended = ended.duration_since(UNIX_EPOCH).unwrap_or_default();
+            metric(
+                "last_run_success",
+                "gauge",
+                "Whether the last run fixed everything",
+                (success as u8).to_string(),
+            );
+            metric(
+                "last_run_timestamp_seconds",
+                "gauge",
+                "When the last run ended",
+                ended.as_secs().to_string(),
+            );
+            metric(
+                "last_run_duration_seconds",
+                "gauge",
+                "How long the last run took",
+                self.last_run_duration.as_secs_f64().to_string(),
+            );
+        }
+        out
+    }
+
+    /// Replaces the file at once, so whatever reads it never sees half of it
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let (mut file, temp) = temp::next_to(path)?;
+        file.write_all(self.render().as_bytes())
+            .and_then(|_| temp.persist(path))
+            .with_context(|| format!("Writing {}", path.display()))
+    }
+}
@@ -0,0 +1,138 @@
+//! Renaming the fields in the saves, which are the game objects written out
+//! by XStream, field by field, as XML elements named like the fields. A save
+//! made with the unfixed game has the original names there, which the fixed
+//! game doesn't have anymore, so the renames from the registry are done to
+//! the saves too.
+//!
+//! XStream can write `_` as `__` and `$` as `_-` in the names, and whether
+//! it does depends on how the game set it up, so that's figured out from
+//! the save itself, by the `_-` of the nested classes being there.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use regex::bytes::{Captures, Regex, RegexBuilder};
+
+use crate::fix;
+
+/// The names as they'd be in the save, and what they are renamed to
+#[derive(Debug)]
+struct Renames {
+    pattern: Regex,
+    renames: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Renames {
+    fn new(renames: &BTreeMap<String, String>, escaped: bool) -> Result<Self> {
+        let encode = |name: &str| match escaped {
+            true => name.replace('_', "__").replace('$', "_-"),
+            false => name.to_owned(),
+        };
+        let renames = renames
+            .iter()
+            .map(|(from, to)| (encode(from).into_bytes(), encode(to).into_bytes()))
+            .collect::<HashMap<_, _>>();
+        let mut names = renames.keys().collect::<Vec<_>>();
+        // the longest first, for the alternation to prefer them
+        names.sort_by_key(|name| std::cmp::Reverse(name.len()));
+        let alternatives = names
+            .iter()
+            .map(|name| regex::escape(&String::from_utf8_lossy(name)))
+            .collect::<Vec<_>>()
+            .join("|");
+        // the opening, the closing and the empty elements
+        let pattern = RegexBuilder::new(&format!(r"<(/?)({})([\s/>])", alternatives))
+            .size_limit(64 * 1024 * 1024)
+            .build()?;
+        Ok(Self { pattern, renames })
+    }
+}
+
+#[derive(Debug)]
+pub struct Migration {
+    plain: Renames,
+    escaped: Renames,
+}
+
+impl Migration {
+    /// From the renames of the registry. The names that the fixing without
+    /// the registry would've given the fields are renamed too, for the saves
+    /// made with a game fixed that way
+    pub fn new<'a>(registry: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Self> {
+        let mut renames = BTreeMap::new();
+        for (original, fixed) in registry {
+            if let Some(other) = fix::fixed_name(original).filter(|other| other != fixed) {
+                renames.insert(other, fixed.to_owned());
+            }
+            renames.insert(original.to_owned(), fixed.to_owned());
+        }
+        Ok(Self {
+            plain: Renames::new(&renames, false)?,
+            escaped: Renames::new(&renames, true)?,
+        })
+    }
+
+    /// How many names there are to rename in the file, and whether it has
+    /// the names escaped
+    pub fn scan(&self, path: &Path) -> Result<(usize, bool)> {
+        let nested_class = Regex::new(r#"(?:<|class=")[^\s>"/]*_-"#).unwrap();
+        let (mut plain, mut escaped, mut is_escaped) = (0, 0, false);
+        for_lines(path, |line| {
+            is_escaped |= nested_class.is_match(line);
+            plain += self.plain.pattern.find_iter(line).count();
+            escaped += self.escaped.pattern.find_iter(line).count();
+            Ok(())
+        })?;
+        Ok(match is_escaped {
+            true => (escaped, true),
+            false => (plain, false),
+        })
+    }
+
+    /// Writes the file with the names renamed, returning how many were
+    pub fn rewrite(&self, path: &Path, escaped: bool, mut output: impl Write) -> Result<usize> {
+        let renames = match escaped {
+            true => &self.escaped,
+            false => &self.plain,
+        };
+        let mut count = 0;
+        for_lines(path, |line| {
+            let line = renames.pattern.replace_all(line, |c: &Captures| {
+                count += 1;
+                let mut element = b"<".to_vec();
+                element.extend_from_slice(&c[1]);
+                element.extend_from_slice(&renames.renames[&c[2]]);
+                element.extend_from_slice(&c[3]);
+                element
+            });
+            output.write_all(&line)?;
+            Ok(())
+        })?;
+        output.flush()?;
+        Ok(count)
+    }
+}
+
+/// The names never span the lines, so going line by line is enough, and
+/// the saves can be hundreds of megabytes
+fn for_lines(path: &Path, mut f: impl FnMut(&[u8]) -> Result<()>) -> Result<()> {
+    let file = File::open(path).with_context(|| format!("Reading {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader
+            .read_until(b'\n', &mut line)
+            .with_context(|| format!("Reading {}", path.display()))?
+            == 0
+        {
+            return Ok(());
+        }
+        f(&line)?;
+    }
+}
@@ -0,0 +1,733 @@
+//! A minimal VCDIFF (RFC 3284) encoder and decoder, so that instead of the
+//! fixed jar itself people can share a small patch that only makes sense when
+//! applied to the original one.
+//!
+//! Only the ADD and COPY instructions of the default code table are emitted,
+//! with no secondary compression, which keeps the output readable by any
+//! conforming decoder (e.g. `xdelta3 -d -s original.jar patch fixed.jar`).
+//! The decoder handles everything the default code table can express, so
+//! patches made by xdelta3 with secondary compression disabled (`-S none`)
+//! can be applied too.
+
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read, Write},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use byteorder::ReadBytesExt;
+
+use crate::hash::sha256_hex;
+
+const MAGIC: [u8; 4] = [0xD6, 0xC3, 0xC4, 0x00];
+
+/// Not part of RFC 3284 itself, but an extension xdelta3 uses for its own
+/// metadata, we use it to store the hashes of the original and the result
+const VCD_APPHEADER: u8 = 0x04;
+const VCD_DECOMPRESS: u8 = 0x01;
+const VCD_CODETABLE: u8 = 0x02;
+
+const VCD_SOURCE: u8 = 0x01;
+const VCD_TARGET: u8 = 0x02;
+/// Another xdelta3 extension, a checksum of the target window
+const VCD_ADLER32: u8 = 0x04;
+
+/// Instruction codes from the default code table with the size given
+/// separately, and COPY using the VCD_SELF address mode
+const ADD: u8 = 1;
+const COPY: u8 = 19;
+
+/// The block size used to find matching regions in the source
+const BLOCK: usize = 32;
+
+/// Keep the windows small enough for the decoders with hard limits
+const WINDOW_SIZE: usize = 8 * 1024 * 1024;
+
+/// Prefix of the application header, followed by the hashes of the original
+/// and the result
+pub const APP_HEADER_PREFIX: &str = "starsector-fixer/sha256/";
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    /// Take the given amount of bytes from the target, starting at the given
+    /// offset, literally
+    Add { target_pos: usize, len: usize },
+    /// Copy the given amount of bytes from the source at the given offset
+    Copy { source_pos: usize, len: usize },
+}
+
+impl Op {
+    fn len(&self) -> usize {
+        match *self {
+            Op::Add { len, .. } | Op::Copy { len, .. } => len,
+        }
+    }
+
+    fn split_at(self, at: usize) -> (Op, Op) {
+        match self {
+            Op::Add { target_pos, len } => (
+                Op::Add {
+                    target_pos,
+                    len: at,
+                },
+                Op::Add {
+                    target_pos: target_pos + at,
+                    len: len - at,
+                },
+            ),
+            Op::Copy { source_pos, len } => (
+                Op::Copy {
+                    source_pos,
+                    len: at,
+                },
+                Op::Copy {
+                    source_pos: source_pos + at,
+                    len: len - at,
+                },
+            ),
+        }
+    }
+}
+
+/// Writes a patch that transforms `source` into `target`
+pub fn encode(source: &[u8], target: &[u8], mut out: impl Write) -> Result<()> {
+    out.write_all(&MAGIC)?;
+    out.write_all(&[VCD_APPHEADER])?;
+    let app_header = format!(
+        "{}{}/{}",
+        APP_HEADER_PREFIX,
+        sha256_hex(source),
+        sha256_hex(target)
+    );
+    write_varint(&mut out, app_header.len() as u64)?;
+    out.write_all(app_header.as_bytes())?;
+
+    let mut ops = diff(source, target).into_iter();
+    let mut carry = None;
+    let mut window = Vec::new();
+
+    loop {
+        window.clear();
+        let mut window_len = 0;
+
+        while let Some(op) = carry.take().or_else(|| ops.next()) {
+            let room = WINDOW_SIZE - window_len;
+            if op.len() > room {
+                let (head, tail) = op.split_at(room);
+                window.push(head);
+                carry = Some(tail);
+                window_len += room;
+                break;
+            }
+            window_len += op.len();
+            window.push(op);
+        }
+
+        if window.is_empty() {
+            break;
+        }
+        write_window(&mut out, &window, window_len, target)?;
+    }
+    Ok(())
+}
+
+fn write_window(out: &mut impl Write, ops: &[Op], target_len: usize, target: &[u8]) -> Result<()> {
+    // the source segment is just the span of the source this window copies from
+    let (seg_start, seg_end) = ops
+        .iter()
+        .filter_map(|op| match *op {
+            Op::Copy { source_pos, len } => Some((source_pos, source_pos + len)),
+            _ => None,
+        })
+        .fold(None, |acc: Option<(usize, usize)>, (s, e)| match acc {
+            Some((as_, ae)) => Some((as_.min(s), ae.max(e))),
+            None => Some((s, e)),
+        })
+        .unwrap_or((0, 0));
+
+    let mut data = Vec::new();
+    let mut instructions = Vec::new();
+    let mut addresses = Vec::new();
+
+    for op in ops {
+        match *op {
+            Op::Add { target_pos, len } => {
+                instructions.push(ADD);
+                write_varint(&mut instructions, len as u64)?;
+                data.extend_from_slice(&target[target_pos..target_pos + len]);
+            }
+            Op::Copy { source_pos, len } => {
+                instructions.push(COPY);
+                write_varint(&mut instructions, len as u64)?;
+                write_varint(&mut addresses, (source_pos - seg_start) as u64)?;
+            }
+        }
+    }
+
+    let mut delta = Vec::new();
+    write_varint(&mut delta, target_len as u64)?;
+    delta.push(0); // Delta_Indicator, nothing is compressed
+    write_varint(&mut delta, data.len() as u64)?;
+    write_varint(&mut delta, instructions.len() as u64)?;
+    write_varint(&mut delta, addresses.len() as u64)?;
+    delta.extend_from_slice(&data);
+    delta.extend_from_slice(&instructions);
+    delta.extend_from_slice(&addresses);
+
+    if seg_end > seg_start {
+        out.write_all(&[VCD_SOURCE])?;
+        write_varint(out, (seg_end - seg_start) as u64)?;
+        write_varint(out, seg_start as u64)?;
+    } else {
+        out.write_all(&[0])?;
+    }
+    write_varint(out, delta.len() as u64)?;
+    out.write_all(&delta)?;
+    Ok(())
+}
+
+/// Greedy block matching with a rolling hash, good enough for our case where
+/// the target is mostly the source with some regions replaced and shifted
+fn diff(source: &[u8], target: &[u8]) -> Vec<Op> {
+    let mut blocks = HashMap::with_capacity(source.len() / BLOCK);
+    for (i, block) in source.chunks_exact(BLOCK).enumerate() {
+        blocks.entry(hash(block)).or_insert(i * BLOCK);
+    }
+
+    let mut ops = Vec::new();
+    let mut pending = 0; // start of the bytes not covered by ops yet
+    let mut pos = 0;
+    let mut rolling = None;
+
+    while pos + BLOCK <= target.len() {
+        let h = match rolling {
+            Some(h) => h,
+            None => hash(&target[pos..pos + BLOCK]),
+        };
+
+        if let Some(&source_pos) = blocks.get(&h) {
+            if source[source_pos..source_pos + BLOCK] == target[pos..pos + BLOCK] {
+                let mut start = pos;
+                let mut source_start = source_pos;
+                while start > pending
+                    && source_start > 0
+                    && source[source_start - 1] == target[start - 1]
+                {
+                    start -= 1;
+                    source_start -= 1;
+                }
+                let len = source[source_start..]
+                    .iter()
+                    .zip(&target[start..])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+
+                if start > pending {
+                    ops.push(Op::Add {
+                        target_pos: pending,
+                        len: start - pending,
+                    });
+                }
+                ops.push(Op::Copy {
+                    source_pos: source_start,
+                    len,
+                });
+                pos = start + len;
+                pending = pos;
+                rolling = None;
+                continue;
+            }
+        }
+
+        if pos + BLOCK < target.len() {
+            rolling = Some(roll(h, target[pos], target[pos + BLOCK]));
+        }
+        pos += 1;
+    }
+
+    if pending < target.len() {
+        ops.push(Op::Add {
+            target_pos: pending,
+            len: target.len() - pending,
+        });
+    }
+    ops
+}
+
+const BASE: u32 = 0x01000193;
+
+fn hash(block: &[u8]) -> u32 {
+    block
+        .iter()
+        .fold(0u32, |h, &b| h.wrapping_mul(BASE).wrapping_add(b as u32))
+}
+
+/// BASE^(BLOCK - 1), the weight of the byte leaving the window
+const TOP: u32 = {
+    let mut top = 1u32;
+    let mut i = 1;
+    while i < BLOCK {
+        top = top.wrapping_mul(BASE);
+        i += 1;
+    }
+    top
+};
+
+fn roll(h: u32, out: u8, r#in: u8) -> u32 {
+    h.wrapping_sub((out as u32).wrapping_mul(TOP))
+        .wrapping_mul(BASE)
+        .wrapping_add(r#in as u32)
+}
+
+/// VCDIFF integers are base-128 big-endian with the high bit as continuation
+fn write_varint(out: &mut impl Write, mut value: u64) -> Result<()> {
+    let mut buf = [0u8; 10];
+    let mut i = buf.len() - 1;
+    buf[i] = (value & 0x7F) as u8;
+    value >>= 7;
+    while value != 0 {
+        i -= 1;
+        buf[i] = (value & 0x7F) as u8 | 0x80;
+        value >>= 7;
+    }
+    out.write_all(&buf[i..])?;
+    Ok(())
+}
+
+fn read_varint(stream: &mut impl Read) -> Result<u64> {
+    let mut value = 0u64;
+    loop {
+        let byte = stream.read_u8()?;
+        ensure!(value >> 57 == 0, "Integer overflow");
+        value = value << 7 | (byte & 0x7F) as u64;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn read_usize(stream: &mut impl Read) -> Result<usize> {
+    Ok(usize::try_from(read_varint(stream)?)?)
+}
+
+/// Applies the patch to `source`, verifying that both it and the result are
+/// what the patch was made for if the patch has the hashes in it
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    let mut stream = Cursor::new(patch);
+
+    let mut magic = [0; 4];
+    stream
+        .read_exact(&mut magic)
+        .context("Not a VCDIFF patch")?;
+    ensure!(magic == MAGIC, "Not a VCDIFF patch");
+
+    let header_indicator = stream.read_u8()?;
+    ensure!(
+        header_indicator & VCD_DECOMPRESS == 0,
+        "Patches with secondary compression are not supported"
+    );
+    ensure!(
+        header_indicator & VCD_CODETABLE == 0,
+        "Patches with custom code tables are not supported"
+    );
+
+    let mut expected_result = None;
+    if header_indicator & VCD_APPHEADER != 0 {
+        let len = read_usize(&mut stream)?;
+        let left = patch.len() as u64 - stream.position();
+        ensure!(
+            len as u64 <= left,
+            "The patch header is past the end of the patch"
+        );
+        let mut header = vec![0; len];
+        stream.read_exact(&mut header)?;
+        if let Some(hashes) = std::str::from_utf8(&header)
+            .ok()
+            .and_then(|h| h.strip_prefix(APP_HEADER_PREFIX))
+        {
+            let (source_hash, result_hash) = hashes
+                .split_once('/')
+                .context("Malformed hashes in the patch header")?;
+            ensure!(
+                sha256_hex(source) == source_hash,
+                "The patch was made for a different file (expected SHA-256 {})",
+                source_hash
+            );
+            expected_result = Some(result_hash.to_owned());
+        }
+    }
+    if expected_result.is_none() {
+        log::warn!("The patch has no hashes in it, the result cannot be verified");
+    }
+
+    let table = CodeTable::default();
+    let mut target = Vec::new();
+
+    while (stream.position() as usize) < patch.len() {
+        decode_window(&mut stream, &table, source, &mut target)
+            .with_context(|| format!("Decoding the window at {}", target.len()))?;
+    }
+
+    if let Some(expected) = expected_result {
+        ensure!(
+            sha256_hex(&target) == expected,
+            "The patched result does not match the expected SHA-256 {}",
+            expected
+        );
+    }
+    Ok(target)
+}
+
+fn decode_window(
+    stream: &mut Cursor<&[u8]>,
+    table: &CodeTable,
+    source: &[u8],
+    target: &mut Vec<u8>,
+) -> Result<()> {
+    let window_indicator = stream.read_u8()?;
+    ensure!(
+        window_indicator & VCD_SOURCE == 0 || window_indicator & VCD_TARGET == 0,
+        "Both VCD_SOURCE and VCD_TARGET are set"
+    );
+
+    let segment = if window_indicator & (VCD_SOURCE | VCD_TARGET) != 0 {
+        let len = read_usize(stream)?;
+        let pos = read_usize(stream)?;
+        // copy out the target segment, as we'll be appending to the target
+        let from = if window_indicator & VCD_SOURCE != 0 {
+            source
+        } else {
+            &target[..]
+        };
+        from.get(pos..pos.checked_add(len).context("Overflow")?)
+            .context("Segment is out of bounds")?
+            .to_vec()
+    } else {
+        Vec::new()
+    };
+
+    let _delta_len = read_usize(stream)?;
+    let window_len = read_usize(stream)?;
+    ensure!(
+        stream.read_u8()? == 0,
+        "Patches with secondary compression are not supported"
+    );
+    let data_len = read_usize(stream)?;
+    let instructions_len = read_usize(stream)?;
+    let addresses_len = read_usize(stream)?;
+    let checksum = if window_indicator & VCD_ADLER32 != 0 {
+        Some(stream.read_u32::<byteorder::BE>()?)
+    } else {
+        None
+    };
+
+    let mut take = |len: usize| -> Result<&[u8]> {
+        let pos = stream.position() as usize;
+        let slice = stream
+            .get_ref()
+            .get(pos..pos.checked_add(len).context("Overflow")?)
+            .context("Unexpected end of the patch")?;
+        stream.set_position((pos + len) as u64);
+        Ok(slice)
+    };
+    let mut data = Cursor::new(take(data_len)?);
+    let mut instructions = Cursor::new(take(instructions_len)?);
+    let mut addresses = Cursor::new(take(addresses_len)?);
+
+    // the windows we write are way smaller, this is just not to let
+    // a broken length make us allocate all of the memory
+    ensure!(
+        window_len <= 1 << 30,
+        "Window of {} bytes is too big",
+        window_len
+    );
+
+    let mut cache = AddressCache::default();
+    // what the window is made of, mostly, the runs and the copies of itself
+    // making it grow past that as it's decoded, not up front
+    let likely_len = segment.len() + data_len + instructions_len;
+    let mut window = Vec::with_capacity(window_len.min(likely_len));
+
+    while (instructions.position() as usize) < instructions_len {
+        let code = instructions.read_u8()? as usize;
+        for instruction in table.0[code] {
+            let size = match instruction {
+                Instruction::Noop => continue,
+                Instruction::Add(0) | Instruction::Run(0) | Instruction::Copy(0, _) => {
+                    read_usize(&mut instructions)?
+                }
+                Instruction::Add(size) | Instruction::Run(size) | Instruction::Copy(size, _) => {
+                    size as usize
+                }
+            };
+            ensure!(
+                size <= window_len - window.len(),
+                "Instruction goes past the end of the window"
+            );
+            match instruction {
+                Instruction::Noop => unreachable!(),
+                Instruction::Add(_) => {
+                    let start = window.len();
+                    window.resize(start + size, 0);
+                    data.read_exact(&mut window[start..])?;
+                }
+                Instruction::Run(_) => {
+                    let byte = data.read_u8()?;
+                    window.resize(window.len() + size, byte);
+                }
+                Instruction::Copy(_, mode) => {
+                    let here = segment.len() + window.len();
+                    let addr = cache.decode(&mut addresses, here, mode)?;
+                    ensure!(addr < here, "COPY address is out of bounds");
+                    for i in addr..addr.saturating_add(size) {
+                        // byte by byte, since it can overlap with itself
+                        let byte = match segment.get(i) {
+                            Some(&byte) => byte,
+                            None => *window
+                                .get(i - segment.len())
+                                .context("COPY address is out of bounds")?,
+                        };
+                        window.push(byte);
+                    }
+                }
+            }
+        }
+    }
+    ensure!(
+        window.len() == window_len,
+        "Decoded window has the wrong size"
+    );
+    if let Some(checksum) = checksum {
+        ensure!(adler32(&window) == checksum, "Window checksum mismatch");
+    }
+    target.extend_from_slice(&window);
+    Ok(())
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Instruction {
+    Noop,
+    Add(u8),
+    Run(u8),
+    Copy(u8, u8),
+}
+
+/// The default code table from section 5.6 of the RFC
+struct CodeTable([[Instruction; 2]; 256]);
+
+impl Default for CodeTable {
+    fn default() -> Self {
+        use Instruction::*;
+
+        let mut table = [[Noop; 2]; 256];
+        let mut i = 0;
+        let mut push = |entry| {
+            table[i] = entry;
+            i += 1;
+        };
+
+        push([Run(0), Noop]);
+        for size in 0..=17 {
+            push([Add(size), Noop]);
+        }
+        for mode in 0..9 {
+            push([Copy(0, mode), Noop]);
+            for size in 4..=18 {
+                push([Copy(size, mode), Noop]);
+            }
+        }
+        for mode in 0..6 {
+            for add_size in 1..=4 {
+                for copy_size in 4..=6 {
+                    push([Add(add_size), Copy(copy_size, mode)]);
+                }
+            }
+        }
+        for mode in 6..9 {
+            for add_size in 1..=4 {
+                push([Add(add_size), Copy(4, mode)]);
+            }
+        }
+        for mode in 0..9 {
+            push([Copy(4, mode), Add(1)]);
+        }
+        Self(table)
+    }
+}
+
+const NEAR_SIZE: usize = 4;
+const SAME_SIZE: usize = 3;
+
+struct AddressCache {
+    near: [usize; NEAR_SIZE],
+    next_slot: usize,
+    same: [usize; SAME_SIZE * 256],
+}
+
+impl Default for AddressCache {
+    fn default() -> Self {
+        Self {
+            near: [0; NEAR_SIZE],
+            next_slot: 0,
+            same: [0; SAME_SIZE * 256],
+        }
+    }
+}
+
+impl AddressCache {
+    fn decode(&mut self, addresses: &mut Cursor<&[u8]>, here: usize, mode: u8) -> Result<usize> {
+        let mode = mode as usize;
+        let addr = match mode {
+            0 => read_usize(addresses)?,
+            1 => here
+                .checked_sub(read_usize(addresses)?)
+                .context("COPY address is out of bounds")?,
+            m if m < 2 + NEAR_SIZE => self.near[m - 2]
+                .checked_add(read_usize(addresses)?)
+                .context("Overflow")?,
+            m if m < 2 + NEAR_SIZE + SAME_SIZE => {
+                let m = m - 2 - NEAR_SIZE;
+                self.same[m * 256 + addresses.read_u8()? as usize]
+            }
+            _ => bail!("Unknown address mode {}", mode),
+        };
+        self.near[self.next_slot] = addr;
+        self.next_slot = (self.next_slot + 1) % NEAR_SIZE;
+        self.same[addr % (SAME_SIZE * 256)] = addr;
+        Ok(addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes that don't compress or match by accident
+    fn noise(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed | 1;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn round_trip(source: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut patch = Vec::new();
+        encode(source, target, &mut patch).unwrap();
+        assert_eq!(apply(source, &patch).unwrap(), target);
+        patch
+    }
+
+    #[test]
+    fn small_changes() {
+        let source = noise(100_000, 1);
+        let mut target = source.clone();
+        target[500..503].copy_from_slice(b"a_b");
+        target.splice(50_000..50_010, b"longer replacement".iter().copied());
+        target.truncate(90_000);
+        target.extend_from_slice(&noise(100, 2));
+
+        let patch = round_trip(&source, &target);
+        assert!(patch.len() < 2_000, "{}", patch.len());
+    }
+
+    #[test]
+    fn edge_cases() {
+        round_trip(b"", b"");
+        round_trip(b"source", b"");
+        round_trip(b"", b"target");
+        round_trip(b"short", b"short");
+        round_trip(&noise(1000, 3), &noise(1000, 4));
+    }
+
+    #[test]
+    fn several_windows() {
+        let source = noise(WINDOW_SIZE + WINDOW_SIZE / 2, 5);
+        let mut target = source.clone();
+        target[WINDOW_SIZE - 10..WINDOW_SIZE + 10].fill(0);
+        round_trip(&source, &target);
+    }
+
+    #[test]
+    fn wrong_source() {
+        let mut patch = Vec::new();
+        encode(b"original", b"fixed", &mut patch).unwrap();
+        assert!(apply(b"something else", &patch).is_err());
+    }
+
+    #[test]
+    fn broken_patches() {
+        let source = noise(10_000, 6);
+        let mut target = source.clone();
+        target[5000] ^= 1;
+        let mut patch = Vec::new();
+        encode(&source, &target, &mut patch).unwrap();
+
+        assert!(apply(&source, b"not a patch").is_err());
+        for len in 0..patch.len() {
+            let _ = apply(&source, &patch[..len]);
+        }
+        // some bytes don't matter (like the length of the delta), but what
+        // does must never give a wrong result
+        for i in 0..patch.len() {
+            let mut broken = patch.clone();
+            broken[i] ^= 1;
+            if let Ok(result) = apply(&source, &broken) {
+                assert_eq!(result, target, "{}", i);
+            }
+        }
+    }
+
+    #[test]
+    fn lying_lengths() {
+        // the header claiming to be almost usize::MAX bytes long
+        let mut patch = MAGIC.to_vec();
+        patch.push(VCD_APPHEADER);
+        write_varint(&mut patch, u64::MAX >> 2).unwrap();
+        assert!(apply(b"", &patch).is_err());
+
+        // a window claiming 1 GiB with nothing in it
+        let mut patch = MAGIC.to_vec();
+        patch.push(0);
+        patch.push(0); // no source segment
+        for len in [5, 1 << 30, 0, 0, 0, 0] {
+            write_varint(&mut patch, len).unwrap();
+        }
+        assert!(apply(b"", &patch).is_err());
+    }
+
+    #[test]
+    fn varints() {
+        for value in [
+            0,
+            1,
+            127,
+            128,
+            16_383,
+            16_384,
+            u32::MAX as u64,
+            u64::MAX >> 1,
+        ] {
+            let mut out = Vec::new();
+            write_varint(&mut out, value).unwrap();
+            assert_eq!(read_varint(&mut &out[..]).unwrap(), value);
+        }
+    }
+}
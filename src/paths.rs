@@ -0,0 +1,91 @@
+//! Windows paths: the same file can be `C:\x`, `\\?\C:\x` or, on a network
+//! share, `\\server\share\x` and `\\?\UNC\server\share\x`. The extended
+//! length (`\\?\`) ones are what get past MAX_PATH, and the standard library
+//! already switches to them when opening files, so they only need undoing
+//! for comparing paths and for the things that can't take them, like cmd.
+
+use std::{
+    io,
+    path::{Component, Path, PathBuf, Prefix},
+};
+
+/// The path without the extended length prefix, when there's an ordinary
+/// way to write it. Other than on Windows, the path as it is
+pub fn simplified(path: &Path) -> PathBuf {
+    let mut components = path.components();
+    let prefix = match components.next() {
+        Some(Component::Prefix(prefix)) => prefix,
+        _ => return path.to_owned(),
+    };
+    let simple = match prefix.kind() {
+        Prefix::VerbatimDisk(disk) => format!("{}:", disk as char),
+        Prefix::VerbatimUNC(server, share) => format!(
+            r"\\{}\{}",
+            server.to_string_lossy(),
+            share.to_string_lossy()
+        ),
+        _ => return path.to_owned(),
+    };
+    // the verbatim ones don't have . and .. resolved, and the ordinary ones
+    // would have them
+    let rest = components.as_path();
+    if rest
+        .components()
+        .any(|c| matches!(c, Component::CurDir | Component::ParentDir))
+    {
+        return path.to_owned();
+    }
+    PathBuf::from(simple).join(rest)
+}
+
+/// An absolute path, written the same way whether the one given was an
+/// extended length one or not
+pub fn absolute(path: &Path) -> io::Result<PathBuf> {
+    std::path::absolute(path).map(|path| simplified(&path))
+}
+
+/// The path under the base, or, if it's not under it (like when the inputs
+/// are on different drives or shares), all of it made relative, with the
+/// drive or the share as the first directories
+pub fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    if let Ok(relative) = path.strip_prefix(base) {
+        return relative.to_owned();
+    }
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) => match prefix.kind() {
+                Prefix::Disk(disk) | Prefix::VerbatimDisk(disk) => {
+                    relative.push((disk as char).to_string())
+                }
+                Prefix::UNC(server, share) | Prefix::VerbatimUNC(server, share) => {
+                    relative.push(server);
+                    relative.push(share);
+                }
+                // the device ones, which are not for files anyway
+                _ => relative.push(
+                    prefix
+                        .as_os_str()
+                        .to_string_lossy()
+                        .replace(['\\', '?', '.', ':'], ""),
+                ),
+            },
+            Component::RootDir => {}
+            other => relative.push(other),
+        }
+    }
+    relative
+}
+
+/// Whether the two paths are the same file, through symlinks or hard links
+/// (the latter only where there's a way to tell)
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if let (Ok(a), Ok(b)) = (std::fs::metadata(a), std::fs::metadata(b)) {
+            return (a.dev(), a.ino()) == (b.dev(), b.ino());
+        }
+    }
+    matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
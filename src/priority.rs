@@ -0,0 +1,109 @@
+//! Lowering the priority of the process for --nice, so fixing in the
+//! background doesn't make whatever else is running stutter.
+
+use anyhow::{bail, Result};
+
+/// Makes the process (and whatever it runs) yield the CPU and the disk to
+/// everything else
+pub fn lower() -> Result<()> {
+    imp::lower()
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::*;
+
+    // from linux/ioprio.h, which libc doesn't have
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+    pub fn lower() -> Result<()> {
+        set_nice()?;
+        // i/o priority only does anything with some of the schedulers, and
+        // the cpu one is the important part anyway
+        let prio = IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT;
+        if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, prio) } == -1 {
+            log::debug!(
+                "Could not lower the i/o priority: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use super::*;
+
+    // from sys/resource.h
+    const IOPOL_TYPE_DISK: libc::c_int = 0;
+    const IOPOL_SCOPE_PROCESS: libc::c_int = 0;
+    const IOPOL_THROTTLE: libc::c_int = 3;
+
+    extern "C" {
+        fn setiopolicy_np(
+            iotype: libc::c_int,
+            scope: libc::c_int,
+            policy: libc::c_int,
+        ) -> libc::c_int;
+    }
+
+    pub fn lower() -> Result<()> {
+        set_nice()?;
+        if unsafe { setiopolicy_np(IOPOL_TYPE_DISK, IOPOL_SCOPE_PROCESS, IOPOL_THROTTLE) } == -1 {
+            log::debug!(
+                "Could not lower the i/o priority: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "macos"))))]
+mod imp {
+    use super::*;
+
+    pub fn lower() -> Result<()> {
+        set_nice()
+    }
+}
+
+#[cfg(unix)]
+fn set_nice() -> Result<()> {
+    // the lowest priority is 19 (or 20 on some), and it's clamped to that
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 19) } == -1 {
+        bail!(
+            "Could not lower the priority: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+mod imp {
+    use super::*;
+    use std::ffi::c_void;
+
+    // lowers the i/o and memory priorities too
+    const PROCESS_MODE_BACKGROUND_BEGIN: u32 = 0x0010_0000;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentProcess() -> *mut c_void;
+        fn SetPriorityClass(process: *mut c_void, class: u32) -> i32;
+    }
+
+    pub fn lower() -> Result<()> {
+        if unsafe { SetPriorityClass(GetCurrentProcess(), PROCESS_MODE_BACKGROUND_BEGIN) } == 0 {
+            bail!(
+                "Could not lower the priority: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(())
+    }
+}
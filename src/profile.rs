@@ -0,0 +1,256 @@
+//! What the commands that know about the install (gen-wrapper and doctor)
+//! assume about it. Starsector is far from the only old Java thing with the
+//! dotted names, so besides the built-in starsector and generic ones, a
+//! profile can be a TOML file like this:
+//!
+//! ```toml
+//! name = "Some Game"
+//! # what starts it, the first one that exists, by which the install is told
+//! launchers = ["somegame.exe", "somegame.sh"]
+//! # what to fix, the ones that don't exist are skipped, and the last part
+//! # can be a glob
+//! jars = ["lib", "*.jar", "plugins"]
+//! jre = ["jre"]
+//! saves = "saves"
+//! # the backups go there instead of next to the jars, relative to them
+//! backups = ".backups"
+//! # the fixing flags the jars need, named like the flags
+//! passes = ["repair-ref-kinds", "verify-refs"]
+//! ```
+//!
+//! Only that much of TOML is understood, see the toml module.
+
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{glob::Glob, json::Value, toml};
+
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    /// How it was given to --profile, for the commands written for later
+    pub arg: OsString,
+    pub launchers: Vec<PathBuf>,
+    pub jars: Vec<String>,
+    pub jre_dirs: Vec<PathBuf>,
+    pub saves: Option<PathBuf>,
+    pub backups: Option<PathBuf>,
+    pub passes: Passes,
+}
+
+/// The fixing flags that the profile turns on
+#[derive(Debug, Clone, Default)]
+pub struct Passes {
+    pub repair_ref_kinds: bool,
+    pub fix_all_name_and_type: bool,
+    pub verify_refs: bool,
+    pub sanitize_names: bool,
+}
+
+impl Profile {
+    pub fn starsector() -> Self {
+        Self {
+            name: "Starsector".to_owned(),
+            arg: "starsector".into(),
+            launchers: [
+                "starsector.exe",
+                "Contents/MacOS/starsector_mac.sh",
+                "starsector.sh",
+            ]
+            .map(PathBuf::from)
+            .into(),
+            // on linux the jars are right in the game directory, next to the
+            // jre, which should be left alone
+            jars: [
+                "starsector-core",
+                "Contents/Resources/Java",
+                "*.jar",
+                "mods",
+            ]
+            .map(str::to_owned)
+            .into(),
+            jre_dirs: ["jre", "jre_linux", "Contents/Home"]
+                .map(PathBuf::from)
+                .into(),
+            saves: Some("saves".into()),
+            backups: None,
+            passes: Passes::default(),
+        }
+    }
+
+    /// Everything in the directory, with no launcher to tell it by
+    pub fn generic() -> Self {
+        Self {
+            name: "generic".to_owned(),
+            arg: "generic".into(),
+            launchers: Vec::new(),
+            jars: vec![".".to_owned()],
+            jre_dirs: vec!["jre".into()],
+            saves: None,
+            backups: None,
+            passes: Passes::default(),
+        }
+    }
+
+    /// One of the built-in profiles by its name, or the profile file
+    pub fn load(arg: &OsStr) -> Result<Self> {
+        match arg.to_str() {
+            Some("starsector") => return Ok(Self::starsector()),
+            Some("generic") => return Ok(Self::generic()),
+            _ => {}
+        }
+        let path = Path::new(arg);
+        let contents =
+            std::fs::read_to_string(path).with_context(|| format!("Reading {}", path.display()))?;
+        let keys = toml::parse(&contents).with_context(|| format!("Reading {}", path.display()))?;
+
+        let mut profile = Self {
+            name: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            // the commands written with it are run from elsewhere
+            arg: crate::paths::absolute(path)?.into(),
+            saves: None,
+            ..Self::generic()
+        };
+        for (key, value) in &keys {
+            let string = || {
+                value
+                    .as_str()
+                    .map(str::to_owned)
+                    .with_context(|| format!("'{}' in {} should be a string", key, path.display()))
+            };
+            let strings = || {
+                match value {
+                    Value::Array(values) => values
+                        .iter()
+                        .map(|value| value.as_str().map(str::to_owned))
+                        .collect::<Option<Vec<_>>>(),
+                    _ => None,
+                }
+                .with_context(|| {
+                    format!(
+                        "'{}' in {} should be a list of strings",
+                        key,
+                        path.display()
+                    )
+                })
+            };
+            match key.as_str() {
+                "name" => profile.name = string()?,
+                "launchers" => profile.launchers = strings()?.into_iter().map(Into::into).collect(),
+                "jars" => profile.jars = strings()?,
+                "jre" => profile.jre_dirs = strings()?.into_iter().map(Into::into).collect(),
+                "saves" => profile.saves = Some(string()?.into()),
+                "backups" => profile.backups = Some(string()?.into()),
+                "passes" => {
+                    for pass in strings()? {
+                        let flag = match pass.as_str() {
+                            "repair-ref-kinds" => &mut profile.passes.repair_ref_kinds,
+                            "fix-all-name-and-type" => &mut profile.passes.fix_all_name_and_type,
+                            "verify-refs" => &mut profile.passes.verify_refs,
+                            "sanitize-names" => &mut profile.passes.sanitize_names,
+                            _ => bail!("Unknown pass '{}' in {}", pass, path.display()),
+                        };
+                        *flag = true;
+                    }
+                }
+                _ => bail!("Unknown key '{}' in {}", key, path.display()),
+            }
+        }
+        for jar in &profile.jars {
+            jar_pattern(jar).with_context(|| format!("Reading {}", path.display()))?;
+        }
+        log::debug!(
+            "Loaded the {} profile from {}",
+            profile.name,
+            path.display()
+        );
+        Ok(profile)
+    }
+
+    /// The argument to pass on, none for the default one
+    pub fn non_default_arg(&self) -> Option<&OsStr> {
+        Some(self.arg.as_os_str()).filter(|arg| *arg != "starsector")
+    }
+
+    /// The first of the launchers that's there, none if the profile has no
+    /// launchers at all, failing if it does but none are there
+    pub fn find_launcher(&self, game_dir: &Path) -> Result<Option<PathBuf>> {
+        if self.launchers.is_empty() {
+            return Ok(None);
+        }
+        match self.launchers.iter().find(|l| game_dir.join(l).is_file()) {
+            Some(launcher) => Ok(Some(launcher.clone())),
+            None => bail!(
+                "{} does not look like a {} directory, give the --launcher or the --profile",
+                game_dir.display(),
+                self.name
+            ),
+        }
+    }
+
+    /// The jars and the directories with them that are there, relative to
+    /// the game directory
+    pub fn find_jars(&self, game_dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut found = Vec::new();
+        for jar in &self.jars {
+            let (dir, pattern) = match jar_pattern(jar)? {
+                (_, None) => {
+                    if game_dir.join(jar).exists() {
+                        found.push(PathBuf::from(jar));
+                    }
+                    continue;
+                }
+                (dir, Some(pattern)) => (dir, pattern),
+            };
+            let mut matched = Vec::new();
+            let entries = match std::fs::read_dir(game_dir.join(dir)) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries {
+                let name = entry?.file_name();
+                if pattern.is_match(&name.to_string_lossy()) {
+                    matched.push(Path::new(dir).join(name));
+                }
+            }
+            matched.sort();
+            found.extend(matched);
+        }
+        Ok(found)
+    }
+
+    /// Where the backup of the jar goes
+    pub fn backup_path(&self, jar: &Path) -> PathBuf {
+        let mut name = jar.file_name().unwrap_or_default().to_owned();
+        name.push(".bak");
+        match &self.backups {
+            Some(dir) => jar.with_file_name(dir).join(name),
+            None => jar.with_file_name(name),
+        }
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::starsector()
+    }
+}
+
+/// The directory part and the glob of the last part, if it has one
+fn jar_pattern(jar: &str) -> Result<(&str, Option<Glob>)> {
+    let (dir, last) = jar.rsplit_once('/').unwrap_or(("", jar));
+    if !last.contains(['*', '?', '[']) {
+        return Ok((dir, None));
+    }
+    if dir.contains(['*', '?', '[']) {
+        bail!("Only the last part of '{}' can be a glob", jar);
+    }
+    Ok((dir, Some(Glob::new(last)?)))
+}
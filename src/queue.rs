@@ -0,0 +1,57 @@
+//! The hot folder: the jars dropped into one directory are fixed into
+//! another one, with a report next to each, and the ones that could not be
+//! fixed are moved aside with the error.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::temp;
+
+/// The jars waiting in the directory. Only the ones right in it, and not the
+/// hidden ones, since that's how the half-copied files usually look
+pub fn waiting(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut jars = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Reading {}", dir.display()))? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_ascii_lowercase();
+        if !name.starts_with('.') && name.ends_with(".jar") && entry.file_type()?.is_file() {
+            jars.push(entry.path());
+        }
+    }
+    jars.sort();
+    Ok(jars)
+}
+
+/// Writes the file at once, so whatever picks it up never sees half of it
+pub fn write_file(path: &Path, contents: &[u8]) -> Result<()> {
+    let (mut file, temp) = temp::next_to(path)?;
+    file.write_all(contents)
+        .and_then(|_| temp.persist(path))
+        .with_context(|| format!("Writing {}", path.display()))
+}
+
+/// Renames the file, or copies it if it's going to another file system
+pub fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if std::fs::rename(from, to).is_err() {
+        std::fs::copy(from, to)
+            .and_then(|_| std::fs::remove_file(from))
+            .with_context(|| format!("Moving {} to {}", from.display(), to.display()))?;
+    }
+    Ok(())
+}
+
+/// Moves the jar that could not be fixed into the failed directory, with
+/// the error in a file next to it
+pub fn fail(jar: &Path, failed_dir: &Path, error: &anyhow::Error) -> Result<()> {
+    std::fs::create_dir_all(failed_dir)
+        .with_context(|| format!("Creating {}", failed_dir.display()))?;
+    let name = jar.file_name().context("The jar has no name")?;
+    let target = failed_dir.join(name);
+    move_file(jar, &target)?;
+    let error = format!("{:#}\n", error);
+    write_file(&crate::with_suffix(&target, ".error.txt"), error.as_bytes())
+}
@@ -0,0 +1,91 @@
+//! The entries with the names that are not valid UTF-8, or that are not
+//! marked as UTF-8 and so are read as CP437. The zip crate only has those
+//! as strings, and would write them as such, changing the bytes, so instead
+//! they are written under an ASCII placeholder of the same length, which is
+//! replaced with the original name once the whole jar is written. With the
+//! length being the same nothing moves.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use anyhow::{bail, Result};
+use zip::{read::ZipFile, ZipArchive};
+
+use crate::console;
+
+/// Where the local header has the name
+const LOCAL_NAME_OFFSET: u64 = 30;
+/// And where the central directory header has it
+const CENTRAL_NAME_OFFSET: u64 = 46;
+
+/// The names to put back, by the index of the entry in the written jar
+#[derive(Debug, Default)]
+pub struct RawNames(Vec<(usize, Vec<u8>)>);
+
+impl RawNames {
+    /// The name to write the entry (the `index`th in the written jar) under,
+    /// remembering the original one if that's a placeholder
+    pub fn name_for(&mut self, file: &ZipFile, index: usize) -> String {
+        let raw = file.name_raw();
+        if file.name().as_bytes() == raw {
+            return file.name().to_owned();
+        }
+        log::debug!("Keeping the name of {} as it is", escaped(raw));
+        self.0.push((index, raw.to_owned()));
+        // the zip crate marks the name as UTF-8 only when it's not ASCII,
+        // and the original is not UTF-8 (or wasn't marked as one) either
+        raw.iter()
+            .map(|&b| match b {
+                b' '..=b'~' => b as char,
+                _ => '_',
+            })
+            .collect()
+    }
+
+    /// Puts the original names over the placeholders in the finished jar
+    pub fn restore(&self, mut output: impl Read + Write + Seek) -> Result<()> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        let mut offsets = Vec::with_capacity(self.0.len() * 2);
+        {
+            let mut zip = ZipArchive::new(&mut output)?;
+            for (index, raw) in &self.0 {
+                let file = zip.by_index_raw(*index)?;
+                if file.name_raw().len() != raw.len() {
+                    bail!(
+                        "The placeholder for {} is not where it should be",
+                        escaped(raw)
+                    );
+                }
+                offsets.push((file.header_start() + LOCAL_NAME_OFFSET, raw));
+                offsets.push((file.central_header_start() + CENTRAL_NAME_OFFSET, raw));
+            }
+        }
+        for (offset, raw) in offsets {
+            output.seek(SeekFrom::Start(offset))?;
+            output.write_all(raw)?;
+        }
+        output.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+}
+
+/// The name of the entry, escaped if it has to be kept as it is
+pub fn display_name(file: &ZipFile) -> String {
+    match file.name().as_bytes() == file.name_raw() {
+        true => file.name().to_owned(),
+        false => escaped(file.name_raw()),
+    }
+}
+
+/// The name for the logs, with the bytes that are not UTF-8 as `\xNN`
+pub fn escaped(raw: &[u8]) -> String {
+    let mut out = String::with_capacity(raw.len());
+    for chunk in raw.utf8_chunks() {
+        out.push_str(&console::escaped(chunk.valid()));
+        for b in chunk.invalid() {
+            out.push_str(&format!("\\x{:02x}", b));
+        }
+    }
+    out
+}
@@ -0,0 +1,158 @@
+//! Finding where the attributes really end when their lengths were made up
+//! to throw the parsers off, by looking for an end after which the rest of
+//! the class makes sense.
+
+use crate::class::Constant;
+
+/// Which part of the class the attribute being read is in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Fields,
+    Methods,
+    Class,
+}
+
+/// What is left to read after the attribute
+#[derive(Debug, Clone, Copy)]
+pub struct Position {
+    pub section: Section,
+    /// The members of the section after the current one
+    pub members_left: u16,
+    /// The attributes of the current member (or the class) after this one
+    pub attributes_left: u16,
+}
+
+/// The end of the attribute that starts (after its header) at `start`: the
+/// declared one if the rest of the class parses after it, otherwise the one
+/// that the contents of the attribute suggest, otherwise the first place the
+/// rest of the class parses from
+pub fn attribute_end(
+    data: &[u8],
+    start: usize,
+    declared: usize,
+    name: &[u8],
+    pool: &[Constant],
+    after: Position,
+) -> Option<usize> {
+    // where the class ends if the attribute ends at the given place
+    let class_end = |end: usize| match end <= data.len() {
+        true => rest_parses(data, end, pool, after),
+        false => None,
+    };
+    let mut candidates = vec![start.saturating_add(declared)];
+    candidates.extend(structural_len(name, &data[start..]).map(|len| start + len));
+
+    // classes with garbage after them are rarer than ones with wrong
+    // lengths, but the scan would always find an attribute that would eat
+    // up all of the garbage, so the known lengths get a chance first
+    let exact = |end: &usize| class_end(*end) == Some(data.len());
+    if let Some(&end) = candidates.iter().find(|end| exact(end)) {
+        return Some(end);
+    }
+    if let Some(&end) = candidates.iter().find(|end| class_end(**end).is_some()) {
+        return Some(end);
+    }
+    (start..=data.len()).find(exact)
+}
+
+fn u16_at(data: &[u8], pos: usize) -> Option<usize> {
+    let b = data.get(pos..pos + 2)?;
+    Some(u16::from_be_bytes([b[0], b[1]]) as usize)
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Option<usize> {
+    let b = data.get(pos..pos + 4)?;
+    Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+}
+
+/// The length of the attribute judging by its contents, for the ones that
+/// have a simple enough structure
+fn structural_len(name: &[u8], info: &[u8]) -> Option<usize> {
+    // a count, followed by that many entries of the given size
+    let table = |header: usize, entry: usize| Some(header + u16_at(info, 0)? * entry);
+    match name {
+        b"ConstantValue" | b"SourceFile" | b"Signature" | b"NestHost" | b"ModuleMainClass" => {
+            Some(2)
+        }
+        b"Deprecated" | b"Synthetic" => Some(0),
+        b"EnclosingMethod" => Some(4),
+        b"Exceptions" | b"NestMembers" | b"PermittedSubclasses" | b"ModulePackages" => table(2, 2),
+        b"InnerClasses" => table(2, 8),
+        b"LineNumberTable" => table(2, 4),
+        b"LocalVariableTable" | b"LocalVariableTypeTable" => table(2, 10),
+        b"MethodParameters" => Some(1 + *info.first()? as usize * 4),
+        b"BootstrapMethods" => {
+            let mut pos = 2;
+            for _ in 0..u16_at(info, 0)? {
+                pos += 4 + u16_at(info, pos + 2)? * 2;
+            }
+            Some(pos)
+        }
+        b"Code" => {
+            let mut pos = 8 + u32_at(info, 4)?;
+            pos += 2 + u16_at(info, pos)? * 8;
+            let count = u16_at(info, pos)?;
+            pos += 2;
+            for _ in 0..count {
+                pos += 6 + u32_at(info, pos + 2)?;
+            }
+            Some(pos)
+        }
+        _ => None,
+    }
+}
+
+fn utf8(pool: &[Constant], index: usize) -> Option<&[u8]> {
+    match pool.get(index) {
+        Some(Constant::Utf8(bytes)) => Some(bytes),
+        _ => None,
+    }
+}
+
+/// Where the class ends, if the rest of it, starting at `pos`, parses
+/// without any more lies in it
+fn rest_parses(data: &[u8], mut pos: usize, pool: &[Constant], after: Position) -> Option<usize> {
+    let Position {
+        mut section,
+        mut members_left,
+        mut attributes_left,
+    } = after;
+    loop {
+        for _ in 0..attributes_left {
+            let name = utf8(pool, u16_at(data, pos)?)?;
+            let len = u32_at(data, pos + 2)?;
+            pos += 6;
+            if pos + len > data.len()
+                || structural_len(name, &data[pos..]).is_some_and(|expected| expected != len)
+            {
+                return None;
+            }
+            pos += len;
+        }
+        match section {
+            Section::Fields | Section::Methods if members_left > 0 => {
+                // the name and the descriptor of the member
+                utf8(pool, u16_at(data, pos + 2)?)?;
+                let descriptor = utf8(pool, u16_at(data, pos + 4)?)?;
+                if !b"(BCDFIJSZL[".contains(descriptor.first()?) {
+                    return None;
+                }
+                attributes_left = u16_at(data, pos + 6)? as u16;
+                pos += 8;
+                members_left -= 1;
+            }
+            Section::Fields => {
+                members_left = u16_at(data, pos)? as u16;
+                attributes_left = 0;
+                section = Section::Methods;
+                pos += 2;
+            }
+            Section::Methods => {
+                attributes_left = u16_at(data, pos)? as u16;
+                section = Section::Class;
+                pos += 2;
+            }
+            Section::Class => return Some(pos),
+        }
+    }
+}
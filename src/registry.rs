@@ -0,0 +1,164 @@
+//! The renames remembered across runs, so that a name is fixed the same way
+//! in every jar no matter when (or by which version of this) it was fixed.
+//! The jars fixed with it are remembered too, to tell when they got replaced
+//! by the unfixed ones, like with an update of the game.
+
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{fix, json, temp};
+
+#[derive(Debug)]
+pub struct Registry {
+    path: PathBuf,
+    /// Original name -> the fixed one
+    renames: BTreeMap<String, String>,
+    /// The fixed jar -> its SHA-256
+    jars: BTreeMap<PathBuf, String>,
+    changed: bool,
+}
+
+impl Registry {
+    /// Reads the registry, starting an empty one if the file does not exist
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut registry = Self {
+            path: path.to_owned(),
+            renames: BTreeMap::new(),
+            jars: BTreeMap::new(),
+            changed: false,
+        };
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::info!("Starting a new rename registry at {}", path.display());
+                return Ok(registry);
+            }
+            Err(e) => return Err(e).with_context(|| format!("Reading {}", path.display())),
+        };
+        let root = json::parse(&contents).with_context(|| format!("Reading {}", path.display()))?;
+        let renames = match root.get("renames").and_then(json::Value::as_object) {
+            Some(renames) => renames,
+            None => bail!("{} has no \"renames\" object", path.display()),
+        };
+        for (original, fixed) in renames {
+            let fixed = fixed.as_str().with_context(|| {
+                format!(
+                    "The rename of '{}' in {} is not a string",
+                    original,
+                    path.display()
+                )
+            })?;
+            registry.renames.insert(original.clone(), fixed.to_owned());
+        }
+        // not there in the older ones
+        let jars = root.get("jars").and_then(json::Value::as_object);
+        for (jar, sha256) in jars.into_iter().flatten() {
+            let sha256 = sha256.as_str().with_context(|| {
+                format!(
+                    "The hash of '{}' in {} is not a string",
+                    jar,
+                    path.display()
+                )
+            })?;
+            registry.jars.insert(jar.into(), sha256.to_owned());
+        }
+        log::debug!(
+            "Loaded {} renames from {}",
+            registry.renames.len(),
+            path.display()
+        );
+        Ok(registry)
+    }
+
+    /// Every original name with its fixed one
+    pub fn renames(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.renames.iter().map(|(o, f)| (o.as_str(), f.as_str()))
+    }
+
+    /// Every jar fixed with the registry, with the SHA-256 it had then
+    pub fn jars(&self) -> impl Iterator<Item = (&Path, &str)> {
+        self.jars.iter().map(|(j, h)| (j.as_path(), h.as_str()))
+    }
+
+    /// Remembers the jar as fixed, with the hash it has now
+    pub fn record_jar(&mut self, jar: &Path, sha256: String) {
+        if self.jars.get(jar) != Some(&sha256) {
+            self.jars.insert(jar.to_owned(), sha256);
+            self.changed = true;
+        }
+    }
+
+    /// The fixed name, either the one that was used before or a new one
+    /// that is then remembered
+    pub fn fixed_name(&mut self, name: &str) -> Option<String> {
+        if let Some(fixed) = self.renames.get(name) {
+            return Some(fixed.clone());
+        }
+        let fixed = fix::fixed_name(name)?;
+        if let Some((other, _)) = self.renames.iter().find(|(_, f)| **f == fixed) {
+            log::warn!(
+                "Both '{}' and '{}' are renamed to '{}', they will clash if they are in the same class",
+                other,
+                name,
+                fixed
+            );
+        }
+        self.renames.insert(name.to_owned(), fixed.clone());
+        self.changed = true;
+        Some(fixed)
+    }
+
+    /// Whether the name has a fixed one already, without making one for it
+    pub fn has_fixed_name(&self, name: &str) -> bool {
+        self.renames.contains_key(name)
+    }
+
+    /// Remembers the name to be fixed into another one than the usual one
+    pub fn set_fixed_name(&mut self, name: &str, fixed: &str) {
+        if let Some(before) = self.renames.get(name).filter(|before| *before != fixed) {
+            log::warn!(
+                "'{}' was renamed to '{}' before, the jars and the saves fixed then have that name",
+                name,
+                before
+            );
+        }
+        if self.renames.get(name).map(String::as_str) != Some(fixed) {
+            self.renames.insert(name.to_owned(), fixed.to_owned());
+            self.changed = true;
+        }
+    }
+
+    /// Writes the registry back, if there were new renames or jars
+    pub fn save(&self) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        let renames = self
+            .renames
+            .iter()
+            .map(|(k, v)| (k.clone(), json::Value::String(v.clone())))
+            .collect();
+        let jars = self
+            .jars
+            .iter()
+            .map(|(k, v)| (k.display().to_string(), json::Value::String(v.clone())))
+            .collect();
+        let root = json::Value::Object(BTreeMap::from([
+            ("renames".to_owned(), json::Value::Object(renames)),
+            ("jars".to_owned(), json::Value::Object(jars)),
+        ]));
+
+        // not to lose the whole thing if we crash mid-write
+        let (mut file, temp) = temp::next_to(&self.path)?;
+        file.write_all(root.to_pretty_string().as_bytes())
+            .and_then(|_| temp.persist(&self.path))
+            .with_context(|| format!("Writing {}", self.path.display()))?;
+        log::info!("Saved the rename registry to {}", self.path.display());
+        Ok(())
+    }
+}
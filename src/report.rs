@@ -0,0 +1,155 @@
+//! The report embedded into the fixed jars with --embed-report, so that any
+//! copy of the jar tells what was done to it.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{fix::FixOptions, json::Value};
+
+pub const PATH: &str = "META-INF/starsector-fixer/report.json";
+
+#[derive(Debug, Clone)]
+pub struct Rename {
+    /// The path of the class in the jar
+    pub class: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// The renames made in the jar being fixed
+pub type Renames = Arc<Mutex<Vec<Rename>>>;
+
+/// A class that was copied as it is because of a constant with an unknown
+/// tag, so that support for it can be added
+#[derive(Debug, Clone)]
+pub struct Skipped {
+    pub class: String,
+    pub tag: u8,
+    pub offset: u64,
+}
+
+fn string(s: impl Into<String>) -> Value {
+    Value::String(s.into())
+}
+
+pub fn build(options: &FixOptions, renames: &[Rename], skipped: &[Skipped]) -> Value {
+    let mut fix_options = BTreeMap::new();
+    if let Some(version) = options.class_version {
+        fix_options.insert("set_class_version".into(), Value::Number(version.into()));
+    }
+    if let Some(policy) = options.source_file {
+        fix_options.insert(
+            "sanitize_source_file".into(),
+            string(format!("{:?}", policy).to_ascii_lowercase()),
+        );
+    }
+    fix_options.insert(
+        "repair_ref_kinds".into(),
+        Value::Bool(options.repair_ref_kinds),
+    );
+    fix_options.insert(
+        "fix_all_name_and_type".into(),
+        Value::Bool(options.all_name_and_type),
+    );
+    fix_options.insert("sanitize_names".into(), Value::Bool(options.sanitize_names));
+    fix_options.insert(
+        "recurse_archives".into(),
+        Value::Bool(options.recurse_archives),
+    );
+    if let Some(rules) = &options.rules {
+        fix_options.insert("rules".into(), string(&*rules.source));
+    }
+    if let Some(mapping) = &options.mapping {
+        fix_options.insert("mapping".into(), string(&*mapping.source));
+    }
+    fix_options.insert("lenient".into(), Value::Bool(options.lenient));
+    fix_options.insert(
+        "trailing_garbage".into(),
+        string(format!("{:?}", options.trailing_garbage).to_ascii_lowercase()),
+    );
+    fix_options.insert(
+        "on_collision".into(),
+        string(format!("{:?}", options.on_collision).to_ascii_lowercase()),
+    );
+    if !options.only_packages.is_empty() {
+        let globs = options.only_packages.iter().map(|g| string(g.as_str()));
+        fix_options.insert("only_package".into(), Value::Array(globs.collect()));
+    }
+
+    let renames = renames
+        .iter()
+        .map(|rename| {
+            Value::Object(BTreeMap::from([
+                ("class".into(), string(&*rename.class)),
+                ("from".into(), string(&*rename.from)),
+                ("to".into(), string(&*rename.to)),
+            ]))
+        })
+        .collect();
+
+    let skipped = skipped
+        .iter()
+        .map(|skipped| {
+            Value::Object(BTreeMap::from([
+                ("class".into(), string(&*skipped.class)),
+                ("tag".into(), Value::Number(skipped.tag.into())),
+                ("offset".into(), Value::Number(skipped.offset as f64)),
+            ]))
+        })
+        .collect();
+
+    Value::Object(BTreeMap::from([
+        ("fixer".into(), string(env!("CARGO_PKG_NAME"))),
+        ("version".into(), string(env!("CARGO_PKG_VERSION"))),
+        ("commit".into(), string(env!("BUILD_COMMIT"))),
+        ("timestamp".into(), string(timestamp(options.now))),
+        ("options".into(), Value::Object(fix_options)),
+        ("renames".into(), Value::Array(renames)),
+        ("unknown_constant_tags".into(), Value::Array(skipped)),
+    ]))
+}
+
+/// The given or the current time in UTC, as in `2022-07-01T12:34:56Z`.
+/// Takes the SOURCE_DATE_EPOCH into account, so the fixed jars can be
+/// reproducible
+fn timestamp(now: Option<SystemTime>) -> String {
+    let since_epoch = |time: SystemTime| {
+        time.duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs())
+    };
+    let secs = match now {
+        Some(now) => since_epoch(now),
+        None => std::env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .and_then(|epoch| epoch.parse().ok())
+            .unwrap_or_else(|| since_epoch(SystemTime::now())),
+    };
+    format!("{}Z", date_time(secs))
+}
+
+/// The time as in `2022-07-01T12:34:56`, without the time zone
+pub fn date_time(secs: u64) -> String {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    let time = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
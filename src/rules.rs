@@ -0,0 +1,362 @@
+//! The renames and the removals given in a rules file, for the kinds of bad
+//! names (and the other junk obfuscators leave) that the fixer doesn't know
+//! about yet, without waiting for a new version of it. The file is TOML,
+//! like this:
+//!
+//! ```toml
+//! # the attributes to remove from the classes, the members and the code,
+//! # before the tables, or it would be in the last one
+//! strip_attributes = ["SourceDebugExtension"]
+//!
+//! # done to the names after the usual fixing, every rule that matches in
+//! # turn, each one to what the one before made of the name
+//! [[rename]]
+//! # which names, "field", "method", "ref-only" (the ones of the members of
+//! # other classes), "local" (the local variables and the parameters) and
+//! # "other" (the rest of the NameAndType constants), all but "other" by
+//! # default
+//! uses = ["field", "method"]
+//! # only the names matching this
+//! match = '^\$'
+//! # every one of these characters is replaced with the one in the same
+//! # place in `into`
+//! chars = "$-"
+//! into = "__"
+//!
+//! [[rename]]
+//! # or a regex replacing the matches, with $1 and such for the groups
+//! regex = '^(\d)'
+//! replace = '_$1'
+//! ```
+//!
+//! The regex renames can also be given with --rename-pattern, like
+//! `--rename-pattern '^access\$(\d+)$=access_$1'`, which are done after the
+//! ones in the file, to all of the names but the "other" ones.
+
+use std::{collections::BTreeSet, path::Path};
+
+use anyhow::{bail, ensure, Context, Result};
+use regex::Regex;
+
+use crate::{
+    class::{Attribute, ClassFile, Code},
+    fix::NameUse,
+    json::Value,
+    toml,
+};
+
+#[derive(Debug)]
+pub struct Rules {
+    /// Where they are from, for the report
+    pub source: String,
+    renames: Vec<Rename>,
+    strip_attributes: BTreeSet<String>,
+}
+
+#[derive(Debug)]
+struct Rename {
+    uses: Vec<NameUse>,
+    matching: Option<Regex>,
+    chars: Vec<(char, char)>,
+    regex: Option<(Regex, String)>,
+}
+
+/// A regex rename given with --rename-pattern
+#[derive(Debug, Clone)]
+pub struct RenamePattern {
+    text: String,
+    regex: Regex,
+    replace: String,
+}
+
+impl RenamePattern {
+    /// Reads the regex and the replacement, separated by the first `=`, so
+    /// an `=` in the regex has to be written as `\x3D`
+    pub fn new(text: &str) -> Result<Self> {
+        let (regex, replace) = text
+            .split_once('=')
+            .context("The pattern should be a regex and its replacement separated by '='")?;
+        Ok(Self {
+            text: text.to_owned(),
+            regex: Regex::new(regex)?,
+            replace: replace.to_owned(),
+        })
+    }
+}
+
+/// The uses that the renames are for when they don't say
+fn default_uses() -> Vec<NameUse> {
+    vec![
+        NameUse::Field,
+        NameUse::Method,
+        NameUse::RefOnly,
+        NameUse::Local,
+    ]
+}
+
+impl Rename {
+    fn apply(&self, name: &str) -> Option<String> {
+        if self.matching.as_ref().is_some_and(|m| !m.is_match(name)) {
+            return None;
+        }
+        let mut result = name
+            .chars()
+            .map(|c| match self.chars.iter().find(|(from, _)| *from == c) {
+                Some((_, to)) => *to,
+                None => c,
+            })
+            .collect::<String>();
+        if let Some((regex, replace)) = &self.regex {
+            result = regex.replace_all(&result, replace.as_str()).into_owned();
+        }
+        (result != name).then_some(result)
+    }
+}
+
+impl Rules {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents =
+            std::fs::read_to_string(path).with_context(|| format!("Reading {}", path.display()))?;
+        Self::parse(&contents)
+            .with_context(|| format!("Reading {}", path.display()))
+            .map(|rules| Self {
+                source: path.display().to_string(),
+                ..rules
+            })
+    }
+
+    /// No rules, for when there are only the --rename-pattern ones
+    pub fn empty() -> Self {
+        Self {
+            source: String::new(),
+            renames: Vec::new(),
+            strip_attributes: BTreeSet::new(),
+        }
+    }
+
+    fn parse(contents: &str) -> Result<Self> {
+        let mut rules = Self::empty();
+        for (key, value) in toml::parse(contents)? {
+            match key.as_str() {
+                "rename" => match value {
+                    Value::Array(tables) => {
+                        for (i, table) in tables.iter().enumerate() {
+                            let rename = parse_rename(table)
+                                .with_context(|| format!("In rename #{}", i + 1))?;
+                            rules.renames.push(rename);
+                        }
+                    }
+                    _ => bail!("'rename' should be given as [[rename]] tables"),
+                },
+                "strip_attributes" => rules.strip_attributes = strings(&key, &value)?.collect(),
+                _ => bail!("Unknown key '{}'", key),
+            }
+        }
+        log::debug!(
+            "Loaded {} renames and {} attributes to strip",
+            rules.renames.len(),
+            rules.strip_attributes.len()
+        );
+        Ok(rules)
+    }
+
+    /// Adds the renames given with --rename-pattern after the ones there are
+    pub fn add_patterns(&mut self, patterns: &[RenamePattern]) {
+        for pattern in patterns {
+            self.renames.push(Rename {
+                uses: default_uses(),
+                matching: None,
+                chars: Vec::new(),
+                regex: Some((pattern.regex.clone(), pattern.replace.clone())),
+            });
+            let source = format!("--rename-pattern {}", pattern.text);
+            self.source = match self.source.is_empty() {
+                true => source,
+                false => format!("{}, {}", self.source, source),
+            };
+        }
+    }
+
+    /// Whether any of the renames are for the names that are not collected
+    /// without --fix-all-name-and-type
+    pub fn wants_all_name_and_type(&self) -> bool {
+        self.renames
+            .iter()
+            .any(|r| r.uses.contains(&NameUse::Other))
+    }
+
+    /// The new name, if any of the rules change it
+    pub fn rename(&self, name: &str, name_use: NameUse) -> Option<String> {
+        let mut renamed = None;
+        for rename in self.renames.iter().filter(|r| r.uses.contains(&name_use)) {
+            if let Some(new) = rename.apply(renamed.as_deref().unwrap_or(name)) {
+                renamed = Some(new);
+            }
+        }
+        renamed
+    }
+
+    /// Removes the attributes of the class, its members and their code that
+    /// the rules say to, returning whether any were there
+    pub fn strip_attributes(&self, class: &mut ClassFile, filename: &str) -> Result<bool> {
+        if self.strip_attributes.is_empty() {
+            return Ok(false);
+        }
+        let mut stripped = BTreeSet::new();
+        let mut strip = |class: &ClassFile, attributes: &mut Vec<Attribute>| -> Result<()> {
+            let mut kept = Vec::with_capacity(attributes.len());
+            for attribute in attributes.drain(..) {
+                let name = class.attribute_name(&attribute)?;
+                match self.strip_attributes.contains(&*name) {
+                    true => {
+                        stripped.insert(name.into_owned());
+                    }
+                    false => kept.push(attribute),
+                }
+            }
+            *attributes = kept;
+            Ok(())
+        };
+
+        let mut attributes = std::mem::take(&mut class.attributes);
+        strip(class, &mut attributes)?;
+        class.attributes = attributes;
+        for is_method in [false, true] {
+            let mut members = match is_method {
+                true => std::mem::take(&mut class.methods),
+                false => std::mem::take(&mut class.fields),
+            };
+            for member in &mut members {
+                strip(class, &mut member.attributes)?;
+                for attribute in &mut member.attributes {
+                    if class.attribute_name(attribute)? != "Code" {
+                        continue;
+                    }
+                    let mut code = Code::parse(&attribute.info)?;
+                    let count = code.attributes.len();
+                    strip(class, &mut code.attributes)?;
+                    if code.attributes.len() != count {
+                        attribute.info = code.to_bytes();
+                    }
+                }
+            }
+            match is_method {
+                true => class.methods = members,
+                false => class.fields = members,
+            }
+        }
+
+        for name in &stripped {
+            log::info!("Removing the {} attributes from {}", name, filename);
+        }
+        Ok(!stripped.is_empty())
+    }
+}
+
+fn parse_rename(table: &Value) -> Result<Rename> {
+    let table = table.as_object().context("Not a table")?;
+    let mut rename = Rename {
+        uses: default_uses(),
+        matching: None,
+        chars: Vec::new(),
+        regex: None,
+    };
+    let (mut chars, mut into, mut regex, mut replace) = (None, None, None, None);
+    for (key, value) in table {
+        let string = || {
+            value
+                .as_str()
+                .with_context(|| format!("'{}' should be a string", key))
+        };
+        match key.as_str() {
+            "uses" => {
+                rename.uses = strings(key, value)?
+                    .map(|name_use| match name_use.as_str() {
+                        "field" => Ok(NameUse::Field),
+                        "method" => Ok(NameUse::Method),
+                        "ref-only" => Ok(NameUse::RefOnly),
+                        "other" => Ok(NameUse::Other),
+                        "local" => Ok(NameUse::Local),
+                        _ => bail!("Unknown use '{}'", name_use),
+                    })
+                    .collect::<Result<_>>()?
+            }
+            "match" => rename.matching = Some(Regex::new(string()?)?),
+            "chars" => chars = Some(string()?),
+            "into" => into = Some(string()?),
+            "regex" => regex = Some(Regex::new(string()?)?),
+            "replace" => replace = Some(string()?),
+            _ => bail!("Unknown key '{}'", key),
+        }
+    }
+    match (chars, into) {
+        (Some(chars), Some(into)) => {
+            ensure!(
+                chars.chars().count() == into.chars().count(),
+                "'chars' and 'into' should have as many characters"
+            );
+            rename.chars = chars.chars().zip(into.chars()).collect();
+        }
+        (None, None) => {}
+        _ => bail!("'chars' and 'into' go together"),
+    }
+    match (regex, replace) {
+        (Some(regex), Some(replace)) => rename.regex = Some((regex, replace.to_owned())),
+        (None, None) => {}
+        _ => bail!("'regex' and 'replace' go together"),
+    }
+    ensure!(
+        !rename.chars.is_empty() || rename.regex.is_some(),
+        "Nothing to rename with, give 'chars' and 'into' or 'regex' and 'replace'"
+    );
+    Ok(rename)
+}
+
+fn strings<'a>(key: &str, value: &'a Value) -> Result<impl Iterator<Item = String> + 'a> {
+    match value {
+        Value::Array(values) if values.iter().all(|v| v.as_str().is_some()) => {
+            Ok(values.iter().filter_map(Value::as_str).map(str::to_owned))
+        }
+        _ => bail!("'{}' should be a list of strings", key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{class::tests::sample, fix::FixOptions};
+
+    #[test]
+    fn patterns() {
+        let mut rules = Rules::parse("[[rename]]\nchars = \"$\"\ninto = \"_\"\n").unwrap();
+        let patterns =
+            ["^access_(\\d+)$=access$$$1", "a=b"].map(|p| RenamePattern::new(p).unwrap());
+        rules.add_patterns(&patterns);
+        assert_eq!(
+            rules.rename("access$100", NameUse::Method).unwrap(),
+            "bccess$100"
+        );
+        assert_eq!(rules.rename("x", NameUse::Field), None);
+        assert_eq!(rules.rename("a", NameUse::Other), None);
+        assert_eq!(
+            rules.source,
+            "--rename-pattern ^access_(\\d+)$=access$$$1, --rename-pattern a=b"
+        );
+
+        assert!(RenamePattern::new("no separator").is_err());
+        assert!(RenamePattern::new("(=x").is_err());
+    }
+
+    #[test]
+    fn longer_names() {
+        let mut rules = Rules::empty();
+        rules.add_patterns(&[RenamePattern::new("^a_b$=a_dot_b").unwrap()]);
+        let options = FixOptions {
+            rules: Some(rules.into()),
+            ..Default::default()
+        };
+        let fixed = crate::fix::fix_class(&sample().to_bytes(), "Test.class", &options, None);
+        let class = ClassFile::parse(&fixed.unwrap().unwrap()).unwrap();
+        assert_eq!(class.utf8(class.fields[0].name_index).unwrap(), "a_dot_b");
+    }
+}
@@ -0,0 +1,246 @@
+//! Going through the directories given as inputs for the jars (and
+//! tarballs) in them.
+//!
+//! A `.fixerignore` in any of the directories excludes things from the scan
+//! the same way a `.gitignore` would: one glob per line, `#` comments, `!`
+//! to bring back something excluded before, a trailing `/` to only match
+//! directories and a `/` anywhere else to match the path relative to the
+//! directory of the ignore file instead of just the name. The --include and
+//! the --exclude globs go the same way, by the name without a `/` and by the
+//! path relative to the given directory with one.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{glob::Glob, tar};
+
+const IGNORE_FILE: &str = ".fixerignore";
+
+#[derive(Debug)]
+struct Rule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+    /// Matched against the path relative to `base` rather than the name
+    anchored: bool,
+    /// The directory with the ignore file that had the rule
+    base: PathBuf,
+}
+
+impl Rule {
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        glob_matches(&self.glob, self.anchored, &self.base, path)
+    }
+}
+
+/// Whether the glob matches the name of the path, or with `anchored`, the
+/// path relative to `base`
+fn glob_matches(glob: &Glob, anchored: bool, base: &Path, path: &Path) -> bool {
+    if !anchored {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        return glob.is_match(&name);
+    }
+    match path.strip_prefix(base) {
+        Ok(relative) => {
+            let relative = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            glob.is_match(&relative)
+        }
+        Err(_) => false,
+    }
+}
+
+fn read_rules(dir: &Path) -> Result<Vec<Rule>> {
+    let path = dir.join(IGNORE_FILE);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Reading {}", path.display())),
+    };
+    let mut rules = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let glob = Glob::new(line.strip_prefix('/').unwrap_or(line))
+            .with_context(|| format!("Reading {}", path.display()))?;
+        rules.push(Rule {
+            glob,
+            negated,
+            dir_only,
+            anchored,
+            base: dir.to_owned(),
+        });
+    }
+    Ok(rules)
+}
+
+fn is_ignored(rules: &[Rule], path: &Path, is_dir: bool) -> bool {
+    // the last rule that matches decides
+    rules
+        .iter()
+        .rev()
+        .find(|rule| rule.matches(path, is_dir))
+        .is_some_and(|rule| !rule.negated)
+}
+
+fn is_archive(path: &Path) -> bool {
+    let jar = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("jar"));
+    jar || tar::Compression::detect(path).is_some()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// How many directories deep to go, 0 being only the files right in the
+    /// given directory
+    pub max_depth: Option<usize>,
+    pub follow_symlinks: bool,
+    /// Only the archives matching one of these are found, all of them if
+    /// there are none
+    pub include: Vec<Glob>,
+    /// The archives and the directories to leave out
+    pub exclude: Vec<Glob>,
+}
+
+impl ScanOptions {
+    fn is_excluded(&self, root: &Path, path: &Path) -> bool {
+        self.exclude.iter().any(|glob| matches(glob, root, path))
+    }
+
+    fn is_included(&self, root: &Path, path: &Path) -> bool {
+        self.include.is_empty() || self.include.iter().any(|glob| matches(glob, root, path))
+    }
+}
+
+fn matches(glob: &Glob, root: &Path, path: &Path) -> bool {
+    glob_matches(glob, glob.as_str().contains('/'), root, path)
+}
+
+struct Walk<'a> {
+    options: &'a ScanOptions,
+    /// The directory given as the input
+    root: &'a Path,
+    rules: Vec<Rule>,
+    /// The canonical paths of everything found so far, so that going into
+    /// the same place through a symlink does not loop or find things twice
+    seen: HashSet<PathBuf>,
+    found: Vec<PathBuf>,
+}
+
+/// Replaces the directories with the archives in them, the files and URLs
+/// are kept as they are
+pub fn expand(inputs: &[PathBuf], options: &ScanOptions) -> Result<Vec<PathBuf>> {
+    let mut expanded = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            let before = expanded.len();
+            let mut walk = Walk {
+                options,
+                root: input,
+                rules: Vec::new(),
+                seen: HashSet::new(),
+                found: expanded,
+            };
+            let result = walk.walk(input, 0);
+            expanded = walk.found;
+            result.with_context(|| format!("Scanning {}", input.display()))?;
+            log::debug!(
+                "Found {} archives in {}",
+                expanded.len() - before,
+                input.display()
+            );
+        } else {
+            expanded.push(input.clone());
+        }
+    }
+    Ok(expanded)
+}
+
+impl Walk<'_> {
+    fn walk(&mut self, dir: &Path, depth: usize) -> Result<()> {
+        if let Ok(canonical) = dir.canonicalize() {
+            if !self.seen.insert(canonical) {
+                log::warn!(
+                    "Not going into {} again, it was already scanned through a symlink",
+                    dir.display()
+                );
+                return Ok(());
+            }
+        }
+        let inherited = self.rules.len();
+        self.rules.extend(read_rules(dir)?);
+
+        let mut entries = std::fs::read_dir(dir)
+            .with_context(|| format!("Reading {}", dir.display()))?
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Reading {}", dir.display()))?;
+        // so that the order things are fixed in and logged doesn't change
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let path = entry.path();
+            let mut file_type = entry.file_type()?;
+            if file_type.is_symlink() {
+                if !self.options.follow_symlinks {
+                    log::debug!("Not following the symlink {}", path.display());
+                    continue;
+                }
+                file_type = match std::fs::metadata(&path) {
+                    Ok(metadata) => metadata.file_type(),
+                    Err(e) => {
+                        log::warn!("Broken symlink {}: {}", path.display(), e);
+                        continue;
+                    }
+                };
+            }
+            let is_dir = file_type.is_dir();
+            if is_ignored(&self.rules, &path, is_dir) || self.options.is_excluded(self.root, &path)
+            {
+                log::debug!("Ignoring {}", path.display());
+                continue;
+            }
+            if is_dir {
+                if self.options.max_depth.is_some_and(|max| depth >= max) {
+                    log::debug!("Not going into {}, it's too deep", path.display());
+                    continue;
+                }
+                self.walk(&path, depth + 1)?;
+            } else if is_archive(&path) && self.options.is_included(self.root, &path) {
+                // the same jar can be linked from several places
+                if self.options.follow_symlinks {
+                    if let Ok(canonical) = path.canonicalize() {
+                        if !self.seen.insert(canonical) {
+                            continue;
+                        }
+                    }
+                }
+                self.found.push(path);
+            }
+        }
+
+        self.rules.truncate(inherited);
+        Ok(())
+    }
+}
@@ -0,0 +1,321 @@
+//! Just enough of HTTP to take a jar in a POST and send the fixed one back,
+//! for running the fixer as a service. Meant to be behind a proper web
+//! server that deals with TLS and the rest of the internet.
+//!
+//! The requests are handled one at a time, the same as everything else, so
+//! each of them has a deadline, for a slow client to not hold up the rest.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+
+/// How long a client can take to send (or receive) a piece of the request
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// How long a client can take to send the whole request, and then to take
+/// the whole response
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// The body grows as it comes, past this, instead of taking whatever the
+/// client says it's going to send up front
+const MAX_PREALLOCATE: u64 = 1024 * 1024;
+/// For the request line and for all of the headers together
+const MAX_HEAD: u64 = 16 * 1024;
+
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// Whatever was after the `?`
+    pub query: String,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &'static str, body: Vec<u8>) -> Self {
+        Self {
+            status,
+            content_type,
+            body,
+        }
+    }
+
+    pub fn text(status: u16, text: impl Into<String>) -> Self {
+        let mut body = text.into().into_bytes();
+        body.push(b'\n');
+        Self::new(status, "text/plain; charset=utf-8", body)
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        200 => "OK",
+        400 => "Bad Request",
+        408 => "Request Timeout",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        422 => "Unprocessable Content",
+        431 => "Request Header Fields Too Large",
+        501 => "Not Implemented",
+        _ => "Internal Server Error",
+    }
+}
+
+/// The connection, with the reads and the writes failing once the time is
+/// out
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    at: Instant,
+}
+
+impl Deadline<'_> {
+    fn timeout(&self) -> io::Result<Duration> {
+        match self.at.saturating_duration_since(Instant::now()) {
+            left if left.is_zero() => Err(io::ErrorKind::TimedOut.into()),
+            left => Ok(left.min(TIMEOUT)),
+        }
+    }
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.set_read_timeout(Some(self.timeout()?))?;
+        self.stream.read(buf)
+    }
+}
+
+impl Write for Deadline<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.set_write_timeout(Some(self.timeout()?))?;
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Reads the request up to the body, or says what's wrong with it
+fn read_request(stream: &mut Deadline, max_body: u64) -> Result<Request, Response> {
+    let mut reader = BufReader::new(stream);
+    let mut head = (&mut reader).take(MAX_HEAD);
+    let mut line = String::new();
+    let too_big = || Response::text(431, "The request head is too big");
+    let bad = |e: io::Error| match e.kind() {
+        // what the timeouts of the sockets are on the unixes and on windows
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+            Response::text(408, "The request took too long")
+        }
+        _ => Response::text(400, "Malformed request"),
+    };
+
+    head.read_line(&mut line).map_err(bad)?;
+    if !line.ends_with('\n') {
+        return Err(too_big());
+    }
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_owned(), target),
+        _ => return Err(Response::text(400, "Malformed request line")),
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (path, query) = (path.to_owned(), query.to_owned());
+
+    let mut length = None;
+    let mut expect_continue = false;
+    loop {
+        line.clear();
+        head.read_line(&mut line).map_err(bad)?;
+        if !line.ends_with('\n') {
+            return Err(too_big());
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| Response::text(400, "Malformed header"))?;
+        let value = value.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "content-length" => {
+                length = Some(
+                    value
+                        .parse::<u64>()
+                        .map_err(|_| Response::text(400, "Malformed Content-Length"))?,
+                )
+            }
+            "transfer-encoding" => {
+                return Err(Response::text(501, "Only bodies with Content-Length work"))
+            }
+            "expect" if value.eq_ignore_ascii_case("100-continue") => expect_continue = true,
+            _ => {}
+        }
+    }
+
+    let length = match (method.as_str(), length) {
+        ("POST", None) => return Err(Response::text(411, "Content-Length is required")),
+        (_, length) => length.unwrap_or_default(),
+    };
+    if length > max_body {
+        return Err(Response::text(
+            413,
+            format!("The upload is bigger than the limit of {} bytes", max_body),
+        ));
+    }
+    // curl waits for a bit for this with the bigger uploads
+    if expect_continue {
+        let _ = reader.get_mut().write_all(b"HTTP/1.1 100 Continue\r\n\r\n");
+    }
+    let mut body = Vec::with_capacity(length.min(MAX_PREALLOCATE) as usize);
+    (&mut reader)
+        .take(length)
+        .read_to_end(&mut body)
+        .map_err(bad)?;
+    if (body.len() as u64) < length {
+        return Err(Response::text(400, "The body is cut off"));
+    }
+    Ok(Request {
+        method,
+        path,
+        query,
+        body,
+    })
+}
+
+fn respond(stream: &mut Deadline, response: &Response) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len()
+    )?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+/// Handles the requests until the process is killed, one connection per
+/// request, uploads bigger than `max_body` are refused
+pub fn run(
+    listen: &str,
+    max_body: u64,
+    mut handler: impl FnMut(&Request) -> Response,
+) -> Result<()> {
+    let listener = TcpListener::bind(listen).with_context(|| format!("Listening on {}", listen))?;
+    log::info!("Listening on http://{}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::warn!("Could not accept a connection: {}", e);
+                continue;
+            }
+        };
+        let peer = stream
+            .peer_addr()
+            .map_or_else(|_| "?".to_owned(), |a| a.to_string());
+        // a client that stops in the middle would block everyone else
+        let mut stream = Deadline {
+            stream: &stream,
+            at: Instant::now() + REQUEST_TIMEOUT,
+        };
+
+        let response = match read_request(&mut stream, max_body) {
+            Ok(request) => {
+                let response = handler(&request);
+                log::info!(
+                    "{} {} {} -> {} ({} bytes)",
+                    peer,
+                    request.method,
+                    request.path,
+                    response.status,
+                    response.body.len()
+                );
+                response
+            }
+            Err(response) => {
+                log::info!("{} -> {}", peer, response.status);
+                response
+            }
+        };
+        // the fixing itself is not on the client
+        stream.at = Instant::now() + REQUEST_TIMEOUT;
+        if let Err(e) = respond(&mut stream, &response) {
+            log::warn!("Could not respond to {}: {}", peer, e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(data: &[u8], timeout: Duration) -> Result<Request, Response> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        // kept open, so that a cut off request waits instead of ending
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(data).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        let mut stream = Deadline {
+            stream: &server,
+            at: Instant::now() + timeout,
+        };
+        read_request(&mut stream, 100)
+    }
+
+    #[test]
+    fn post() {
+        let request = read(
+            b"POST /fix?name=a.jar HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc",
+            TIMEOUT,
+        )
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/fix");
+        assert_eq!(request.query, "name=a.jar");
+        assert_eq!(request.body, b"abc");
+    }
+
+    #[test]
+    fn refused() {
+        let status = |data: &[u8]| read(data, TIMEOUT).unwrap_err().status;
+        assert_eq!(status(b"POST / HTTP/1.1\r\n\r\n"), 411);
+        assert_eq!(
+            status(b"POST / HTTP/1.1\r\nContent-Length: 101\r\n\r\n"),
+            413
+        );
+        assert_eq!(status(b"POST / HTTP/1.1\r\nContent-Length: x\r\n\r\n"), 400);
+        assert_eq!(status(b"POST / HTTP/1.1\r\nBroken\r\n\r\n"), 400);
+        assert_eq!(status(b"GET\r\n\r\n"), 400);
+    }
+
+    #[test]
+    fn deadline() {
+        // the body never comes, it's the whole request that times out and
+        // not just one read
+        let started = Instant::now();
+        let response = read(
+            b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc",
+            Duration::from_millis(200),
+        )
+        .unwrap_err();
+        assert_eq!(response.status, 408);
+        assert!(started.elapsed() < TIMEOUT);
+    }
+}
@@ -0,0 +1,360 @@
+//! Loading some of the fixed classes into a VM that cares about the names,
+//! for knowing that the game will start before starting it.
+//!
+//! The loader is a tiny class made right here, for Java 5, so that it needs
+//! no stack maps and runs on anything. It links every class it's given
+//! (which is when they are verified) without initializing them, so none of
+//! the code of the game runs, and prints the name of each one, followed by
+//! the error for the ones that failed.
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    class::{Attribute, ClassFile, Code, Constant, ExceptionHandler, Member, ACC_STATIC},
+    doctor, temp,
+};
+
+/// More than enough to tell if the fixing broke something, while keeping
+/// the command line short enough for Windows
+const SAMPLE: usize = 200;
+
+const LOADER: &str = "StarsectorFixerSmokeTest";
+
+/// The java to test with: the one of the game if it's a strict one, or the
+/// one on the PATH
+pub fn find_java(jar: &Path, jre_dirs: &[PathBuf]) -> Option<PathBuf> {
+    let jar = crate::paths::absolute(jar).ok()?;
+    // the jars can be in a subdirectory of the game, or in a mod
+    let bundled = jar
+        .ancestors()
+        .skip(1)
+        .take(5)
+        .find_map(|dir| doctor::find_jre(dir, jre_dirs));
+    match bundled {
+        Some(jre) if jre.is_strict() => return Some(doctor::java(&jre.home)),
+        Some(jre) => log::debug!(
+            "The JRE in {} allows the bad names, not testing with it",
+            jre.home.display()
+        ),
+        None => {}
+    }
+    let java = PathBuf::from("java");
+    match doctor::describe_java(&java) {
+        Ok((version, vendor)) if doctor::is_strict(&vendor, &version) => Some(java),
+        Ok((version, vendor)) => {
+            log::debug!(
+                "The java on the PATH ({} {}) allows the bad names",
+                vendor,
+                version
+            );
+            None
+        }
+        Err(e) => {
+            log::debug!("{:#}", e);
+            None
+        }
+    }
+}
+
+/// Links a sample of the classes (the paths of them in the jar) with the
+/// java, failing if any of them don't verify
+pub fn smoke_test(java: &Path, jar: &Path, classes: &[String]) -> Result<()> {
+    let names = sample(classes);
+    if names.is_empty() {
+        return Ok(());
+    }
+    let dir = temp::create_dir()?;
+    let loader = dir.path().join(format!("{}.class", LOADER));
+    std::fs::write(&loader, loader_class()?.to_bytes())
+        .with_context(|| format!("Writing {}", loader.display()))?;
+
+    // the other jars next to it are likely to be what it needs
+    let mut classpath = vec![dir.path().to_owned(), jar.to_owned()];
+    if let Some(entries) = jar.parent().and_then(|dir| std::fs::read_dir(dir).ok()) {
+        let mut others = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "jar") && path != jar)
+            .collect::<Vec<_>>();
+        others.sort();
+        classpath.extend(others);
+    }
+    let classpath: OsString = std::env::join_paths(&classpath)?;
+
+    log::info!(
+        "Smoke testing {} of the fixed classes with {}",
+        names.len(),
+        java.display()
+    );
+    let output = Command::new(java)
+        .arg("-Xverify:all")
+        .arg("-cp")
+        .arg(classpath)
+        .arg(LOADER)
+        .args(&names)
+        .output()
+        .with_context(|| format!("Running {}", java.display()))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        bail!(
+            "The smoke test did not run ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let (mut failed, mut unchecked) = (Vec::new(), 0);
+    let mut lines = stdout.lines();
+    for name in &names {
+        let line = lines.next().unwrap_or_default();
+        let error = match line.strip_prefix(name.as_str()) {
+            Some("") => continue,
+            Some(rest) => rest.trim(),
+            None => "not tested",
+        };
+        // the classes from the jars that are not there can't be checked,
+        // but that's not the fault of the fixing
+        if error.starts_with("java.lang.NoClassDefFoundError")
+            || error.starts_with("java.lang.ClassNotFoundException")
+        {
+            log::debug!("Could not check {}: {}", name, error);
+            unchecked += 1;
+            continue;
+        }
+        log::error!("{} does not load: {}", name, error);
+        failed.push(name);
+    }
+    if unchecked != 0 {
+        log::warn!(
+            "{} of the classes could not be checked, they need the classes from other jars",
+            unchecked
+        );
+    }
+    match failed.len() {
+        0 => {
+            log::info!("The fixed classes in {} load fine", jar.display());
+            Ok(())
+        }
+        count => bail!(
+            "{} of the {} fixed classes tested in {} do not load",
+            count,
+            names.len(),
+            jar.display()
+        ),
+    }
+}
+
+/// The binary names of the classes, an evenly spread out part of them if
+/// there are too many
+fn sample(classes: &[String]) -> Vec<String> {
+    let names = classes
+        .iter()
+        .filter(|path| !path.starts_with("META-INF/") && !path.ends_with("module-info.class"))
+        .filter_map(|path| path.strip_suffix(".class"))
+        .map(|path| path.replace('/', "."))
+        .collect::<Vec<_>>();
+    let step = names.len().div_ceil(SAMPLE).max(1);
+    names.into_iter().step_by(step).collect()
+}
+
+/// The class that does the loading, the same as this Java:
+///
+/// ```java
+/// public static void main(String[] names) {
+///     for (int i = 0; i < names.length; i++) {
+///         String name = names[i];
+///         try {
+///             Class.forName(name, false, StarsectorFixerSmokeTest.class.getClassLoader())
+///                 .getDeclaredMethods();
+///             System.out.println(name);
+///         } catch (Throwable e) {
+///             System.out.println(new StringBuilder(name).append(' ')
+///                 .append(e.toString().replace('\n', ' ')).toString());
+///         }
+///     }
+/// }
+/// ```
+fn loader_class() -> Result<ClassFile> {
+    let mut class = ClassFile {
+        minor_version: 0,
+        major_version: 49,
+        constant_pool: vec![Constant::Unusable],
+        access_flags: 0x0021,
+        this_class: 0,
+        super_class: 0,
+        interfaces: Vec::new(),
+        fields: Vec::new(),
+        methods: Vec::new(),
+        attributes: Vec::new(),
+        trailing: Vec::new(),
+    };
+    let add = |class: &mut ClassFile, constant: Constant| {
+        class.constant_pool.push(constant);
+        (class.constant_pool.len() - 1) as u16
+    };
+    let class_ref = |class: &mut ClassFile, name: &str| -> Result<u16> {
+        let name = class.add_utf8(name)?;
+        Ok(add(class, Constant::Class(name)))
+    };
+    let this = class_ref(&mut class, LOADER)?;
+    let object = class_ref(&mut class, "java/lang/Object")?;
+    let java_class = class_ref(&mut class, "java/lang/Class")?;
+    let system = class_ref(&mut class, "java/lang/System")?;
+    let print_stream = class_ref(&mut class, "java/io/PrintStream")?;
+    let builder = class_ref(&mut class, "java/lang/StringBuilder")?;
+    let string = class_ref(&mut class, "java/lang/String")?;
+    let throwable = class_ref(&mut class, "java/lang/Throwable")?;
+    class.this_class = this;
+    class.super_class = object;
+
+    let member = |class: &mut ClassFile, owner: u16, name: &str, descriptor: &str| {
+        let name = class.add_utf8(name)?;
+        let descriptor = class.add_utf8(descriptor)?;
+        let name_and_type = add(class, Constant::NameAndType { name, descriptor });
+        let constant = match descriptor_is_method(class, descriptor) {
+            true => Constant::MethodRef {
+                class: owner,
+                name_and_type,
+            },
+            false => Constant::FieldRef {
+                class: owner,
+                name_and_type,
+            },
+        };
+        Ok::<_, anyhow::Error>(add(class, constant))
+    };
+    let get_class_loader = member(
+        &mut class,
+        java_class,
+        "getClassLoader",
+        "()Ljava/lang/ClassLoader;",
+    )?;
+    let for_name = member(
+        &mut class,
+        java_class,
+        "forName",
+        "(Ljava/lang/String;ZLjava/lang/ClassLoader;)Ljava/lang/Class;",
+    )?;
+    let get_declared_methods = member(
+        &mut class,
+        java_class,
+        "getDeclaredMethods",
+        "()[Ljava/lang/reflect/Method;",
+    )?;
+    let out = member(&mut class, system, "out", "Ljava/io/PrintStream;")?;
+    let println = member(&mut class, print_stream, "println", "(Ljava/lang/String;)V")?;
+    let builder_init = member(&mut class, builder, "<init>", "(Ljava/lang/String;)V")?;
+    let append_char = member(
+        &mut class,
+        builder,
+        "append",
+        "(C)Ljava/lang/StringBuilder;",
+    )?;
+    let append_string = member(
+        &mut class,
+        builder,
+        "append",
+        "(Ljava/lang/String;)Ljava/lang/StringBuilder;",
+    )?;
+    let builder_to_string = member(&mut class, builder, "toString", "()Ljava/lang/String;")?;
+    let to_string = member(&mut class, object, "toString", "()Ljava/lang/String;")?;
+    let replace = member(&mut class, string, "replace", "(CC)Ljava/lang/String;")?;
+
+    let [this, get_class_loader, for_name, get_declared_methods, out, println] = [
+        this,
+        get_class_loader,
+        for_name,
+        get_declared_methods,
+        out,
+        println,
+    ]
+    .map(u16::to_be_bytes);
+    let [builder, builder_init, append_char, append_string, builder_to_string, to_string, replace] =
+        [
+            builder,
+            builder_init,
+            append_char,
+            append_string,
+            builder_to_string,
+            to_string,
+            replace,
+        ]
+        .map(u16::to_be_bytes);
+    #[rustfmt::skip]
+    let code = [
+        /* 0 */ 0x03, // iconst_0
+        /* 1 */ 0x3C, // istore_1
+        /* 2 */ 0x1B, // iload_1
+        /* 3 */ 0x2A, // aload_0
+        /* 4 */ 0xBE, // arraylength
+        /* 5 */ 0xA2, 0x00, 75, // if_icmpge 80
+        /* 8 */ 0x2A, // aload_0
+        /* 9 */ 0x1B, // iload_1
+        /* 10 */ 0x32, // aaload
+        /* 11 */ 0x4D, // astore_2
+        /* 12 */ 0x2C, // aload_2
+        /* 13 */ 0x03, // iconst_0
+        /* 14 */ 0x13, this[0], this[1], // ldc_w
+        /* 17 */ 0xB6, get_class_loader[0], get_class_loader[1], // invokevirtual
+        /* 20 */ 0xB8, for_name[0], for_name[1], // invokestatic
+        /* 23 */ 0xB6, get_declared_methods[0], get_declared_methods[1], // invokevirtual
+        /* 26 */ 0x57, // pop
+        /* 27 */ 0xB2, out[0], out[1], // getstatic
+        /* 30 */ 0x2C, // aload_2
+        /* 31 */ 0xB6, println[0], println[1], // invokevirtual
+        /* 34 */ 0xA7, 0x00, 40, // goto 74
+        /* 37 */ 0x4E, // astore_3
+        /* 38 */ 0xB2, out[0], out[1], // getstatic
+        /* 41 */ 0xBB, builder[0], builder[1], // new
+        /* 44 */ 0x59, // dup
+        /* 45 */ 0x2C, // aload_2
+        /* 46 */ 0xB7, builder_init[0], builder_init[1], // invokespecial
+        /* 49 */ 0x10, b' ', // bipush
+        /* 51 */ 0xB6, append_char[0], append_char[1], // invokevirtual
+        /* 54 */ 0x2D, // aload_3
+        /* 55 */ 0xB6, to_string[0], to_string[1], // invokevirtual
+        /* 58 */ 0x10, b'\n', // bipush
+        /* 60 */ 0x10, b' ', // bipush
+        /* 62 */ 0xB6, replace[0], replace[1], // invokevirtual
+        /* 65 */ 0xB6, append_string[0], append_string[1], // invokevirtual
+        /* 68 */ 0xB6, builder_to_string[0], builder_to_string[1], // invokevirtual
+        /* 71 */ 0xB6, println[0], println[1], // invokevirtual
+        /* 74 */ 0x84, 1, 1, // iinc
+        /* 77 */ 0xA7, 0xFF, (-75i8) as u8, // goto 2
+        /* 80 */ 0xB1, // return
+    ];
+    let code = Code {
+        max_stack: 5,
+        max_locals: 4,
+        code: code.to_vec(),
+        exception_table: vec![ExceptionHandler {
+            start_pc: 12,
+            end_pc: 34,
+            handler_pc: 37,
+            catch_type: throwable,
+        }],
+        attributes: Vec::new(),
+    };
+    let main = Member {
+        access_flags: 0x0001 | ACC_STATIC,
+        name_index: class.add_utf8("main")?,
+        descriptor_index: class.add_utf8("([Ljava/lang/String;)V")?,
+        attributes: vec![Attribute {
+            name_index: class.add_utf8("Code")?,
+            info: code.to_bytes(),
+        }],
+    };
+    class.methods.push(main);
+    Ok(class)
+}
+
+fn descriptor_is_method(class: &ClassFile, descriptor: u16) -> bool {
+    class
+        .utf8_bytes(descriptor)
+        .is_ok_and(|descriptor| descriptor.starts_with(b"("))
+}
@@ -0,0 +1,74 @@
+//! How much space there is left on the drive, to fail before writing the
+//! output and not in the middle of it.
+
+use std::path::Path;
+
+/// The bytes that can be written into the directory, if it can be known
+pub fn available(dir: &Path) -> Option<u64> {
+    match imp::available(dir) {
+        Ok(bytes) => Some(bytes),
+        Err(e) => {
+            log::debug!("Could not get the free space of {}: {}", dir.display(), e);
+            None
+        }
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::{ffi::CString, io, os::unix::ffi::OsStrExt, path::Path};
+
+    pub fn available(dir: &Path) -> io::Result<u64> {
+        let path = CString::new(dir.as_os_str().as_bytes())?;
+        let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+        if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let stat = unsafe { stat.assume_init() };
+        // the available ones and not the free ones, some are kept for root
+        #[allow(clippy::unnecessary_cast)]
+        Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::{io, os::windows::ffi::OsStrExt, path::Path};
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetDiskFreeSpaceExW(
+            directory: *const u16,
+            available: *mut u64,
+            total: *mut u64,
+            free: *mut u64,
+        ) -> i32;
+    }
+
+    pub fn available(dir: &Path) -> io::Result<u64> {
+        let dir: Vec<u16> = dir.as_os_str().encode_wide().chain([0]).collect();
+        let mut available = 0;
+        // the quota of the user is taken into account in this one
+        let result = unsafe {
+            GetDiskFreeSpaceExW(
+                dir.as_ptr(),
+                &mut available,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        match result {
+            0 => Err(io::Error::last_os_error()),
+            _ => Ok(available),
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod imp {
+    use std::{io, path::Path};
+
+    pub fn available(_: &Path) -> io::Result<u64> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
@@ -0,0 +1,581 @@
+//! Just enough of tar to go through an archive, fix the jars inside and write
+//! it back with everything else (ownership, permissions, timestamps, GNU and
+//! PAX extensions) left as it was.
+
+use std::{
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::memory::{Budget, Spool};
+
+const BLOCK: usize = 512;
+/// GNU tar pads archives to a multiple of 20 blocks, so do we
+const RECORD: usize = 20 * BLOCK;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Tells if the path looks like a tarball, and how it's compressed
+    pub fn detect(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+        if name.ends_with(".tar") {
+            Some(Self::None)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::Gzip)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(Self::Zstd)
+        } else {
+            None
+        }
+    }
+
+    pub fn reader<'a>(self, input: impl Read + 'a) -> Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Self::None => Box::new(input),
+            Self::Gzip => Box::new(flate2::read::MultiGzDecoder::new(input)),
+            Self::Zstd => Box::new(zstd::Decoder::new(input)?),
+        })
+    }
+
+    pub fn writer<W: Write>(self, output: W) -> Result<Compressor<W>> {
+        Ok(match self {
+            Self::None => Compressor::None(output),
+            Self::Gzip => Compressor::Gzip(flate2::write::GzEncoder::new(
+                output,
+                flate2::Compression::default(),
+            )),
+            Self::Zstd => Compressor::Zstd(zstd::Encoder::new(output, 0)?),
+        })
+    }
+}
+
+pub enum Compressor<W: Write> {
+    None(W),
+    Gzip(flate2::write::GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Compressor<W> {
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Self::None(w) => Ok(w),
+            Self::Gzip(w) => w.finish(),
+            Self::Zstd(w) => w.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Compressor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::None(w) => w.write(buf),
+            Self::Gzip(w) => w.write(buf),
+            Self::Zstd(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::None(w) => w.flush(),
+            Self::Gzip(w) => w.flush(),
+            Self::Zstd(w) => w.flush(),
+        }
+    }
+}
+
+/// One entry, with all of the header blocks (including GNU long names and
+/// PAX headers) that precede its data
+struct Entry {
+    headers: Vec<[u8; BLOCK]>,
+    /// Index of the PAX header in `headers`, if there is one
+    pax: Option<usize>,
+    path: String,
+    size: u64,
+    kind: u8,
+}
+
+impl Entry {
+    fn is_file(&self) -> bool {
+        matches!(self.kind, b'0' | b'\0' | b'7')
+    }
+
+    /// Updates the size of the entry in its own header, and in the PAX one
+    /// if that had the size in it
+    fn set_size(&mut self, size: u64) -> Result<()> {
+        self.size = size;
+
+        let header = self.headers.last_mut().expect("entries have a header");
+        write_numeric(&mut header[124..136], size);
+        update_checksum(header);
+
+        if let Some(pos) = self.pax {
+            let pax_len = data_len_of(&self.headers[pos])?;
+            let end = pos + 1 + blocks_for(pax_len);
+            let records = pax_records(&self.headers[pos + 1..end], pax_len)?;
+            if records.iter().any(|(k, _)| k == "size") {
+                let records = records
+                    .into_iter()
+                    .map(|(k, v)| {
+                        let v = if k == "size" { size.to_string() } else { v };
+                        (k, v)
+                    })
+                    .collect::<Vec<_>>();
+                let data = encode_pax(&records);
+
+                let mut pax_header = self.headers[pos];
+                write_numeric(&mut pax_header[124..136], data.len() as u64);
+                update_checksum(&mut pax_header);
+
+                let mut replacement = vec![pax_header];
+                replacement.extend(data.chunks(BLOCK).map(|chunk| {
+                    let mut block = [0; BLOCK];
+                    block[..chunk.len()].copy_from_slice(chunk);
+                    block
+                }));
+                self.headers.splice(pos..end, replacement);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn blocks_for(size: u64) -> usize {
+    size.div_ceil(BLOCK as u64) as usize
+}
+
+fn data_len_of(header: &[u8; BLOCK]) -> Result<u64> {
+    parse_numeric(&header[124..136]).context("Bad size field")
+}
+
+fn parse_numeric(field: &[u8]) -> Result<u64> {
+    // GNU base-256 for the values that don't fit into octal
+    if field[0] & 0x80 != 0 {
+        return Ok(field[1..]
+            .iter()
+            .fold((field[0] & 0x7F) as u64, |acc, &b| acc << 8 | b as u64));
+    }
+    let s = std::str::from_utf8(field)?;
+    let s = s.trim_matches(|c: char| c == '\0' || c == ' ');
+    if s.is_empty() {
+        return Ok(0);
+    }
+    Ok(u64::from_str_radix(s, 8)?)
+}
+
+fn write_numeric(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    if value < 1 << (3 * digits) {
+        let s = format!("{:0width$o}\0", value, width = digits);
+        field.copy_from_slice(s.as_bytes());
+    } else {
+        field.fill(0);
+        for (i, b) in field.iter_mut().rev().enumerate().take(8) {
+            *b = (value >> (8 * i)) as u8;
+        }
+        field[0] |= 0x80;
+    }
+}
+
+fn update_checksum(header: &mut [u8; BLOCK]) {
+    header[148..156].fill(b' ');
+    let sum: u32 = header.iter().map(|&b| b as u32).sum();
+    let s = format!("{:06o}\0 ", sum);
+    header[148..156].copy_from_slice(s.as_bytes());
+}
+
+fn verify_checksum(header: &[u8; BLOCK]) -> bool {
+    let expected = match parse_numeric(&header[148..156]) {
+        Ok(sum) => sum,
+        Err(_) => return false,
+    };
+    let sum: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { b' ' } else { b } as u64)
+        .sum();
+    sum == expected
+}
+
+fn c_str(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn pax_records(blocks: &[[u8; BLOCK]], len: u64) -> Result<Vec<(String, String)>> {
+    let mut data = blocks.concat();
+    data.truncate(len as usize);
+    let mut records = Vec::new();
+    let mut rest = &data[..];
+    while let Some(space) = rest.iter().position(|&b| b == b' ') {
+        let len: usize = std::str::from_utf8(&rest[..space])?.parse()?;
+        ensure!(
+            len >= space + 2 && len <= rest.len() && rest[len - 1] == b'\n',
+            "Malformed PAX record"
+        );
+        let record = String::from_utf8_lossy(&rest[space + 1..len - 1]).into_owned();
+        if let Some((k, v)) = record.split_once('=') {
+            records.push((k.to_owned(), v.to_owned()));
+        }
+        rest = &rest[len..];
+    }
+    Ok(records)
+}
+
+fn encode_pax(records: &[(String, String)]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for (k, v) in records {
+        // the length includes its own digits, so find the fixed point
+        let body = k.len() + v.len() + 3; // space, '=' and the newline
+        let mut len = body + 1;
+        while body + len.to_string().len() != len {
+            len = body + len.to_string().len();
+        }
+        data.extend_from_slice(format!("{} {}={}\n", len, k, v).as_bytes());
+    }
+    data
+}
+
+fn read_block(input: &mut impl Read) -> Result<Option<[u8; BLOCK]>> {
+    let mut block = [0; BLOCK];
+    let mut filled = 0;
+    while filled < BLOCK {
+        match input.read(&mut block[filled..])? {
+            0 if filled == 0 => return Ok(None),
+            0 => bail!("Unexpected end of the tar archive"),
+            n => filled += n,
+        }
+    }
+    Ok(Some(block))
+}
+
+fn read_entry(input: &mut impl Read) -> Result<Option<Entry>> {
+    let mut headers = Vec::new();
+    let mut long_name = None;
+    let mut pax_path = None;
+    let mut pax_size = None;
+    let mut pax = None;
+
+    loop {
+        let header = match read_block(input)? {
+            Some(h) if h.iter().all(|&b| b == 0) => return Ok(None),
+            None => return Ok(None),
+            Some(h) => h,
+        };
+        ensure!(verify_checksum(&header), "Bad tar header checksum");
+        headers.push(header);
+
+        let kind = header[156];
+        let size = data_len_of(&header)?;
+        match kind {
+            // GNU long names and PAX headers describe the next header
+            b'L' | b'K' | b'x' | b'g' => {
+                let start = headers.len();
+                for _ in 0..blocks_for(size) {
+                    headers.push(read_block(input)?.context("Unexpected end of the tar archive")?);
+                }
+                let data = &headers[start..];
+                match kind {
+                    b'L' => {
+                        let mut name = data.concat();
+                        name.truncate(size as usize);
+                        long_name = Some(c_str(&name));
+                    }
+                    b'x' => {
+                        pax = Some(start - 1);
+                        for (k, v) in pax_records(data, size)? {
+                            match &*k {
+                                "path" => pax_path = Some(v),
+                                "size" => pax_size = Some(v.parse()?),
+                                _ => {}
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {
+                let path = pax_path.or(long_name).unwrap_or_else(|| {
+                    let name = c_str(&header[0..100]);
+                    let prefix = c_str(&header[345..500]);
+                    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                        format!("{}/{}", prefix, name)
+                    } else {
+                        name
+                    }
+                });
+                return Ok(Some(Entry {
+                    headers,
+                    pax,
+                    path,
+                    size: pax_size.unwrap_or(size),
+                    kind,
+                }));
+            }
+        }
+    }
+}
+
+/// Goes through the tarball, calling `fix` for every jar in it, which
+/// returns the fixed jar if it was changed. Returns whether anything was
+/// changed at all. Everything but the jars is copied right through, the jars
+/// are kept in memory or in temporary files, as the budget allows
+pub fn fix_tarball(
+    input: impl Read,
+    output: impl Write,
+    budget: &Budget,
+    mut fix: impl FnMut(&str, &mut Spool) -> Result<Option<Spool>>,
+) -> Result<bool> {
+    let mut input = input;
+    let mut output = CountingWriter {
+        inner: output,
+        written: 0,
+    };
+    let mut changed = false;
+
+    while let Some(mut entry) = read_entry(&mut input)? {
+        // not trusting the size, the data has to be there
+        let padded = (blocks_for(entry.size) * BLOCK) as u64;
+        let unexpected_end = || {
+            format!(
+                "Unexpected end of the tar archive when reading {}",
+                entry.path
+            )
+        };
+
+        if !entry.is_file() || !entry.path.to_ascii_lowercase().ends_with(".jar") {
+            for header in &entry.headers {
+                output.write_all(header)?;
+            }
+            let copied = io::copy(&mut (&mut input).take(padded), &mut output)?;
+            ensure!(copied == padded, unexpected_end());
+            continue;
+        }
+
+        let mut data = Spool::new(budget, entry.size)?;
+        let copied = io::copy(&mut (&mut input).take(entry.size), &mut data)?;
+        let padding = io::copy(&mut (&mut input).take(padded - entry.size), &mut io::sink())?;
+        ensure!(copied + padding == padded, unexpected_end());
+        data.rewind()?;
+
+        if let Some(fixed) =
+            fix(&entry.path, &mut data).with_context(|| format!("Processing {}", entry.path))?
+        {
+            data = fixed;
+            entry.set_size(data.len()?)?;
+            changed = true;
+        }
+        data.rewind()?;
+
+        for header in &entry.headers {
+            output.write_all(header)?;
+        }
+        let len = io::copy(&mut data, &mut output)?;
+        let padding = blocks_for(len) * BLOCK - len as usize;
+        output.write_all(&[0; BLOCK][..padding])?;
+    }
+
+    // the end-of-archive marker, plus padding to the full record
+    output.write_all(&[0; 2 * BLOCK])?;
+    let padding = (RECORD - output.written % RECORD) % RECORD;
+    output.write_all(&vec![0; padding])?;
+    Ok(changed)
+}
+
+struct CountingWriter<W> {
+    inner: W,
+    written: usize,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(name: &str, kind: u8, size: u64) -> [u8; BLOCK] {
+        let mut header = [0; BLOCK];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        write_numeric(&mut header[100..108], 0o644);
+        write_numeric(&mut header[124..136], size);
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        update_checksum(&mut header);
+        header
+    }
+
+    fn data_blocks(data: &[u8]) -> Vec<u8> {
+        let mut blocks = data.to_vec();
+        blocks.resize(blocks_for(data.len() as u64) * BLOCK, 0);
+        blocks
+    }
+
+    fn archive(entries: &[(&str, u8, &[u8])]) -> Vec<u8> {
+        let mut tar = Vec::new();
+        for &(name, kind, data) in entries {
+            tar.extend_from_slice(&header(name, kind, data.len() as u64));
+            tar.extend(data_blocks(data));
+        }
+        tar.extend_from_slice(&[0; 2 * BLOCK]);
+        tar
+    }
+
+    fn pax(data: &[u8]) -> Result<Vec<(String, String)>> {
+        let mut blocks = data_blocks(data);
+        let blocks = blocks
+            .chunks_mut(BLOCK)
+            .map(|chunk| chunk.try_into().unwrap())
+            .collect::<Vec<[u8; BLOCK]>>();
+        pax_records(&blocks, data.len() as u64)
+    }
+
+    #[test]
+    fn pax_round_trip() {
+        let records = vec![
+            ("path".to_owned(), "a/very/long/path.jar".to_owned()),
+            ("size".to_owned(), "1234567".to_owned()),
+            // length right at the digit count boundary
+            ("x".to_owned(), "y".repeat(92)),
+        ];
+        let data = encode_pax(&records);
+        assert_eq!(pax(&data).unwrap(), records);
+    }
+
+    #[test]
+    fn malformed_pax_records() {
+        for data in [
+            &b"2 xx"[..],
+            b"1 ",
+            b"0 ",
+            b"9 a=b\n",
+            b"6 a=bc",
+            b"6 a=bcX",
+            b"x a=b\n",
+        ] {
+            assert!(pax(data).is_err(), "{:?}", String::from_utf8_lossy(data));
+        }
+    }
+
+    #[test]
+    fn numeric_fields() {
+        let mut field = [0; 12];
+        write_numeric(&mut field, 0o1234);
+        assert_eq!(&field, b"00000001234\0");
+        assert_eq!(parse_numeric(&field).unwrap(), 0o1234);
+
+        // too big for octal, so base-256
+        write_numeric(&mut field, 1 << 40);
+        assert_eq!(field[0] & 0x80, 0x80);
+        assert_eq!(parse_numeric(&field).unwrap(), 1 << 40);
+
+        assert_eq!(parse_numeric(b"  17 \0\0").unwrap(), 0o17);
+        assert!(parse_numeric(b"9\0").is_err());
+    }
+
+    #[test]
+    fn bad_checksum() {
+        let mut tar = archive(&[("a.txt", b'0', b"hello")]);
+        tar[0] = b'b';
+        assert!(read_entry(&mut &tar[..]).is_err());
+    }
+
+    #[test]
+    fn gnu_long_name() {
+        let name = format!("{}/mod.jar", "dir".repeat(50));
+        let mut long = name.as_bytes().to_vec();
+        long.push(0);
+        let tar = archive(&[("././@LongLink", b'L', &long), ("short", b'0', b"data")]);
+
+        let entry = read_entry(&mut &tar[..]).unwrap().unwrap();
+        assert_eq!(entry.path, name);
+        assert_eq!(entry.size, 4);
+        assert_eq!(entry.headers.len(), 3);
+        assert!(entry.is_file());
+    }
+
+    #[test]
+    fn pax_path_and_size() {
+        let records = encode_pax(&[
+            ("path".to_owned(), "from/pax.jar".to_owned()),
+            ("size".to_owned(), "3".to_owned()),
+        ]);
+        let tar = archive(&[("PaxHeader", b'x', &records), ("short", b'0', b"abc")]);
+
+        let entry = read_entry(&mut &tar[..]).unwrap().unwrap();
+        assert_eq!(entry.path, "from/pax.jar");
+        assert_eq!(entry.size, 3);
+        assert_eq!(entry.pax, Some(0));
+    }
+
+    #[test]
+    fn malformed_pax_header() {
+        let tar = archive(&[("PaxHeader", b'x', b"2 xx"), ("short", b'0', b"abc")]);
+        assert!(read_entry(&mut &tar[..]).is_err());
+    }
+
+    #[test]
+    fn truncated_archive() {
+        let tar = archive(&[("a.jar", b'0', &[1; 1000])]);
+        let budget = Budget::default();
+        let result = fix_tarball(&tar[..BLOCK + 600], io::sink(), &budget, |_, _| Ok(None));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn fixing_updates_the_sizes() {
+        let records = encode_pax(&[
+            ("path".to_owned(), "mods/a.jar".to_owned()),
+            ("size".to_owned(), "3".to_owned()),
+        ]);
+        let tar = archive(&[
+            ("readme.txt", b'0', b"not a jar"),
+            ("PaxHeader", b'x', &records),
+            ("a.jar", b'0', b"abc"),
+        ]);
+
+        let budget = Budget::default();
+        let mut output = Vec::new();
+        let changed = fix_tarball(&tar[..], &mut output, &budget, |path, data| {
+            assert_eq!(path, "mods/a.jar");
+            let mut old = Vec::new();
+            data.read_to_end(&mut old)?;
+            assert_eq!(old, b"abc");
+
+            let mut fixed = Spool::new(&budget, 0)?;
+            fixed.write_all(&[b'x'; 600])?;
+            Ok(Some(fixed))
+        })
+        .unwrap();
+        assert!(changed);
+        assert_eq!(output.len() % RECORD, 0);
+
+        let mut input = &output[..];
+        let readme = read_entry(&mut input).unwrap().unwrap();
+        assert_eq!(readme.path, "readme.txt");
+        input = &input[BLOCK..];
+
+        let jar = read_entry(&mut input).unwrap().unwrap();
+        assert_eq!(jar.path, "mods/a.jar");
+        assert_eq!(jar.size, 600);
+        assert_eq!(data_len_of(jar.headers.last().unwrap()).unwrap(), 600);
+        assert!(input[..600].iter().all(|&b| b == b'x'));
+        assert!(read_entry(&mut &input[2 * BLOCK..]).unwrap().is_none());
+    }
+}
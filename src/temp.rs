@@ -0,0 +1,164 @@
+//! Temporary files with names that are unique to the run, so that two runs
+//! at once (or one that was killed midway) don't collide on them.
+
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::{Context, Result};
+
+use crate::interrupt::{self, Working};
+
+/// Removes the file when dropped, unless it was persisted
+#[derive(Debug)]
+pub struct TempPath(Option<PathBuf>, Working);
+
+impl TempPath {
+    pub fn path(&self) -> &Path {
+        self.0.as_deref().expect("only taken when persisting")
+    }
+
+    /// Moves the file to where it was meant to be
+    pub fn persist(mut self, to: &Path) -> io::Result<()> {
+        std::fs::rename(self.path(), to)?;
+        self.0 = None;
+        Ok(())
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        if let Some(path) = &self.0 {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// A directory that is removed with everything in it when dropped
+#[derive(Debug)]
+pub struct TempDir(PathBuf, Working);
+
+impl TempDir {
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+pub fn create_dir() -> Result<TempDir> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    loop {
+        let path = std::env::temp_dir().join(format!(
+            "starsector-fixer.{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        match std::fs::create_dir(&path) {
+            Ok(()) => return Ok(TempDir(path, interrupt::working())),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e).with_context(|| format!("Creating {}", path.display())),
+        }
+    }
+}
+
+/// In the temp directory, for the things that never leave it
+pub fn create() -> Result<(File, TempPath)> {
+    create_in(&std::env::temp_dir(), "starsector-fixer", ".tmp")
+}
+
+/// In the temp directory, with the name ending with the suffix, for the
+/// things that are told apart by their extension
+pub fn create_ending(suffix: &str) -> Result<(File, TempPath)> {
+    create_in(&std::env::temp_dir(), "starsector-fixer", suffix)
+}
+
+/// In the same directory as the target, so that renaming it in place of the
+/// target is atomic
+pub fn next_to(target: &Path) -> Result<(File, TempPath)> {
+    create_in(&dir_of(target), &file_name(target), ".temp")
+}
+
+fn create_in(dir: &Path, prefix: &str, suffix: &str) -> Result<(File, TempPath)> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    loop {
+        let name = format!(
+            "{}.{}-{}{}",
+            prefix,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst),
+            suffix
+        );
+        let path = dir.join(name);
+        match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => return Ok((file, TempPath(Some(path), interrupt::working()))),
+            // left over from a crashed run with the same pid
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(e).with_context(|| format!("Creating {}", path.display()));
+            }
+        }
+    }
+}
+
+/// Removes the temp files of the target that the runs which were killed
+/// midway left behind. The target has to be locked, since then there's no
+/// one else who could be still writing them
+pub fn remove_stale(target: &Path) {
+    let prefix = format!("{}.", file_name(target));
+    // how they were named before
+    let old = format!("{}temp", prefix);
+    let entries = match std::fs::read_dir(dir_of(target)) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let is_stale = name == old
+            || name
+                .strip_prefix(&prefix)
+                .and_then(|rest| rest.strip_suffix(".temp"))
+                .and_then(|id| id.split_once('-'))
+                .is_some_and(|(pid, n)| is_number(pid) && is_number(n));
+        if !is_stale {
+            continue;
+        }
+        match std::fs::remove_file(entry.path()) {
+            Ok(()) => log::info!(
+                "Removed {}, left over from an interrupted run",
+                entry.path().display()
+            ),
+            Err(e) => log::warn!("Could not remove {}: {}", entry.path().display(), e),
+        }
+    }
+}
+
+fn is_number(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn dir_of(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_owned(),
+        _ => PathBuf::from("."),
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
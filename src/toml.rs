@@ -0,0 +1,253 @@
+//! The bit of TOML that the profiles and the rules are written in: strings,
+//! booleans and arrays of them, and the arrays of tables with `[[name]]`.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context, Result};
+
+use crate::json::Value;
+
+/// The `key = value` lines, with the arrays allowed to span several, the
+/// ones after a `[[name]]` going into a new table of the `name` array
+pub fn parse(input: &str) -> Result<BTreeMap<String, Value>> {
+    let mut root = BTreeMap::new();
+    let mut table: Option<(String, BTreeMap<String, Value>)> = None;
+    let mut lines = input.lines().enumerate();
+    while let Some((number, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let context = || format!("On line {}", number + 1);
+        if let Some(name) = line.strip_prefix("[[").and_then(|l| l.strip_suffix("]]")) {
+            if let Some((name, keys)) = table.take() {
+                push_table(&mut root, name, keys).with_context(context)?;
+            }
+            table = Some((name.trim().trim_matches('"').to_owned(), BTreeMap::new()));
+            continue;
+        }
+        if line.starts_with('[') {
+            bail!("{}: only the arrays of tables are supported", context());
+        }
+        let keys = match &mut table {
+            Some((_, keys)) => keys,
+            None => &mut root,
+        };
+        let (key, value) = line.split_once('=').with_context(context)?;
+        let key = key.trim().trim_matches('"').to_owned();
+        let mut value = value.trim().to_owned();
+        if value.starts_with('[') {
+            while !array_closed(&value) {
+                match lines.next() {
+                    Some((_, line)) => {
+                        value.push('\n');
+                        value.push_str(line);
+                    }
+                    None => bail!("{}: the array is not closed", context()),
+                }
+            }
+        }
+        let mut chars = value.chars().peekable();
+        let parsed = parse_value(&mut chars).with_context(context)?;
+        skip_blank(&mut chars);
+        if chars.next().is_some() {
+            bail!("{}: something after the value", context());
+        }
+        if keys.insert(key.clone(), parsed).is_some() {
+            bail!("{}: '{}' is given twice", context(), key);
+        }
+    }
+    if let Some((name, keys)) = table {
+        push_table(&mut root, name, keys)?;
+    }
+    Ok(root)
+}
+
+fn push_table(
+    root: &mut BTreeMap<String, Value>,
+    name: String,
+    keys: BTreeMap<String, Value>,
+) -> Result<()> {
+    match root
+        .entry(name.clone())
+        .or_insert_with(|| Value::Array(Vec::new()))
+    {
+        Value::Array(tables) => tables.push(Value::Object(keys)),
+        _ => bail!("'{}' is given both as a value and as tables", name),
+    }
+    Ok(())
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+/// Whether the brackets outside of the strings and comments are balanced
+fn array_closed(value: &str) -> bool {
+    let (mut depth, mut quote, mut escaped, mut comment) = (0, None, false, false);
+    for c in value.chars() {
+        match (quote, c) {
+            _ if comment => comment = c != '\n',
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => comment = true,
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            _ => {}
+        }
+        escaped = false;
+    }
+    depth == 0
+}
+
+/// Whitespace, newlines and comments
+fn skip_blank(chars: &mut Chars) {
+    while let Some(&c) = chars.peek() {
+        match c {
+            '#' => while chars.next_if(|&c| c != '\n').is_some() {},
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            _ => break,
+        }
+    }
+}
+
+fn parse_value(chars: &mut Chars) -> Result<Value> {
+    skip_blank(chars);
+    match chars.next() {
+        Some('"') => {
+            let mut string = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => return Ok(Value::String(string)),
+                    Some('\\') => string.push(match chars.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some(c @ ('"' | '\\')) => c,
+                        Some(c) => bail!("Unknown escape '\\{}'", c),
+                        None => bail!("The string is not closed"),
+                    }),
+                    Some('\n') | None => bail!("The string is not closed"),
+                    Some(c) => string.push(c),
+                }
+            }
+        }
+        // the literal ones, with no escapes, handy for the Windows paths
+        Some('\'') => {
+            let mut string = String::new();
+            loop {
+                match chars.next() {
+                    Some('\'') => return Ok(Value::String(string)),
+                    Some('\n') | None => bail!("The string is not closed"),
+                    Some(c) => string.push(c),
+                }
+            }
+        }
+        Some('[') => {
+            let mut values = Vec::new();
+            loop {
+                skip_blank(chars);
+                if chars.next_if_eq(&']').is_some() {
+                    return Ok(Value::Array(values));
+                }
+                values.push(parse_value(chars)?);
+                skip_blank(chars);
+                match chars.next() {
+                    Some(',') => {}
+                    Some(']') => return Ok(Value::Array(values)),
+                    _ => bail!("Expected a comma or the end of the array"),
+                }
+            }
+        }
+        Some(c) if c.is_ascii_alphabetic() => {
+            let mut word = c.to_string();
+            while let Some(c) = chars.next_if(char::is_ascii_alphanumeric) {
+                word.push(c);
+            }
+            match word.as_str() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ => bail!("Expected a value, not '{}'", word),
+            }
+        }
+        _ => bail!("Expected a string, a boolean or an array"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_owned())
+    }
+
+    #[test]
+    fn values_and_tables() {
+        let parsed = parse(
+            r#"
+# a comment
+name = "with \"escapes\"\n"
+path = 'C:\Games\Starsector'
+enabled = true
+list = [
+    "a", # the first one
+    'b',
+    [false],
+]
+
+[[rename]]
+from = "a.b"
+to = "a_b"
+
+[[ rename ]]
+"from" = "c]d"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(parsed["name"], string("with \"escapes\"\n"));
+        assert_eq!(parsed["path"], string(r"C:\Games\Starsector"));
+        assert_eq!(parsed["enabled"], Value::Bool(true));
+        assert_eq!(
+            parsed["list"],
+            Value::Array(vec![
+                string("a"),
+                string("b"),
+                Value::Array(vec![Value::Bool(false)])
+            ])
+        );
+        let tables = match &parsed["rename"] {
+            Value::Array(tables) => tables,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(tables.len(), 2);
+        assert_eq!(tables[0].get("to"), Some(&string("a_b")));
+        assert_eq!(tables[1].get("from"), Some(&string("c]d")));
+    }
+
+    #[test]
+    fn errors() {
+        for input in [
+            "key",
+            "key = ",
+            "key = 1",
+            "key = \"unterminated",
+            "key = 'unterminated",
+            "key = \"\\q\"",
+            "key = [\"a\" \"b\"]",
+            "key = [\n\"a\",\n",
+            "key = true false",
+            "key = maybe",
+            "key = true\nkey = false",
+            "[table]",
+            "tables = true\n[[tables]]",
+        ] {
+            assert!(parse(input).is_err(), "{}", input);
+        }
+    }
+}
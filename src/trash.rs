@@ -0,0 +1,187 @@
+//! Sending the replaced jars to the recycle bin (or the trash) for --trash,
+//! where they are easy to find and put back.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// Moves the file to the trash, it's not where it was after this
+pub fn put(path: &Path) -> Result<()> {
+    let path = crate::paths::absolute(path)?;
+    imp::put(&path).with_context(|| format!("Moving {} to the trash", path.display()))?;
+    log::info!("Moved {} to the trash", path.display());
+    Ok(())
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod imp {
+    use std::{
+        fs::OpenOptions,
+        io::{self, Write},
+        os::unix::ffi::OsStrExt,
+        path::Path,
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use super::*;
+
+    /// The path in the .trashinfo, which is URL-encoded
+    fn encoded(path: &Path) -> String {
+        let mut encoded = String::new();
+        for &b in path.as_os_str().as_bytes() {
+            match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                    encoded.push(b as char)
+                }
+                _ => encoded.push_str(&format!("%{:02X}", b)),
+            }
+        }
+        encoded
+    }
+
+    /// The home trash of the freedesktop.org spec. The jars on the other
+    /// drives are copied there, instead of using the trash directories of
+    /// those, which the file managers don't always look into anyway
+    pub fn put(path: &Path) -> Result<()> {
+        let trash = crate::dirs::trash().context("Could not find the trash")?;
+        let (files, info) = (trash.join("files"), trash.join("info"));
+        std::fs::create_dir_all(&files)?;
+        std::fs::create_dir_all(&info)?;
+
+        let name = path.file_name().context("The file has no name")?;
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let contents = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encoded(path),
+            crate::report::date_time(secs)
+        );
+        // the info file is what claims the name, as the spec says
+        for i in 1.. {
+            // game.jar, game.2.jar, and so on
+            let trashed = match (i, Path::new(name).file_stem()) {
+                (1, _) | (_, None) => name.to_owned(),
+                (_, Some(stem)) => {
+                    let mut trashed = stem.to_owned();
+                    trashed.push(format!(".{}", i));
+                    if let Some(extension) = Path::new(name).extension() {
+                        trashed.push(".");
+                        trashed.push(extension);
+                    }
+                    trashed
+                }
+            };
+            let mut info_path = info.join(&trashed);
+            info_path.as_mut_os_string().push(".trashinfo");
+            let mut file = match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&info_path)
+            {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            };
+            file.write_all(contents.as_bytes())?;
+            let target = files.join(&trashed);
+            let moved = std::fs::rename(path, &target)
+                .or_else(|_| std::fs::copy(path, &target).and_then(|_| std::fs::remove_file(path)));
+            if let Err(e) = moved {
+                let _ = std::fs::remove_file(&info_path);
+                return Err(e.into());
+            }
+            return Ok(());
+        }
+        unreachable!()
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use std::process::Command;
+
+    use anyhow::bail;
+
+    use super::*;
+
+    /// Through the Finder, that way it can be put back from the trash
+    pub fn put(path: &Path) -> Result<()> {
+        let path = path.to_str().context("The path is not valid UTF-8")?;
+        let escaped = path.replace('\\', "\\\\").replace('"', "\\\"");
+        let script = format!(
+            "tell application \"Finder\" to delete POSIX file \"{}\"",
+            escaped
+        );
+        let output = Command::new("osascript").arg("-e").arg(script).output()?;
+        if !output.status.success() {
+            bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::{ffi::c_void, os::windows::ffi::OsStrExt, ptr};
+
+    use anyhow::bail;
+
+    use super::*;
+
+    const FO_DELETE: u32 = 3;
+    const FOF_SILENT: u16 = 0x0004;
+    const FOF_NOCONFIRMATION: u16 = 0x0010;
+    const FOF_ALLOWUNDO: u16 = 0x0040;
+    const FOF_NOERRORUI: u16 = 0x0400;
+
+    #[repr(C)]
+    struct ShFileOpStruct {
+        hwnd: *mut c_void,
+        func: u32,
+        from: *const u16,
+        to: *const u16,
+        flags: u16,
+        any_operations_aborted: i32,
+        name_mappings: *mut c_void,
+        progress_title: *const u16,
+    }
+
+    #[link(name = "shell32")]
+    extern "system" {
+        fn SHFileOperationW(op: *mut ShFileOpStruct) -> i32;
+    }
+
+    /// Deleting with undo allowed is what puts it into the recycle bin
+    pub fn put(path: &Path) -> Result<()> {
+        let path = crate::paths::simplified(path);
+        // a list of paths, ending with an empty one
+        let from: Vec<u16> = path.as_os_str().encode_wide().chain([0, 0]).collect();
+        let mut op = ShFileOpStruct {
+            hwnd: ptr::null_mut(),
+            func: FO_DELETE,
+            from: from.as_ptr(),
+            to: ptr::null(),
+            flags: FOF_ALLOWUNDO | FOF_NOCONFIRMATION | FOF_SILENT | FOF_NOERRORUI,
+            any_operations_aborted: 0,
+            name_mappings: ptr::null_mut(),
+            progress_title: ptr::null(),
+        };
+        match unsafe { SHFileOperationW(&mut op) } {
+            0 if op.any_operations_aborted == 0 => Ok(()),
+            0 => bail!("It was cancelled"),
+            code => bail!("SHFileOperation failed with {:#x}", code),
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod imp {
+    use anyhow::bail;
+
+    use super::*;
+
+    pub fn put(_: &Path) -> Result<()> {
+        bail!("There's no trash on this platform")
+    }
+}
@@ -0,0 +1,298 @@
+//! What the fixed jars remember of the originals, for unfixing them without
+//! the backups. The renames keep the lengths of the names, so what changes
+//! in a class is a few bytes here and there, and only those are kept. The
+//! classes that changed in other ways (like with --sanitize-names) are kept
+//! whole, and so are the original manifest and the removed signatures.
+//!
+//! The archives fixed with --recurse-archives are not kept, since that would
+//! be the whole of them, so they are only listed, staying fixed when the
+//! jar is unfixed.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::{hash, json::Value};
+
+pub const PATH: &str = "META-INF/starsector-fixer/unfix.json";
+
+/// How to get the original class back from the fixed one
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// The offsets, with the original and the fixed bytes there
+    Bytes(Vec<(usize, u8, u8)>),
+    Whole(Vec<u8>),
+}
+
+impl Change {
+    pub fn new(original: &[u8], fixed: &[u8]) -> Self {
+        if original.len() != fixed.len() {
+            return Self::Whole(original.to_vec());
+        }
+        let bytes: Vec<_> = original
+            .iter()
+            .zip(fixed)
+            .enumerate()
+            .filter(|(_, (a, b))| a != b)
+            .map(|(offset, (&a, &b))| (offset, a, b))
+            .collect();
+        // each one is way more than a byte in the JSON
+        match bytes.len() * 16 > original.len() {
+            true => Self::Whole(original.to_vec()),
+            false => Self::Bytes(bytes),
+        }
+    }
+
+    /// The original class, making sure that the fixed one is the same one
+    /// that this was made for
+    pub fn undo(&self, fixed: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::Whole(original) => Ok(original.clone()),
+            Self::Bytes(bytes) => {
+                let mut original = fixed.to_vec();
+                for &(offset, was, is) in bytes {
+                    ensure!(
+                        fixed.get(offset) == Some(&is),
+                        "The byte at {} is not what the fixing made it, the class was changed after",
+                        offset
+                    );
+                    original[offset] = was;
+                }
+                Ok(original)
+            }
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            Self::Bytes(bytes) => {
+                let bytes = bytes
+                    .iter()
+                    .map(|&(offset, was, is)| {
+                        Value::Array(vec![
+                            Value::Number(offset as f64),
+                            Value::Number(was.into()),
+                            Value::Number(is.into()),
+                        ])
+                    })
+                    .collect();
+                Value::Object(BTreeMap::from([("bytes".into(), Value::Array(bytes))]))
+            }
+            Self::Whole(original) => Value::Object(BTreeMap::from([(
+                "original".into(),
+                Value::String(hash::to_hex(original)),
+            )])),
+        }
+    }
+
+    fn from_json(value: &Value) -> Result<Self> {
+        if let Some(original) = value.get("original").and_then(Value::as_str) {
+            return Ok(Self::Whole(from_hex(original)?));
+        }
+        let bytes = match value.get("bytes") {
+            Some(Value::Array(bytes)) => bytes,
+            _ => bail!("Expected \"bytes\" or \"original\""),
+        };
+        let number = |value: &Value, max: f64| match value {
+            Value::Number(n) if n.fract() == 0.0 && (0.0..=max).contains(n) => Ok(*n),
+            _ => bail!("Expected a number up to {}, got {:?}", max, value),
+        };
+        let mut result = Vec::with_capacity(bytes.len());
+        for byte in bytes {
+            match byte {
+                Value::Array(triple) if triple.len() == 3 => result.push((
+                    number(&triple[0], u32::MAX as f64)? as usize,
+                    number(&triple[1], 255.0)? as u8,
+                    number(&triple[2], 255.0)? as u8,
+                )),
+                _ => bail!("Expected [offset, original, fixed], got {:?}", byte),
+            }
+        }
+        Ok(Self::Bytes(result))
+    }
+}
+
+/// Everything about the original jar that the fixing changed
+#[derive(Debug, Clone, Default)]
+pub struct Original {
+    /// By the paths of the classes
+    pub classes: BTreeMap<String, Change>,
+    /// `None` if there was no manifest
+    pub manifest: Option<Vec<u8>>,
+    /// Whether the manifest is known, it's found out while the jar is
+    /// written
+    pub manifest_known: bool,
+    /// The bytes after the end of the jar, if they were removed
+    pub trailing: Option<Vec<u8>>,
+    /// The entries that were removed, like the signatures, by their paths
+    pub removed: BTreeMap<String, Vec<u8>>,
+    /// The paths of the archives in the jar that were fixed, which can't be
+    /// unfixed
+    pub nested: BTreeSet<String>,
+}
+
+impl Original {
+    /// Remembers the change of the class, from the original one if it was
+    /// fixed before and this knows what it was
+    pub fn record(&mut self, path: &str, before: &[u8], fixed: &[u8]) -> Result<()> {
+        let original = match self.classes.get(path) {
+            Some(change) => change
+                .undo(before)
+                .with_context(|| format!("Getting the original of {}", path))?,
+            None => before.to_vec(),
+        };
+        self.classes
+            .insert(path.to_owned(), Change::new(&original, fixed));
+        Ok(())
+    }
+
+    /// The manifest, unless the one from the earlier fixing is known
+    pub fn record_manifest(&mut self, manifest: Option<&[u8]>) {
+        if !self.manifest_known {
+            self.manifest = manifest.map(<[u8]>::to_vec);
+            self.manifest_known = true;
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let root = crate::json::parse(text)?;
+        let mut original = Self {
+            manifest_known: true,
+            ..Self::default()
+        };
+        match root.get("classes").and_then(Value::as_object) {
+            Some(classes) => {
+                for (path, change) in classes {
+                    let change = Change::from_json(change).with_context(|| path.clone())?;
+                    original.classes.insert(path.clone(), change);
+                }
+            }
+            None => bail!("No \"classes\" object"),
+        }
+        original.manifest = match root.get("manifest") {
+            Some(Value::String(hex)) => Some(from_hex(hex).context("The manifest")?),
+            Some(Value::Null) => None,
+            _ => bail!("No \"manifest\" string or null"),
+        };
+        if let Some(trailing) = root.get("trailing").and_then(Value::as_str) {
+            original.trailing = Some(from_hex(trailing).context("The trailing bytes")?);
+        }
+        if let Some(removed) = root.get("removed").and_then(Value::as_object) {
+            for (path, hex) in removed {
+                let hex = hex
+                    .as_str()
+                    .with_context(|| format!("{} is not a string", path))?;
+                let bytes = from_hex(hex).with_context(|| path.clone())?;
+                original.removed.insert(path.clone(), bytes);
+            }
+        }
+        if let Some(Value::Array(nested)) = root.get("nested") {
+            for path in nested {
+                let path = path
+                    .as_str()
+                    .with_context(|| format!("{:?} is not a string", path))?;
+                original.nested.insert(path.to_owned());
+            }
+        }
+        Ok(original)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let classes = self
+            .classes
+            .iter()
+            .map(|(path, change)| (path.clone(), change.to_json()))
+            .collect();
+        let mut root = BTreeMap::from([
+            ("classes".into(), Value::Object(classes)),
+            (
+                "manifest".into(),
+                match &self.manifest {
+                    Some(manifest) => Value::String(hash::to_hex(manifest)),
+                    None => Value::Null,
+                },
+            ),
+        ]);
+        if let Some(trailing) = &self.trailing {
+            root.insert("trailing".into(), Value::String(hash::to_hex(trailing)));
+        }
+        if !self.removed.is_empty() {
+            let removed = self
+                .removed
+                .iter()
+                .map(|(path, bytes)| (path.clone(), Value::String(hash::to_hex(bytes))))
+                .collect();
+            root.insert("removed".into(), Value::Object(removed));
+        }
+        if !self.nested.is_empty() {
+            let nested = self.nested.iter().cloned().map(Value::String).collect();
+            root.insert("nested".into(), Value::Array(nested));
+        }
+        Value::Object(root)
+    }
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    ensure!(hex.len().is_multiple_of(2), "Odd number of hex digits");
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .with_context(|| format!("Bad hex at {}", i))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes() {
+        let original = b"some.name and more".to_vec();
+        let fixed = b"some_name and more".to_vec();
+        let change = Change::new(&original, &fixed);
+        assert_eq!(change, Change::Bytes(vec![(4, b'.', b'_')]));
+        assert_eq!(change.undo(&fixed).unwrap(), original);
+        assert!(change.undo(&original).is_err());
+
+        let longer = b"some_longer_name and more".to_vec();
+        assert_eq!(
+            Change::new(&original, &longer),
+            Change::Whole(original.clone())
+        );
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let mut original = Original::default();
+        original.record("A.class", b"a.b", b"a_b").unwrap();
+        original.record("B.class", b"x", b"longer").unwrap();
+        original.record_manifest(Some(b"Manifest-Version: 1.0\r\n"));
+        original.trailing = Some(b"garbage".to_vec());
+        original
+            .removed
+            .insert("META-INF/A.SF".into(), b"signature".to_vec());
+        original.nested.insert("lib/inner.jar".into());
+
+        let text = original.to_json().to_pretty_string();
+        let parsed = Original::parse(&text).unwrap();
+        assert_eq!(parsed.classes, original.classes);
+        assert_eq!(parsed.manifest, original.manifest);
+        assert_eq!(parsed.trailing, original.trailing);
+        assert_eq!(parsed.removed, original.removed);
+        assert_eq!(parsed.nested, original.nested);
+    }
+
+    #[test]
+    fn refixing_keeps_the_original() {
+        let mut original = Original::default();
+        original.record("A.class", b"a.b.c", b"a_b.c").unwrap();
+        original.record("A.class", b"a_b.c", b"a_b_c").unwrap();
+        assert_eq!(
+            original.classes["A.class"].undo(b"a_b_c").unwrap(),
+            b"a.b.c"
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! Finding the classes and members that nothing in the jar refers to, which in
+//! obfuscated jars are likely to be decoys.
+//!
+//! This is a heuristic: anything used through reflection, or from outside of
+//! the jar, looks unused too.
+
+use std::collections::BTreeSet;
+
+use crate::{
+    class::{ACC_ENUM, ACC_PRIVATE, ACC_STATIC},
+    index::{JarIndex, MemberInfo},
+};
+
+#[derive(Debug, Default)]
+pub struct UnusedReport {
+    pub classes: Vec<String>,
+    /// Owner, name and descriptor
+    pub fields: Vec<(String, String, String)>,
+    pub methods: Vec<(String, String, String)>,
+}
+
+/// Methods that are called by the VM or the standard library, without any
+/// refs to them in the bytecode
+fn is_entry_point(method: &MemberInfo, enum_class: bool) -> bool {
+    matches!(
+        (&*method.name, &*method.descriptor),
+        ("<init>", _)
+            | ("<clinit>", _)
+            | ("main", "([Ljava/lang/String;)V")
+            | ("toString", "()Ljava/lang/String;")
+            | ("hashCode", "()I")
+            | ("equals", "(Ljava/lang/Object;)Z")
+            | ("finalize", "()V")
+            | ("clone", "()Ljava/lang/Object;")
+            | ("writeObject", "(Ljava/io/ObjectOutputStream;)V")
+            | ("readObject", "(Ljava/io/ObjectInputStream;)V")
+            | ("readResolve", "()Ljava/lang/Object;")
+            | ("writeReplace", "()Ljava/lang/Object;")
+    ) || enum_class && matches!(&*method.name, "values" | "valueOf")
+}
+
+pub fn find_unused(index: &JarIndex) -> UnusedReport {
+    let mut used_members = BTreeSet::new();
+    let mut used_classes = BTreeSet::new();
+
+    for class in index.classes.values() {
+        used_classes.extend(class.class_refs.iter().map(String::as_str));
+        used_classes.extend(class.supertypes());
+        for member_ref in &class.refs {
+            if let Some(declaring) = index.resolve(member_ref) {
+                used_members.insert((
+                    declaring.name.as_str(),
+                    member_ref.name.as_str(),
+                    member_ref.descriptor.as_str(),
+                ));
+            }
+        }
+    }
+
+    let mut report = UnusedReport::default();
+
+    for class in index.classes.values() {
+        let enum_class = class.access_flags & ACC_ENUM != 0;
+        let has_main = class
+            .methods
+            .iter()
+            .any(|m| m.name == "main" && m.access_flags & ACC_STATIC != 0);
+        if !used_classes.contains(class.name.as_str()) && !has_main {
+            report.classes.push(class.name.clone());
+        }
+
+        for field in &class.fields {
+            // the compiler inlines constants, and serialization uses this one
+            if field.name == "serialVersionUID" {
+                continue;
+            }
+            if !used_members.contains(&(&*class.name, &*field.name, &*field.descriptor)) {
+                report.fields.push((
+                    class.name.clone(),
+                    field.name.clone(),
+                    field.descriptor.clone(),
+                ));
+            }
+        }
+
+        let (ancestors, external_ancestors) = index.ancestors(class);
+        for method in &class.methods {
+            if is_entry_point(method, enum_class)
+                || used_members.contains(&(&*class.name, &*method.name, &*method.descriptor))
+            {
+                continue;
+            }
+            let virtual_method = method.access_flags & (ACC_PRIVATE | ACC_STATIC) == 0;
+            if virtual_method {
+                // could be implementing something from outside of the jar
+                if external_ancestors {
+                    continue;
+                }
+                // calls to the overridden method can end up here
+                let overrides_used = ancestors.iter().any(|ancestor| {
+                    used_members.contains(&(&*ancestor.name, &*method.name, &*method.descriptor))
+                });
+                if overrides_used {
+                    continue;
+                }
+            }
+            report.methods.push((
+                class.name.clone(),
+                method.name.clone(),
+                method.descriptor.clone(),
+            ));
+        }
+    }
+    report
+}
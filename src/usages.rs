@@ -0,0 +1,72 @@
+//! Finding where a member is used across several jars, which is the thing to
+//! know before renaming it.
+
+use std::path::{Path, PathBuf};
+
+use crate::index::{JarIndex, MemberRef, RefKind};
+
+#[derive(Debug)]
+pub struct Usage<'a> {
+    pub jar: &'a Path,
+    /// The class with the ref constant
+    pub class: &'a str,
+    pub member_ref: &'a MemberRef,
+    /// The class the ref resolves to, if it's in one of the jars
+    pub declared_in: Option<String>,
+}
+
+/// The refs to members with the name in all of the jars, which are looked up
+/// in the order given, like a classpath. With `owner`, only the refs that
+/// resolve to a member of that class count
+pub fn find_usages<'a>(
+    jars: &'a [(PathBuf, JarIndex)],
+    name: &str,
+    owner: Option<&str>,
+) -> Vec<Usage<'a>> {
+    // the first jar with the class wins, same as on the classpath
+    let mut classpath = JarIndex::default();
+    for (_, index) in jars.iter().rev() {
+        for (class_name, class) in &index.classes {
+            classpath.classes.insert(class_name.clone(), class.clone());
+        }
+    }
+
+    let mut usages = Vec::new();
+    for (jar, index) in jars {
+        for class in index.classes.values() {
+            for member_ref in class.refs.iter().filter(|r| r.name == name) {
+                let declared_in = classpath.resolve(member_ref).map(|c| c.name.clone());
+                let resolved = declared_in.as_ref().unwrap_or(&member_ref.owner);
+                if owner.is_some_and(|owner| owner != resolved) {
+                    continue;
+                }
+                usages.push(Usage {
+                    jar,
+                    class: &class.name,
+                    member_ref,
+                    declared_in,
+                });
+            }
+        }
+    }
+    usages
+}
+
+impl Usage<'_> {
+    pub fn describe(&self) -> String {
+        let owner = self.declared_in.as_ref().unwrap_or(&self.member_ref.owner);
+        let kind = match self.member_ref.kind {
+            RefKind::Field => "field",
+            RefKind::Method => "method",
+            RefKind::InterfaceMethod => "interface method",
+        };
+        let mut description = format!(
+            "{} uses {} {}.{} {}",
+            self.class, kind, owner, self.member_ref.name, self.member_ref.descriptor
+        );
+        if self.declared_in.is_none() {
+            description.push_str(" (not declared in any of the jars)");
+        }
+        description
+    }
+}
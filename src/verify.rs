@@ -0,0 +1,142 @@
+//! Reading the fixed jar back before it replaces anything, since a broken
+//! jar in place of the original is worse than no fixing at all.
+
+use std::{collections::BTreeSet, fs::File, io::BufReader, path::Path};
+
+use anyhow::{bail, ensure, Context, Result};
+use zip::ZipArchive;
+
+use crate::{
+    class::{ClassFile, Constant},
+    fix,
+    limits::Limits,
+};
+
+/// Reads every entry of the jar to the end (which checks their CRCs), and
+/// parses every class in it, checking that it writes back to the same length
+/// and that its constants refer to the constants of the right kinds. The
+/// `fixed` classes also must not have any bad names left. Returns how many
+/// classes there are
+pub fn verify_jar(jar: &Path, fixed: &[String], limits: &Limits) -> Result<usize> {
+    let file = File::open(jar).with_context(|| format!("Reading {}", jar.display()))?;
+    let mut zip = ZipArchive::new(BufReader::new(file)).context("Reading the archive")?;
+    let mut missing = fixed.iter().map(String::as_str).collect::<BTreeSet<_>>();
+    let mut classes = 0;
+    let mut buf = Vec::new();
+    for i in 0..zip.len() {
+        limits.check_time()?;
+        let mut entry = zip.by_index(i)?;
+        let name = entry.name().to_owned();
+        if !entry.is_file() || !name.ends_with(".class") {
+            std::io::copy(&mut entry, &mut std::io::sink())
+                .with_context(|| format!("Reading {}", name))?;
+            continue;
+        }
+        limits.prepare_buffer(&mut buf, entry.size());
+        limits
+            .read_class(entry, &mut buf)
+            .and_then(|_| verify_class(&buf, missing.remove(name.as_str())))
+            .with_context(|| format!("Verifying {}", name))?;
+        classes += 1;
+    }
+    if let Some(name) = missing.first() {
+        bail!("The fixed class {} is not in the jar", name);
+    }
+    log::debug!("Verified the {} classes in {}", classes, jar.display());
+    Ok(classes)
+}
+
+fn verify_class(bytecode: &[u8], fixed: bool) -> Result<()> {
+    let class = ClassFile::parse(bytecode)?;
+    let written = class.to_bytes().len();
+    ensure!(
+        written == bytecode.len(),
+        "The class is {} bytes long, but it's {} when written back",
+        bytecode.len(),
+        written
+    );
+    verify_constants(&class)?;
+    if fixed {
+        if let Some(bad) = fix::bad_names(&class, false)?.first() {
+            bail!("The bad name '{}' is still there", bad.name);
+        }
+    }
+    Ok(())
+}
+
+/// Every index into the constant pool points at a constant that can be there
+fn verify_constants(class: &ClassFile) -> Result<()> {
+    let expect = |index: u16, kind: &str, ok: fn(&Constant) -> bool| -> Result<()> {
+        let constant = class.constant(index)?;
+        ensure!(
+            ok(constant),
+            "Constant #{} should be a {}, but it's {:?}",
+            index,
+            kind,
+            constant
+        );
+        Ok(())
+    };
+    let is_utf8 = |c: &Constant| matches!(c, Constant::Utf8(_));
+    let is_class = |c: &Constant| matches!(c, Constant::Class(_));
+    let is_name_and_type = |c: &Constant| matches!(c, Constant::NameAndType { .. });
+
+    for (i, constant) in class.constant_pool.iter().enumerate() {
+        match *constant {
+            Constant::Class(name)
+            | Constant::String(name)
+            | Constant::MethodType(name)
+            | Constant::Module(name)
+            | Constant::Package(name) => expect(name, "UTF8_INFO", is_utf8),
+            Constant::FieldRef {
+                class: owner,
+                name_and_type,
+            }
+            | Constant::MethodRef {
+                class: owner,
+                name_and_type,
+            }
+            | Constant::InterfaceMethodRef {
+                class: owner,
+                name_and_type,
+            } => expect(owner, "CLASS_INFO", is_class)
+                .and_then(|_| expect(name_and_type, "NAME_AND_TYPE_INFO", is_name_and_type)),
+            Constant::NameAndType { name, descriptor } => expect(name, "UTF8_INFO", is_utf8)
+                .and_then(|_| expect(descriptor, "UTF8_INFO", is_utf8)),
+            Constant::MethodHandle { reference, .. } => expect(reference, "member ref", |c| {
+                matches!(
+                    c,
+                    Constant::FieldRef { .. }
+                        | Constant::MethodRef { .. }
+                        | Constant::InterfaceMethodRef { .. }
+                )
+            }),
+            Constant::Dynamic { name_and_type, .. }
+            | Constant::InvokeDynamic { name_and_type, .. } => {
+                expect(name_and_type, "NAME_AND_TYPE_INFO", is_name_and_type)
+            }
+            _ => Ok(()),
+        }
+        .with_context(|| format!("In constant #{}", i))?;
+    }
+
+    expect(class.this_class, "CLASS_INFO", is_class)?;
+    // only java/lang/Object and the module-info have no superclass
+    if class.super_class != 0 {
+        expect(class.super_class, "CLASS_INFO", is_class)?;
+    }
+    for &interface in &class.interfaces {
+        expect(interface, "CLASS_INFO", is_class)?;
+    }
+    for member in class.fields.iter().chain(&class.methods) {
+        expect(member.name_index, "UTF8_INFO", is_utf8)?;
+        expect(member.descriptor_index, "UTF8_INFO", is_utf8)?;
+        for attribute in &member.attributes {
+            expect(attribute.name_index, "UTF8_INFO", is_utf8)?;
+        }
+    }
+    for attribute in &class.attributes {
+        expect(attribute.name_index, "UTF8_INFO", is_utf8)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,162 @@
+//! Class file versions, and which of them the things a class uses need.
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    bytecode::{self, JSR, JSR_W, RET},
+    class::{ClassFile, Code, Constant, ACC_ABSTRACT, ACC_INTERFACE},
+};
+
+/// The earliest major version that javac could produce (JDK 1.0.2)
+pub const MIN_MAJOR: u16 = 45;
+
+/// The newest class file version (Java 17) whose additions this knows about,
+/// the newer ones are processed as if they had nothing new
+pub const MAX_KNOWN_MAJOR: u16 = 61;
+
+/// The minor version of the classes that use the preview features of their
+/// Java release (compiled with --enable-preview)
+pub const PREVIEW_MINOR: u16 = 0xFFFF;
+
+/// The Java release the major version is from, like "Java 8" for 52
+pub fn release_name(major: u16) -> String {
+    match major {
+        45 => "Java 1.1".into(),
+        46..=48 => format!("Java 1.{}", major - 44),
+        _ => format!("Java {}", major.saturating_sub(44)),
+    }
+}
+
+/// Accepts both the major version (52) and the Java release (8, or 1.8)
+pub fn parse_version(s: &str) -> Result<u16> {
+    let s = s.strip_prefix("1.").unwrap_or(s);
+    let version: u16 = s
+        .parse()
+        .with_context(|| format!("'{}' is not a class file version", s))?;
+    Ok(if version < MIN_MAJOR {
+        version + 44
+    } else {
+        version
+    })
+}
+
+/// The class file version the attribute was introduced in, and whether the
+/// class behaves differently without it (as opposed to just losing some
+/// metadata, like annotations becoming invisible for reflection)
+fn attribute_version(name: &str) -> Option<(u16, bool)> {
+    Some(match name {
+        "EnclosingMethod"
+        | "Signature"
+        | "SourceDebugExtension"
+        | "LocalVariableTypeTable"
+        | "RuntimeVisibleAnnotations"
+        | "RuntimeInvisibleAnnotations"
+        | "RuntimeVisibleParameterAnnotations"
+        | "RuntimeInvisibleParameterAnnotations"
+        | "AnnotationDefault" => (49, false),
+        "StackMapTable" => (50, false),
+        "BootstrapMethods" => (51, true),
+        "MethodParameters"
+        | "RuntimeVisibleTypeAnnotations"
+        | "RuntimeInvisibleTypeAnnotations" => (52, false),
+        "Module" | "ModulePackages" | "ModuleMainClass" => (53, true),
+        "NestHost" | "NestMembers" => (55, true),
+        "Record" => (60, true),
+        "PermittedSubclasses" => (61, true),
+        _ => return None,
+    })
+}
+
+/// Checks that changing the version of the class to `target` doesn't make it
+/// invalid, as far as it's possible to tell without doing the verifier's job
+pub fn check_target(class: &ClassFile, target: u16) -> Result<()> {
+    // preview features change from release to release, so such classes only
+    // run on the exact one they were compiled for
+    if class.minor_version == PREVIEW_MINOR && class.major_version != target {
+        bail!(
+            "It uses the preview features of {}, which only work with that exact version",
+            release_name(class.major_version)
+        );
+    }
+
+    let too_new = |what: &str, needed: u16| -> Result<()> {
+        if needed > target {
+            bail!(
+                "It uses {}, which needs class file version {} ({}) or newer",
+                what,
+                needed,
+                release_name(needed)
+            );
+        }
+        Ok(())
+    };
+
+    for constant in &class.constant_pool {
+        match constant {
+            Constant::MethodHandle { .. } => too_new("method handle constants", 51)?,
+            Constant::MethodType(_) => too_new("method type constants", 51)?,
+            Constant::InvokeDynamic { .. } => too_new("invokedynamic", 51)?,
+            Constant::Module(_) | Constant::Package(_) => too_new("module constants", 53)?,
+            Constant::Dynamic { .. } => too_new("dynamic constants", 55)?,
+            _ => {}
+        }
+    }
+
+    if class.access_flags & ACC_INTERFACE != 0 {
+        for method in &class.methods {
+            let name = class.utf8(method.name_index)?;
+            if method.access_flags & ACC_ABSTRACT == 0 && name != "<clinit>" {
+                too_new("non-abstract interface methods", 52)?;
+            }
+        }
+    }
+
+    let members = class.fields.iter().chain(&class.methods);
+    let attributes = members.flat_map(|m| &m.attributes).chain(&class.attributes);
+    for attribute in attributes {
+        let name = class.attribute_name(attribute)?;
+        // VMs ignore the attributes from the versions newer than the class
+        match attribute_version(&name) {
+            Some((needed, true)) => too_new(&format!("the {} attribute", name), needed)?,
+            Some((needed, false)) if needed > target => {
+                log::debug!("The {} attribute will be ignored at the new version", name)
+            }
+            _ => {}
+        }
+        if name != "Code" {
+            continue;
+        }
+        check_code(class, &Code::parse(&attribute.info)?, target)?;
+    }
+    Ok(())
+}
+
+fn check_code(class: &ClassFile, code: &Code, target: u16) -> Result<()> {
+    // the split verifier, which needs the stack map frames, is mandatory
+    // since 51, and jsr/ret are not allowed with it
+    if target >= 51 && class.major_version < 51 {
+        let instructions = bytecode::instructions(&code.code)?;
+        if let Some(i) = instructions
+            .iter()
+            .find(|i| matches!(i.opcode, JSR | JSR_W | RET))
+        {
+            bail!(
+                "It uses jsr/ret instructions (at pc {}), which are not allowed since class file version 51 (Java 7)",
+                i.pc
+            );
+        }
+        let has_frames = code
+            .attributes
+            .iter()
+            .map(|a| class.attribute_name(a))
+            .collect::<Result<Vec<_>>>()?
+            .iter()
+            .any(|name| name == "StackMapTable");
+        let needs_frames =
+            !code.exception_table.is_empty() || instructions.iter().any(|i| i.is_branch());
+        if needs_frames && !has_frames {
+            bail!("It has methods without stack map frames, which are required since class file version 51 (Java 7)");
+        }
+    }
+    Ok(())
+}
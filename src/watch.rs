@@ -0,0 +1,68 @@
+//! Noticing the jars that were replaced (say, by a game update) by polling
+//! their modification times, since there's no portable way to be told.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// What a file looked like, by the times and sizes that change when it's
+/// written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some(Stamp {
+        modified: metadata.modified().ok(),
+        len: metadata.len(),
+    })
+}
+
+#[derive(Debug, Default)]
+pub struct Watcher {
+    /// The files as they were on the last poll
+    seen: HashMap<PathBuf, Stamp>,
+    /// The files as they were when they were last handed out
+    handled: HashMap<PathBuf, Stamp>,
+    polled: bool,
+}
+
+impl Watcher {
+    /// The files that changed since they were last handed out, and which
+    /// didn't change since the last poll, so that the ones still being
+    /// written are left for later. On the first poll that's all of them
+    pub fn poll(&mut self, files: &[PathBuf]) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        let mut seen = HashMap::new();
+        for file in files {
+            let stamp = match stamp(file) {
+                Some(stamp) => stamp,
+                None => continue,
+            };
+            let settled = !self.polled || self.seen.get(file) == Some(&stamp);
+            if settled && self.handled.get(file) != Some(&stamp) {
+                changed.push(file.clone());
+            }
+            seen.insert(file.clone(), stamp);
+        }
+        self.seen = seen;
+        self.polled = true;
+        changed
+    }
+
+    /// Remembers the files as they are now, after they were dealt with
+    /// (which usually means written to)
+    pub fn mark_handled(&mut self, files: &[PathBuf]) {
+        for file in files {
+            if let Some(stamp) = stamp(file) {
+                self.seen.insert(file.clone(), stamp);
+                self.handled.insert(file.clone(), stamp);
+            }
+        }
+    }
+}